@@ -0,0 +1,111 @@
+//! Compares `Channel` with `RingChannel` when flooding a veth pair
+//!
+//! Needs root and a veth pair, created for example with:
+//!
+//! ```text
+//! ip link add pakit0 type veth peer name pakit1
+//! ip link set pakit0 up && ip link set pakit1 up
+//! ```
+//!
+//! Run with `cargo bench --bench ring_veth`. Interface names can be changed
+//! with `PAKIT_TX_IFACE` and `PAKIT_RX_IFACE`.
+
+use pakit::io::PacketIo;
+use pakit::{Channel, RingChannel};
+use std::env;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const FRAMES: usize = 200_000;
+const BATCH: usize = 256;
+
+fn frame() -> Vec<u8> {
+    let mut frame = vec![0xff; 6];
+    frame.extend_from_slice(&[0x02, 0, 0, 0, 0, 1]);
+    frame.extend_from_slice(&[0x88, 0xb5]);
+    frame.resize(64, 0xab);
+    frame
+}
+
+/// Counts frames received on `iface` until nothing arrives for a while
+fn count_received(iface: String) -> thread::JoinHandle<usize> {
+    thread::spawn(move || {
+        let mut rx = RingChannel::from(iface).unwrap();
+        let mut received = 0;
+        while let Some(batch) = rx.recv_batch(Duration::from_millis(500)).unwrap() {
+            received += batch.len();
+        }
+        received
+    })
+}
+
+fn report(name: &str, sent: usize, received: usize, elapsed: Duration) {
+    let secs = elapsed.as_secs_f64();
+    println!(
+        "{:<28} {:>8} sent {:>8} received {:>12.0} pps {:>8.1} Mbps",
+        name,
+        sent,
+        received,
+        sent as f64 / secs,
+        (sent * 64 * 8) as f64 / secs / 1_000_000.0
+    );
+}
+
+fn main() {
+    let tx_iface = env::var("PAKIT_TX_IFACE").unwrap_or_else(|_| "pakit0".to_string());
+    let rx_iface = env::var("PAKIT_RX_IFACE").unwrap_or_else(|_| "pakit1".to_string());
+    let frame = frame();
+
+    match Channel::from(&tx_iface) {
+        Ok(mut channel) => {
+            let receiver = count_received(rx_iface.clone());
+            thread::sleep(Duration::from_millis(100));
+            let time_start = Instant::now();
+            for _ in 0..FRAMES {
+                PacketIo::send(&mut channel, &frame).unwrap();
+            }
+            let elapsed = time_start.elapsed();
+            report("Channel::send", FRAMES, receiver.join().unwrap(), elapsed);
+        }
+        Err(err) => println!(
+            "Skipping Channel::send, cannot open {}: {:?}",
+            tx_iface, err
+        ),
+    }
+
+    let mut ring = match RingChannel::from(&tx_iface) {
+        Ok(ring) => ring,
+        Err(err) => {
+            println!("Skipping benchmark, cannot open {}: {:?}", tx_iface, err);
+            return;
+        }
+    };
+    let receiver = count_received(rx_iface.clone());
+    thread::sleep(Duration::from_millis(100));
+    let time_start = Instant::now();
+    for _ in 0..FRAMES {
+        PacketIo::send(&mut ring, &frame).unwrap();
+    }
+    let elapsed = time_start.elapsed();
+    report(
+        "RingChannel::send",
+        FRAMES,
+        receiver.join().unwrap(),
+        elapsed,
+    );
+
+    let batch: Vec<&[u8]> = (0..BATCH).map(|_| frame.as_slice()).collect();
+    let receiver = count_received(rx_iface);
+    thread::sleep(Duration::from_millis(100));
+    let time_start = Instant::now();
+    for _ in 0..FRAMES / BATCH {
+        ring.send_batch(&batch).unwrap();
+    }
+    let elapsed = time_start.elapsed();
+    report(
+        "RingChannel::send_batch",
+        FRAMES / BATCH * BATCH,
+        receiver.join().unwrap(),
+        elapsed,
+    );
+}
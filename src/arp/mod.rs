@@ -0,0 +1,15 @@
+//! ARP helpers built on `ArpHdr`
+//!
+//! Everything here runs on any `PacketIo`, so it can be used on a `Channel`
+//! as well as tested on a `LoopbackIo`.
+
+mod oui;
+pub use oui::*;
+mod resolver;
+pub use resolver::*;
+mod scan;
+pub use scan::*;
+mod spoof;
+pub use spoof::*;
+mod watch;
+pub use watch::*;
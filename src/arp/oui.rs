@@ -0,0 +1,54 @@
+/// Small table of well known OUIs, sorted by prefix
+const OUI_TABLE: &[([u8; 3], &str)] = &[
+    ([0x00, 0x00, 0x0c], "Cisco"),
+    ([0x00, 0x03, 0x93], "Apple"),
+    ([0x00, 0x03, 0xff], "Microsoft"),
+    ([0x00, 0x04, 0x4b], "NVIDIA"),
+    ([0x00, 0x05, 0x69], "VMware"),
+    ([0x00, 0x09, 0x0f], "Fortinet"),
+    ([0x00, 0x0c, 0x29], "VMware"),
+    ([0x00, 0x0d, 0xb9], "PC Engines"),
+    ([0x00, 0x10, 0x18], "Broadcom"),
+    ([0x00, 0x11, 0x32], "Synology"),
+    ([0x00, 0x14, 0x22], "Dell"),
+    ([0x00, 0x15, 0x5d], "Microsoft"),
+    ([0x00, 0x16, 0x3e], "Xensource"),
+    ([0x00, 0x17, 0xf2], "Apple"),
+    ([0x00, 0x18, 0x0a], "Cisco Meraki"),
+    ([0x00, 0x1a, 0x11], "Google"),
+    ([0x00, 0x1b, 0x17], "Palo Alto Networks"),
+    ([0x00, 0x1b, 0x21], "Intel"),
+    ([0x00, 0x1b, 0x63], "Apple"),
+    ([0x00, 0x1c, 0x14], "VMware"),
+    ([0x00, 0x1c, 0x42], "Parallels"),
+    ([0x00, 0x25, 0x90], "Super Micro"),
+    ([0x00, 0x26, 0xbb], "Apple"),
+    ([0x00, 0x50, 0x43], "Marvell"),
+    ([0x00, 0x50, 0x56], "VMware"),
+    ([0x00, 0xe0, 0x4c], "Realtek"),
+    ([0x08, 0x00, 0x27], "Oracle VirtualBox"),
+    ([0x24, 0xa4, 0x3c], "Ubiquiti"),
+    ([0x3c, 0x5a, 0xb4], "Google"),
+    ([0x44, 0xd9, 0xe7], "Ubiquiti"),
+    ([0x52, 0x54, 0x00], "QEMU"),
+    ([0x68, 0x72, 0x51], "Ubiquiti"),
+    ([0x78, 0x8a, 0x20], "Ubiquiti"),
+    ([0x80, 0x2a, 0xa8], "Ubiquiti"),
+    ([0xb8, 0x27, 0xeb], "Raspberry Pi"),
+    ([0xdc, 0xa6, 0x32], "Raspberry Pi"),
+    ([0xe4, 0x5f, 0x01], "Raspberry Pi"),
+    ([0xf0, 0x9f, 0xc2], "Ubiquiti"),
+    ([0xf4, 0xf5, 0xd8], "Google"),
+    ([0xfc, 0xec, 0xda], "Ubiquiti"),
+];
+
+/// Returns vendor owning the OUI of `mac`, if it is in the built in table
+///
+/// The table only holds common vendors of servers, virtual machines and
+/// network gear, it is not a full copy of the IEEE registry.
+pub fn oui_vendor(mac: &[u8; 6]) -> Option<&'static str> {
+    OUI_TABLE
+        .binary_search_by(|(oui, _)| oui[..].cmp(&mac[0..3]))
+        .ok()
+        .map(|i| OUI_TABLE[i].1)
+}
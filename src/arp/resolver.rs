@@ -0,0 +1,236 @@
+use crate::error::{ErrorType, PaError};
+use crate::hdr::{eth_type, ArpHdr, EthHdr, REP};
+use crate::iface::{Interface, Ipv4Net};
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::utility::ip_to_string;
+use crate::Pdu;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Resolves IPv4 addresses to MAC addresses with ARP requests
+///
+/// Resolved addresses are cached for `ttl`. A request is sent again up to
+/// `retries` times when no reply came within `timeout`.
+#[derive(Debug, Clone)]
+pub struct ArpResolver {
+    pub src_mac: [u8; 6],
+    pub src_ip: [u8; 4],
+    /// Subnet reachable without going through `gateway`
+    pub subnet: Option<Ipv4Net>,
+    pub gateway: Option<[u8; 4]>,
+    pub ttl: Duration,
+    pub timeout: Duration,
+    pub retries: usize,
+    cache: HashMap<[u8; 4], ([u8; 6], Instant)>,
+}
+
+impl ArpResolver {
+    /// Creates resolver sending requests from `src_mac` and `src_ip`
+    pub fn new(src_mac: [u8; 6], src_ip: [u8; 4]) -> Self {
+        Self {
+            src_mac,
+            src_ip,
+            subnet: None,
+            gateway: None,
+            ttl: Duration::from_secs(60),
+            timeout: Duration::from_secs(1),
+            retries: 3,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Creates resolver using address, subnet and gateway of `interf`
+    pub fn from_interface(interf: &Interface) -> Result<Self, PaError> {
+        let mac = interf.mac.ok_or_else(|| {
+            PaError::new("Interface has no MAC address", ErrorType::InterfaceError)
+        })?;
+        let net = interf.ipv4.first().ok_or_else(|| {
+            PaError::new("Interface has no IPv4 address", ErrorType::InterfaceError)
+        })?;
+        let mut resolver = Self::new(mac, net.addr);
+        resolver.subnet = Some(*net);
+        resolver.gateway = interf.gateway().unwrap_or(None);
+        Ok(resolver)
+    }
+
+    /// Returns cached MAC of `ip` if it has not expired
+    pub fn cached(&self, ip: [u8; 4]) -> Option<[u8; 6]> {
+        match self.cache.get(&ip) {
+            Some((mac, since)) if since.elapsed() < self.ttl => Some(*mac),
+            _ => None,
+        }
+    }
+
+    /// Adds or refreshes a cache entry
+    pub fn insert(&mut self, ip: [u8; 4], mac: [u8; 6]) {
+        self.cache.insert(ip, (mac, Instant::now()));
+    }
+
+    pub fn remove(&mut self, ip: [u8; 4]) {
+        self.cache.remove(&ip);
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Returns address whose MAC is needed to reach `ip`
+    ///
+    /// That is `ip` itself when it is in `subnet` or no gateway is known,
+    /// else the gateway.
+    pub fn next_hop(&self, ip: [u8; 4]) -> [u8; 4] {
+        match (self.subnet, self.gateway) {
+            (Some(subnet), Some(gateway)) if !subnet.contains(ip) => gateway,
+            _ => ip,
+        }
+    }
+
+    /// Builds broadcast ARP request for `ip`
+    pub fn request(&self, ip: [u8; 4]) -> Result<Pdu, PaError> {
+        let mut arp = ArpHdr::new();
+        arp.src_hw_addr = self.src_mac;
+        arp.src_proto_addr = self.src_ip;
+        arp.dst_proto_addr = ip;
+        let mut pdu = Pdu::new()
+            .header(EthHdr::from_raw(
+                self.src_mac,
+                [0xff; 6],
+                eth_type::ARP as u16,
+            ))
+            .header(arp);
+        pdu.build()?;
+        Ok(pdu)
+    }
+
+    /// Returns MAC of `ip`, from cache or by asking on `io`
+    pub fn resolve_mac<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        ip: [u8; 4],
+    ) -> Result<[u8; 6], PaError> {
+        if let Some(mac) = self.cached(ip) {
+            return Ok(mac);
+        }
+
+        let request = self.request(ip)?;
+        for _ in 0..self.retries.max(1) {
+            request.send_on(io)?;
+            let deadline = Instant::now() + self.timeout;
+            while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+                let frame = match io.recv_timeout(left)? {
+                    Some(frame) => frame,
+                    None => break,
+                };
+                let reply = Pdu::parse(&frame);
+                if !reply.answers(&request) {
+                    continue;
+                }
+                if let Some(Proto::Arp(arp)) = reply.headers.get(&3) {
+                    if arp.opr.to_usize() == REP as usize {
+                        self.insert(ip, arp.src_hw_addr);
+                        return Ok(arp.src_hw_addr);
+                    }
+                }
+            }
+        }
+
+        Err(PaError::new(
+            format!("No ARP reply from {}", ip_to_string(&ip)),
+            ErrorType::TimeoutError,
+        ))
+    }
+
+    /// Resolves MACs of all `ips` at once, without waiting on each of them
+    ///
+    /// Requests for addresses not in cache are sent `interval` apart, then
+    /// replies are awaited for `timeout`. Addresses which did not answer
+    /// are asked again in up to `retries` rounds in total. Returns those of
+    /// `ips` now in cache.
+    pub fn resolve_all<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        ips: &[[u8; 4]],
+        interval: Duration,
+    ) -> Result<Vec<[u8; 4]>, PaError> {
+        let mut pending: HashSet<[u8; 4]> = ips
+            .iter()
+            .copied()
+            .filter(|ip| self.cached(*ip).is_none())
+            .collect();
+        for _ in 0..self.retries.max(1) {
+            if pending.is_empty() {
+                break;
+            }
+            let round: Vec<[u8; 4]> = ips
+                .iter()
+                .copied()
+                .filter(|ip| pending.contains(ip))
+                .collect();
+            let mut next_send = Instant::now();
+            for ip in round.iter() {
+                self.collect_replies(io, next_send, &mut pending)?;
+                self.request(*ip)?.send_on(io)?;
+                next_send = Instant::now() + interval;
+            }
+            let until = Instant::now() + self.timeout;
+            self.collect_replies(io, until, &mut pending)?;
+        }
+        Ok(ips
+            .iter()
+            .copied()
+            .filter(|ip| self.cached(*ip).is_some())
+            .collect())
+    }
+
+    /// Caches replies to requests for `pending` addresses received until `until`
+    fn collect_replies<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        until: Instant,
+        pending: &mut HashSet<[u8; 4]>,
+    ) -> Result<(), PaError> {
+        while let Some(left) = until.checked_duration_since(Instant::now()) {
+            let frame = match io.recv_timeout(left)? {
+                Some(frame) => frame,
+                None => break,
+            };
+            if let Some(Proto::Arp(arp)) = Pdu::parse(&frame).headers.remove(&3) {
+                if arp.opr.to_usize() == REP as usize
+                    && arp.dst_proto_addr == self.src_ip
+                    && pending.remove(&arp.src_proto_addr)
+                {
+                    self.insert(arp.src_proto_addr, arp.src_hw_addr);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Pdu {
+    /// Builds `Pdu` after filling unset Ethernet addresses through `resolver`
+    ///
+    /// When the Ethernet destination is all zeros and next layer is IPv4, it
+    /// is set to the MAC of the next hop towards IPv4 destination. An all zeros
+    /// Ethernet source is set to MAC of `resolver`.
+    pub fn build_resolved<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        resolver: &mut ArpResolver,
+    ) -> Result<(), PaError> {
+        let dst_ip = match self.headers.get(&3) {
+            Some(Proto::IPv4(ipv4)) => Some(ipv4.dst_ip_addr),
+            _ => None,
+        };
+        if let Some(Proto::Eth(eth)) = self.headers.get_mut(&2) {
+            if eth.src_hw_addr == [0; 6] {
+                eth.src_hw_addr = resolver.src_mac;
+            }
+            if let (true, Some(dst_ip)) = (eth.dst_hw_addr == [0; 6], dst_ip) {
+                eth.dst_hw_addr = resolver.resolve_mac(io, resolver.next_hop(dst_ip))?;
+            }
+        }
+        self.build()
+    }
+}
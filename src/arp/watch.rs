@@ -0,0 +1,416 @@
+use crate::error::PaError;
+use crate::hdr::{ArpHdr, EthHdr, REP, REQ};
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::utility::{ip_to_string, mac_to_string};
+use crate::Pdu;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "pcap")]
+use crate::error::ErrorType;
+#[cfg(feature = "pcap")]
+use pcap_file::PcapReader;
+#[cfg(feature = "pcap")]
+use std::fs::File;
+
+/// Kind of anomaly found by `ArpWatch`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArpEventKind {
+    /// First time `ip` is seen
+    NewStation { ip: [u8; 4], mac: [u8; 6] },
+    /// `ip` moved from `old_mac` to `new_mac`
+    BindingChanged {
+        ip: [u8; 4],
+        old_mac: [u8; 6],
+        new_mac: [u8; 6],
+    },
+    /// Binding of `ip` changed `changes` times within `WatchOpts::flap_window`
+    MacFlapping {
+        ip: [u8; 4],
+        macs: Vec<[u8; 6]>,
+        changes: usize,
+    },
+    /// `count` gratuitous ARPs for `ip` within `WatchOpts::gratuitous_window`
+    GratuitousFlood {
+        ip: [u8; 4],
+        mac: [u8; 6],
+        count: usize,
+    },
+    /// Several MACs answered the same request for `ip`
+    DuplicateIp { ip: [u8; 4], macs: Vec<[u8; 6]> },
+    /// Reply from `ip` to `dst_ip` without a request seen before it
+    UnsolicitedReply {
+        ip: [u8; 4],
+        mac: [u8; 6],
+        dst_ip: [u8; 4],
+    },
+    /// Ethernet source and ARP sender hardware address differ
+    SenderMismatch {
+        ip: [u8; 4],
+        eth_mac: [u8; 6],
+        arp_mac: [u8; 6],
+    },
+}
+
+impl fmt::Display for ArpEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let macs = |macs: &[[u8; 6]]| {
+            macs.iter()
+                .map(|mac| mac_to_string(mac))
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        match self {
+            Self::NewStation { ip, mac } => {
+                write!(
+                    f,
+                    "new station {} at {}",
+                    ip_to_string(ip),
+                    mac_to_string(mac)
+                )
+            }
+            Self::BindingChanged {
+                ip,
+                old_mac,
+                new_mac,
+            } => write!(
+                f,
+                "{} moved from {} to {}",
+                ip_to_string(ip),
+                mac_to_string(old_mac),
+                mac_to_string(new_mac)
+            ),
+            Self::MacFlapping {
+                ip,
+                macs: m,
+                changes,
+            } => write!(
+                f,
+                "{} flapping between {} ({} changes)",
+                ip_to_string(ip),
+                macs(m),
+                changes
+            ),
+            Self::GratuitousFlood { ip, mac, count } => write!(
+                f,
+                "{} gratuitous ARPs for {} from {}",
+                count,
+                ip_to_string(ip),
+                mac_to_string(mac)
+            ),
+            Self::DuplicateIp { ip, macs: m } => {
+                write!(f, "{} claimed by {}", ip_to_string(ip), macs(m))
+            }
+            Self::UnsolicitedReply { ip, mac, dst_ip } => write!(
+                f,
+                "unsolicited reply {} at {} sent to {}",
+                ip_to_string(ip),
+                mac_to_string(mac),
+                ip_to_string(dst_ip)
+            ),
+            Self::SenderMismatch {
+                ip,
+                eth_mac,
+                arp_mac,
+            } => write!(
+                f,
+                "{} sent from {} claims sender {}",
+                ip_to_string(ip),
+                mac_to_string(eth_mac),
+                mac_to_string(arp_mac)
+            ),
+        }
+    }
+}
+
+/// Anomaly found by `ArpWatch`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArpEvent {
+    /// Time of the frame which raised the event
+    pub ts: Duration,
+    pub kind: ArpEventKind,
+}
+
+/// Thresholds of `ArpWatch`
+#[derive(Debug, Clone)]
+pub struct WatchOpts {
+    /// How long a request waits for its replies
+    pub request_timeout: Duration,
+    pub gratuitous_window: Duration,
+    /// Gratuitous ARPs of one IP within window raising `GratuitousFlood`
+    pub gratuitous_threshold: usize,
+    pub flap_window: Duration,
+    /// Binding changes of one IP within window raising `MacFlapping`
+    pub flap_threshold: usize,
+}
+
+impl WatchOpts {
+    pub fn new() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(3),
+            gratuitous_window: Duration::from_secs(10),
+            gratuitous_threshold: 10,
+            flap_window: Duration::from_secs(60),
+            flap_threshold: 3,
+        }
+    }
+}
+
+impl Default for WatchOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// IP to MAC binding seen on the wire
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub mac: [u8; 6],
+    pub first_seen: Duration,
+    pub last_seen: Duration,
+    changes: VecDeque<(Duration, [u8; 6])>,
+}
+
+struct PendingRequest {
+    sent: Duration,
+    replied_by: Vec<[u8; 6]>,
+}
+
+/// Removes entries older than `window` before `now`
+fn expire<T>(times: &mut VecDeque<(Duration, T)>, now: Duration, window: Duration) {
+    while let Some((ts, _)) = times.front() {
+        if now.saturating_sub(*ts) <= window {
+            break;
+        }
+        times.pop_front();
+    }
+}
+
+/// Passive ARP monitor
+///
+/// Keeps a table of IP to MAC bindings from ARP traffic it is fed and raises
+/// an `ArpEvent` for every anomaly. Frames are never sent.
+pub struct ArpWatch {
+    pub opts: WatchOpts,
+    bindings: HashMap<[u8; 4], Binding>,
+    /// Requests keyed by asking IP and asked IP
+    pending: HashMap<([u8; 4], [u8; 4]), PendingRequest>,
+    gratuitous: HashMap<[u8; 4], VecDeque<(Duration, ())>>,
+}
+
+impl ArpWatch {
+    pub fn new() -> Self {
+        Self::with_opts(WatchOpts::new())
+    }
+
+    pub fn with_opts(opts: WatchOpts) -> Self {
+        Self {
+            opts,
+            bindings: HashMap::new(),
+            pending: HashMap::new(),
+            gratuitous: HashMap::new(),
+        }
+    }
+
+    /// Returns current binding table
+    pub fn bindings(&self) -> &HashMap<[u8; 4], Binding> {
+        &self.bindings
+    }
+
+    /// Processes one frame seen at `ts`, non ARP frames are ignored
+    pub fn process(&mut self, frame: &[u8], ts: Duration) -> Vec<ArpEvent> {
+        let mut pdu = Pdu::parse(frame);
+        match (pdu.headers.remove(&2), pdu.headers.remove(&3)) {
+            (Some(Proto::Eth(eth)), Some(Proto::Arp(arp))) => {
+                self.process_arp(Some(&eth), &arp, ts)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Processes one ARP header seen at `ts` in a frame with header `eth`
+    pub fn process_arp(
+        &mut self,
+        eth: Option<&EthHdr>,
+        arp: &ArpHdr,
+        ts: Duration,
+    ) -> Vec<ArpEvent> {
+        let mut events = Vec::new();
+        let mut raise = |kind| events.push(ArpEvent { ts, kind });
+        let ip = arp.src_proto_addr;
+        let mac = arp.src_hw_addr;
+        let opr = arp.opr.to_usize() as u16;
+
+        if let Some(eth) = eth {
+            if eth.src_hw_addr != mac {
+                raise(ArpEventKind::SenderMismatch {
+                    ip,
+                    eth_mac: eth.src_hw_addr,
+                    arp_mac: mac,
+                });
+            }
+        }
+
+        let timeout = self.opts.request_timeout;
+        self.pending
+            .retain(|_, req| ts.saturating_sub(req.sent) <= timeout);
+
+        // Probes are sent from 0.0.0.0 and announce no binding
+        if ip == [0; 4] {
+            return events;
+        }
+
+        let is_gratuitous = ip == arp.dst_proto_addr;
+        if is_gratuitous {
+            let times = self.gratuitous.entry(ip).or_default();
+            expire(times, ts, self.opts.gratuitous_window);
+            times.push_back((ts, ()));
+            if times.len() == self.opts.gratuitous_threshold {
+                raise(ArpEventKind::GratuitousFlood {
+                    ip,
+                    mac,
+                    count: times.len(),
+                });
+            }
+        } else if opr == REQ {
+            self.pending.insert(
+                (ip, arp.dst_proto_addr),
+                PendingRequest {
+                    sent: ts,
+                    replied_by: Vec::new(),
+                },
+            );
+        } else if opr == REP {
+            match self.pending.get_mut(&(arp.dst_proto_addr, ip)) {
+                Some(req) => {
+                    if !req.replied_by.contains(&mac) {
+                        req.replied_by.push(mac);
+                        if req.replied_by.len() > 1 {
+                            raise(ArpEventKind::DuplicateIp {
+                                ip,
+                                macs: req.replied_by.clone(),
+                            });
+                        }
+                    }
+                }
+                None => raise(ArpEventKind::UnsolicitedReply {
+                    ip,
+                    mac,
+                    dst_ip: arp.dst_proto_addr,
+                }),
+            }
+        }
+
+        match self.bindings.get_mut(&ip) {
+            None => {
+                self.bindings.insert(
+                    ip,
+                    Binding {
+                        mac,
+                        first_seen: ts,
+                        last_seen: ts,
+                        changes: VecDeque::new(),
+                    },
+                );
+                raise(ArpEventKind::NewStation { ip, mac });
+            }
+            Some(binding) if binding.mac == mac => binding.last_seen = ts,
+            Some(binding) => {
+                raise(ArpEventKind::BindingChanged {
+                    ip,
+                    old_mac: binding.mac,
+                    new_mac: mac,
+                });
+                expire(&mut binding.changes, ts, self.opts.flap_window);
+                binding.changes.push_back((ts, binding.mac));
+                binding.mac = mac;
+                binding.last_seen = ts;
+                if binding.changes.len() == self.opts.flap_threshold {
+                    let mut macs: Vec<[u8; 6]> = Vec::new();
+                    for (_, old_mac) in binding.changes.iter() {
+                        if !macs.contains(old_mac) {
+                            macs.push(*old_mac);
+                        }
+                    }
+                    if !macs.contains(&mac) {
+                        macs.push(mac);
+                    }
+                    raise(ArpEventKind::MacFlapping {
+                        ip,
+                        macs,
+                        changes: binding.changes.len(),
+                    });
+                }
+            }
+        }
+
+        events
+    }
+
+    /// Watches frames received on `io` and passes every event to `on_event`
+    ///
+    /// Stops after `limit` frames or when receiving fails. Returns number of
+    /// frames processed.
+    pub fn run<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        limit: Option<usize>,
+        mut on_event: impl FnMut(ArpEvent),
+    ) -> Result<usize, PaError> {
+        let time_start = Instant::now();
+        let mut count = 0;
+        while limit != Some(count) {
+            let frame = io.recv()?;
+            for event in self.process(&frame, time_start.elapsed()) {
+                on_event(event);
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Same as `run` but sends events on `sender`
+    pub fn run_with_sender<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        limit: Option<usize>,
+        sender: Sender<ArpEvent>,
+    ) -> Result<usize, PaError> {
+        self.run(io, limit, |event| {
+            // Nobody listening any more is not an error of the watch
+            let _ = sender.send(event);
+        })
+    }
+
+    /// Watches every frame of pcap file at `pcap_path`, using its timestamps
+    #[cfg(feature = "pcap")]
+    pub fn watch_pcap(
+        &mut self,
+        pcap_path: impl ToString,
+        mut on_event: impl FnMut(ArpEvent),
+    ) -> Result<usize, PaError> {
+        let pcap = File::open(pcap_path.to_string())
+            .map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+        let pcap_reader = PcapReader::new(pcap)
+            .map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+        let mut count = 0;
+        for packet in pcap_reader {
+            let packet =
+                packet.map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+            for event in self.process(&packet.data, packet.header.timestamp()) {
+                on_event(event);
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl Default for ArpWatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
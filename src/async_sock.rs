@@ -0,0 +1,217 @@
+//! Contains `AsyncChannel`, a tokio based counterpart of `Channel`
+//!
+//! Available with `async` feature on Linux. The `AF_PACKET` socket is
+//! registered with the tokio reactor, so receiving never blocks a worker
+//! thread. Every `await` point is cancellation safe: a frame is either fully
+//! received or left in the socket, and a frame is either fully sent or not at
+//! all.
+
+use crate::error::{ErrorType, PaError};
+use crate::sock::Channel;
+use crate::sys;
+use crate::{debug, Pdu, Rules};
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::collections::VecDeque;
+use std::os::unix::io::OwnedFd;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+use tokio::time::{timeout_at, Instant};
+
+/// Largest frame read from the socket at once
+const MAX_FRAME_LEN: usize = 65536;
+
+/// Asynchronous layer 2 channel
+///
+/// Implements `Stream` of received `Pdu`s and `Sink` of `Pdu`s to send. Must
+/// be created from within a tokio runtime.
+pub struct AsyncChannel {
+    fd: AsyncFd<OwnedFd>,
+    buffer: Vec<u8>,
+    pending: VecDeque<Vec<u8>>,
+}
+
+impl AsyncChannel {
+    fn open(fd: OwnedFd) -> Result<Self, PaError> {
+        // SAFETY: `OwnedFd` keeps the socket open, and always returns the same
+        // descriptor, until the `AsyncFd` owning it is dropped.
+        let fd = unsafe { AsyncFd::register(fd) }.map_err(std::io::Error::from)?;
+        Ok(Self {
+            fd,
+            buffer: vec![0; MAX_FRAME_LEN],
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// Opens channel on first interface which is up, not loopback and has an IP
+    pub fn new() -> Result<Self, PaError> {
+        let interf = Channel::get_interface_list()
+            .into_iter()
+            .find(|e| e.is_up() && !e.is_loopback() && !e.ips.is_empty())
+            .ok_or_else(|| {
+                PaError::new(
+                    "Error in getting default interface",
+                    ErrorType::ChannelError,
+                )
+            })?;
+        Self::open(sys::packet_socket(interf.index, true)?)
+    }
+
+    /// Opens channel on interface called `interface`
+    pub fn from(interface: impl ToString) -> Result<Self, PaError> {
+        let interface = interface.to_string();
+        let interf = Channel::get_interface_list()
+            .into_iter()
+            .find(|e| e.name == interface)
+            .ok_or_else(|| PaError::new("Interface not found", ErrorType::InterfaceError))?;
+        Self::open(sys::packet_socket(interf.index, true)?)
+    }
+
+    /// Returns two channels connected to each other in memory
+    pub fn pair() -> Result<(Self, Self), PaError> {
+        let (a, b) = sys::socket_pair(true)?;
+        Ok((Self::open(a)?, Self::open(b)?))
+    }
+
+    /// Sends one raw frame
+    pub async fn send_packet(&self, frame: &[u8]) -> Result<usize, PaError> {
+        loop {
+            let mut guard = self.fd.writable().await?;
+            match guard.try_io(|fd| sys::send(fd, frame)) {
+                Ok(result) => return Ok(result?),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Receives one raw frame
+    pub async fn recv(&mut self) -> Result<Vec<u8>, PaError> {
+        loop {
+            let Self { fd, buffer, .. } = self;
+            let mut guard = fd.readable().await?;
+            match guard.try_io(|fd| sys::recv(fd, buffer)) {
+                Ok(result) => return Ok(buffer[..result?].to_vec()),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Sends `pdu` and waits at most `timeout` for the first `Pdu` answering it
+    ///
+    /// Returns `Ok(None)` if no answer came in time. `pdu` must be built.
+    pub async fn sr1(&mut self, pdu: &Pdu, timeout: Duration) -> Result<Option<Pdu>, PaError> {
+        let deadline = Instant::now() + timeout;
+        self.send_packet(&pdu.buffer).await?;
+        loop {
+            let frame = match timeout_at(deadline, self.recv()).await {
+                Ok(frame) => frame?,
+                Err(_elapsed) => return Ok(None),
+            };
+            if frame.len() < 14 {
+                continue;
+            }
+            let reply = Pdu::parse(&frame);
+            if reply.answers(pdu) {
+                return Ok(Some(reply));
+            }
+        }
+    }
+
+    /// Asynchronous version of `auto_reply`
+    pub async fn auto_reply(
+        &mut self,
+        rules: &Rules,
+        limit: Option<usize>,
+    ) -> Result<usize, PaError> {
+        let mut total_send = 0;
+        while limit != Some(total_send) {
+            let frame = self.recv().await?;
+            if frame.len() < 14 {
+                continue;
+            }
+            let pdu = Pdu::parse(&frame);
+            if let Some(mut reply) = rules.find(&pdu).and_then(|callback| callback(pdu)) {
+                reply.build()?;
+                self.send_packet(&reply.buffer).await?;
+                debug!("Send crafted response for matched Query");
+                total_send += 1;
+            }
+        }
+        Ok(total_send)
+    }
+
+    /// Sends every queued frame and closes the channel
+    pub async fn shutdown(mut self) -> Result<(), PaError> {
+        while let Some(frame) = self.pending.pop_front() {
+            self.send_packet(&frame).await?;
+        }
+        Ok(())
+    }
+
+    fn poll_send_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), PaError>> {
+        while let Some(frame) = self.pending.front() {
+            let mut guard = match self.fd.poll_write_ready(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.try_io(|fd| sys::send(fd, frame)) {
+                Ok(Ok(_)) => {
+                    self.pending.pop_front();
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err.into())),
+                Err(_would_block) => continue,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Stream for AsyncChannel {
+    type Item = Result<Pdu, PaError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Self { fd, buffer, .. } = self.get_mut();
+        loop {
+            let mut guard = match fd.poll_read_ready(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.try_io(|fd| sys::recv(fd, buffer)) {
+                Ok(Ok(0)) => return Poll::Ready(None),
+                Ok(Ok(len)) if len < 14 => continue,
+                Ok(Ok(len)) => return Poll::Ready(Some(Ok(Pdu::parse(&buffer[..len])))),
+                Ok(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl Sink<Pdu> for AsyncChannel {
+    type Error = PaError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), PaError>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Pdu) -> Result<(), PaError> {
+        if item.buffer.is_empty() {
+            return Err(PaError::new(
+                "Pdu must be built before sending",
+                ErrorType::ConstructError,
+            ));
+        }
+        self.get_mut().pending.push_back(item.buffer);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), PaError>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), PaError>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+}
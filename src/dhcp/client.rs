@@ -0,0 +1,234 @@
+use crate::error::PaError;
+use crate::hdr::{
+    bootp_op, dhcp_msg, dhcp_opt, dhcp_port, eth_type, Dhcp, DhcpOption, EthHdr, IPv4Hdr, UdpHdr,
+    BROADCAST,
+};
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::utility::random_u32;
+use crate::Pdu;
+use std::time::{Duration, Instant};
+
+/// Returns locally administered MAC number `index`, used to spoof clients
+pub fn spoofed_mac(index: u32) -> [u8; 6] {
+    let [a, b, c, d] = index.to_be_bytes();
+    [0x02, 0, a, b, c, d]
+}
+
+/// Options of `DhcpClient`
+#[derive(Debug, Clone)]
+pub struct ClientOpts {
+    /// How long each OFFER or ACK is awaited
+    pub timeout: Duration,
+    /// Number of times a message is sent again when unanswered
+    pub retries: usize,
+    /// Asks servers to broadcast their replies
+    pub broadcast: bool,
+    /// Options asked for through the parameter request list
+    pub params: Vec<u8>,
+}
+
+impl ClientOpts {
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(2),
+            retries: 2,
+            broadcast: true,
+            params: vec![
+                dhcp_opt::SUBNET_MASK,
+                dhcp_opt::ROUTER,
+                dhcp_opt::DNS,
+                dhcp_opt::DOMAIN_NAME,
+            ],
+        }
+    }
+}
+
+impl Default for ClientOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Address acquired by `DhcpClient`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientLease {
+    pub mac: [u8; 6],
+    pub ip: [u8; 4],
+    pub server_ip: [u8; 4],
+    pub server_mac: [u8; 6],
+    /// Lease time in seconds
+    pub lease_time: Option<u32>,
+    /// ACK holding every option given by the server
+    pub ack: Dhcp,
+}
+
+/// Emulates DHCP clients, each one using its own MAC
+#[derive(Debug, Clone)]
+pub struct DhcpClient {
+    pub opts: ClientOpts,
+    xid: u32,
+}
+
+impl DhcpClient {
+    pub fn new() -> Self {
+        Self::with_opts(ClientOpts::new())
+    }
+
+    pub fn with_opts(opts: ClientOpts) -> Self {
+        Self {
+            opts,
+            xid: random_u32(),
+        }
+    }
+
+    fn message(&self, msg_type: u8, mac: [u8; 6]) -> Dhcp {
+        let mut dhcp = Dhcp::request(msg_type, self.xid, mac);
+        if self.opts.broadcast {
+            dhcp.flags = BROADCAST;
+        }
+        dhcp.options.push(DhcpOption::ClientId {
+            htype: 1,
+            id: mac.to_vec(),
+        });
+        dhcp
+    }
+
+    /// Broadcasts `dhcp` and waits for a reply of one of `msg_types`,
+    /// sending it again up to `opts.retries` times
+    fn exchange<T: PacketIo>(
+        &self,
+        io: &mut T,
+        dhcp: Dhcp,
+        msg_types: &[u8],
+    ) -> Result<Option<(Dhcp, [u8; 6])>, PaError> {
+        let mac = dhcp.mac();
+        let mut pdu = Pdu::new()
+            .header(EthHdr::from_raw(mac, [0xff; 6], eth_type::IPv4 as u16))
+            .header(IPv4Hdr::from("0.0.0.0", "255.255.255.255", 0)?)
+            .header(UdpHdr::from(dhcp_port::CLIENT, dhcp_port::SERVER))
+            .header(dhcp);
+        pdu.build()?;
+        for _ in 0..=self.opts.retries {
+            pdu.send_on(io)?;
+            let until = Instant::now() + self.opts.timeout;
+            while let Some(left) = until.checked_duration_since(Instant::now()) {
+                let frame = match io.recv_timeout(left)? {
+                    Some(frame) => frame,
+                    None => break,
+                };
+                let mut reply = Pdu::parse(&frame);
+                let server_mac = match reply.headers.get(&2) {
+                    Some(Proto::Eth(eth)) => eth.src_hw_addr,
+                    _ => continue,
+                };
+                match reply.headers.remove(&7) {
+                    Some(Proto::Dhcp(reply))
+                        if reply.op == bootp_op::REPLY
+                            && reply.xid == self.xid
+                            && reply.mac() == mac
+                            && reply.msg_type().is_some_and(|t| msg_types.contains(&t)) =>
+                    {
+                        return Ok(Some((reply, server_mac)));
+                    }
+                    _ => continue,
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Runs DISCOVER, OFFER, REQUEST and ACK for `mac`
+    ///
+    /// Returns `None` when no server offered an address or the request was
+    /// refused with a NAK.
+    pub fn acquire<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        mac: [u8; 6],
+    ) -> Result<Option<ClientLease>, PaError> {
+        self.xid = self.xid.wrapping_add(1);
+        let mut discover = self.message(dhcp_msg::DISCOVER, mac);
+        discover
+            .options
+            .push(DhcpOption::ParamRequest(self.opts.params.clone()));
+        let offer = match self.exchange(io, discover, &[dhcp_msg::OFFER])? {
+            Some((offer, _)) => offer,
+            None => return Ok(None),
+        };
+        let server_ip = match offer.option(dhcp_opt::SERVER_ID) {
+            Some(DhcpOption::ServerId(ip)) => *ip,
+            _ => return Ok(None),
+        };
+
+        let mut request = self.message(dhcp_msg::REQUEST, mac);
+        request.options.extend(vec![
+            DhcpOption::RequestedIp(offer.yiaddr),
+            DhcpOption::ServerId(server_ip),
+            DhcpOption::ParamRequest(self.opts.params.clone()),
+        ]);
+        match self.exchange(io, request, &[dhcp_msg::ACK, dhcp_msg::NAK])? {
+            Some((ack, server_mac)) if ack.msg_type() == Some(dhcp_msg::ACK) => {
+                let lease_time = match ack.option(dhcp_opt::LEASE_TIME) {
+                    Some(DhcpOption::LeaseTime(secs)) => Some(*secs),
+                    _ => None,
+                };
+                Ok(Some(ClientLease {
+                    mac,
+                    ip: ack.yiaddr,
+                    server_ip,
+                    server_mac,
+                    lease_time,
+                    ack,
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Acquires an address for each of `macs`, as done to exhaust pools
+    ///
+    /// Returns leases of clients which got an address.
+    pub fn acquire_all<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        macs: impl IntoIterator<Item = [u8; 6]>,
+    ) -> Result<Vec<ClientLease>, PaError> {
+        let mut leases = Vec::new();
+        for mac in macs {
+            if let Some(lease) = self.acquire(io, mac)? {
+                leases.push(lease);
+            }
+        }
+        Ok(leases)
+    }
+
+    /// Gives `lease` back to its server
+    pub fn release<T: PacketIo>(&mut self, io: &mut T, lease: &ClientLease) -> Result<(), PaError> {
+        self.xid = self.xid.wrapping_add(1);
+        let mut release = Dhcp::request(dhcp_msg::RELEASE, self.xid, lease.mac);
+        release.ciaddr = lease.ip;
+        release.options.push(DhcpOption::ServerId(lease.server_ip));
+        let mut ip = IPv4Hdr::new();
+        ip.src_ip_addr = lease.ip;
+        ip.dst_ip_addr = lease.server_ip;
+        let mut pdu = Pdu::new()
+            .header(EthHdr::from_raw(
+                lease.mac,
+                lease.server_mac,
+                eth_type::IPv4 as u16,
+            ))
+            .header(ip)
+            .header(UdpHdr::from(dhcp_port::CLIENT, dhcp_port::SERVER))
+            .header(release);
+        pdu.build()?;
+        pdu.send_on(io)?;
+        Ok(())
+    }
+}
+
+impl Default for DhcpClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
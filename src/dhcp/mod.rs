@@ -0,0 +1,11 @@
+//! DHCPv4 client emulator and lightweight server built on `Dhcp`
+//!
+//! `DhcpClient` runs DISCOVER, OFFER, REQUEST and ACK exchanges for any
+//! number of spoofed MACs, as used to exhaust address pools. `DhcpServer`
+//! hands out leases from a pool through the receive, match and reply loop
+//! of `auto_reply`. Both run on any `PacketIo` sending Ethernet frames.
+
+mod client;
+pub use client::*;
+mod server;
+pub use server::*;
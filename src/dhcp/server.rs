@@ -0,0 +1,312 @@
+use crate::error::PaError;
+use crate::hdr::{
+    bootp_op, dhcp_msg, dhcp_opt, dhcp_port, eth_type, Dhcp, DhcpOption, DhcpQuery, EthHdr,
+    IPv4Hdr, UdpHdr, BROADCAST,
+};
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::{auto_reply, Pdu, QueryHdr, Rules};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Options of `DhcpServer`
+#[derive(Debug, Clone)]
+pub struct ServerOpts {
+    pub server_mac: [u8; 6],
+    pub server_ip: [u8; 4],
+    /// First address of the pool
+    pub pool_start: [u8; 4],
+    /// Last address of the pool
+    pub pool_end: [u8; 4],
+    pub lease_time: Duration,
+    /// How long an offered address is held back for the client
+    pub offer_timeout: Duration,
+    /// Options sent in every OFFER and ACK, such as subnet mask, router and
+    /// DNS servers
+    pub options: Vec<DhcpOption>,
+}
+
+impl ServerOpts {
+    pub fn new(
+        server_mac: [u8; 6],
+        server_ip: [u8; 4],
+        pool_start: [u8; 4],
+        pool_end: [u8; 4],
+    ) -> Self {
+        Self {
+            server_mac,
+            server_ip,
+            pool_start,
+            pool_end,
+            lease_time: Duration::from_secs(3600),
+            offer_timeout: Duration::from_secs(60),
+            options: Vec::new(),
+        }
+    }
+}
+
+/// State of a `Lease`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseState {
+    /// Offered and held back until the client requests it
+    Offered,
+    /// Acknowledged to the client
+    Bound,
+    /// Declined by the client as in use, not handed out until it expires
+    Declined,
+}
+
+/// Address handed out by `DhcpServer`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub ip: [u8; 4],
+    pub mac: [u8; 6],
+    pub state: LeaseState,
+    pub expires: Instant,
+}
+
+/// Minimal DHCP server with a lease pool and reservations
+///
+/// Clones share their leases, so a server can be inspected while a clone
+/// answers through `rules` or `run` on another thread.
+#[derive(Debug, Clone)]
+pub struct DhcpServer {
+    pub opts: ServerOpts,
+    /// Addresses always handed to given MACs, also outside of the pool
+    pub reservations: HashMap<[u8; 6], [u8; 4]>,
+    leases: Arc<Mutex<BTreeMap<[u8; 4], Lease>>>,
+}
+
+impl DhcpServer {
+    pub fn new(opts: ServerOpts) -> Self {
+        Self {
+            opts,
+            reservations: HashMap::new(),
+            leases: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Reserves `ip` for `mac`
+    pub fn reserve(&mut self, mac: [u8; 6], ip: [u8; 4]) {
+        self.reservations.insert(mac, ip);
+    }
+
+    /// Current leases sorted by address, including expired ones not yet
+    /// handed out again
+    pub fn leases(&self) -> Vec<Lease> {
+        self.leases.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Lease held by `mac`, if any
+    pub fn lease(&self, mac: [u8; 6]) -> Option<Lease> {
+        self.leases
+            .lock()
+            .unwrap()
+            .values()
+            .find(|lease| lease.mac == mac && lease.state != LeaseState::Declined)
+            .cloned()
+    }
+
+    fn in_pool(&self, ip: [u8; 4]) -> bool {
+        let ip = u32::from_be_bytes(ip);
+        u32::from_be_bytes(self.opts.pool_start) <= ip
+            && ip <= u32::from_be_bytes(self.opts.pool_end)
+            && ip != u32::from_be_bytes(self.opts.server_ip)
+    }
+
+    /// Checks if `ip` may be handed to `mac`
+    fn available(
+        &self,
+        leases: &BTreeMap<[u8; 4], Lease>,
+        ip: [u8; 4],
+        mac: [u8; 6],
+        now: Instant,
+    ) -> bool {
+        let reserved = self.reservations.get(&mac) == Some(&ip);
+        if !reserved
+            && (!self.in_pool(ip)
+                || self
+                    .reservations
+                    .iter()
+                    .any(|(other, addr)| *addr == ip && *other != mac))
+        {
+            return false;
+        }
+        match leases.get(&ip) {
+            Some(lease) if lease.expires > now => {
+                lease.mac == mac && lease.state != LeaseState::Declined
+            }
+            _ => true,
+        }
+    }
+
+    /// Picks address offered to `mac`: its current lease, its reservation,
+    /// the address it asked for or the first free one of the pool
+    fn pick(
+        &self,
+        leases: &BTreeMap<[u8; 4], Lease>,
+        mac: [u8; 6],
+        requested: Option<[u8; 4]>,
+        now: Instant,
+    ) -> Option<[u8; 4]> {
+        let held = leases
+            .values()
+            .find(|lease| lease.mac == mac && lease.state != LeaseState::Declined)
+            .map(|lease| lease.ip);
+        let reserved = self.reservations.get(&mac).copied();
+        let start = u32::from_be_bytes(self.opts.pool_start);
+        let end = u32::from_be_bytes(self.opts.pool_end);
+        held.into_iter()
+            .chain(reserved)
+            .chain(requested)
+            .chain((start..=end).map(u32::to_be_bytes))
+            .find(|ip| self.available(leases, *ip, mac, now))
+    }
+
+    fn lease_reply(&self, msg: &Dhcp, msg_type: u8, ip: [u8; 4]) -> Dhcp {
+        let mut reply = msg.reply(msg_type);
+        reply.yiaddr = ip;
+        reply
+            .options
+            .push(DhcpOption::ServerId(self.opts.server_ip));
+        reply
+            .options
+            .push(DhcpOption::LeaseTime(self.opts.lease_time.as_secs() as u32));
+        reply.options.extend(self.opts.options.iter().cloned());
+        reply
+    }
+
+    /// Crafts reply to client message `msg`, `None` if it needs no reply
+    /// or the pool is exhausted
+    pub fn handle(&self, msg: &Dhcp) -> Option<Dhcp> {
+        if msg.op != bootp_op::REQUEST {
+            return None;
+        }
+        let now = Instant::now();
+        let mac = msg.mac();
+        let requested = match msg.option(dhcp_opt::REQUESTED_IP) {
+            Some(DhcpOption::RequestedIp(ip)) => Some(*ip),
+            _ => None,
+        };
+        let server_id = match msg.option(dhcp_opt::SERVER_ID) {
+            Some(DhcpOption::ServerId(ip)) => Some(*ip),
+            _ => None,
+        };
+        let mut leases = self.leases.lock().unwrap();
+        let leases = &mut *leases;
+        let insert = |leases: &mut BTreeMap<[u8; 4], Lease>, ip, state, valid: Duration| {
+            leases.retain(|_, lease| lease.mac != mac || lease.state == LeaseState::Declined);
+            leases.insert(
+                ip,
+                Lease {
+                    ip,
+                    mac,
+                    state,
+                    expires: now + valid,
+                },
+            );
+        };
+        match msg.msg_type()? {
+            dhcp_msg::DISCOVER => {
+                let ip = self.pick(leases, mac, requested, now)?;
+                insert(leases, ip, LeaseState::Offered, self.opts.offer_timeout);
+                Some(self.lease_reply(msg, dhcp_msg::OFFER, ip))
+            }
+            dhcp_msg::REQUEST => {
+                if server_id.is_some_and(|id| id != self.opts.server_ip) {
+                    // Client took the offer of another server
+                    leases
+                        .retain(|_, lease| lease.mac != mac || lease.state != LeaseState::Offered);
+                    return None;
+                }
+                let ip = requested.unwrap_or(msg.ciaddr);
+                if ip != [0; 4] && self.available(leases, ip, mac, now) {
+                    insert(leases, ip, LeaseState::Bound, self.opts.lease_time);
+                    Some(self.lease_reply(msg, dhcp_msg::ACK, ip))
+                } else {
+                    let mut nak = msg.reply(dhcp_msg::NAK);
+                    nak.options.push(DhcpOption::ServerId(self.opts.server_ip));
+                    Some(nak)
+                }
+            }
+            dhcp_msg::DECLINE => {
+                let ip = requested?;
+                if leases.get(&ip).is_some_and(|lease| lease.mac == mac) {
+                    insert(leases, ip, LeaseState::Declined, self.opts.lease_time);
+                }
+                None
+            }
+            dhcp_msg::RELEASE => {
+                if leases
+                    .get(&msg.ciaddr)
+                    .is_some_and(|lease| lease.mac == mac)
+                {
+                    leases.remove(&msg.ciaddr);
+                }
+                None
+            }
+            dhcp_msg::INFORM => {
+                let mut ack = msg.reply(dhcp_msg::ACK);
+                ack.options.push(DhcpOption::ServerId(self.opts.server_ip));
+                ack.options.extend(self.opts.options.iter().cloned());
+                Some(ack)
+            }
+            _ => None,
+        }
+    }
+
+    /// Crafts reply frame to DHCP message in `pdu`, or `None` if it needs no
+    /// reply
+    ///
+    /// Replies go to the relay agent if any, else to the client address or,
+    /// for NAKs and clients asking so, to broadcast.
+    pub fn reply(&self, pdu: Pdu) -> Option<Pdu> {
+        let (eth, msg) = match (pdu.headers.get(&2), pdu.headers.get(&7)) {
+            (Some(Proto::Eth(eth)), Some(Proto::Dhcp(msg))) => (eth, msg),
+            _ => return None,
+        };
+        let reply = self.handle(msg)?;
+        let broadcast = ([0xff; 6], [0xff; 4]);
+        let (port, (dst_mac, dst_ip)) = if msg.giaddr != [0; 4] {
+            (dhcp_port::SERVER, (eth.src_hw_addr, msg.giaddr))
+        } else if reply.msg_type() == Some(dhcp_msg::NAK) {
+            (dhcp_port::CLIENT, broadcast)
+        } else if msg.ciaddr != [0; 4] {
+            (dhcp_port::CLIENT, (eth.src_hw_addr, msg.ciaddr))
+        } else if msg.flags & BROADCAST != 0 {
+            (dhcp_port::CLIENT, broadcast)
+        } else {
+            (dhcp_port::CLIENT, (msg.mac(), reply.yiaddr))
+        };
+        let mut ip = IPv4Hdr::new();
+        ip.src_ip_addr = self.opts.server_ip;
+        ip.dst_ip_addr = dst_ip;
+        let frame = Pdu::new()
+            .header(EthHdr::from_raw(
+                self.opts.server_mac,
+                dst_mac,
+                eth_type::IPv4 as u16,
+            ))
+            .header(ip)
+            .header(UdpHdr::from(dhcp_port::SERVER, port))
+            .header(reply);
+        Some(frame)
+    }
+
+    /// Rules answering client messages, as taken by `Channel::auto_reply`
+    pub fn rules(&self) -> Rules {
+        let server = self.clone();
+        let mut query = DhcpQuery::new();
+        query.op = Some(bootp_op::REQUEST);
+        let mut rules = Rules::new();
+        rules.add_rule(QueryHdr::Dhcp(query), move |pdu| server.reply(pdu));
+        rules
+    }
+
+    /// Answers client messages received on `io` until `limit` replies are
+    /// sent. Returns number of replies sent.
+    pub fn run<T: PacketIo>(&self, io: &mut T, limit: Option<usize>) -> Result<usize, PaError> {
+        auto_reply(io, &self.rules(), limit)
+    }
+}
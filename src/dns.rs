@@ -0,0 +1,291 @@
+//! Authoritative fake DNS responder for isolated labs
+//!
+//! `DnsResponder` answers queries from a `Zone` through the receive, match
+//! and reply loop of `auto_reply`, so it runs on an interface without any
+//! DNS server. Replies are built from the query by swapping source and
+//! destination of its Ethernet, IP and UDP headers.
+
+use crate::error::PaError;
+use crate::hdr::{
+    dns_class, dns_port, dns_rcode, dns_type, name_matches, Dns, DnsQuery, DnsRecord, EthHdr,
+    IPv4Hdr, IPv6Hdr, RData, UdpHdr,
+};
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::{auto_reply, Pdu, QueryHdr, Rules};
+use std::thread;
+use std::time::Duration;
+
+/// Largest UDP response to queries without EDNS0
+const UDP_SIZE: usize = 512;
+
+/// UDP payload size advertised in responses to EDNS0 queries
+const EDNS_UDP_SIZE: u16 = 1232;
+
+/// Longest chain of CNAMEs followed inside the zone
+const MAX_CNAMES: usize = 8;
+
+/// Records and injected failures answered by `DnsResponder`
+///
+/// Names may be wildcards like `*.lab`, which answer names below `lab`
+/// holding no record of their own. The most specific wildcard wins.
+#[derive(Clone, Debug, Default)]
+pub struct Zone {
+    records: Vec<DnsRecord>,
+    failures: Vec<(String, u8)>,
+}
+
+impl Zone {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `record`, whose name may be a wildcard
+    pub fn add(&mut self, record: DnsRecord) {
+        self.records.push(record);
+    }
+
+    /// Answers every query for `name` with `rcode` and no records, e.g.
+    /// `dns_rcode::NX_DOMAIN` or `dns_rcode::SERV_FAIL`. Takes precedence
+    /// over records of the zone.
+    pub fn fail(&mut self, name: &str, rcode: u8) {
+        self.failures.push((name.to_string(), rcode));
+    }
+
+    /// Records of `name`, or of the most specific wildcard above it
+    fn records(&self, name: &str) -> Vec<&DnsRecord> {
+        let exact: Vec<&DnsRecord> = self
+            .records
+            .iter()
+            .filter(|record| !record.name.starts_with("*.") && name_matches(&record.name, name))
+            .collect();
+        if !exact.is_empty() {
+            return exact;
+        }
+        let wildcards = self
+            .records
+            .iter()
+            .filter(|record| record.name.starts_with("*.") && name_matches(&record.name, name));
+        let longest = wildcards.clone().map(|record| record.name.len()).max();
+        wildcards
+            .filter(|record| Some(record.name.len()) == longest)
+            .collect()
+    }
+
+    /// Answers for `name` of type `qtype`, following CNAMEs inside the zone
+    ///
+    /// Records are owned by the name asked for, which expands wildcards and
+    /// keeps case of the question.
+    /// Returns rcode on injected failure or `NX_DOMAIN` for unknown names.
+    pub fn lookup(&self, name: &str, qtype: u16) -> Result<Vec<DnsRecord>, u8> {
+        if let Some((_, rcode)) = self
+            .failures
+            .iter()
+            .find(|(pattern, _)| name_matches(pattern, name))
+        {
+            return Err(*rcode);
+        }
+        let mut answers = Vec::new();
+        let mut name = name.to_string();
+        for _ in 0..MAX_CNAMES {
+            let records = self.records(&name);
+            if records.is_empty() {
+                break;
+            }
+            let owned = |record: &DnsRecord| DnsRecord {
+                name: name.clone(),
+                ..record.clone()
+            };
+            let matching: Vec<DnsRecord> = records
+                .iter()
+                .filter(|record| qtype == dns_type::ANY || record.rtype == qtype)
+                .map(|record| owned(record))
+                .collect();
+            if !matching.is_empty() {
+                answers.extend(matching);
+                return Ok(answers);
+            }
+            match records
+                .iter()
+                .find(|record| record.rtype == dns_type::CNAME)
+            {
+                Some(record) => {
+                    answers.push(owned(record));
+                    if let RData::CNAME(target) = &record.data {
+                        name = target.clone();
+                    }
+                }
+                // Name exists without data of this type
+                None => return Ok(answers),
+            }
+        }
+        if answers.is_empty() {
+            Err(dns_rcode::NX_DOMAIN)
+        } else {
+            Ok(answers)
+        }
+    }
+}
+
+/// Options of `DnsResponder`
+#[derive(Debug, Clone)]
+pub struct ResponderOpts {
+    /// TTL replacing those of zone records, e.g. 0 to keep clients from
+    /// caching answers
+    pub ttl: Option<u32>,
+    /// Time waited before each reply. Replies are sent in order, so the
+    /// delay also holds back following queries.
+    pub delay: Duration,
+    /// Sets truncated flag and leaves out records on every response, so
+    /// clients retry over TCP
+    pub truncate: bool,
+}
+
+impl ResponderOpts {
+    pub fn new() -> Self {
+        Self {
+            ttl: None,
+            delay: Duration::ZERO,
+            truncate: false,
+        }
+    }
+}
+
+impl Default for ResponderOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Authoritative DNS responder answering standard queries over UDP
+///
+/// Responses larger than 512 bytes, or than the size advertised through
+/// EDNS0, are truncated. Queries over TCP are left unanswered.
+#[derive(Clone, Debug, Default)]
+pub struct DnsResponder {
+    pub zone: Zone,
+    pub opts: ResponderOpts,
+}
+
+impl DnsResponder {
+    pub fn new(zone: Zone) -> Self {
+        Self {
+            zone,
+            opts: ResponderOpts::new(),
+        }
+    }
+
+    /// Crafts response to DNS `query`
+    pub fn respond(&self, query: &Dns) -> Dns {
+        let mut reply = query.reply();
+        reply.aa = true;
+        reply.ra = false;
+        match query.questions.as_slice() {
+            [question] if question.qclass == dns_class::IN || question.qclass == dns_class::ANY => {
+                match self.zone.lookup(&question.name, question.qtype) {
+                    Ok(answers) => reply.answers = answers,
+                    Err(rcode) => reply.rcode = rcode,
+                }
+            }
+            [_] => reply.rcode = dns_rcode::REFUSED,
+            _ => reply.rcode = dns_rcode::FORM_ERR,
+        }
+        if let Some(ttl) = self.opts.ttl {
+            reply.answers.iter_mut().for_each(|record| record.ttl = ttl);
+        }
+        let limit = match query.edns() {
+            Some(edns) => {
+                reply.additionals.push(DnsRecord::opt(EDNS_UDP_SIZE, false));
+                (edns.class as usize).max(UDP_SIZE)
+            }
+            None => UDP_SIZE,
+        };
+        let too_long = reply.to_bytes().map_or(true, |bytes| bytes.len() > limit);
+        if self.opts.truncate || too_long {
+            reply.tc = true;
+            reply.answers.clear();
+            reply.authorities.clear();
+            reply
+                .additionals
+                .retain(|record| record.rtype == dns_type::OPT);
+        }
+        reply
+    }
+
+    /// Crafts reply frame to DNS query in `pdu`, or `None` if `pdu` holds
+    /// no query over UDP to port 53
+    ///
+    /// Multicast DNS queries on port 5353 are left alone, their multicast
+    /// destination can't be the source of a reply.
+    pub fn reply(&self, pdu: Pdu) -> Option<Pdu> {
+        match pdu.headers.get(&4) {
+            Some(Proto::UDP(udp)) if udp.dst_port.to_usize() == dns_port::DNS as usize => {}
+            _ => return None,
+        }
+        let reply = match (udp_reply(&pdu), pdu.headers.get(&7)) {
+            (Some(reply), Some(Proto::Dns(query))) if !query.qr => {
+                reply.header(self.respond(query))
+            }
+            _ => return None,
+        };
+        if !self.opts.delay.is_zero() {
+            thread::sleep(self.opts.delay);
+        }
+        Some(reply)
+    }
+
+    /// Rules answering standard queries to port 53, as taken by `Channel::auto_reply`
+    pub fn rules(self) -> Rules {
+        let mut rules = Rules::new();
+        rules.add_rule(QueryHdr::Dns(DnsQuery::from(None, None)), move |pdu| {
+            self.reply(pdu)
+        });
+        rules
+    }
+
+    /// Answers queries received on `io` until `limit` replies are sent.
+    /// Returns number of replies sent.
+    pub fn run<T: PacketIo>(self, io: &mut T, limit: Option<usize>) -> Result<usize, PaError> {
+        auto_reply(io, &self.rules(), limit)
+    }
+}
+
+/// Starts reply to UDP datagram `query` by swapping addresses and ports of
+/// its Ethernet, IP and UDP headers
+///
+/// Ethernet header is left out if `query` starts at IP. Returns `None` if
+/// `query` isn't UDP over IP.
+pub fn udp_reply(query: &Pdu) -> Option<Pdu> {
+    let udp = match query.headers.get(&4) {
+        Some(Proto::UDP(udp)) => udp,
+        _ => return None,
+    };
+    let mut reply = Pdu::new();
+    match query.headers.get(&3) {
+        Some(Proto::IPv4(ipv4)) => {
+            let mut ip = IPv4Hdr::new();
+            ip.src_ip_addr = ipv4.dst_ip_addr;
+            ip.dst_ip_addr = ipv4.src_ip_addr;
+            reply.set_header(ip);
+        }
+        Some(Proto::IPv6(ipv6)) => {
+            let mut ip = IPv6Hdr::new();
+            ip.src_ip_addr = ipv6.dst_ip_addr;
+            ip.dst_ip_addr = ipv6.src_ip_addr;
+            reply.set_header(ip);
+        }
+        _ => return None,
+    }
+    if let Some(Proto::Eth(eth)) = query.headers.get(&2) {
+        reply.set_header(EthHdr::from_raw(
+            eth.dst_hw_addr,
+            eth.src_hw_addr,
+            eth.eth_type.to_usize() as u16,
+        ));
+    }
+    reply.set_header(UdpHdr::from(
+        udp.dst_port.to_usize() as u16,
+        udp.src_port.to_usize() as u16,
+    ));
+    Some(reply)
+}
@@ -66,6 +66,16 @@ impl Bits {
         val.push_str(&self.data);
         val
     }
+
+    /// Returns value stored in struct as unsigned integer
+    pub fn to_usize(&self) -> usize {
+        self.check_error();
+        if self.data.is_empty() {
+            0
+        } else {
+            usize::from_str_radix(&self.data, 2).unwrap()
+        }
+    }
 }
 
 impl Display for Bits {
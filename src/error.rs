@@ -10,6 +10,7 @@ pub enum ErrorType {
     UnwrapHeaderError,
     PcapFileError,
     LengthError,
+    TimeoutError,
 }
 
 /// This error struct is used in error handling of this library
@@ -28,3 +29,9 @@ impl PaError {
         }
     }
 }
+
+impl From<std::io::Error> for PaError {
+    fn from(err: std::io::Error) -> Self {
+        Self::new(err.to_string(), ErrorType::ChannelError)
+    }
+}
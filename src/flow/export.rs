@@ -0,0 +1,109 @@
+use super::Flow;
+use crate::error::PaError;
+use std::io::Write;
+
+/// Columns written by `CsvExporter`
+pub const CSV_HEADER: &str = "src,dst,src_port,dst_port,proto,vlan,first_seen,last_seen,\
+packets,bytes,rev_packets,rev_bytes,tcp_flags,rev_tcp_flags,end";
+
+/// Writes flows as CSV lines, header first
+///
+/// Timestamps are seconds with microseconds, an untagged flow has an empty
+/// `vlan` column.
+pub struct CsvExporter<W: Write> {
+    writer: W,
+    header: bool,
+}
+
+impl<W: Write> CsvExporter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            header: false,
+        }
+    }
+
+    /// Writes `flow`, preceded by `CSV_HEADER` on first call
+    pub fn export(&mut self, flow: &Flow) -> Result<(), PaError> {
+        if !self.header {
+            writeln!(self.writer, "{}", CSV_HEADER)?;
+            self.header = true;
+        }
+        let key = &flow.key;
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{:.6},{:.6},{},{},{},{},{},{},{}",
+            key.src,
+            key.dst,
+            key.src_port,
+            key.dst_port,
+            key.proto,
+            key.vlan.map(|vlan| vlan.to_string()).unwrap_or_default(),
+            flow.first_seen.as_secs_f64(),
+            flow.last_seen.as_secs_f64(),
+            flow.packets,
+            flow.bytes,
+            flow.rev_packets,
+            flow.rev_bytes,
+            flow.tcp_flags,
+            flow.rev_tcp_flags,
+            flow.end
+        )?;
+        Ok(())
+    }
+
+    /// Returns underlying writer, flushed
+    pub fn into_inner(mut self) -> Result<W, PaError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Writes flows as JSON lines, one object per flow
+///
+/// Fields are named as the columns of `CSV_HEADER`, an untagged flow has
+/// `"vlan":null`.
+pub struct JsonExporter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonExporter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Writes `flow` as one line
+    pub fn export(&mut self, flow: &Flow) -> Result<(), PaError> {
+        let key = &flow.key;
+        writeln!(
+            self.writer,
+            "{{\"src\":\"{}\",\"dst\":\"{}\",\"src_port\":{},\"dst_port\":{},\"proto\":{},\
+\"vlan\":{},\"first_seen\":{:.6},\"last_seen\":{:.6},\"packets\":{},\"bytes\":{},\
+\"rev_packets\":{},\"rev_bytes\":{},\"tcp_flags\":{},\"rev_tcp_flags\":{},\"end\":\"{}\"}}",
+            key.src,
+            key.dst,
+            key.src_port,
+            key.dst_port,
+            key.proto,
+            key.vlan
+                .map(|vlan| vlan.to_string())
+                .unwrap_or_else(|| "null".to_string()),
+            flow.first_seen.as_secs_f64(),
+            flow.last_seen.as_secs_f64(),
+            flow.packets,
+            flow.bytes,
+            flow.rev_packets,
+            flow.rev_bytes,
+            flow.tcp_flags,
+            flow.rev_tcp_flags,
+            flow.end
+        )?;
+        Ok(())
+    }
+
+    /// Returns underlying writer, flushed
+    pub fn into_inner(mut self) -> Result<W, PaError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
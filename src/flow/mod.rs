@@ -0,0 +1,12 @@
+//! Flow tracking of live or captured traffic
+//!
+//! `FlowTable` turns packets into bidirectional flow records, which can be
+//! written out with `CsvExporter` or `JsonExporter`, or sent to a collector
+//! with `NetFlowExporter`. `NetFlowCollector` decodes such exports.
+
+mod export;
+pub use export::*;
+mod netflow;
+pub use netflow::*;
+mod table;
+pub use table::*;
@@ -0,0 +1,533 @@
+use super::{Flow, FlowKey};
+use crate::arp::ArpResolver;
+use crate::error::PaError;
+use crate::hdr::{
+    ie, netflow_port, FieldSpec, FlowSet, IPv4Hdr, Ipfix, NetFlowV5, NetFlowV9, Template, UdpHdr,
+    V5Record,
+};
+use crate::io::PacketIo;
+use crate::link::IpLink;
+use crate::proto::Proto;
+use crate::Pdu;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Template id of IPv4 records sent by `NetFlowExporter`
+pub const TEMPLATE_IPV4: u16 = 256;
+/// Template id of IPv6 records sent by `NetFlowExporter`
+pub const TEMPLATE_IPV6: u16 = 257;
+
+/// Export format of `NetFlowExporter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetFlowVersion {
+    V5,
+    V9,
+    Ipfix,
+}
+
+/// One direction of a `Flow`
+struct Record {
+    key: FlowKey,
+    packets: u64,
+    bytes: u64,
+    tcp_flags: u16,
+    first: Duration,
+    last: Duration,
+}
+
+fn records(flows: &[Flow]) -> Vec<Record> {
+    let mut records = Vec::with_capacity(flows.len());
+    for flow in flows {
+        let record = |key, packets, bytes, tcp_flags| Record {
+            key,
+            packets,
+            bytes,
+            tcp_flags,
+            first: flow.first_seen,
+            last: flow.last_seen,
+        };
+        if flow.packets > 0 {
+            records.push(record(flow.key, flow.packets, flow.bytes, flow.tcp_flags));
+        }
+        if flow.rev_packets > 0 {
+            records.push(record(
+                flow.key.reverse(),
+                flow.rev_packets,
+                flow.rev_bytes,
+                flow.rev_tcp_flags,
+            ));
+        }
+    }
+    records
+}
+
+fn addr_bytes(addr: IpAddr) -> Vec<u8> {
+    match addr {
+        IpAddr::V4(addr) => addr.octets().to_vec(),
+        IpAddr::V6(addr) => addr.octets().to_vec(),
+    }
+}
+
+/// Turns `Flow`s into NetFlow v5, v9 or IPFIX exports
+///
+/// Bidirectional flows are sent as one record per direction. NetFlow v5 has
+/// no room for IPv6 so such flows are left out of it. NetFlow v9 and IPFIX
+/// messages carry templates `TEMPLATE_IPV4` and `TEMPLATE_IPV6` in the
+/// first message and every `template_refresh` messages after it.
+///
+/// Flow timestamps are taken as time since the Unix epoch, which they are
+/// for pcap captures, and uptimes count from `boot`.
+pub struct NetFlowExporter {
+    pub version: NetFlowVersion,
+    /// Source id of NetFlow v9, observation domain of IPFIX, and engine type
+    /// and id in the low 16 bits for NetFlow v5
+    pub domain: u32,
+    /// Records per message, NetFlow v5 collectors accept at most 30
+    pub max_records: usize,
+    /// Messages after which templates are sent again, 0 sends them in
+    /// every message
+    pub template_refresh: u32,
+    pub boot: Duration,
+    /// Source address of exports, unset takes the one of the interface
+    pub src_ip: [u8; 4],
+    pub src_port: u16,
+    flows_sent: u32,
+    messages_sent: u32,
+}
+
+impl NetFlowExporter {
+    pub fn new(version: NetFlowVersion) -> Self {
+        Self {
+            version,
+            domain: 0,
+            max_records: 30,
+            template_refresh: 20,
+            boot: Duration::from_secs(0),
+            src_ip: [0; 4],
+            src_port: netflow_port::NETFLOW,
+            flows_sent: 0,
+            messages_sent: 0,
+        }
+    }
+
+    /// Templates of records sent in NetFlow v9 or IPFIX
+    pub fn templates(&self) -> Vec<Template> {
+        let (flags_len, first, last, time_len) = match self.version {
+            NetFlowVersion::Ipfix => (2, ie::FLOW_START_MILLISECONDS, ie::FLOW_END_MILLISECONDS, 8),
+            _ => (1, ie::FIRST_SWITCHED, ie::LAST_SWITCHED, 4),
+        };
+        let template = |id, src, dst, addr_len| {
+            Template::new(
+                id,
+                vec![
+                    FieldSpec::new(src, addr_len),
+                    FieldSpec::new(dst, addr_len),
+                    FieldSpec::new(ie::L4_SRC_PORT, 2),
+                    FieldSpec::new(ie::L4_DST_PORT, 2),
+                    FieldSpec::new(ie::PROTOCOL, 1),
+                    FieldSpec::new(ie::TCP_FLAGS, flags_len),
+                    FieldSpec::new(ie::IN_PKTS, 8),
+                    FieldSpec::new(ie::IN_BYTES, 8),
+                    FieldSpec::new(ie::SRC_VLAN, 2),
+                    FieldSpec::new(first, time_len),
+                    FieldSpec::new(last, time_len),
+                ],
+            )
+        };
+        vec![
+            template(TEMPLATE_IPV4, ie::IPV4_SRC_ADDR, ie::IPV4_DST_ADDR, 4),
+            template(TEMPLATE_IPV6, ie::IPV6_SRC_ADDR, ie::IPV6_DST_ADDR, 16),
+        ]
+    }
+
+    fn uptime(&self, ts: Duration) -> u32 {
+        ts.saturating_sub(self.boot).as_millis() as u32
+    }
+
+    /// Returns exports of `flows` made at `now`, each `Pdu` holds just the
+    /// export and needs IPv4 and UDP headers to be sent
+    pub fn export(&mut self, flows: &[Flow], now: Duration) -> Vec<Pdu> {
+        let mut records = records(flows);
+        if self.version == NetFlowVersion::V5 {
+            records.retain(|record| record.key.src.is_ipv4());
+        }
+        let mut pdus = Vec::new();
+        let chunks: Vec<&[Record]> = if records.is_empty() && self.version != NetFlowVersion::V5 {
+            // Templates may still be due
+            vec![&[]]
+        } else {
+            records.chunks(self.max_records.max(1)).collect()
+        };
+        for chunk in chunks {
+            let pdu = match self.version {
+                NetFlowVersion::V5 => Pdu::new().header(self.v5(chunk, now)),
+                NetFlowVersion::V9 => Pdu::new().header(self.v9(chunk, now)),
+                NetFlowVersion::Ipfix => Pdu::new().header(self.ipfix(chunk, now)),
+            };
+            self.messages_sent += 1;
+            self.flows_sent += chunk.len() as u32;
+            pdus.push(pdu);
+        }
+        pdus
+    }
+
+    fn v5(&self, records: &[Record], now: Duration) -> NetFlowV5 {
+        let mut netflow = NetFlowV5::new();
+        netflow.sys_uptime = self.uptime(now);
+        netflow.unix_secs = now.as_secs() as u32;
+        netflow.unix_nsecs = now.subsec_nanos();
+        netflow.flow_sequence = self.flows_sent;
+        netflow.engine_type = (self.domain >> 8) as u8;
+        netflow.engine_id = self.domain as u8;
+        for record in records {
+            let addr = |addr| match addr {
+                IpAddr::V4(addr) => addr.octets(),
+                IpAddr::V6(_) => [0; 4],
+            };
+            netflow.records.push(V5Record {
+                src_addr: addr(record.key.src),
+                dst_addr: addr(record.key.dst),
+                packets: record.packets.min(u32::MAX as u64) as u32,
+                bytes: record.bytes.min(u32::MAX as u64) as u32,
+                first: self.uptime(record.first),
+                last: self.uptime(record.last),
+                src_port: record.key.src_port,
+                dst_port: record.key.dst_port,
+                tcp_flags: record.tcp_flags as u8,
+                proto: record.key.proto,
+                ..V5Record::default()
+            });
+        }
+        netflow
+    }
+
+    /// Sets of `records`, preceded by templates when due
+    fn sets(&self, records: &[Record]) -> (Vec<FlowSet>, u16) {
+        let mut sets = Vec::new();
+        let mut count = 0;
+        if self.template_refresh == 0 || self.messages_sent.is_multiple_of(self.template_refresh) {
+            sets.push(FlowSet::Template(self.templates()));
+            count += 2;
+        }
+        for template in self.templates() {
+            let ipv6 = template.id == TEMPLATE_IPV6;
+            let mut data = Vec::new();
+            for record in records.iter().filter(|r| r.key.src.is_ipv6() == ipv6) {
+                let (first, last) = match self.version {
+                    NetFlowVersion::Ipfix => (
+                        (record.first.as_millis() as u64).to_be_bytes().to_vec(),
+                        (record.last.as_millis() as u64).to_be_bytes().to_vec(),
+                    ),
+                    _ => (
+                        self.uptime(record.first).to_be_bytes().to_vec(),
+                        self.uptime(record.last).to_be_bytes().to_vec(),
+                    ),
+                };
+                let flags = match self.version {
+                    NetFlowVersion::Ipfix => record.tcp_flags.to_be_bytes().to_vec(),
+                    _ => vec![record.tcp_flags as u8],
+                };
+                let values: [&[u8]; 11] = [
+                    &addr_bytes(record.key.src),
+                    &addr_bytes(record.key.dst),
+                    &record.key.src_port.to_be_bytes(),
+                    &record.key.dst_port.to_be_bytes(),
+                    &[record.key.proto],
+                    &flags,
+                    &record.packets.to_be_bytes(),
+                    &record.bytes.to_be_bytes(),
+                    &record.key.vlan.unwrap_or(0).to_be_bytes(),
+                    &first,
+                    &last,
+                ];
+                data.extend(template.encode(&values).unwrap());
+                count += 1;
+            }
+            if !data.is_empty() {
+                sets.push(FlowSet::Data {
+                    id: template.id,
+                    data,
+                });
+            }
+        }
+        (sets, count)
+    }
+
+    fn v9(&self, records: &[Record], now: Duration) -> NetFlowV9 {
+        let (sets, count) = self.sets(records);
+        NetFlowV9 {
+            count,
+            sys_uptime: self.uptime(now),
+            unix_secs: now.as_secs() as u32,
+            sequence: self.messages_sent,
+            source_id: self.domain,
+            sets,
+        }
+    }
+
+    fn ipfix(&self, records: &[Record], now: Duration) -> Ipfix {
+        Ipfix {
+            length: 0,
+            export_time: now.as_secs() as u32,
+            // Counts data records sent before this message
+            sequence: self.flows_sent,
+            domain_id: self.domain,
+            sets: self.sets(records).0,
+        }
+    }
+
+    fn send_on<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        link: &mut IpLink,
+        collector: [u8; 4],
+        port: u16,
+        flows: &[Flow],
+        now: Duration,
+    ) -> Result<usize, PaError> {
+        let src_ip = link.src_ip(self.src_ip)?;
+        let pdus = self.export(flows, now);
+        let count = pdus.len();
+        for pdu in pdus {
+            let mut ipv4 = IPv4Hdr::new();
+            ipv4.src_ip_addr = src_ip;
+            ipv4.dst_ip_addr = collector;
+            let pdu = pdu.header(ipv4).header(UdpHdr::from(self.src_port, port));
+            link.send(io, pdu)?;
+        }
+        Ok(count)
+    }
+
+    /// Sends exports of `flows` to `collector` at IP layer, returns number
+    /// of messages sent
+    pub fn send<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        collector: [u8; 4],
+        port: u16,
+        flows: &[Flow],
+        now: Duration,
+    ) -> Result<usize, PaError> {
+        self.send_on(io, &mut IpLink::new(None), collector, port, flows, now)
+    }
+
+    /// Like `send`, but sends Ethernet frames addressed through `resolver`
+    pub fn send_eth<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        resolver: &mut ArpResolver,
+        collector: [u8; 4],
+        port: u16,
+        flows: &[Flow],
+        now: Duration,
+    ) -> Result<usize, PaError> {
+        let mut link = IpLink::new(Some(resolver));
+        self.send_on(io, &mut link, collector, port, flows, now)
+    }
+}
+
+/// Flow record decoded by `NetFlowCollector`
+///
+/// NetFlow v5 records have `template_id` 0 and are given the fields of
+/// their v9 equivalents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowRecord {
+    pub exporter: IpAddr,
+    pub domain: u32,
+    pub template_id: u16,
+    pub fields: Vec<(FieldSpec, Vec<u8>)>,
+}
+
+impl FlowRecord {
+    /// Value of first field `id` which isn't enterprise specific
+    pub fn get(&self, id: u16) -> Option<&[u8]> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.id == id && field.enterprise.is_none())
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Value of first field `id` of `enterprise`
+    pub fn get_enterprise(&self, enterprise: u32, id: u16) -> Option<&[u8]> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.id == id && field.enterprise == Some(enterprise))
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Value of field `id` read as unsigned integer of up to 8 bytes
+    pub fn uint(&self, id: u16) -> Option<u64> {
+        let value = self.get(id)?;
+        if value.len() > 8 {
+            return None;
+        }
+        Some(value.iter().fold(0, |n, b| n << 8 | *b as u64))
+    }
+}
+
+/// Decodes NetFlow v5, v9 and IPFIX exports
+///
+/// Templates are cached per exporter address, version and observation
+/// domain, the source id of NetFlow v9. IPFIX template withdrawals remove
+/// them. Data sets arriving before their template are dropped and counted
+/// in `missing`.
+pub struct NetFlowCollector {
+    templates: HashMap<(IpAddr, u16, u32), HashMap<u16, Template>>,
+    pub missing: usize,
+}
+
+impl NetFlowCollector {
+    pub fn new() -> Self {
+        Self {
+            templates: HashMap::new(),
+            missing: 0,
+        }
+    }
+
+    /// Template `id` known for `domain` of `exporter` using `version`
+    pub fn template(
+        &self,
+        exporter: IpAddr,
+        version: u16,
+        domain: u32,
+        id: u16,
+    ) -> Option<&Template> {
+        self.templates.get(&(exporter, version, domain))?.get(&id)
+    }
+
+    /// Decodes records of export `payload` sent by `exporter`
+    pub fn collect(
+        &mut self,
+        exporter: IpAddr,
+        payload: &[u8],
+    ) -> Result<Vec<FlowRecord>, PaError> {
+        match payload.get(..2) {
+            Some([0, 5]) => Ok(self.collect_v5(exporter, &NetFlowV5::from_bytes(payload)?)),
+            Some([0, 9]) => {
+                let netflow = NetFlowV9::from_bytes(payload)?;
+                Ok(self.collect_sets(exporter, 9, netflow.source_id, &netflow.sets))
+            }
+            _ => {
+                let ipfix = Ipfix::from_bytes(payload)?;
+                Ok(self.collect_sets(exporter, 10, ipfix.domain_id, &ipfix.sets))
+            }
+        }
+    }
+
+    /// Decodes records of export carried by `pdu`, whether parsed or left
+    /// `Raw` as sent to a port not known for flow exports
+    pub fn process(&mut self, pdu: &Pdu) -> Vec<FlowRecord> {
+        let exporter: IpAddr = match pdu.headers.get(&3) {
+            Some(Proto::IPv4(ipv4)) => ipv4.src_ip_addr.into(),
+            Some(Proto::IPv6(ipv6)) => ipv6.src_ip_addr.into(),
+            _ => return Vec::new(),
+        };
+        match pdu.headers.get(&7) {
+            Some(Proto::NetFlowV5(netflow)) => self.collect_v5(exporter, netflow),
+            Some(Proto::NetFlowV9(netflow)) => {
+                self.collect_sets(exporter, 9, netflow.source_id, &netflow.sets)
+            }
+            Some(Proto::Ipfix(ipfix)) => {
+                self.collect_sets(exporter, 10, ipfix.domain_id, &ipfix.sets)
+            }
+            Some(Proto::Raw(raw)) if matches!(pdu.headers.get(&4), Some(Proto::UDP(_))) => {
+                self.collect(exporter, &raw.data).unwrap_or_default()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Decodes records of export carried by Ethernet `frame`
+    pub fn process_frame(&mut self, frame: &[u8]) -> Vec<FlowRecord> {
+        self.process(&Pdu::parse(frame))
+    }
+
+    fn collect_v5(&mut self, exporter: IpAddr, netflow: &NetFlowV5) -> Vec<FlowRecord> {
+        let domain = (netflow.engine_type as u32) << 8 | netflow.engine_id as u32;
+        let field = |id, value: &[u8]| (FieldSpec::new(id, value.len() as u16), value.to_vec());
+        netflow
+            .records
+            .iter()
+            .map(|record| FlowRecord {
+                exporter,
+                domain,
+                template_id: 0,
+                fields: vec![
+                    field(ie::IPV4_SRC_ADDR, &record.src_addr),
+                    field(ie::IPV4_DST_ADDR, &record.dst_addr),
+                    field(ie::IPV4_NEXT_HOP, &record.next_hop),
+                    field(ie::INPUT_SNMP, &record.input.to_be_bytes()),
+                    field(ie::OUTPUT_SNMP, &record.output.to_be_bytes()),
+                    field(ie::IN_PKTS, &record.packets.to_be_bytes()),
+                    field(ie::IN_BYTES, &record.bytes.to_be_bytes()),
+                    field(ie::FIRST_SWITCHED, &record.first.to_be_bytes()),
+                    field(ie::LAST_SWITCHED, &record.last.to_be_bytes()),
+                    field(ie::L4_SRC_PORT, &record.src_port.to_be_bytes()),
+                    field(ie::L4_DST_PORT, &record.dst_port.to_be_bytes()),
+                    field(ie::TCP_FLAGS, &[record.tcp_flags]),
+                    field(ie::PROTOCOL, &[record.proto]),
+                    field(ie::SRC_TOS, &[record.tos]),
+                    field(ie::SRC_AS, &record.src_as.to_be_bytes()),
+                    field(ie::DST_AS, &record.dst_as.to_be_bytes()),
+                    field(ie::SRC_MASK, &[record.src_mask]),
+                    field(ie::DST_MASK, &[record.dst_mask]),
+                ],
+            })
+            .collect()
+    }
+
+    fn collect_sets(
+        &mut self,
+        exporter: IpAddr,
+        version: u16,
+        domain: u32,
+        sets: &[FlowSet],
+    ) -> Vec<FlowRecord> {
+        let templates = self
+            .templates
+            .entry((exporter, version, domain))
+            .or_default();
+        let mut records = Vec::new();
+        for set in sets {
+            match set {
+                FlowSet::Template(list) | FlowSet::OptionsTemplate(list) => {
+                    let set_id = match set {
+                        FlowSet::Template(_) => 2,
+                        _ => 3,
+                    };
+                    for template in list {
+                        if !template.is_withdrawal() {
+                            templates.insert(template.id, template.clone());
+                        } else if version == 10 && template.id == set_id {
+                            // Withdraws every template of the set
+                            let options = set_id == 3;
+                            templates.retain(|_, known| (known.scope_fields > 0) != options);
+                        } else {
+                            templates.remove(&template.id);
+                        }
+                    }
+                }
+                FlowSet::Data { id, data } if *id >= 256 => match templates.get(id) {
+                    Some(template) => {
+                        records.extend(template.decode(data).into_iter().map(|fields| FlowRecord {
+                            exporter,
+                            domain,
+                            template_id: *id,
+                            fields,
+                        }))
+                    }
+                    None => self.missing += 1,
+                },
+                FlowSet::Data { .. } => {}
+            }
+        }
+        records
+    }
+}
+
+impl Default for NetFlowCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
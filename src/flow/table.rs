@@ -0,0 +1,364 @@
+#[cfg(feature = "pcap")]
+use crate::error::ErrorType;
+use crate::error::PaError;
+use crate::hdr::tcp_flags;
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::Pdu;
+#[cfg(feature = "pcap")]
+use pcap_file::PcapReader;
+use std::collections::{BTreeSet, HashMap};
+#[cfg(feature = "pcap")]
+use std::fs::File;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// EtherType of 802.1Q VLAN tag
+pub const VLAN_8021Q: u16 = 0x8100;
+/// EtherType of 802.1ad service tag, outer tag of Q-in-Q
+pub const VLAN_8021AD: u16 = 0x88a8;
+
+/// Identifies a flow, `src` is the side which sent the first packet seen
+///
+/// Protocols without ports, including ICMP, use port 0 so that requests
+/// and replies end up in the same flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FlowKey {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub proto: u8,
+    /// Outermost VLAN id of frame
+    pub vlan: Option<u16>,
+}
+
+impl FlowKey {
+    /// Returns key of packets going the other way
+    pub fn reverse(&self) -> Self {
+        Self {
+            src: self.dst,
+            dst: self.src,
+            src_port: self.dst_port,
+            dst_port: self.src_port,
+            ..*self
+        }
+    }
+}
+
+/// Why a flow left `FlowTable`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowEnd {
+    /// Nothing seen for `idle_timeout`
+    Idle,
+    /// Flow lasted `active_timeout`, later packets start a new flow
+    Active,
+    /// TCP connection was reset or both sides sent FIN
+    Finished,
+    /// Dropped to stay under `max_flows`
+    Evicted,
+    /// Capture ended, see `FlowTable::finish`
+    Flushed,
+}
+
+impl std::fmt::Display for FlowEnd {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let end = match self {
+            FlowEnd::Idle => "idle",
+            FlowEnd::Active => "active",
+            FlowEnd::Finished => "finished",
+            FlowEnd::Evicted => "evicted",
+            FlowEnd::Flushed => "flushed",
+        };
+        write!(f, "{}", end)
+    }
+}
+
+/// Counters of one bidirectional flow, `rev_` fields count packets sent
+/// from `key.dst` to `key.src`
+///
+/// Bytes are counted from the start of the IP header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flow {
+    pub key: FlowKey,
+    pub first_seen: Duration,
+    pub last_seen: Duration,
+    pub packets: u64,
+    pub bytes: u64,
+    pub rev_packets: u64,
+    pub rev_bytes: u64,
+    /// `tcp_flags` seen from `key.src`
+    pub tcp_flags: u16,
+    /// `tcp_flags` seen from `key.dst`
+    pub rev_tcp_flags: u16,
+    pub end: FlowEnd,
+}
+
+impl Flow {
+    fn new(key: FlowKey, ts: Duration) -> Self {
+        Self {
+            key,
+            first_seen: ts,
+            last_seen: ts,
+            packets: 0,
+            bytes: 0,
+            rev_packets: 0,
+            rev_bytes: 0,
+            tcp_flags: 0,
+            rev_tcp_flags: 0,
+            end: FlowEnd::Flushed,
+        }
+    }
+
+    /// Time between first and last packet
+    pub fn duration(&self) -> Duration {
+        self.last_seen.saturating_sub(self.first_seen)
+    }
+
+    /// Returns `true` if TCP connection of flow is over
+    fn finished(&self) -> bool {
+        let all = self.tcp_flags | self.rev_tcp_flags;
+        all & tcp_flags::RST != 0
+            || (self.tcp_flags & tcp_flags::FIN != 0 && self.rev_tcp_flags & tcp_flags::FIN != 0)
+    }
+}
+
+/// Removes VLAN tags from `frame`, returns outermost VLAN id and frame
+/// with tags removed
+fn untag(frame: &[u8]) -> (Option<u16>, Vec<u8>) {
+    let mut vlan = None;
+    let mut start = 12;
+    while frame.len() >= start + 4 {
+        let eth_type = u16::from_be_bytes([frame[start], frame[start + 1]]);
+        if eth_type != VLAN_8021Q && eth_type != VLAN_8021AD {
+            break;
+        }
+        let tci = u16::from_be_bytes([frame[start + 2], frame[start + 3]]);
+        vlan.get_or_insert(tci & 0x0fff);
+        start += 4;
+    }
+    if vlan.is_none() || frame.len() < 12 {
+        return (vlan, frame.to_vec());
+    }
+    let mut untagged = frame[..12].to_vec();
+    untagged.extend_from_slice(&frame[start..]);
+    (vlan, untagged)
+}
+
+/// Aggregates packets into bidirectional flows, in the manner of a
+/// NetFlow or IPFIX exporter
+///
+/// Flows leave the table when idle for `idle_timeout`, when they last for
+/// `active_timeout`, when their TCP connection ends, or when a new flow
+/// would exceed `max_flows`, in which case the least recently seen flow is
+/// evicted. Memory stays bounded however long the capture. Every flow
+/// leaving the table is handed to the `on_flow` callback, which can pass it
+/// to a `CsvExporter` or `JsonExporter`.
+pub struct FlowTable {
+    pub idle_timeout: Duration,
+    pub active_timeout: Duration,
+    pub max_flows: usize,
+    flows: HashMap<FlowKey, (Flow, u64)>,
+    /// Keys ordered by `last_seen`, made unique by counter
+    by_last_seen: BTreeSet<(Duration, u64, FlowKey)>,
+    counter: u64,
+    last_expire: Duration,
+}
+
+impl FlowTable {
+    pub fn new() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(15),
+            active_timeout: Duration::from_secs(1800),
+            max_flows: 65536,
+            flows: HashMap::new(),
+            by_last_seen: BTreeSet::new(),
+            counter: 0,
+            last_expire: Duration::from_secs(0),
+        }
+    }
+
+    /// Number of flows being tracked
+    pub fn flows(&self) -> usize {
+        self.flows.len()
+    }
+
+    fn remove(&mut self, key: &FlowKey, end: FlowEnd) -> Option<Flow> {
+        let (mut flow, id) = self.flows.remove(key)?;
+        self.by_last_seen.remove(&(flow.last_seen, id, *key));
+        flow.end = end;
+        Some(flow)
+    }
+
+    /// Hands flows which timed out at `ts` to `on_flow`
+    pub fn expire(&mut self, ts: Duration, mut on_flow: impl FnMut(Flow)) {
+        self.last_expire = ts;
+        while let Some(&(last_seen, _, key)) = self.by_last_seen.iter().next() {
+            if ts.saturating_sub(last_seen) < self.idle_timeout {
+                break;
+            }
+            on_flow(self.remove(&key, FlowEnd::Idle).unwrap());
+        }
+        let active_timeout = self.active_timeout;
+        let active: Vec<FlowKey> = self
+            .flows
+            .iter()
+            .filter(|(_, (flow, _))| ts.saturating_sub(flow.first_seen) >= active_timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in active {
+            on_flow(self.remove(&key, FlowEnd::Active).unwrap());
+        }
+    }
+
+    /// Hands every flow left to `on_flow` at end of capture, oldest first
+    pub fn finish(&mut self, mut on_flow: impl FnMut(Flow)) {
+        while let Some(&(_, _, key)) = self.by_last_seen.iter().next() {
+            on_flow(self.remove(&key, FlowEnd::Flushed).unwrap());
+        }
+    }
+
+    /// Processes one Ethernet frame seen at `ts`, 802.1Q and 802.1ad tags
+    /// are removed and the outermost VLAN id becomes part of the flow key
+    pub fn process_frame(&mut self, frame: &[u8], ts: Duration, on_flow: impl FnMut(Flow)) {
+        let (vlan, frame) = untag(frame);
+        self.process(&Pdu::parse(&frame), vlan, ts, on_flow)
+    }
+
+    /// Processes one parsed packet seen at `ts` on `vlan`, packets other
+    /// than IPv4 or IPv6 are ignored
+    pub fn process(
+        &mut self,
+        pdu: &Pdu,
+        vlan: Option<u16>,
+        ts: Duration,
+        mut on_flow: impl FnMut(Flow),
+    ) {
+        if ts.saturating_sub(self.last_expire) >= Duration::from_secs(1) {
+            self.expire(ts, &mut on_flow);
+        }
+        let (src, dst, proto, len): (IpAddr, IpAddr, u8, usize) = match pdu.headers.get(&3) {
+            Some(Proto::IPv4(ipv4)) => (
+                ipv4.src_ip_addr.into(),
+                ipv4.dst_ip_addr.into(),
+                ipv4.proto.to_usize() as u8,
+                ipv4.total_len.to_usize(),
+            ),
+            Some(Proto::IPv6(ipv6)) => (
+                ipv6.src_ip_addr.into(),
+                ipv6.dst_ip_addr.into(),
+                ipv6.next_hdr.to_usize() as u8,
+                40 + ipv6.payload_len.to_usize(),
+            ),
+            _ => return,
+        };
+        let (src_port, dst_port, flags) = match pdu.headers.get(&4) {
+            Some(Proto::TCP(tcp)) => (
+                tcp.src_port.to_usize() as u16,
+                tcp.dst_port.to_usize() as u16,
+                tcp.flags.to_usize() as u16,
+            ),
+            Some(Proto::UDP(udp)) => (
+                udp.src_port.to_usize() as u16,
+                udp.dst_port.to_usize() as u16,
+                0,
+            ),
+            _ => (0, 0, 0),
+        };
+        let forward = FlowKey {
+            src,
+            dst,
+            src_port,
+            dst_port,
+            proto,
+            vlan,
+        };
+        let backward = forward.reverse();
+
+        let key = if self.flows.contains_key(&forward) {
+            forward
+        } else if self.flows.contains_key(&backward) {
+            backward
+        } else {
+            if self.flows.len() >= self.max_flows {
+                if let Some(&(_, _, oldest)) = self.by_last_seen.iter().next() {
+                    on_flow(self.remove(&oldest, FlowEnd::Evicted).unwrap());
+                }
+            }
+            self.counter += 1;
+            self.flows
+                .insert(forward, (Flow::new(forward, ts), self.counter));
+            self.by_last_seen.insert((ts, self.counter, forward));
+            forward
+        };
+
+        // Captures merged from several interfaces are not always in order
+        let (flow, id) = self.flows.get_mut(&key).unwrap();
+        self.by_last_seen.remove(&(flow.last_seen, *id, key));
+        flow.first_seen = flow.first_seen.min(ts);
+        flow.last_seen = flow.last_seen.max(ts);
+        self.by_last_seen.insert((flow.last_seen, *id, key));
+        if key == forward {
+            flow.packets += 1;
+            flow.bytes += len as u64;
+            flow.tcp_flags |= flags;
+        } else {
+            flow.rev_packets += 1;
+            flow.rev_bytes += len as u64;
+            flow.rev_tcp_flags |= flags;
+        }
+        if flow.finished() {
+            on_flow(self.remove(&key, FlowEnd::Finished).unwrap());
+        }
+    }
+
+    /// Tracks flows of frames received on `io` until `limit` frames were
+    /// processed, timestamps count from the call
+    pub fn run<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        limit: Option<usize>,
+        mut on_flow: impl FnMut(Flow),
+    ) -> Result<usize, PaError> {
+        let time_start = Instant::now();
+        let mut count = 0;
+        while limit != Some(count) {
+            let frame = io.recv()?;
+            self.process_frame(&frame, time_start.elapsed(), &mut on_flow);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Tracks flows of every frame of pcap file at `pcap_path` using its
+    /// timestamps, then calls `finish`
+    ///
+    /// Frames are read one at a time, so only the flow table grows with
+    /// the capture.
+    #[cfg(feature = "pcap")]
+    pub fn track_pcap(
+        &mut self,
+        pcap_path: impl ToString,
+        mut on_flow: impl FnMut(Flow),
+    ) -> Result<usize, PaError> {
+        let pcap = File::open(pcap_path.to_string())
+            .map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+        let pcap_reader = PcapReader::new(pcap)
+            .map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+        let mut count = 0;
+        for packet in pcap_reader {
+            let packet =
+                packet.map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+            self.process_frame(&packet.data, packet.header.timestamp(), &mut on_flow);
+            count += 1;
+        }
+        self.finish(on_flow);
+        Ok(count)
+    }
+}
+
+impl Default for FlowTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
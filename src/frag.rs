@@ -0,0 +1,412 @@
+//! IPv4 fragmentation and reassembly
+//!
+//! `fragment` splits a datagram the way a router would, while `FragOpts`
+//! allows fragments no router would send: tiny, overlapping or out of
+//! order. `Reassembler` puts fragments back together, resolving overlaps the
+//! way a chosen operating system does.
+
+use crate::dstructs::Bits;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::{EthHdr, Hdr, IPv4Hdr, Raw};
+use crate::proto::Proto;
+use crate::utility::random_u32;
+use crate::Pdu;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Don't Fragment bit of `IPv4Hdr::flags`
+pub const DONT_FRAGMENT: usize = 0b010;
+/// More Fragments bit of `IPv4Hdr::flags`
+pub const MORE_FRAGMENTS: usize = 0b001;
+
+/// Order in which `fragment` returns fragments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FragOrder {
+    InOrder,
+    Reversed,
+    Random,
+}
+
+/// Options of `fragment_with`
+#[derive(Debug, Clone)]
+pub struct FragOpts {
+    /// Data bytes of first fragment, rounded up to a multiple of 8
+    ///
+    /// Small values split the upper layer header over several fragments.
+    /// `None` fills it up to MTU like the others.
+    pub first_size: Option<usize>,
+    /// Bytes every fragment repeats from end of the previous one, rounded
+    /// up to a multiple of 8
+    pub overlap: usize,
+    /// Byte written over repeated data, `None` repeats original data
+    pub overlap_fill: Option<u8>,
+    pub order: FragOrder,
+}
+
+impl FragOpts {
+    pub fn new() -> Self {
+        Self {
+            first_size: None,
+            overlap: 0,
+            overlap_fill: None,
+            order: FragOrder::InOrder,
+        }
+    }
+}
+
+impl Default for FragOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn round_up(len: usize) -> usize {
+    len.div_ceil(8) * 8
+}
+
+/// Splits built IPv4 `pdu` into fragments no longer than `mtu`
+pub fn fragment(pdu: &Pdu, mtu: usize) -> Result<Vec<Pdu>, PaError> {
+    fragment_with(pdu, mtu, &FragOpts::new())
+}
+
+/// Same as `fragment` with evasion options of `opts`
+///
+/// Every fragment is built and keeps Ethernet header of `pdu` if any. A
+/// `pdu` which is itself a fragment is split further.
+pub fn fragment_with(pdu: &Pdu, mtu: usize, opts: &FragOpts) -> Result<Vec<Pdu>, PaError> {
+    if !matches!(pdu.headers.get(&3), Some(Proto::IPv4(_))) {
+        return Err(PaError::new(
+            "Pdu has no IPv4 header",
+            ErrorType::UnwrapHeaderError,
+        ));
+    }
+    let eth = match pdu.headers.get(&2) {
+        Some(Proto::Eth(eth)) => Some(eth),
+        _ => None,
+    };
+    let start = if eth.is_some() { 14 } else { 0 };
+    if pdu.buffer.len() < start + 20 {
+        return Err(PaError::new(
+            "Pdu has to be built before fragmenting",
+            ErrorType::LengthError,
+        ));
+    }
+    // Header as built, with length and protocol filled in
+    let ipv4 = IPv4Hdr::parse((&pdu.buffer[start..start + 20]).into());
+    let hdr_len = ipv4.ihl.to_usize() * 4;
+    let total_len = ipv4.total_len.to_usize();
+    if pdu.buffer.len() < start + total_len || total_len < hdr_len {
+        return Err(PaError::new(
+            "IPv4 total length exceeds built Pdu",
+            ErrorType::LengthError,
+        ));
+    }
+    let flags = ipv4.flags.to_usize();
+    if flags & DONT_FRAGMENT != 0 {
+        return Err(PaError::new(
+            "Datagram has Don't Fragment set",
+            ErrorType::ConstructError,
+        ));
+    }
+    let max_len = mtu.saturating_sub(hdr_len) / 8 * 8;
+    let overlap = round_up(opts.overlap);
+    if max_len == 0 || overlap >= max_len {
+        return Err(PaError::new(
+            "MTU too small to fragment",
+            ErrorType::LengthError,
+        ));
+    }
+    let data = &pdu.buffer[start + hdr_len..start + total_len];
+    let base = ipv4.frag_offset.to_usize() * 8;
+
+    // Pieces as (offset, data)
+    let first_len = opts
+        .first_size
+        .map(|size| round_up(size.max(1)).min(max_len))
+        .unwrap_or(max_len)
+        .min(data.len());
+    let mut pieces = vec![(0, data[..first_len].to_vec())];
+    let mut done = first_len;
+    while done < data.len() {
+        let offset = done.saturating_sub(overlap);
+        let end = (offset + max_len).min(data.len());
+        let mut piece = data[offset..end].to_vec();
+        if let Some(fill) = opts.overlap_fill {
+            piece[..done - offset].iter_mut().for_each(|b| *b = fill);
+        }
+        pieces.push((offset, piece));
+        done = end;
+    }
+
+    let count = pieces.len();
+    let mut frags = Vec::with_capacity(count);
+    for (i, (offset, piece)) in pieces.into_iter().enumerate() {
+        let mut hdr = ipv4.clone();
+        let more = i + 1 < count || flags & MORE_FRAGMENTS != 0;
+        let more = if more { MORE_FRAGMENTS } else { 0 };
+        hdr.flags = Bits::from((flags & !MORE_FRAGMENTS) | more, 3);
+        hdr.frag_offset = Bits::from((base + offset) / 8, 13);
+        hdr.total_len = Bits::from(0, 16);
+        hdr.hdr_checksum = Bits::from(0, 16);
+        let mut frag = Pdu::new().header(hdr).header(Raw::from(&piece));
+        if let Some(eth) = eth {
+            frag.set_header(eth.clone());
+        }
+        frag.build()?;
+        frags.push(frag);
+    }
+
+    match opts.order {
+        FragOrder::InOrder => {}
+        FragOrder::Reversed => frags.reverse(),
+        FragOrder::Random => {
+            for i in (1..frags.len()).rev() {
+                frags.swap(i, random_u32() as usize % (i + 1));
+            }
+        }
+    }
+    Ok(frags)
+}
+
+/// How `Reassembler` resolves bytes received in more than one fragment
+///
+/// Follows target based reassembly policies of Novak, also used by Snort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Data received first wins
+    First,
+    /// Data received last wins
+    Last,
+    /// New data wins if its fragment starts before the old one
+    Bsd,
+    /// New data wins if its fragment starts before or where the old one does
+    Linux,
+    /// New data wins only if its fragment starts before and ends at or after
+    /// the old one
+    Windows,
+}
+
+impl OverlapPolicy {
+    /// Returns `true` if fragment `new` replaces bytes of `old`, both given
+    /// as (offset, end)
+    fn replaces(self, new: (usize, usize), old: (usize, usize)) -> bool {
+        match self {
+            OverlapPolicy::First => false,
+            OverlapPolicy::Last => true,
+            OverlapPolicy::Bsd => new.0 < old.0,
+            OverlapPolicy::Linux => new.0 <= old.0,
+            OverlapPolicy::Windows => new.0 < old.0 && new.1 >= old.1,
+        }
+    }
+}
+
+/// Fragments of one datagram are those sharing source, destination,
+/// protocol and identification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FragKey {
+    src: [u8; 4],
+    dst: [u8; 4],
+    proto: u8,
+    id: u16,
+}
+
+struct Datagram {
+    first_seen: Duration,
+    /// Header of first fragment
+    header: Option<IPv4Hdr>,
+    eth: Option<EthHdr>,
+    /// Fragments in order of arrival as (offset, data)
+    fragments: Vec<(usize, Vec<u8>)>,
+    /// Length known from last fragment
+    len: Option<usize>,
+}
+
+impl Datagram {
+    /// Returns length of datagram once first and last fragment and every
+    /// byte between them were received
+    fn complete(&self) -> Option<usize> {
+        let len = self.len?;
+        self.header.as_ref()?;
+        let mut ranges: Vec<(usize, usize)> = self
+            .fragments
+            .iter()
+            .map(|(offset, frag)| (*offset, offset + frag.len()))
+            .collect();
+        ranges.sort_unstable();
+        let mut covered = 0;
+        for (offset, end) in ranges {
+            if offset > covered {
+                return None;
+            }
+            covered = covered.max(end);
+        }
+        (covered >= len).then_some(len)
+    }
+
+    /// Puts together data of a complete datagram `len` bytes long
+    fn assemble(&self, len: usize, policy: OverlapPolicy) -> Vec<u8> {
+        let mut data = vec![0; len];
+        // Index of fragment each byte came from
+        let mut owner: Vec<Option<usize>> = vec![None; len];
+        for (i, (offset, frag)) in self.fragments.iter().enumerate() {
+            let new = (*offset, offset + frag.len());
+            for pos in new.0..new.1.min(len) {
+                let take = match owner[pos] {
+                    None => true,
+                    Some(old) => {
+                        let (old_offset, old_frag) = &self.fragments[old];
+                        policy.replaces(new, (*old_offset, old_offset + old_frag.len()))
+                    }
+                };
+                if take {
+                    data[pos] = frag[pos - offset];
+                    owner[pos] = Some(i);
+                }
+            }
+        }
+        data
+    }
+}
+
+/// Buffers IPv4 fragments until their datagram is complete
+pub struct Reassembler {
+    pub policy: OverlapPolicy,
+    /// Datagrams incomplete this long after their first fragment are dropped
+    pub timeout: Duration,
+    /// Datagrams kept at once, the oldest one is dropped to make room
+    pub max_pending: usize,
+    /// Fragments kept per datagram, the oldest one is dropped to make room
+    pub max_fragments: usize,
+    pending: HashMap<FragKey, Datagram>,
+}
+
+impl Reassembler {
+    pub fn new(policy: OverlapPolicy) -> Self {
+        Self {
+            policy,
+            timeout: Duration::from_secs(30),
+            max_pending: 1024,
+            max_fragments: 256,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Number of datagrams waiting for fragments
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Drops datagrams timed out at `ts`, returns how many were dropped
+    pub fn expire(&mut self, ts: Duration) -> usize {
+        let timeout = self.timeout;
+        let before = self.pending.len();
+        self.pending
+            .retain(|_, datagram| ts.saturating_sub(datagram.first_seen) < timeout);
+        before - self.pending.len()
+    }
+
+    /// Processes Ethernet frame seen at `ts`, see `process`
+    pub fn process_frame(&mut self, frame: &[u8], ts: Duration) -> Option<Pdu> {
+        let eth = match Pdu::parse(frame).headers.remove(&2) {
+            Some(Proto::Eth(eth)) if frame.len() > 14 => eth,
+            _ => return None,
+        };
+        self.add(Some(eth), &frame[14..], ts)
+    }
+
+    /// Processes packet starting at IPv4 header seen at `ts`
+    ///
+    /// Returns the datagram once its last missing fragment arrives, parsed
+    /// with `buffer` holding its bytes. IPv4 options are not kept. Packets
+    /// which are not fragments are returned as they are. Expired datagrams
+    /// are dropped first.
+    pub fn process(&mut self, packet: &[u8], ts: Duration) -> Option<Pdu> {
+        self.add(None, packet, ts)
+    }
+
+    fn add(&mut self, eth: Option<EthHdr>, packet: &[u8], ts: Duration) -> Option<Pdu> {
+        self.expire(ts);
+        if packet.len() < 20 || packet[0] >> 4 != 4 {
+            return None;
+        }
+        let ipv4 = IPv4Hdr::parse((&packet[0..20]).into());
+        let hdr_len = ipv4.ihl.to_usize() * 4;
+        let total_len = ipv4.total_len.to_usize().min(packet.len());
+        if hdr_len < 20 || total_len < hdr_len {
+            return None;
+        }
+        let flags = ipv4.flags.to_usize();
+        let offset = ipv4.frag_offset.to_usize() * 8;
+        let more = flags & MORE_FRAGMENTS != 0;
+        if offset == 0 && !more {
+            return Some(Self::finish(eth, &packet[..total_len]));
+        }
+
+        let key = FragKey {
+            src: ipv4.src_ip_addr,
+            dst: ipv4.dst_ip_addr,
+            proto: ipv4.proto.to_usize() as u8,
+            id: ipv4.id.to_usize() as u16,
+        };
+        let data = packet[hdr_len..total_len].to_vec();
+        let end = offset + data.len();
+        if !self.pending.contains_key(&key) && self.pending.len() >= self.max_pending.max(1) {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, datagram)| datagram.first_seen)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                self.pending.remove(&oldest);
+            }
+        }
+        let datagram = self.pending.entry(key).or_insert_with(|| Datagram {
+            first_seen: ts,
+            header: None,
+            eth: None,
+            fragments: Vec::new(),
+            len: None,
+        });
+        if end + hdr_len > 65535 || datagram.len.is_some_and(|len| end > len) {
+            // Oversized datagram or data past its end, as in ping of death
+            self.pending.remove(&key);
+            return None;
+        }
+        if !more {
+            datagram.len = Some(end);
+        }
+        if offset == 0 {
+            datagram.header = Some(ipv4);
+            datagram.eth = eth;
+        }
+        if datagram.fragments.len() >= self.max_fragments.max(1) {
+            datagram.fragments.remove(0);
+        }
+        datagram.fragments.push((offset, data));
+
+        let len = datagram.complete()?;
+        let data = datagram.assemble(len, self.policy);
+        let datagram = self.pending.remove(&key)?;
+        let mut hdr = datagram.header?;
+        hdr.flags = Bits::from(hdr.flags.to_usize() & !MORE_FRAGMENTS, 3);
+        hdr.frag_offset = Bits::from(0, 13);
+        hdr.ihl = Bits::from(5, 4);
+        hdr.total_len = Bits::from(20 + data.len(), 16);
+        hdr.set_checksum().ok()?;
+        let mut packet: Vec<u8> = hdr.create().ok()?.into();
+        packet.extend_from_slice(&data);
+        Some(Self::finish(datagram.eth, &packet))
+    }
+
+    fn finish(eth: Option<EthHdr>, packet: &[u8]) -> Pdu {
+        let mut pdu = Pdu::parse_ip(packet);
+        pdu.buffer = packet.to_vec();
+        if let Some(eth) = eth {
+            let mut frame: Vec<u8> = eth.create().map(Into::into).unwrap_or_default();
+            frame.extend_from_slice(packet);
+            pdu.buffer = frame;
+            pdu.set_header(eth);
+        }
+        pdu
+    }
+}
@@ -0,0 +1,446 @@
+use crate::dstructs::Packet;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+
+#[path = "query/dhcp_query.rs"]
+mod dhcp_query;
+pub use dhcp_query::*;
+
+/// UDP ports of DHCP servers and clients
+pub mod dhcp_port {
+    pub const SERVER: u16 = 67;
+    pub const CLIENT: u16 = 68;
+}
+
+/// BOOTP operation
+pub mod bootp_op {
+    pub const REQUEST: u8 = 1;
+    pub const REPLY: u8 = 2;
+}
+
+/// DHCP message types carried by option 53
+pub mod dhcp_msg {
+    pub const DISCOVER: u8 = 1;
+    pub const OFFER: u8 = 2;
+    pub const REQUEST: u8 = 3;
+    pub const DECLINE: u8 = 4;
+    pub const ACK: u8 = 5;
+    pub const NAK: u8 = 6;
+    pub const RELEASE: u8 = 7;
+    pub const INFORM: u8 = 8;
+}
+
+/// Option codes of [RFC 2132](https://datatracker.ietf.org/doc/html/rfc2132)
+/// and [RFC 3046](https://datatracker.ietf.org/doc/html/rfc3046)
+pub mod dhcp_opt {
+    pub const PAD: u8 = 0;
+    pub const SUBNET_MASK: u8 = 1;
+    pub const ROUTER: u8 = 3;
+    pub const DNS: u8 = 6;
+    pub const DOMAIN_NAME: u8 = 15;
+    pub const REQUESTED_IP: u8 = 50;
+    pub const LEASE_TIME: u8 = 51;
+    pub const MSG_TYPE: u8 = 53;
+    pub const SERVER_ID: u8 = 54;
+    pub const PARAM_REQUEST: u8 = 55;
+    pub const CLIENT_ID: u8 = 61;
+    pub const RELAY_AGENT: u8 = 82;
+    pub const END: u8 = 255;
+}
+
+/// Sub-options of relay agent information (option 82)
+pub mod relay_sub {
+    pub const CIRCUIT_ID: u8 = 1;
+    pub const REMOTE_ID: u8 = 2;
+}
+
+/// Magic cookie starting options of DHCP messages
+pub const DHCP_MAGIC: [u8; 4] = [99, 130, 83, 99];
+
+/// Length of fixed BOOTP fields before options
+const FIXED_LEN: usize = 236;
+
+/// Messages are padded to the smallest BOOTP message accepted by relays
+const MIN_LEN: usize = 300;
+
+/// Broadcast bit of `flags`
+pub const BROADCAST: u16 = 0x8000;
+
+fn parse_error(msg: &str) -> PaError {
+    PaError::new(msg, ErrorType::ParseError)
+}
+
+fn addrs(data: &[u8]) -> Option<Vec<[u8; 4]>> {
+    if data.is_empty() || !data.len().is_multiple_of(4) {
+        return None;
+    }
+    Some(
+        data.chunks(4)
+            .map(|addr| [addr[0], addr[1], addr[2], addr[3]])
+            .collect(),
+    )
+}
+
+fn addr(data: &[u8]) -> Option<[u8; 4]> {
+    match data {
+        [a, b, c, d] => Some([*a, *b, *c, *d]),
+        _ => None,
+    }
+}
+
+/// Option of a DHCP message
+///
+/// Options of known code whose data doesn't fit their type are kept as
+/// `Raw`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DhcpOption {
+    MessageType(u8),
+    SubnetMask([u8; 4]),
+    Router(Vec<[u8; 4]>),
+    Dns(Vec<[u8; 4]>),
+    DomainName(String),
+    RequestedIp([u8; 4]),
+    /// Lease time in seconds
+    LeaseTime(u32),
+    ServerId([u8; 4]),
+    ParamRequest(Vec<u8>),
+    /// Client identifier as hardware type and address, or type 0 and any
+    /// other identifier
+    ClientId {
+        htype: u8,
+        id: Vec<u8>,
+    },
+    /// Relay agent information as sub-option codes and data
+    RelayAgent(Vec<(u8, Vec<u8>)>),
+    Raw {
+        code: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl DhcpOption {
+    pub fn code(&self) -> u8 {
+        match self {
+            DhcpOption::MessageType(_) => dhcp_opt::MSG_TYPE,
+            DhcpOption::SubnetMask(_) => dhcp_opt::SUBNET_MASK,
+            DhcpOption::Router(_) => dhcp_opt::ROUTER,
+            DhcpOption::Dns(_) => dhcp_opt::DNS,
+            DhcpOption::DomainName(_) => dhcp_opt::DOMAIN_NAME,
+            DhcpOption::RequestedIp(_) => dhcp_opt::REQUESTED_IP,
+            DhcpOption::LeaseTime(_) => dhcp_opt::LEASE_TIME,
+            DhcpOption::ServerId(_) => dhcp_opt::SERVER_ID,
+            DhcpOption::ParamRequest(_) => dhcp_opt::PARAM_REQUEST,
+            DhcpOption::ClientId { .. } => dhcp_opt::CLIENT_ID,
+            DhcpOption::RelayAgent(_) => dhcp_opt::RELAY_AGENT,
+            DhcpOption::Raw { code, .. } => *code,
+        }
+    }
+
+    fn parse(code: u8, data: &[u8]) -> Self {
+        let parsed = match code {
+            dhcp_opt::MSG_TYPE => match data {
+                [msg_type] => Some(DhcpOption::MessageType(*msg_type)),
+                _ => None,
+            },
+            dhcp_opt::SUBNET_MASK => addr(data).map(DhcpOption::SubnetMask),
+            dhcp_opt::ROUTER => addrs(data).map(DhcpOption::Router),
+            dhcp_opt::DNS => addrs(data).map(DhcpOption::Dns),
+            dhcp_opt::DOMAIN_NAME => String::from_utf8(data.to_vec())
+                .ok()
+                .map(DhcpOption::DomainName),
+            dhcp_opt::REQUESTED_IP => addr(data).map(DhcpOption::RequestedIp),
+            dhcp_opt::LEASE_TIME => {
+                addr(data).map(|b| DhcpOption::LeaseTime(u32::from_be_bytes(b)))
+            }
+            dhcp_opt::SERVER_ID => addr(data).map(DhcpOption::ServerId),
+            dhcp_opt::PARAM_REQUEST => Some(DhcpOption::ParamRequest(data.to_vec())),
+            dhcp_opt::CLIENT_ID if data.len() >= 2 => Some(DhcpOption::ClientId {
+                htype: data[0],
+                id: data[1..].to_vec(),
+            }),
+            dhcp_opt::RELAY_AGENT => {
+                let mut subs = Vec::new();
+                let mut at = 0;
+                while at < data.len() {
+                    match data.get(at + 1) {
+                        Some(len) if at + 2 + (*len as usize) <= data.len() => {
+                            let end = at + 2 + *len as usize;
+                            subs.push((data[at], data[at + 2..end].to_vec()));
+                            at = end;
+                        }
+                        _ => break,
+                    }
+                }
+                (at == data.len()).then_some(DhcpOption::RelayAgent(subs))
+            }
+            _ => None,
+        };
+        parsed.unwrap_or_else(|| DhcpOption::Raw {
+            code,
+            data: data.to_vec(),
+        })
+    }
+
+    fn data(&self) -> Result<Vec<u8>, PaError> {
+        Ok(match self {
+            DhcpOption::MessageType(msg_type) => vec![*msg_type],
+            DhcpOption::SubnetMask(addr)
+            | DhcpOption::RequestedIp(addr)
+            | DhcpOption::ServerId(addr) => addr.to_vec(),
+            DhcpOption::Router(addrs) | DhcpOption::Dns(addrs) => addrs.concat(),
+            DhcpOption::DomainName(name) => name.as_bytes().to_vec(),
+            DhcpOption::LeaseTime(secs) => secs.to_be_bytes().to_vec(),
+            DhcpOption::ParamRequest(codes) => codes.clone(),
+            DhcpOption::ClientId { htype, id } => {
+                let mut data = vec![*htype];
+                data.extend_from_slice(id);
+                data
+            }
+            DhcpOption::RelayAgent(subs) => {
+                let mut data = Vec::new();
+                for (code, sub) in subs.iter() {
+                    if sub.len() > 255 {
+                        return Err(PaError::new(
+                            "Relay agent sub-option too long",
+                            ErrorType::LengthError,
+                        ));
+                    }
+                    data.push(*code);
+                    data.push(sub.len() as u8);
+                    data.extend_from_slice(sub);
+                }
+                data
+            }
+            DhcpOption::Raw { data, .. } => data.clone(),
+        })
+    }
+}
+
+/// DHCP message according to [RFC 2131](https://datatracker.ietf.org/doc/html/rfc2131),
+/// or plain BOOTP message when `options` is empty
+///
+/// Options longer than 255 bytes are split into several options of the
+/// same code, which are joined again on parsing as by RFC 3396. `sname` and
+/// `file` are written padded with zeros, which are trimmed on parsing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dhcp {
+    pub op: u8,
+    pub htype: u8,
+    pub hlen: u8,
+    pub hops: u8,
+    pub xid: u32,
+    pub secs: u16,
+    pub flags: u16,
+    pub ciaddr: [u8; 4],
+    pub yiaddr: [u8; 4],
+    pub siaddr: [u8; 4],
+    pub giaddr: [u8; 4],
+    pub chaddr: [u8; 16],
+    /// Server host name of up to 64 bytes
+    pub sname: Vec<u8>,
+    /// Boot file name of up to 128 bytes
+    pub file: Vec<u8>,
+    pub options: Vec<DhcpOption>,
+}
+
+/// BOOTP message, which is a DHCP message without options
+pub type Bootp = Dhcp;
+
+impl Dhcp {
+    pub fn new() -> Self {
+        Self {
+            op: bootp_op::REQUEST,
+            htype: 1,
+            hlen: 6,
+            hops: 0,
+            xid: 0,
+            secs: 0,
+            flags: 0,
+            ciaddr: [0; 4],
+            yiaddr: [0; 4],
+            siaddr: [0; 4],
+            giaddr: [0; 4],
+            chaddr: [0; 16],
+            sname: Vec::new(),
+            file: Vec::new(),
+            options: Vec::new(),
+        }
+    }
+
+    /// Creates client message of type `msg_type` from Ethernet address `mac`
+    pub fn request(msg_type: u8, xid: u32, mac: [u8; 6]) -> Self {
+        let mut dhcp = Self::new();
+        dhcp.xid = xid;
+        dhcp.chaddr[..6].copy_from_slice(&mac);
+        dhcp.options.push(DhcpOption::MessageType(msg_type));
+        dhcp
+    }
+
+    /// Creates server reply of type `msg_type` to this message, keeping
+    /// transaction, flags, relay address and client address, and echoing
+    /// relay agent information as by RFC 3046
+    pub fn reply(&self, msg_type: u8) -> Self {
+        let mut reply = Self {
+            op: bootp_op::REPLY,
+            htype: self.htype,
+            hlen: self.hlen,
+            xid: self.xid,
+            flags: self.flags,
+            giaddr: self.giaddr,
+            chaddr: self.chaddr,
+            ..Self::new()
+        };
+        reply.options.push(DhcpOption::MessageType(msg_type));
+        if let Some(relay) = self.option(dhcp_opt::RELAY_AGENT) {
+            reply.options.push(relay.clone());
+        }
+        reply
+    }
+
+    /// First option of `code`
+    pub fn option(&self, code: u8) -> Option<&DhcpOption> {
+        self.options.iter().find(|option| option.code() == code)
+    }
+
+    /// Type of DHCP message, `None` for BOOTP
+    pub fn msg_type(&self) -> Option<u8> {
+        match self.option(dhcp_opt::MSG_TYPE) {
+            Some(DhcpOption::MessageType(msg_type)) => Some(*msg_type),
+            _ => None,
+        }
+    }
+
+    /// Ethernet address of client
+    pub fn mac(&self) -> [u8; 6] {
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&self.chaddr[..6]);
+        mac
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        if bytes.len() < FIXED_LEN {
+            return Err(parse_error("BOOTP message cut short"));
+        }
+        let addr = |at: usize| [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+        let trim = |field: &[u8]| {
+            let len = field.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+            field[..len].to_vec()
+        };
+        let mut dhcp = Self {
+            op: bytes[0],
+            htype: bytes[1],
+            hlen: bytes[2],
+            hops: bytes[3],
+            xid: u32::from_be_bytes(addr(4)),
+            secs: u16::from_be_bytes([bytes[8], bytes[9]]),
+            flags: u16::from_be_bytes([bytes[10], bytes[11]]),
+            ciaddr: addr(12),
+            yiaddr: addr(16),
+            siaddr: addr(20),
+            giaddr: addr(24),
+            chaddr: [0; 16],
+            sname: trim(&bytes[44..108]),
+            file: trim(&bytes[108..236]),
+            options: Vec::new(),
+        };
+        dhcp.chaddr.copy_from_slice(&bytes[28..44]);
+        if bytes.get(FIXED_LEN..FIXED_LEN + 4) != Some(&DHCP_MAGIC[..]) {
+            return Ok(dhcp);
+        }
+
+        // Codes in order of first appearance with their joined data
+        let mut options: Vec<(u8, Vec<u8>)> = Vec::new();
+        let mut at = FIXED_LEN + 4;
+        while at < bytes.len() {
+            let code = bytes[at];
+            match code {
+                dhcp_opt::PAD => {
+                    at += 1;
+                    continue;
+                }
+                dhcp_opt::END => break,
+                _ => (),
+            }
+            let len = match bytes.get(at + 1) {
+                Some(len) => *len as usize,
+                None => return Err(parse_error("DHCP option cut short")),
+            };
+            let data = match bytes.get(at + 2..at + 2 + len) {
+                Some(data) => data,
+                None => return Err(parse_error("DHCP option cut short")),
+            };
+            match options.iter_mut().find(|(known, _)| *known == code) {
+                Some((_, joined)) => joined.extend_from_slice(data),
+                None => options.push((code, data.to_vec())),
+            }
+            at += 2 + len;
+        }
+        dhcp.options = options
+            .iter()
+            .map(|(code, data)| DhcpOption::parse(*code, data))
+            .collect();
+        Ok(dhcp)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PaError> {
+        if self.sname.len() > 64 || self.file.len() > 128 {
+            return Err(PaError::new(
+                "BOOTP server or file name too long",
+                ErrorType::LengthError,
+            ));
+        }
+        let mut bytes = Vec::with_capacity(MIN_LEN);
+        bytes.extend_from_slice(&[self.op, self.htype, self.hlen, self.hops]);
+        bytes.extend_from_slice(&self.xid.to_be_bytes());
+        bytes.extend_from_slice(&self.secs.to_be_bytes());
+        bytes.extend_from_slice(&self.flags.to_be_bytes());
+        for addr in [self.ciaddr, self.yiaddr, self.siaddr, self.giaddr].iter() {
+            bytes.extend_from_slice(addr);
+        }
+        bytes.extend_from_slice(&self.chaddr);
+        bytes.extend_from_slice(&self.sname);
+        bytes.resize(108, 0);
+        bytes.extend_from_slice(&self.file);
+        bytes.resize(FIXED_LEN, 0);
+        if !self.options.is_empty() {
+            bytes.extend_from_slice(&DHCP_MAGIC);
+            for option in self.options.iter() {
+                let data = option.data()?;
+                if data.is_empty() {
+                    bytes.extend_from_slice(&[option.code(), 0]);
+                }
+                for part in data.chunks(255) {
+                    bytes.extend_from_slice(&[option.code(), part.len() as u8]);
+                    bytes.extend_from_slice(part);
+                }
+            }
+            bytes.push(dhcp_opt::END);
+        }
+        if bytes.len() < MIN_LEN {
+            bytes.resize(MIN_LEN, 0);
+        }
+        Ok(bytes)
+    }
+}
+
+impl Default for Dhcp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hdr for Dhcp {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes()?.into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Dhcp(self.clone())
+    }
+}
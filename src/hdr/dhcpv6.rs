@@ -0,0 +1,483 @@
+use crate::dstructs::Packet;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+
+#[path = "query/dhcpv6_query.rs"]
+mod dhcpv6_query;
+pub use dhcpv6_query::*;
+
+/// UDP ports of DHCPv6 clients and servers
+pub mod dhcpv6_port {
+    pub const CLIENT: u16 = 546;
+    pub const SERVER: u16 = 547;
+}
+
+/// DHCPv6 message types
+pub mod dhcpv6_msg {
+    pub const SOLICIT: u8 = 1;
+    pub const ADVERTISE: u8 = 2;
+    pub const REQUEST: u8 = 3;
+    pub const CONFIRM: u8 = 4;
+    pub const RENEW: u8 = 5;
+    pub const REBIND: u8 = 6;
+    pub const REPLY: u8 = 7;
+    pub const RELEASE: u8 = 8;
+    pub const DECLINE: u8 = 9;
+    pub const RECONFIGURE: u8 = 10;
+    pub const INFORMATION_REQUEST: u8 = 11;
+}
+
+/// Option codes of [RFC 8415](https://datatracker.ietf.org/doc/html/rfc8415)
+/// and [RFC 3646](https://datatracker.ietf.org/doc/html/rfc3646)
+pub mod dhcpv6_opt {
+    pub const CLIENT_ID: u16 = 1;
+    pub const SERVER_ID: u16 = 2;
+    pub const IA_NA: u16 = 3;
+    pub const IA_ADDR: u16 = 5;
+    pub const ORO: u16 = 6;
+    pub const PREFERENCE: u16 = 7;
+    pub const ELAPSED_TIME: u16 = 8;
+    pub const STATUS_CODE: u16 = 13;
+    pub const RAPID_COMMIT: u16 = 14;
+    pub const DNS_SERVERS: u16 = 23;
+    pub const DOMAIN_LIST: u16 = 24;
+    pub const IA_PD: u16 = 25;
+    pub const IA_PREFIX: u16 = 26;
+}
+
+/// Status codes carried by `Dhcpv6Option::StatusCode`
+pub mod dhcpv6_status {
+    pub const SUCCESS: u16 = 0;
+    pub const UNSPEC_FAIL: u16 = 1;
+    pub const NO_ADDRS_AVAIL: u16 = 2;
+    pub const NO_BINDING: u16 = 3;
+    pub const NOT_ON_LINK: u16 = 4;
+    pub const USE_MULTICAST: u16 = 5;
+    pub const NO_PREFIX_AVAIL: u16 = 6;
+}
+
+/// Multicast address of all DHCPv6 relay agents and servers `ff02::1:2`
+pub const ALL_DHCP_SERVERS: [u8; 16] = [0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 2];
+
+/// Returns DUID based on link-layer address `mac`, as by RFC 8415 section 11.4
+pub fn duid_ll(mac: [u8; 6]) -> Vec<u8> {
+    let mut duid = vec![0, 3, 0, 1];
+    duid.extend_from_slice(&mac);
+    duid
+}
+
+fn parse_error(msg: &str) -> PaError {
+    PaError::new(msg, ErrorType::ParseError)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn addr_at(bytes: &[u8], at: usize) -> [u8; 16] {
+    let mut addr = [0; 16];
+    addr.copy_from_slice(&bytes[at..at + 16]);
+    addr
+}
+
+/// Reads uncompressed domain names of option 24
+fn read_domains(data: &[u8]) -> Option<Vec<String>> {
+    let mut domains = Vec::new();
+    let mut labels: Vec<String> = Vec::new();
+    let mut at = 0;
+    while at < data.len() {
+        let len = data[at] as usize;
+        if len == 0 {
+            domains.push(labels.join("."));
+            labels.clear();
+            at += 1;
+            continue;
+        }
+        let label = data.get(at + 1..at + 1 + len)?;
+        if len > 63 || label.contains(&b'.') {
+            return None;
+        }
+        labels.push(String::from_utf8(label.to_vec()).ok()?);
+        at += 1 + len;
+    }
+    labels.is_empty().then_some(domains)
+}
+
+/// Option of a DHCPv6 message
+///
+/// Options of known code whose data doesn't fit their type are kept as
+/// `Raw`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Dhcpv6Option {
+    /// DUID of client
+    ClientId(Vec<u8>),
+    /// DUID of server
+    ServerId(Vec<u8>),
+    /// Identity association for non-temporary addresses, with T1 and T2 in
+    /// seconds and options such as `IaAddr` and `StatusCode`
+    IaNa {
+        iaid: u32,
+        t1: u32,
+        t2: u32,
+        options: Vec<Dhcpv6Option>,
+    },
+    /// Address of an `IaNa` with lifetimes in seconds
+    IaAddr {
+        addr: [u8; 16],
+        preferred: u32,
+        valid: u32,
+        options: Vec<Dhcpv6Option>,
+    },
+    /// Codes of options requested by client
+    Oro(Vec<u16>),
+    Preference(u8),
+    /// Time since client began exchange, in hundredths of a second
+    ElapsedTime(u16),
+    StatusCode {
+        code: u16,
+        message: String,
+    },
+    RapidCommit,
+    DnsServers(Vec<[u8; 16]>),
+    DomainList(Vec<String>),
+    /// Identity association for prefix delegation, with options such as
+    /// `IaPrefix` and `StatusCode`
+    IaPd {
+        iaid: u32,
+        t1: u32,
+        t2: u32,
+        options: Vec<Dhcpv6Option>,
+    },
+    /// Prefix of an `IaPd` with lifetimes in seconds
+    IaPrefix {
+        preferred: u32,
+        valid: u32,
+        prefix_len: u8,
+        prefix: [u8; 16],
+        options: Vec<Dhcpv6Option>,
+    },
+    Raw {
+        code: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl Dhcpv6Option {
+    pub fn code(&self) -> u16 {
+        match self {
+            Dhcpv6Option::ClientId(_) => dhcpv6_opt::CLIENT_ID,
+            Dhcpv6Option::ServerId(_) => dhcpv6_opt::SERVER_ID,
+            Dhcpv6Option::IaNa { .. } => dhcpv6_opt::IA_NA,
+            Dhcpv6Option::IaAddr { .. } => dhcpv6_opt::IA_ADDR,
+            Dhcpv6Option::Oro(_) => dhcpv6_opt::ORO,
+            Dhcpv6Option::Preference(_) => dhcpv6_opt::PREFERENCE,
+            Dhcpv6Option::ElapsedTime(_) => dhcpv6_opt::ELAPSED_TIME,
+            Dhcpv6Option::StatusCode { .. } => dhcpv6_opt::STATUS_CODE,
+            Dhcpv6Option::RapidCommit => dhcpv6_opt::RAPID_COMMIT,
+            Dhcpv6Option::DnsServers(_) => dhcpv6_opt::DNS_SERVERS,
+            Dhcpv6Option::DomainList(_) => dhcpv6_opt::DOMAIN_LIST,
+            Dhcpv6Option::IaPd { .. } => dhcpv6_opt::IA_PD,
+            Dhcpv6Option::IaPrefix { .. } => dhcpv6_opt::IA_PREFIX,
+            Dhcpv6Option::Raw { code, .. } => *code,
+        }
+    }
+
+    /// Options nested in an IA or its address or prefix
+    pub fn options(&self) -> &[Dhcpv6Option] {
+        match self {
+            Dhcpv6Option::IaNa { options, .. }
+            | Dhcpv6Option::IaAddr { options, .. }
+            | Dhcpv6Option::IaPd { options, .. }
+            | Dhcpv6Option::IaPrefix { options, .. } => options,
+            _ => &[],
+        }
+    }
+
+    /// Status code nested in this option, if any
+    pub fn status(&self) -> Option<u16> {
+        self.options().iter().find_map(|option| match option {
+            Dhcpv6Option::StatusCode { code, .. } => Some(*code),
+            _ => None,
+        })
+    }
+
+    fn parse(code: u16, data: &[u8]) -> Self {
+        let parsed = match (code, data.len()) {
+            (dhcpv6_opt::CLIENT_ID, _) => Some(Dhcpv6Option::ClientId(data.to_vec())),
+            (dhcpv6_opt::SERVER_ID, _) => Some(Dhcpv6Option::ServerId(data.to_vec())),
+            (dhcpv6_opt::IA_NA, len) | (dhcpv6_opt::IA_PD, len) if len >= 12 => {
+                parse_options(&data[12..]).ok().map(|options| {
+                    let (iaid, t1, t2) = (u32_at(data, 0), u32_at(data, 4), u32_at(data, 8));
+                    match code {
+                        dhcpv6_opt::IA_NA => Dhcpv6Option::IaNa {
+                            iaid,
+                            t1,
+                            t2,
+                            options,
+                        },
+                        _ => Dhcpv6Option::IaPd {
+                            iaid,
+                            t1,
+                            t2,
+                            options,
+                        },
+                    }
+                })
+            }
+            (dhcpv6_opt::IA_ADDR, len) if len >= 24 => {
+                parse_options(&data[24..])
+                    .ok()
+                    .map(|options| Dhcpv6Option::IaAddr {
+                        addr: addr_at(data, 0),
+                        preferred: u32_at(data, 16),
+                        valid: u32_at(data, 20),
+                        options,
+                    })
+            }
+            (dhcpv6_opt::IA_PREFIX, len) if len >= 25 => {
+                parse_options(&data[25..])
+                    .ok()
+                    .map(|options| Dhcpv6Option::IaPrefix {
+                        preferred: u32_at(data, 0),
+                        valid: u32_at(data, 4),
+                        prefix_len: data[8],
+                        prefix: addr_at(data, 9),
+                        options,
+                    })
+            }
+            (dhcpv6_opt::ORO, len) if len.is_multiple_of(2) => Some(Dhcpv6Option::Oro(
+                (0..len).step_by(2).map(|at| u16_at(data, at)).collect(),
+            )),
+            (dhcpv6_opt::PREFERENCE, 1) => Some(Dhcpv6Option::Preference(data[0])),
+            (dhcpv6_opt::ELAPSED_TIME, 2) => Some(Dhcpv6Option::ElapsedTime(u16_at(data, 0))),
+            (dhcpv6_opt::STATUS_CODE, len) if len >= 2 => String::from_utf8(data[2..].to_vec())
+                .ok()
+                .map(|message| Dhcpv6Option::StatusCode {
+                    code: u16_at(data, 0),
+                    message,
+                }),
+            (dhcpv6_opt::RAPID_COMMIT, 0) => Some(Dhcpv6Option::RapidCommit),
+            (dhcpv6_opt::DNS_SERVERS, len) if len.is_multiple_of(16) => {
+                Some(Dhcpv6Option::DnsServers(
+                    (0..len).step_by(16).map(|at| addr_at(data, at)).collect(),
+                ))
+            }
+            (dhcpv6_opt::DOMAIN_LIST, _) => read_domains(data).map(Dhcpv6Option::DomainList),
+            _ => None,
+        };
+        parsed.unwrap_or_else(|| Dhcpv6Option::Raw {
+            code,
+            data: data.to_vec(),
+        })
+    }
+
+    fn data(&self) -> Result<Vec<u8>, PaError> {
+        let mut data = Vec::new();
+        match self {
+            Dhcpv6Option::ClientId(duid) | Dhcpv6Option::ServerId(duid) => {
+                data.extend_from_slice(duid)
+            }
+            Dhcpv6Option::IaNa {
+                iaid,
+                t1,
+                t2,
+                options,
+            }
+            | Dhcpv6Option::IaPd {
+                iaid,
+                t1,
+                t2,
+                options,
+            } => {
+                for value in [iaid, t1, t2].iter() {
+                    data.extend_from_slice(&value.to_be_bytes());
+                }
+                write_options(options, &mut data)?;
+            }
+            Dhcpv6Option::IaAddr {
+                addr,
+                preferred,
+                valid,
+                options,
+            } => {
+                data.extend_from_slice(addr);
+                data.extend_from_slice(&preferred.to_be_bytes());
+                data.extend_from_slice(&valid.to_be_bytes());
+                write_options(options, &mut data)?;
+            }
+            Dhcpv6Option::IaPrefix {
+                preferred,
+                valid,
+                prefix_len,
+                prefix,
+                options,
+            } => {
+                data.extend_from_slice(&preferred.to_be_bytes());
+                data.extend_from_slice(&valid.to_be_bytes());
+                data.push(*prefix_len);
+                data.extend_from_slice(prefix);
+                write_options(options, &mut data)?;
+            }
+            Dhcpv6Option::Oro(codes) => codes
+                .iter()
+                .for_each(|code| data.extend_from_slice(&code.to_be_bytes())),
+            Dhcpv6Option::Preference(preference) => data.push(*preference),
+            Dhcpv6Option::ElapsedTime(time) => data.extend_from_slice(&time.to_be_bytes()),
+            Dhcpv6Option::StatusCode { code, message } => {
+                data.extend_from_slice(&code.to_be_bytes());
+                data.extend_from_slice(message.as_bytes());
+            }
+            Dhcpv6Option::RapidCommit => {}
+            Dhcpv6Option::DnsServers(servers) => {
+                servers
+                    .iter()
+                    .for_each(|server| data.extend_from_slice(server));
+            }
+            Dhcpv6Option::DomainList(domains) => {
+                for domain in domains.iter() {
+                    for label in domain.split('.').filter(|label| !label.is_empty()) {
+                        if label.len() > 63 {
+                            return Err(PaError::new(
+                                "DHCPv6 domain label too long",
+                                ErrorType::LengthError,
+                            ));
+                        }
+                        data.push(label.len() as u8);
+                        data.extend_from_slice(label.as_bytes());
+                    }
+                    data.push(0);
+                }
+            }
+            Dhcpv6Option::Raw { data: raw, .. } => data.extend_from_slice(raw),
+        }
+        Ok(data)
+    }
+}
+
+fn parse_options(bytes: &[u8]) -> Result<Vec<Dhcpv6Option>, PaError> {
+    let mut options = Vec::new();
+    let mut at = 0;
+    while at < bytes.len() {
+        if bytes.len() < at + 4 {
+            return Err(parse_error("DHCPv6 option cut short"));
+        }
+        let len = u16_at(bytes, at + 2) as usize;
+        let data = match bytes.get(at + 4..at + 4 + len) {
+            Some(data) => data,
+            None => return Err(parse_error("DHCPv6 option cut short")),
+        };
+        options.push(Dhcpv6Option::parse(u16_at(bytes, at), data));
+        at += 4 + len;
+    }
+    Ok(options)
+}
+
+fn write_options(options: &[Dhcpv6Option], bytes: &mut Vec<u8>) -> Result<(), PaError> {
+    for option in options.iter() {
+        let data = option.data()?;
+        if data.len() > u16::MAX as usize {
+            return Err(PaError::new(
+                "DHCPv6 option too long",
+                ErrorType::LengthError,
+            ));
+        }
+        bytes.extend_from_slice(&option.code().to_be_bytes());
+        bytes.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&data);
+    }
+    Ok(())
+}
+
+/// DHCPv6 client or server message according to
+/// [RFC 8415](https://datatracker.ietf.org/doc/html/rfc8415)
+///
+/// Relay messages aren't parsed and stay `Raw` in a `Pdu`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dhcpv6 {
+    pub msg_type: u8,
+    /// Transaction id of 24 bits
+    pub xid: u32,
+    pub options: Vec<Dhcpv6Option>,
+}
+
+impl Dhcpv6 {
+    pub fn new(msg_type: u8, xid: u32) -> Self {
+        Self {
+            msg_type,
+            xid,
+            options: Vec::new(),
+        }
+    }
+
+    /// Creates message of type `msg_type` answering this one, keeping
+    /// transaction and client id
+    pub fn reply(&self, msg_type: u8) -> Self {
+        let mut reply = Self::new(msg_type, self.xid);
+        if let Some(client_id) = self.option(dhcpv6_opt::CLIENT_ID) {
+            reply.options.push(client_id.clone());
+        }
+        reply
+    }
+
+    /// First option of `code`
+    pub fn option(&self, code: u16) -> Option<&Dhcpv6Option> {
+        self.options.iter().find(|option| option.code() == code)
+    }
+
+    /// DUID of client, if any
+    pub fn client_id(&self) -> Option<&[u8]> {
+        match self.option(dhcpv6_opt::CLIENT_ID) {
+            Some(Dhcpv6Option::ClientId(duid)) => Some(duid),
+            _ => None,
+        }
+    }
+
+    /// DUID of server, if any
+    pub fn server_id(&self) -> Option<&[u8]> {
+        match self.option(dhcpv6_opt::SERVER_ID) {
+            Some(Dhcpv6Option::ServerId(duid)) => Some(duid),
+            _ => None,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        if bytes.len() < 4 {
+            return Err(parse_error("DHCPv6 message cut short"));
+        }
+        if bytes[0] > dhcpv6_msg::INFORMATION_REQUEST || bytes[0] == 0 {
+            return Err(parse_error("Unknown DHCPv6 message type"));
+        }
+        Ok(Self {
+            msg_type: bytes[0],
+            xid: u32::from_be_bytes([0, bytes[1], bytes[2], bytes[3]]),
+            options: parse_options(&bytes[4..])?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PaError> {
+        let mut bytes = vec![self.msg_type];
+        bytes.extend_from_slice(&self.xid.to_be_bytes()[1..]);
+        write_options(&self.options, &mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Hdr for Dhcpv6 {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes()?.into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Dhcpv6(self.clone())
+    }
+}
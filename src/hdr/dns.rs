@@ -0,0 +1,684 @@
+use crate::dstructs::Packet;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+use std::collections::HashMap;
+
+#[path = "query/dns_query.rs"]
+mod dns_query;
+pub use dns_query::*;
+
+/// UDP and TCP ports DNS is served on
+pub mod dns_port {
+    pub const DNS: u16 = 53;
+    pub const MDNS: u16 = 5353;
+}
+
+/// Types of resource records and questions
+pub mod dns_type {
+    pub const A: u16 = 1;
+    pub const NS: u16 = 2;
+    pub const CNAME: u16 = 5;
+    pub const SOA: u16 = 6;
+    pub const PTR: u16 = 12;
+    pub const MX: u16 = 15;
+    pub const TXT: u16 = 16;
+    pub const AAAA: u16 = 28;
+    pub const SRV: u16 = 33;
+    pub const OPT: u16 = 41;
+    pub const ANY: u16 = 255;
+    pub const CAA: u16 = 257;
+}
+
+pub mod dns_class {
+    pub const IN: u16 = 1;
+    pub const CH: u16 = 3;
+    pub const ANY: u16 = 255;
+}
+
+pub mod dns_rcode {
+    pub const NO_ERROR: u8 = 0;
+    pub const FORM_ERR: u8 = 1;
+    pub const SERV_FAIL: u8 = 2;
+    pub const NX_DOMAIN: u8 = 3;
+    pub const NOT_IMP: u8 = 4;
+    pub const REFUSED: u8 = 5;
+}
+
+/// Longest name in wire format, see RFC 1035 section 2.3.4
+const MAX_NAME_LEN: usize = 255;
+/// Compression pointers followed for one name before giving up
+const MAX_POINTERS: usize = 64;
+
+fn parse_error(msg: &str) -> PaError {
+    PaError::new(msg, ErrorType::ParseError)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Result<u16, PaError> {
+    match bytes.get(at..at + 2) {
+        Some(b) => Ok(u16::from_be_bytes([b[0], b[1]])),
+        None => Err(parse_error("DNS message cut short")),
+    }
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Result<u32, PaError> {
+    match bytes.get(at..at + 4) {
+        Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(parse_error("DNS message cut short")),
+    }
+}
+
+/// Reads name at `at` of `msg`, returns it and the offset after it
+///
+/// Compression pointers may only point before the pointer itself, which
+/// rules out loops, and at most `MAX_POINTERS` are followed.
+fn read_name(msg: &[u8], mut at: usize) -> Result<(String, usize), PaError> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut wire_len = 1;
+    let mut end = None;
+    let mut pointers = 0;
+    loop {
+        let len = *msg
+            .get(at)
+            .ok_or_else(|| parse_error("DNS name cut short"))? as usize;
+        match len & 0xc0 {
+            0x00 if len == 0 => break,
+            0x00 => {
+                let label = msg
+                    .get(at + 1..at + 1 + len)
+                    .ok_or_else(|| parse_error("DNS label cut short"))?;
+                wire_len += len + 1;
+                if wire_len > MAX_NAME_LEN {
+                    return Err(parse_error("DNS name too long"));
+                }
+                labels.push(escape_label(label));
+                at += len + 1;
+            }
+            0xc0 => {
+                let target = (u16_at(msg, at)? & 0x3fff) as usize;
+                pointers += 1;
+                if target >= at || pointers > MAX_POINTERS {
+                    return Err(parse_error("DNS compression pointer loops"));
+                }
+                end.get_or_insert(at + 2);
+                at = target;
+            }
+            _ => return Err(parse_error("Unknown DNS label type")),
+        }
+    }
+    Ok((labels.join("."), end.unwrap_or(at + 1)))
+}
+
+/// Writes label in presentation format, escaping dots, backslashes and
+/// bytes which aren't printable
+fn escape_label(label: &[u8]) -> String {
+    let mut text = String::with_capacity(label.len());
+    for &byte in label {
+        match byte {
+            b'.' | b'\\' => {
+                text.push('\\');
+                text.push(byte as char);
+            }
+            0x21..=0x7e => text.push(byte as char),
+            _ => text.push_str(&format!("\\{:03}", byte)),
+        }
+    }
+    text
+}
+
+/// Splits name in presentation format into labels, undoing `escape_label`
+fn split_name(name: &str) -> Result<Vec<Vec<u8>>, PaError> {
+    let mut labels = Vec::new();
+    let mut label = Vec::new();
+    let mut bytes = name.trim_end_matches('.').bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'.' => labels.push(std::mem::take(&mut label)),
+            b'\\' => {
+                let digits: Vec<u8> = bytes.clone().take(3).collect();
+                if digits.len() == 3 && digits.iter().all(u8::is_ascii_digit) {
+                    let value = digits.iter().fold(0u32, |n, d| n * 10 + (d - b'0') as u32);
+                    label.push(value as u8);
+                    bytes.nth(2);
+                } else if let Some(byte) = bytes.next() {
+                    label.push(byte);
+                }
+            }
+            _ => label.push(byte),
+        }
+    }
+    if !label.is_empty() {
+        labels.push(label);
+    }
+    let wire_len: usize = labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1;
+    if labels
+        .iter()
+        .any(|label| label.is_empty() || label.len() > 63)
+    {
+        return Err(PaError::new(
+            format!("Invalid label in DNS name {:?}", name),
+            ErrorType::ConstructError,
+        ));
+    }
+    if wire_len > MAX_NAME_LEN {
+        return Err(PaError::new(
+            format!("DNS name {:?} too long", name),
+            ErrorType::LengthError,
+        ));
+    }
+    Ok(labels)
+}
+
+/// Builds messages, remembering names written for compression
+struct NameWriter {
+    data: Vec<u8>,
+    /// Offsets of names written, keyed by lowercase labels
+    names: HashMap<Vec<Vec<u8>>, usize>,
+}
+
+impl NameWriter {
+    fn name(&mut self, name: &str, compress: bool) -> Result<(), PaError> {
+        let labels = split_name(name)?;
+        for i in 0..labels.len() {
+            let suffix: Vec<Vec<u8>> = labels[i..]
+                .iter()
+                .map(|label| label.to_ascii_lowercase())
+                .collect();
+            if compress {
+                if let Some(&offset) = self.names.get(&suffix) {
+                    self.data
+                        .extend_from_slice(&(0xc000 | offset as u16).to_be_bytes());
+                    return Ok(());
+                }
+            }
+            if self.data.len() < 0x4000 {
+                self.names.entry(suffix).or_insert(self.data.len());
+            }
+            self.data.push(labels[i].len() as u8);
+            self.data.extend_from_slice(&labels[i]);
+        }
+        self.data.push(0);
+        Ok(())
+    }
+}
+
+/// Question of a DNS message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+impl DnsQuestion {
+    pub fn new(name: &str, qtype: u16) -> Self {
+        Self {
+            name: name.to_string(),
+            qtype,
+            qclass: dns_class::IN,
+        }
+    }
+}
+
+/// Data of a resource record
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RData {
+    A([u8; 4]),
+    AAAA([u8; 16]),
+    CNAME(String),
+    NS(String),
+    PTR(String),
+    MX {
+        preference: u16,
+        exchange: String,
+    },
+    /// Character strings of up to 255 bytes each
+    TXT(Vec<Vec<u8>>),
+    SOA {
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    CAA {
+        flags: u8,
+        tag: String,
+        value: Vec<u8>,
+    },
+    /// EDNS0 options as code and data
+    OPT(Vec<(u16, Vec<u8>)>),
+    /// Data of unknown types, or of known types which failed to parse
+    Raw(Vec<u8>),
+}
+
+impl RData {
+    /// Record type carrying this data, 0 for `Raw`
+    pub fn rtype(&self) -> u16 {
+        match self {
+            RData::A(_) => dns_type::A,
+            RData::AAAA(_) => dns_type::AAAA,
+            RData::CNAME(_) => dns_type::CNAME,
+            RData::NS(_) => dns_type::NS,
+            RData::PTR(_) => dns_type::PTR,
+            RData::MX { .. } => dns_type::MX,
+            RData::TXT(_) => dns_type::TXT,
+            RData::SOA { .. } => dns_type::SOA,
+            RData::SRV { .. } => dns_type::SRV,
+            RData::CAA { .. } => dns_type::CAA,
+            RData::OPT(_) => dns_type::OPT,
+            RData::Raw(_) => 0,
+        }
+    }
+
+    /// Parses data of type `rtype` found at `start..end` of `msg`
+    fn parse(msg: &[u8], rtype: u16, start: usize, end: usize) -> Result<Self, PaError> {
+        let data = &msg[start..end];
+        let name_at = |at: usize| -> Result<String, PaError> {
+            let (name, after) = read_name(&msg[..end], at)?;
+            if after == end {
+                Ok(name)
+            } else {
+                Err(parse_error("Trailing bytes after DNS name"))
+            }
+        };
+        Ok(match rtype {
+            dns_type::A if data.len() == 4 => RData::A([data[0], data[1], data[2], data[3]]),
+            dns_type::AAAA if data.len() == 16 => {
+                let mut addr = [0; 16];
+                addr.copy_from_slice(data);
+                RData::AAAA(addr)
+            }
+            dns_type::CNAME => RData::CNAME(name_at(start)?),
+            dns_type::NS => RData::NS(name_at(start)?),
+            dns_type::PTR => RData::PTR(name_at(start)?),
+            dns_type::MX => RData::MX {
+                preference: u16_at(data, 0)?,
+                exchange: name_at(start + 2)?,
+            },
+            dns_type::TXT => {
+                let mut strings = Vec::new();
+                let mut at = 0;
+                while at < data.len() {
+                    let len = data[at] as usize;
+                    let string = data
+                        .get(at + 1..at + 1 + len)
+                        .ok_or_else(|| parse_error("TXT string cut short"))?;
+                    strings.push(string.to_vec());
+                    at += len + 1;
+                }
+                RData::TXT(strings)
+            }
+            dns_type::SOA => {
+                let (mname, at) = read_name(&msg[..end], start)?;
+                let (rname, at) = read_name(&msg[..end], at)?;
+                if end - at != 20 {
+                    return Err(parse_error("SOA record of wrong length"));
+                }
+                RData::SOA {
+                    mname,
+                    rname,
+                    serial: u32_at(msg, at)?,
+                    refresh: u32_at(msg, at + 4)?,
+                    retry: u32_at(msg, at + 8)?,
+                    expire: u32_at(msg, at + 12)?,
+                    minimum: u32_at(msg, at + 16)?,
+                }
+            }
+            dns_type::SRV => RData::SRV {
+                priority: u16_at(data, 0)?,
+                weight: u16_at(data, 2)?,
+                port: u16_at(data, 4)?,
+                target: name_at(start + 6)?,
+            },
+            dns_type::CAA if data.len() >= 2 => {
+                let tag_len = data[1] as usize;
+                let tag = data
+                    .get(2..2 + tag_len)
+                    .ok_or_else(|| parse_error("CAA tag cut short"))?;
+                RData::CAA {
+                    flags: data[0],
+                    tag: String::from_utf8_lossy(tag).to_string(),
+                    value: data[2 + tag_len..].to_vec(),
+                }
+            }
+            dns_type::OPT => {
+                let mut options = Vec::new();
+                let mut at = 0;
+                while at < data.len() {
+                    let code = u16_at(data, at)?;
+                    let len = u16_at(data, at + 2)? as usize;
+                    let option = data
+                        .get(at + 4..at + 4 + len)
+                        .ok_or_else(|| parse_error("EDNS option cut short"))?;
+                    options.push((code, option.to_vec()));
+                    at += 4 + len;
+                }
+                RData::OPT(options)
+            }
+            _ => RData::Raw(data.to_vec()),
+        })
+    }
+
+    fn create(&self, writer: &mut NameWriter) -> Result<(), PaError> {
+        let data = &mut writer.data;
+        match self {
+            RData::A(addr) => data.extend_from_slice(addr),
+            RData::AAAA(addr) => data.extend_from_slice(addr),
+            RData::CNAME(name) | RData::NS(name) | RData::PTR(name) => writer.name(name, true)?,
+            RData::MX {
+                preference,
+                exchange,
+            } => {
+                data.extend_from_slice(&preference.to_be_bytes());
+                writer.name(exchange, true)?;
+            }
+            RData::TXT(strings) => {
+                for string in strings {
+                    if string.len() > 255 {
+                        return Err(PaError::new(
+                            "TXT string longer than 255 bytes",
+                            ErrorType::LengthError,
+                        ));
+                    }
+                    data.push(string.len() as u8);
+                    data.extend_from_slice(string);
+                }
+            }
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                writer.name(mname, true)?;
+                writer.name(rname, true)?;
+                for value in [serial, refresh, retry, expire, minimum].iter() {
+                    writer.data.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                data.extend_from_slice(&priority.to_be_bytes());
+                data.extend_from_slice(&weight.to_be_bytes());
+                data.extend_from_slice(&port.to_be_bytes());
+                // RFC 2782 forbids compressing the target
+                writer.name(target, false)?;
+            }
+            RData::CAA { flags, tag, value } => {
+                data.push(*flags);
+                data.push(tag.len() as u8);
+                data.extend_from_slice(tag.as_bytes());
+                data.extend_from_slice(value);
+            }
+            RData::OPT(options) => {
+                for (code, option) in options {
+                    data.extend_from_slice(&code.to_be_bytes());
+                    data.extend_from_slice(&(option.len() as u16).to_be_bytes());
+                    data.extend_from_slice(option);
+                }
+            }
+            RData::Raw(raw) => data.extend_from_slice(raw),
+        }
+        Ok(())
+    }
+}
+
+/// Resource record of answer, authority or additional section
+///
+/// In OPT records `class` holds the UDP payload size and `ttl` the extended
+/// rcode, version and flags.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DnsRecord {
+    pub name: String,
+    pub rtype: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: RData,
+}
+
+impl DnsRecord {
+    /// Creates record of class IN whose type follows from `data`, set
+    /// `rtype` for `RData::Raw`
+    pub fn new(name: &str, ttl: u32, data: RData) -> Self {
+        Self {
+            name: name.to_string(),
+            rtype: data.rtype(),
+            class: dns_class::IN,
+            ttl,
+            data,
+        }
+    }
+
+    /// Creates EDNS0 OPT record advertising `udp_size`, with DNSSEC OK bit
+    /// set by `dnssec_ok`
+    pub fn opt(udp_size: u16, dnssec_ok: bool) -> Self {
+        Self {
+            name: String::new(),
+            rtype: dns_type::OPT,
+            class: udp_size,
+            ttl: if dnssec_ok { 0x8000 } else { 0 },
+            data: RData::OPT(Vec::new()),
+        }
+    }
+}
+
+/// DNS message according to [RFC 1035](https://datatracker.ietf.org/doc/html/rfc1035)
+///
+/// Section counts are written from the lengths of the sections. Over TCP
+/// the message is preceded by its length when built in a `Pdu`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dns {
+    pub id: u16,
+    /// `true` for responses
+    pub qr: bool,
+    pub opcode: u8,
+    pub aa: bool,
+    pub tc: bool,
+    pub rd: bool,
+    pub ra: bool,
+    pub z: bool,
+    pub ad: bool,
+    pub cd: bool,
+    pub rcode: u8,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsRecord>,
+    pub authorities: Vec<DnsRecord>,
+    pub additionals: Vec<DnsRecord>,
+}
+
+impl Dns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates recursive query for `name` of type `qtype`
+    pub fn query(id: u16, name: &str, qtype: u16) -> Self {
+        Self {
+            id,
+            rd: true,
+            questions: vec![DnsQuestion::new(name, qtype)],
+            ..Self::default()
+        }
+    }
+
+    /// Creates response to this query without records, keeping id,
+    /// opcode, questions and recursion desired
+    pub fn reply(&self) -> Self {
+        Self {
+            id: self.id,
+            qr: true,
+            opcode: self.opcode,
+            rd: self.rd,
+            ra: true,
+            cd: self.cd,
+            questions: self.questions.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// OPT record of additional section, if any
+    pub fn edns(&self) -> Option<&DnsRecord> {
+        self.additionals
+            .iter()
+            .find(|record| record.rtype == dns_type::OPT)
+    }
+
+    fn flags(&self) -> u16 {
+        let bit = |set: bool, shift: u16| (set as u16) << shift;
+        bit(self.qr, 15)
+            | ((self.opcode as u16) & 0xf) << 11
+            | bit(self.aa, 10)
+            | bit(self.tc, 9)
+            | bit(self.rd, 8)
+            | bit(self.ra, 7)
+            | bit(self.z, 6)
+            | bit(self.ad, 5)
+            | bit(self.cd, 4)
+            | (self.rcode as u16) & 0xf
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        let flags = u16_at(bytes, 2)?;
+        let bit = |shift: u16| flags >> shift & 1 == 1;
+        let mut dns = Self {
+            id: u16_at(bytes, 0)?,
+            qr: bit(15),
+            opcode: (flags >> 11 & 0xf) as u8,
+            aa: bit(10),
+            tc: bit(9),
+            rd: bit(8),
+            ra: bit(7),
+            z: bit(6),
+            ad: bit(5),
+            cd: bit(4),
+            rcode: (flags & 0xf) as u8,
+            ..Self::default()
+        };
+        let counts = [
+            u16_at(bytes, 4)?,
+            u16_at(bytes, 6)?,
+            u16_at(bytes, 8)?,
+            u16_at(bytes, 10)?,
+        ];
+        let mut at = 12;
+        for _ in 0..counts[0] {
+            let (name, after) = read_name(bytes, at)?;
+            dns.questions.push(DnsQuestion {
+                name,
+                qtype: u16_at(bytes, after)?,
+                qclass: u16_at(bytes, after + 2)?,
+            });
+            at = after + 4;
+        }
+        for (i, count) in counts[1..].iter().enumerate() {
+            for _ in 0..*count {
+                let (name, after) = read_name(bytes, at)?;
+                let rtype = u16_at(bytes, after)?;
+                let len = u16_at(bytes, after + 8)? as usize;
+                let start = after + 10;
+                if bytes.len() < start + len {
+                    return Err(parse_error("DNS record cut short"));
+                }
+                let data = RData::parse(bytes, rtype, start, start + len)
+                    .unwrap_or_else(|_| RData::Raw(bytes[start..start + len].to_vec()));
+                let record = DnsRecord {
+                    name,
+                    rtype,
+                    class: u16_at(bytes, after + 2)?,
+                    ttl: u32_at(bytes, after + 4)?,
+                    data,
+                };
+                match i {
+                    0 => dns.answers.push(record),
+                    1 => dns.authorities.push(record),
+                    _ => dns.additionals.push(record),
+                }
+                at = start + len;
+            }
+        }
+        Ok(dns)
+    }
+
+    /// Returns message in wire format, compressing names
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PaError> {
+        let mut writer = NameWriter {
+            data: Vec::with_capacity(512),
+            names: HashMap::new(),
+        };
+        writer.data.extend_from_slice(&self.id.to_be_bytes());
+        writer.data.extend_from_slice(&self.flags().to_be_bytes());
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len(),
+        ]
+        .iter()
+        {
+            writer
+                .data
+                .extend_from_slice(&(*count as u16).to_be_bytes());
+        }
+        for question in self.questions.iter() {
+            writer.name(&question.name, true)?;
+            writer.data.extend_from_slice(&question.qtype.to_be_bytes());
+            writer
+                .data
+                .extend_from_slice(&question.qclass.to_be_bytes());
+        }
+        let records = self
+            .answers
+            .iter()
+            .chain(self.authorities.iter())
+            .chain(self.additionals.iter());
+        for record in records {
+            writer.name(&record.name, true)?;
+            writer.data.extend_from_slice(&record.rtype.to_be_bytes());
+            writer.data.extend_from_slice(&record.class.to_be_bytes());
+            writer.data.extend_from_slice(&record.ttl.to_be_bytes());
+            let len_at = writer.data.len();
+            writer.data.extend_from_slice(&[0, 0]);
+            record.data.create(&mut writer)?;
+            let len = writer.data.len() - len_at - 2;
+            if len > u16::MAX as usize {
+                return Err(PaError::new(
+                    "DNS record data too long",
+                    ErrorType::LengthError,
+                ));
+            }
+            writer.data[len_at..len_at + 2].copy_from_slice(&(len as u16).to_be_bytes());
+        }
+        Ok(writer.data)
+    }
+}
+
+impl Hdr for Dns {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes()?.into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Dns(self.clone())
+    }
+}
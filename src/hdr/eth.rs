@@ -10,9 +10,13 @@ mod eth_query;
 pub use eth_query::*;
 
 pub mod eth_type {
-    pub const ARP: usize = 0x0800;
+    pub const ARP: usize = 0x0806;
     #[allow(non_upper_case_globals)]
-    pub const IPv4: usize = 0x0806;
+    pub const IPv4: usize = 0x0800;
+    #[allow(non_upper_case_globals)]
+    pub const IPv6: usize = 0x86dd;
+    pub const MPLS_UNICAST: usize = 0x8847;
+    pub const MPLS_MULTICAST: usize = 0x8848;
 }
 
 /// The internal structure of an Ethernet frame is specified in IEEE 802.3
@@ -72,12 +76,16 @@ impl EthHdr {
     }
 
     fn length(&self) -> usize {
-        60
+        1514
     }
 
     pub fn encapsulate(&self, data: impl Hdr) -> Result<Vec<u8>, PaError> {
+        self.encapsulate_raw(data.create()?.into())
+    }
+
+    /// Same as `encapsulate` but takes already created bytes of upper layers
+    pub fn encapsulate_raw(&self, mut data: Vec<u8>) -> Result<Vec<u8>, PaError> {
         let mut encapsulated: Vec<u8> = self.create()?.into();
-        let mut data = data.create()?.into();
 
         encapsulated.append(&mut data);
 
@@ -0,0 +1,153 @@
+use crate::dstructs::Packet;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+
+/// UDP port of Geneve
+pub mod geneve_port {
+    pub const GENEVE: u16 = 6081;
+}
+
+fn parse_error(msg: &str) -> PaError {
+    PaError::new(msg, ErrorType::ParseError)
+}
+
+/// TLV option of a Geneve header
+///
+/// `data` is padded with zeros to a multiple of 4 bytes when built.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeneveOption {
+    pub class: u16,
+    /// Type of option, its high bit marks it critical
+    pub kind: u8,
+    pub data: Vec<u8>,
+}
+
+impl GeneveOption {
+    pub fn new(class: u16, kind: u8, data: Vec<u8>) -> Self {
+        Self { class, kind, data }
+    }
+
+    /// Receivers not knowing this option must drop the packet
+    pub fn critical(&self) -> bool {
+        self.kind & 0x80 != 0
+    }
+}
+
+/// Geneve header according to [RFC 8926](https://datatracker.ietf.org/doc/html/rfc8926)
+///
+/// A `protocol` of 0 is filled in from the inner frame when built in a
+/// `Pdu`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Geneve {
+    pub version: u8,
+    /// Packet carries control messages
+    pub oam: bool,
+    /// Some options are critical
+    pub critical: bool,
+    /// EtherType of inner frame
+    pub protocol: u16,
+    /// Virtual network identifier of 24 bits
+    pub vni: u32,
+    pub options: Vec<GeneveOption>,
+}
+
+impl Geneve {
+    pub fn new(vni: u32) -> Self {
+        Self {
+            vni,
+            ..Self::default()
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        8 + self
+            .options
+            .iter()
+            .map(|option| 4 + option.data.len().div_ceil(4) * 4)
+            .sum::<usize>()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        if bytes.len() < 8 {
+            return Err(parse_error("Geneve header cut short"));
+        }
+        let opts_len = (bytes[0] & 0x3f) as usize * 4;
+        let mut geneve = Self {
+            version: bytes[0] >> 6,
+            oam: bytes[1] & 0x80 != 0,
+            critical: bytes[1] & 0x40 != 0,
+            protocol: u16::from_be_bytes([bytes[2], bytes[3]]),
+            vni: u32::from_be_bytes([0, bytes[4], bytes[5], bytes[6]]),
+            options: Vec::new(),
+        };
+        if geneve.version != 0 {
+            return Err(parse_error("Unsupported Geneve version"));
+        }
+        let opts = bytes
+            .get(8..8 + opts_len)
+            .ok_or_else(|| parse_error("Geneve options cut short"))?;
+        let mut at = 0;
+        while at < opts.len() {
+            if opts.len() < at + 4 {
+                return Err(parse_error("Geneve option cut short"));
+            }
+            let len = (opts[at + 3] & 0x1f) as usize * 4;
+            let data = opts
+                .get(at + 4..at + 4 + len)
+                .ok_or_else(|| parse_error("Geneve option cut short"))?;
+            geneve.options.push(GeneveOption {
+                class: u16::from_be_bytes([opts[at], opts[at + 1]]),
+                kind: opts[at + 2],
+                data: data.to_vec(),
+            });
+            at += 4 + len;
+        }
+        Ok(geneve)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PaError> {
+        let opts_len = self.length() - 8;
+        if opts_len > 0x3f * 4 {
+            return Err(PaError::new(
+                "Geneve options too long",
+                ErrorType::LengthError,
+            ));
+        }
+        let mut bytes = vec![
+            self.version << 6 | (opts_len / 4) as u8,
+            (self.oam as u8) << 7 | (self.critical as u8) << 6,
+        ];
+        bytes.extend_from_slice(&self.protocol.to_be_bytes());
+        bytes.extend_from_slice(&(self.vni << 8).to_be_bytes());
+        for option in self.options.iter() {
+            let len = option.data.len().div_ceil(4);
+            if len > 0x1f {
+                return Err(PaError::new(
+                    "Geneve option too long",
+                    ErrorType::LengthError,
+                ));
+            }
+            bytes.extend_from_slice(&option.class.to_be_bytes());
+            bytes.extend_from_slice(&[option.kind, len as u8]);
+            bytes.extend_from_slice(&option.data);
+            bytes.resize(bytes.len() + len * 4 - option.data.len(), 0);
+        }
+        Ok(bytes)
+    }
+}
+
+impl Hdr for Geneve {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes()?.into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Geneve(self.clone())
+    }
+}
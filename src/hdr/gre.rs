@@ -0,0 +1,282 @@
+use crate::dstructs::Packet;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+
+/// Protocol types carried by GRE besides IPv4 and IPv6
+pub mod gre_proto {
+    /// Transparent Ethernet bridging, an Ethernet frame follows
+    pub const TEB: u16 = 0x6558;
+    pub const ERSPAN_II: u16 = 0x88be;
+    pub const ERSPAN_III: u16 = 0x22eb;
+}
+
+const FLAG_CHECKSUM: u8 = 0x80;
+const FLAG_KEY: u8 = 0x20;
+const FLAG_SEQ: u8 = 0x10;
+
+fn parse_error(msg: &str) -> PaError {
+    PaError::new(msg, ErrorType::ParseError)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// GRE header according to [RFC 2784](https://datatracker.ietf.org/doc/html/rfc2784)
+/// and [RFC 2890](https://datatracker.ietf.org/doc/html/rfc2890)
+///
+/// Optional fields are present when `Some`. A `checksum` of `Some(0)` and a
+/// `protocol` of 0 are filled in when built in a `Pdu`, the latter from
+/// the inner frame or ERSPAN header.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Gre {
+    pub checksum: Option<u16>,
+    pub key: Option<u32>,
+    pub seq: Option<u32>,
+    pub version: u8,
+    /// EtherType of carried payload
+    pub protocol: u16,
+}
+
+impl Gre {
+    pub fn new(protocol: u16) -> Self {
+        Self {
+            protocol,
+            ..Self::default()
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        4 + 4
+            * (self.checksum.is_some() as usize
+                + self.key.is_some() as usize
+                + self.seq.is_some() as usize)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        if bytes.len() < 4 {
+            return Err(parse_error("GRE header cut short"));
+        }
+        let flags = bytes[0];
+        let mut gre = Self {
+            version: bytes[1] & 0x07,
+            protocol: u16::from_be_bytes([bytes[2], bytes[3]]),
+            ..Self::default()
+        };
+        if gre.version != 0 {
+            return Err(parse_error("Unsupported GRE version"));
+        }
+        let mut at = 4;
+        let mut field = |present: bool| -> Result<Option<u32>, PaError> {
+            if !present {
+                return Ok(None);
+            }
+            if bytes.len() < at + 4 {
+                return Err(parse_error("GRE header cut short"));
+            }
+            at += 4;
+            Ok(Some(u32_at(bytes, at - 4)))
+        };
+        gre.checksum = field(flags & FLAG_CHECKSUM != 0)?.map(|word| (word >> 16) as u16);
+        gre.key = field(flags & FLAG_KEY != 0)?;
+        gre.seq = field(flags & FLAG_SEQ != 0)?;
+        Ok(gre)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let flags = self.checksum.map_or(0, |_| FLAG_CHECKSUM)
+            | self.key.map_or(0, |_| FLAG_KEY)
+            | self.seq.map_or(0, |_| FLAG_SEQ);
+        let mut bytes = vec![flags, self.version & 0x07];
+        bytes.extend_from_slice(&self.protocol.to_be_bytes());
+        if let Some(checksum) = self.checksum {
+            bytes.extend_from_slice(&checksum.to_be_bytes());
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        for value in [self.key, self.seq].iter().flatten() {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+impl Hdr for Gre {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes().into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Gre(self.clone())
+    }
+}
+
+/// ERSPAN header following GRE, carrying a mirrored Ethernet frame
+///
+/// Version 1 is type II and version 2 is type III. Fields only known to
+/// one type are ignored by the other, e.g. `index` is type II only and
+/// `timestamp` type III only.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Erspan {
+    pub version: u8,
+    pub vlan: u16,
+    pub cos: u8,
+    /// Encapsulation of original frame for type II, bad or short frame
+    /// flags for type III
+    pub encap: u8,
+    /// Mirrored frame was truncated
+    pub truncated: bool,
+    pub session_id: u16,
+    /// Port index or direction of type II
+    pub index: u32,
+    /// Timestamp of type III in units of `granularity`
+    pub timestamp: u32,
+    /// Security group tag of type III
+    pub sgt: u16,
+    /// Type III frame is Ethernet with protocol header kept
+    pub p_bit: bool,
+    pub frame_type: u8,
+    pub hw_id: u8,
+    /// Mirrored on egress for type III
+    pub direction: bool,
+    pub granularity: u8,
+    /// Optional platform specific subheader of type III
+    pub platform: Option<[u8; 8]>,
+}
+
+impl Erspan {
+    /// Creates type II header of session `session_id`
+    pub fn type2(session_id: u16) -> Self {
+        Self {
+            version: 1,
+            vlan: 0,
+            cos: 0,
+            encap: 0,
+            truncated: false,
+            session_id,
+            index: 0,
+            timestamp: 0,
+            sgt: 0,
+            p_bit: false,
+            frame_type: 0,
+            hw_id: 0,
+            direction: false,
+            granularity: 0,
+            platform: None,
+        }
+    }
+
+    /// Creates type III header of session `session_id`
+    pub fn type3(session_id: u16) -> Self {
+        Self {
+            version: 2,
+            ..Self::type2(session_id)
+        }
+    }
+
+    /// GRE protocol type announcing this header
+    pub fn gre_protocol(&self) -> u16 {
+        match self.version {
+            1 => gre_proto::ERSPAN_II,
+            _ => gre_proto::ERSPAN_III,
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        match self.version {
+            1 => 8,
+            _ => 12 + self.platform.map_or(0, |_| 8),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        if bytes.len() < 8 {
+            return Err(parse_error("ERSPAN header cut short"));
+        }
+        let version = bytes[0] >> 4;
+        let mut erspan = Self::type2(u16::from_be_bytes([bytes[2], bytes[3]]) & 0x3ff);
+        erspan.version = version;
+        erspan.vlan = u16::from_be_bytes([bytes[0], bytes[1]]) & 0xfff;
+        erspan.cos = bytes[2] >> 5;
+        erspan.encap = (bytes[2] >> 3) & 0x03;
+        erspan.truncated = bytes[2] & 0x04 != 0;
+        match version {
+            1 => erspan.index = u32_at(bytes, 4) & 0xf_ffff,
+            2 if bytes.len() >= 12 => {
+                erspan.timestamp = u32_at(bytes, 4);
+                erspan.sgt = u16::from_be_bytes([bytes[8], bytes[9]]);
+                erspan.p_bit = bytes[10] & 0x80 != 0;
+                erspan.frame_type = (bytes[10] >> 2) & 0x1f;
+                erspan.hw_id = (bytes[10] & 0x03) << 4 | bytes[11] >> 4;
+                erspan.direction = bytes[11] & 0x08 != 0;
+                erspan.granularity = (bytes[11] >> 1) & 0x03;
+                if bytes[11] & 0x01 != 0 {
+                    let platform = bytes
+                        .get(12..20)
+                        .ok_or_else(|| parse_error("ERSPAN subheader cut short"))?;
+                    let mut sub = [0; 8];
+                    sub.copy_from_slice(platform);
+                    erspan.platform = Some(sub);
+                }
+            }
+            2 => return Err(parse_error("ERSPAN header cut short")),
+            _ => return Err(parse_error("Unsupported ERSPAN version")),
+        }
+        Ok(erspan)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = ((self.version as u16) << 12 | (self.vlan & 0xfff))
+            .to_be_bytes()
+            .to_vec();
+        bytes.push(
+            (self.cos & 0x07) << 5
+                | (self.encap & 0x03) << 3
+                | (self.truncated as u8) << 2
+                | (self.session_id >> 8) as u8 & 0x03,
+        );
+        bytes.push(self.session_id as u8);
+        match self.version {
+            1 => bytes.extend_from_slice(&(self.index & 0xf_ffff).to_be_bytes()),
+            _ => {
+                bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+                bytes.extend_from_slice(&self.sgt.to_be_bytes());
+                bytes.push(
+                    (self.p_bit as u8) << 7
+                        | (self.frame_type & 0x1f) << 2
+                        | (self.hw_id >> 4) & 0x03,
+                );
+                bytes.push(
+                    (self.hw_id & 0x0f) << 4
+                        | (self.direction as u8) << 3
+                        | (self.granularity & 0x03) << 1
+                        | self.platform.is_some() as u8,
+                );
+                if let Some(platform) = self.platform {
+                    bytes.extend_from_slice(&platform);
+                }
+            }
+        }
+        bytes
+    }
+}
+
+impl Hdr for Erspan {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes().into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_else(|_| Self::type2(0))
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Erspan(self.clone())
+    }
+}
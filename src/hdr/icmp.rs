@@ -0,0 +1,128 @@
+use crate::dstructs::Bits;
+use crate::dstructs::Packet;
+use crate::error::PaError;
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+use crate::utility::checksum;
+
+pub mod icmp_type {
+    pub const ECHO_REPLY: u8 = 0;
+    pub const DEST_UNREACHABLE: u8 = 3;
+    pub const ECHO_REQUEST: u8 = 8;
+    pub const TIME_EXCEEDED: u8 = 11;
+}
+
+/// ICMP header according to [RFC 792](https://datatracker.ietf.org/doc/html/rfc792)
+///
+/// Last four bytes are kept as `id` and `seq`, which is their meaning in echo
+/// messages. Other messages store whatever they carry there.
+#[derive(Clone)]
+pub struct IcmpHdr {
+    pub icmp_type: Bits,
+    pub code: Bits,
+    pub checksum: Bits,
+    pub id: Bits,
+    pub seq: Bits,
+}
+
+impl IcmpHdr {
+    pub fn new() -> Self {
+        Self {
+            icmp_type: Bits::from(0, 8),
+            code: Bits::from(0, 8),
+            checksum: Bits::from(0, 16),
+            id: Bits::from(0, 16),
+            seq: Bits::from(0, 16),
+        }
+    }
+
+    /// Creates echo request with identifier `id` and sequence number `seq`
+    pub fn echo_request(id: u16, seq: u16) -> Self {
+        Self {
+            icmp_type: Bits::from(icmp_type::ECHO_REQUEST.into(), 8),
+            code: Bits::from(0, 8),
+            checksum: Bits::from(0, 16),
+            id: Bits::from(id.into(), 16),
+            seq: Bits::from(seq.into(), 16),
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        8
+    }
+
+    /// Recomputes `checksum` over the header and `payload` following it
+    pub fn set_checksum(&mut self, payload: &[u8]) -> Result<(), PaError> {
+        self.checksum = Bits::from(0, 16);
+        let mut raw: Vec<u8> = self.create()?.into();
+        raw.extend_from_slice(payload);
+        self.checksum = Bits::from(checksum(&raw).into(), 16);
+        Ok(())
+    }
+}
+
+impl Default for IcmpHdr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hdr for IcmpHdr {
+    fn create(&self) -> Result<Packet, PaError> {
+        let mut packet_data = Packet::new();
+        packet_data.append(self.icmp_type.clone().into());
+        packet_data.append(self.code.clone().into());
+        packet_data.append(self.checksum.clone().into());
+        packet_data.append(self.id.clone().into());
+        packet_data.append(self.seq.clone().into());
+        Ok(packet_data)
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        Self {
+            icmp_type: bytes.get_bin_slice(0, 8).into(),
+            code: bytes.get_bin_slice(8, 16).into(),
+            checksum: bytes.get_bin_slice(16, 32).into(),
+            id: bytes.get_bin_slice(32, 48).into(),
+            seq: bytes.get_bin_slice(48, 64).into(),
+        }
+    }
+
+    fn get(&self) -> Proto {
+        Proto::ICMP(self.clone())
+    }
+}
+
+impl PartialEq for IcmpHdr {
+    fn eq(&self, other: &Self) -> bool {
+        self.icmp_type == other.icmp_type
+            && self.code == other.code
+            && self.checksum == other.checksum
+            && self.id == other.id
+            && self.seq == other.seq
+    }
+}
+
+impl std::fmt::Debug for IcmpHdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(
+            format!(
+                "
+Type: {}
+Code: {}
+Checksum: {}
+Identifier: {}
+Sequence Number: {}",
+                self.icmp_type, self.code, self.checksum, self.id, self.seq,
+            )
+            .as_str(),
+        )
+    }
+}
+
+impl std::fmt::Display for IcmpHdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let packet_vec: Vec<u8> = self.create().unwrap().into();
+        f.write_str(format!("{:?}", packet_vec).as_str())
+    }
+}
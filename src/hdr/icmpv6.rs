@@ -0,0 +1,66 @@
+use crate::dstructs::Packet;
+use crate::error::PaError;
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+
+pub mod icmpv6_type {
+    pub const DEST_UNREACHABLE: u8 = 1;
+    pub const PACKET_TOO_BIG: u8 = 2;
+    pub const TIME_EXCEEDED: u8 = 3;
+    pub const ECHO_REQUEST: u8 = 128;
+    pub const ECHO_REPLY: u8 = 129;
+    pub const ROUTER_SOLICIT: u8 = 133;
+    pub const ROUTER_ADVERT: u8 = 134;
+    pub const NEIGHBOR_SOLICIT: u8 = 135;
+    pub const NEIGHBOR_ADVERT: u8 = 136;
+}
+
+/// ICMPv6 header according to [RFC 4443](https://datatracker.ietf.org/doc/html/rfc4443)
+///
+/// Only type, code and checksum are kept here, the message body follows as
+/// next layer. A `checksum` of 0 is computed over the IPv6 pseudo header
+/// when built in a `Pdu`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Icmpv6Hdr {
+    pub icmp_type: u8,
+    pub code: u8,
+    pub checksum: u16,
+}
+
+impl Icmpv6Hdr {
+    pub fn new(icmp_type: u8) -> Self {
+        Self {
+            icmp_type,
+            code: 0,
+            checksum: 0,
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        4
+    }
+}
+
+impl Hdr for Icmpv6Hdr {
+    fn create(&self) -> Result<Packet, PaError> {
+        let mut bytes = vec![self.icmp_type, self.code];
+        bytes.extend_from_slice(&self.checksum.to_be_bytes());
+        Ok(bytes.into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        match bytes.get(..4) {
+            Some(b) => Self {
+                icmp_type: b[0],
+                code: b[1],
+                checksum: u16::from_be_bytes([b[2], b[3]]),
+            },
+            None => Self::default(),
+        }
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Icmpv6(self.clone())
+    }
+}
@@ -3,7 +3,7 @@ use crate::dstructs::Packet;
 use crate::error::{ErrorType, PaError};
 use crate::hdr::Hdr;
 use crate::proto::Proto;
-use crate::utility::{ip_to_string, parse_ip};
+use crate::utility::{checksum, ip_to_string, parse_ip};
 
 #[path = "query/ipv4_query.rs"]
 mod ipv4_query;
@@ -13,6 +13,8 @@ pub mod ip_proto {
     pub const ICMP: u8 = 0x01;
     pub const TCP: u8 = 0x06;
     pub const UDP: u8 = 0x11;
+    pub const GRE: u8 = 0x2f;
+    pub const ICMPV6: u8 = 0x3a;
 }
 
 /// IPv4 header according to [RFC 791](https://datatracker.ietf.org/doc/html/rfc791)
@@ -40,8 +42,8 @@ impl IPv4Hdr {
             tos: Bits::from(0, 8),
             total_len: Bits::from(0, 16),
             id: Bits::from(0, 16),
-            flags: Bits::from(0, 2),
-            frag_offset: Bits::from(0, 14),
+            flags: Bits::from(0, 3),
+            frag_offset: Bits::from(0, 13),
             ttl: Bits::from(64, 8),
             proto: Bits::from(0, 8),
             hdr_checksum: Bits::from(0, 16),
@@ -61,8 +63,8 @@ impl IPv4Hdr {
             tos: Bits::from(0, 8),
             total_len: Bits::from(0, 16),
             id: Bits::from(0, 16),
-            flags: Bits::from(0, 2),
-            frag_offset: Bits::from(0, 14),
+            flags: Bits::from(0, 3),
+            frag_offset: Bits::from(0, 13),
             ttl: Bits::from(64, 8),
             proto: Bits::from(proto.into(), 8),
             hdr_checksum: Bits::from(0, 16),
@@ -92,6 +94,14 @@ impl IPv4Hdr {
     pub fn length(&self) -> usize {
         65535
     }
+
+    /// Recomputes `hdr_checksum` over the header as it is currently filled
+    pub fn set_checksum(&mut self) -> Result<(), PaError> {
+        self.hdr_checksum = Bits::from(0, 16);
+        let raw: Vec<u8> = self.create()?.into();
+        self.hdr_checksum = Bits::from(checksum(&raw).into(), 16);
+        Ok(())
+    }
 }
 
 impl Hdr for IPv4Hdr {
@@ -133,11 +143,11 @@ impl Hdr for IPv4Hdr {
         Self {
             ver: bytes.get_bin_slice(0, 4).into(),
             ihl: bytes.get_bin_slice(4, 8).into(),
-            tos: bytes.get_bin_slice(8, 14).into(),
-            total_len: bytes.get_bin_slice(14, 16).into(),
-            id: bytes.get_bin_slice(16, 32).into(),
-            flags: bytes.get_bin_slice(32, 48).into(),
-            frag_offset: bytes.get_bin_slice(48, 64).into(),
+            tos: bytes.get_bin_slice(8, 16).into(),
+            total_len: bytes.get_bin_slice(16, 32).into(),
+            id: bytes.get_bin_slice(32, 48).into(),
+            flags: bytes.get_bin_slice(48, 51).into(),
+            frag_offset: bytes.get_bin_slice(51, 64).into(),
             ttl: bytes.get_bin_slice(64, 72).into(),
             proto: bytes.get_bin_slice(72, 80).into(),
             hdr_checksum: bytes.get_bin_slice(80, 96).into(),
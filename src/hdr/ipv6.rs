@@ -0,0 +1,143 @@
+use crate::dstructs::Bits;
+use crate::dstructs::Packet;
+use crate::error::PaError;
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+use crate::utility::{ipv6_to_string, parse_ipv6};
+use std::convert::TryInto;
+
+/// IPv6 header according to [RFC 8200](https://datatracker.ietf.org/doc/html/rfc8200)
+#[derive(Clone)]
+pub struct IPv6Hdr {
+    pub ver: Bits,
+    pub traffic_class: Bits,
+    pub flow_label: Bits,
+    pub payload_len: Bits,
+    pub next_hdr: Bits,
+    pub hop_limit: Bits,
+    pub src_ip_addr: [u8; 16],
+    pub dst_ip_addr: [u8; 16],
+}
+
+impl IPv6Hdr {
+    pub fn new() -> Self {
+        Self {
+            ver: Bits::from(6, 4),
+            traffic_class: Bits::from(0, 8),
+            flow_label: Bits::from(0, 20),
+            payload_len: Bits::from(0, 16),
+            next_hdr: Bits::from(0, 8),
+            hop_limit: Bits::from(64, 8),
+            src_ip_addr: [0; 16],
+            dst_ip_addr: [0; 16],
+        }
+    }
+
+    pub fn from(
+        src_addr: impl ToString,
+        dst_addr: impl ToString,
+        next_hdr: u8,
+    ) -> Result<Self, PaError> {
+        let mut hdr = Self::new();
+        hdr.next_hdr = Bits::from(next_hdr.into(), 8);
+        hdr.src_ip_addr = parse_ipv6(src_addr)?;
+        hdr.dst_ip_addr = parse_ipv6(dst_addr)?;
+        Ok(hdr)
+    }
+
+    pub fn length(&self) -> usize {
+        40
+    }
+}
+
+impl Default for IPv6Hdr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hdr for IPv6Hdr {
+    fn create(&self) -> Result<Packet, PaError> {
+        let mut packet_data = Packet::new();
+        packet_data.append(self.ver.clone().into());
+        packet_data.append(self.traffic_class.clone().into());
+        packet_data.append(self.flow_label.clone().into());
+        packet_data.append(self.payload_len.clone().into());
+        packet_data.append(self.next_hdr.clone().into());
+        packet_data.append(self.hop_limit.clone().into());
+
+        for i in 0..16 {
+            packet_data.push(self.src_ip_addr[i]);
+        }
+
+        for i in 0..16 {
+            packet_data.push(self.dst_ip_addr[i]);
+        }
+
+        Ok(packet_data)
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        Self {
+            ver: bytes.get_bin_slice(0, 4).into(),
+            traffic_class: bytes.get_bin_slice(4, 12).into(),
+            flow_label: bytes.get_bin_slice(12, 32).into(),
+            payload_len: bytes.get_bin_slice(32, 48).into(),
+            next_hdr: bytes.get_bin_slice(48, 56).into(),
+            hop_limit: bytes.get_bin_slice(56, 64).into(),
+            src_ip_addr: bytes.get_slice(64, 192).try_into().unwrap(),
+            dst_ip_addr: bytes.get_slice(192, 320).try_into().unwrap(),
+        }
+    }
+
+    fn get(&self) -> Proto {
+        Proto::IPv6(self.clone())
+    }
+}
+
+impl PartialEq for IPv6Hdr {
+    fn eq(&self, other: &Self) -> bool {
+        self.ver == other.ver
+            && self.traffic_class == other.traffic_class
+            && self.flow_label == other.flow_label
+            && self.payload_len == other.payload_len
+            && self.next_hdr == other.next_hdr
+            && self.hop_limit == other.hop_limit
+            && self.src_ip_addr == other.src_ip_addr
+            && self.dst_ip_addr == other.dst_ip_addr
+    }
+}
+
+impl std::fmt::Debug for IPv6Hdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(
+            format!(
+                "
+Version: {}
+Traffic Class: {}
+Flow Label: {}
+Payload Length: {}
+Next Header: {}
+Hop Limit: {}
+Source IP Address: {}
+Destination IP Address: {}",
+                self.ver,
+                self.traffic_class,
+                self.flow_label,
+                self.payload_len,
+                self.next_hdr,
+                self.hop_limit,
+                ipv6_to_string(&self.src_ip_addr),
+                ipv6_to_string(&self.dst_ip_addr),
+            )
+            .as_str(),
+        )
+    }
+}
+
+impl std::fmt::Display for IPv6Hdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let packet_vec: Vec<u8> = self.create().unwrap().into();
+        f.write_str(format!("{:?}", packet_vec).as_str())
+    }
+}
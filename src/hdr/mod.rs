@@ -1,9 +1,39 @@
 mod arp;
+mod dhcp;
+mod dhcpv6;
+mod dns;
 mod eth;
+mod geneve;
+mod gre;
+mod icmp;
+mod icmpv6;
 mod ipv4;
+mod ipv6;
+mod mpls;
+mod ndp;
+mod netflow;
+mod raw;
+mod tcp;
 mod traits;
+mod udp;
+mod vxlan;
 
 pub use arp::*;
+pub use dhcp::*;
+pub use dhcpv6::*;
+pub use dns::*;
 pub use eth::*;
+pub use geneve::*;
+pub use gre::*;
+pub use icmp::*;
+pub use icmpv6::*;
 pub use ipv4::*;
+pub use ipv6::*;
+pub use mpls::*;
+pub use ndp::*;
+pub use netflow::*;
+pub use raw::*;
+pub use tcp::*;
 pub use traits::*;
+pub use udp::*;
+pub use vxlan::*;
@@ -0,0 +1,153 @@
+use crate::dstructs::Packet;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::{eth_type, gre_proto, Hdr};
+use crate::proto::Proto;
+
+#[path = "query/mpls_query.rs"]
+mod mpls_query;
+pub use mpls_query::*;
+
+/// Reserved labels of [RFC 3032](https://datatracker.ietf.org/doc/html/rfc3032)
+pub mod mpls_label {
+    pub const IPV4_EXPLICIT_NULL: u32 = 0;
+    pub const ROUTER_ALERT: u32 = 1;
+    pub const IPV6_EXPLICIT_NULL: u32 = 2;
+    pub const IMPLICIT_NULL: u32 = 3;
+}
+
+/// Entry of an MPLS label stack
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MplsLabel {
+    /// Label of 20 bits
+    pub label: u32,
+    /// Traffic class of 3 bits
+    pub tc: u8,
+    pub ttl: u8,
+}
+
+impl MplsLabel {
+    pub fn new(label: u32) -> Self {
+        Self {
+            label,
+            tc: 0,
+            ttl: 64,
+        }
+    }
+}
+
+/// MPLS label stack according to [RFC 3032](https://datatracker.ietf.org/doc/html/rfc3032),
+/// optionally followed by a pseudowire control word of
+/// [RFC 4385](https://datatracker.ietf.org/doc/html/rfc4385)
+///
+/// The bottom of stack bit is set on the last label when built and ends the
+/// stack when parsed. In a `Pdu` the labelled packet is kept as `inner`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mpls {
+    /// Labels from top to bottom of stack
+    pub labels: Vec<MplsLabel>,
+    pub control_word: Option<u32>,
+}
+
+impl Mpls {
+    pub fn new(labels: Vec<MplsLabel>) -> Self {
+        Self {
+            labels,
+            control_word: None,
+        }
+    }
+
+    /// Creates stack for pseudowire `label` under transport `tunnel` label,
+    /// followed by an empty control word
+    pub fn pseudowire(tunnel: u32, label: u32) -> Self {
+        Self {
+            labels: vec![MplsLabel::new(tunnel), MplsLabel::new(label)],
+            control_word: Some(0),
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        4 * self.labels.len() + self.control_word.map_or(0, |_| 4)
+    }
+
+    /// Guesses EtherType of `payload` following this stack: IPv4 and IPv6
+    /// from explicit null labels or the IP version, else Ethernet
+    pub fn payload_type(&self, payload: &[u8]) -> u16 {
+        let bottom = self.labels.last().map(|entry| entry.label);
+        match (self.control_word, bottom, payload.first().map(|b| b >> 4)) {
+            (Some(_), _, _) => gre_proto::TEB,
+            (None, Some(mpls_label::IPV4_EXPLICIT_NULL), _) | (None, _, Some(4)) => {
+                eth_type::IPv4 as u16
+            }
+            (None, Some(mpls_label::IPV6_EXPLICIT_NULL), _) | (None, _, Some(6)) => {
+                eth_type::IPv6 as u16
+            }
+            _ => gre_proto::TEB,
+        }
+    }
+
+    /// Parses label stack from `bytes`
+    ///
+    /// A first nibble of 0 after the stack is taken as pseudowire control
+    /// word, unless explicit null labels announce IP.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        let mut mpls = Self::default();
+        for entry in bytes.chunks(4) {
+            if entry.len() < 4 {
+                break;
+            }
+            let word = u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            mpls.labels.push(MplsLabel {
+                label: word >> 12,
+                tc: (word >> 9) as u8 & 0x07,
+                ttl: word as u8,
+            });
+            if word & 0x100 != 0 {
+                let rest = &bytes[mpls.length()..];
+                let ip = matches!(
+                    word >> 12,
+                    mpls_label::IPV4_EXPLICIT_NULL | mpls_label::IPV6_EXPLICIT_NULL
+                );
+                if !ip && rest.len() >= 4 && rest[0] >> 4 == 0 {
+                    mpls.control_word =
+                        Some(u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]));
+                }
+                return Ok(mpls);
+            }
+        }
+        Err(PaError::new(
+            "MPLS label stack without bottom",
+            ErrorType::ParseError,
+        ))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.length());
+        for (i, entry) in self.labels.iter().enumerate() {
+            let bos = (i + 1 == self.labels.len()) as u32;
+            let word = (entry.label & 0xf_ffff) << 12
+                | (entry.tc as u32 & 0x07) << 9
+                | bos << 8
+                | entry.ttl as u32;
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        if let Some(control_word) = self.control_word {
+            bytes.extend_from_slice(&control_word.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+impl Hdr for Mpls {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes().into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Mpls(self.clone())
+    }
+}
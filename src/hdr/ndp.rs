@@ -0,0 +1,332 @@
+use crate::dstructs::Packet;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+
+/// Option types of [RFC 4861](https://datatracker.ietf.org/doc/html/rfc4861)
+/// and [RFC 8106](https://datatracker.ietf.org/doc/html/rfc8106)
+pub mod ndp_opt {
+    pub const SOURCE_LINK_ADDR: u8 = 1;
+    pub const TARGET_LINK_ADDR: u8 = 2;
+    pub const PREFIX_INFO: u8 = 3;
+    pub const MTU: u8 = 5;
+    pub const RDNSS: u8 = 25;
+}
+
+/// All-nodes multicast address `ff02::1`
+pub const ALL_NODES: [u8; 16] = [0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+
+/// All-routers multicast address `ff02::2`
+pub const ALL_ROUTERS: [u8; 16] = [0xff, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+/// Returns Ethernet address IPv6 multicast address `ip` is sent to
+pub fn multicast_mac(ip: [u8; 16]) -> [u8; 6] {
+    [0x33, 0x33, ip[12], ip[13], ip[14], ip[15]]
+}
+
+/// Returns link-local address formed from `mac` through modified EUI-64
+pub fn link_local(mac: [u8; 6]) -> [u8; 16] {
+    let mut ip = [0; 16];
+    ip[..2].copy_from_slice(&[0xfe, 0x80]);
+    ip[8..].copy_from_slice(&[
+        mac[0] ^ 2,
+        mac[1],
+        mac[2],
+        0xff,
+        0xfe,
+        mac[3],
+        mac[4],
+        mac[5],
+    ]);
+    ip
+}
+
+fn parse_error(msg: &str) -> PaError {
+    PaError::new(msg, ErrorType::ParseError)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn addr_at(bytes: &[u8], at: usize) -> [u8; 16] {
+    let mut addr = [0; 16];
+    addr.copy_from_slice(&bytes[at..at + 16]);
+    addr
+}
+
+/// Prefix advertised in a Router Advertisement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrefixInfo {
+    pub prefix: [u8; 16],
+    pub prefix_len: u8,
+    /// Hosts may reach addresses of the prefix directly
+    pub on_link: bool,
+    /// Hosts may form addresses of the prefix through SLAAC
+    pub autonomous: bool,
+    /// Valid lifetime in seconds
+    pub valid: u32,
+    /// Preferred lifetime in seconds
+    pub preferred: u32,
+}
+
+impl PrefixInfo {
+    /// Creates on-link prefix usable for SLAAC, valid for a day and
+    /// preferred for four hours
+    pub fn new(prefix: [u8; 16], prefix_len: u8) -> Self {
+        Self {
+            prefix,
+            prefix_len,
+            on_link: true,
+            autonomous: true,
+            valid: 86400,
+            preferred: 14400,
+        }
+    }
+}
+
+/// Option of a Neighbor Discovery message
+///
+/// Options of known type whose length doesn't fit their type are kept as
+/// `Raw`, with data following type and length.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NdpOption {
+    SourceLinkAddr([u8; 6]),
+    TargetLinkAddr([u8; 6]),
+    Prefix(PrefixInfo),
+    Mtu(u32),
+    /// Recursive DNS servers with their lifetime in seconds
+    Rdnss {
+        lifetime: u32,
+        servers: Vec<[u8; 16]>,
+    },
+    Raw {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl NdpOption {
+    pub fn kind(&self) -> u8 {
+        match self {
+            NdpOption::SourceLinkAddr(_) => ndp_opt::SOURCE_LINK_ADDR,
+            NdpOption::TargetLinkAddr(_) => ndp_opt::TARGET_LINK_ADDR,
+            NdpOption::Prefix(_) => ndp_opt::PREFIX_INFO,
+            NdpOption::Mtu(_) => ndp_opt::MTU,
+            NdpOption::Rdnss { .. } => ndp_opt::RDNSS,
+            NdpOption::Raw { kind, .. } => *kind,
+        }
+    }
+
+    /// Parses `data` following type and length
+    fn parse(kind: u8, data: &[u8]) -> Self {
+        let mac = || {
+            let mut mac = [0; 6];
+            mac.copy_from_slice(&data[..6]);
+            mac
+        };
+        match (kind, data.len()) {
+            (ndp_opt::SOURCE_LINK_ADDR, 6) => NdpOption::SourceLinkAddr(mac()),
+            (ndp_opt::TARGET_LINK_ADDR, 6) => NdpOption::TargetLinkAddr(mac()),
+            (ndp_opt::PREFIX_INFO, 30) => NdpOption::Prefix(PrefixInfo {
+                prefix_len: data[0],
+                on_link: data[1] & 0x80 != 0,
+                autonomous: data[1] & 0x40 != 0,
+                valid: u32_at(data, 2),
+                preferred: u32_at(data, 6),
+                prefix: addr_at(data, 14),
+            }),
+            (ndp_opt::MTU, 6) => NdpOption::Mtu(u32_at(data, 2)),
+            (ndp_opt::RDNSS, len) if len >= 22 && (len - 6).is_multiple_of(16) => {
+                NdpOption::Rdnss {
+                    lifetime: u32_at(data, 2),
+                    servers: (6..len).step_by(16).map(|at| addr_at(data, at)).collect(),
+                }
+            }
+            _ => NdpOption::Raw {
+                kind,
+                data: data.to_vec(),
+            },
+        }
+    }
+
+    /// Appends option to `bytes`, padded to a multiple of 8 bytes
+    fn write(&self, bytes: &mut Vec<u8>) -> Result<(), PaError> {
+        let start = bytes.len();
+        bytes.extend_from_slice(&[self.kind(), 0]);
+        match self {
+            NdpOption::SourceLinkAddr(mac) | NdpOption::TargetLinkAddr(mac) => {
+                bytes.extend_from_slice(mac)
+            }
+            NdpOption::Prefix(info) => {
+                let flags = (info.on_link as u8) << 7 | (info.autonomous as u8) << 6;
+                bytes.extend_from_slice(&[info.prefix_len, flags]);
+                bytes.extend_from_slice(&info.valid.to_be_bytes());
+                bytes.extend_from_slice(&info.preferred.to_be_bytes());
+                bytes.extend_from_slice(&[0; 4]);
+                bytes.extend_from_slice(&info.prefix);
+            }
+            NdpOption::Mtu(mtu) => {
+                bytes.extend_from_slice(&[0, 0]);
+                bytes.extend_from_slice(&mtu.to_be_bytes());
+            }
+            NdpOption::Rdnss { lifetime, servers } => {
+                bytes.extend_from_slice(&[0, 0]);
+                bytes.extend_from_slice(&lifetime.to_be_bytes());
+                servers
+                    .iter()
+                    .for_each(|server| bytes.extend_from_slice(server));
+            }
+            NdpOption::Raw { data, .. } => bytes.extend_from_slice(data),
+        }
+        let len = (bytes.len() - start).div_ceil(8);
+        if len > 255 {
+            return Err(PaError::new("NDP option too long", ErrorType::LengthError));
+        }
+        bytes.resize(start + len * 8, 0);
+        bytes[start + 1] = len as u8;
+        Ok(())
+    }
+}
+
+fn parse_options(bytes: &[u8]) -> Result<Vec<NdpOption>, PaError> {
+    let mut options = Vec::new();
+    let mut at = 0;
+    while at < bytes.len() {
+        let len = match bytes.get(at + 1) {
+            Some(0) => return Err(parse_error("NDP option of length 0")),
+            Some(len) => *len as usize * 8,
+            None => return Err(parse_error("NDP option cut short")),
+        };
+        let data = match bytes.get(at + 2..at + len) {
+            Some(data) => data,
+            None => return Err(parse_error("NDP option cut short")),
+        };
+        options.push(NdpOption::parse(bytes[at], data));
+        at += len;
+    }
+    Ok(options)
+}
+
+fn write_options(options: &[NdpOption], bytes: &mut Vec<u8>) -> Result<(), PaError> {
+    options.iter().try_for_each(|option| option.write(bytes))
+}
+
+/// Router Solicitation following its ICMPv6 header
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RouterSolicit {
+    pub options: Vec<NdpOption>,
+}
+
+impl RouterSolicit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        match bytes.get(4..) {
+            Some(options) => Ok(Self {
+                options: parse_options(options)?,
+            }),
+            None => Err(parse_error("Router Solicitation cut short")),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PaError> {
+        let mut bytes = vec![0; 4];
+        write_options(&self.options, &mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Hdr for RouterSolicit {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes()?.into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::RouterSolicit(self.clone())
+    }
+}
+
+/// Router Advertisement following its ICMPv6 header, according to
+/// [RFC 4861](https://datatracker.ietf.org/doc/html/rfc4861)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RouterAdvert {
+    /// Hop limit hosts should use, 0 for unspecified
+    pub hop_limit: u8,
+    /// Addresses are available through DHCPv6
+    pub managed: bool,
+    /// Other configuration is available through DHCPv6
+    pub other: bool,
+    /// Seconds the router may be used as default router, 0 for never
+    pub lifetime: u16,
+    /// Reachable time in milliseconds, 0 for unspecified
+    pub reachable: u32,
+    /// Retransmission timer in milliseconds, 0 for unspecified
+    pub retrans: u32,
+    pub options: Vec<NdpOption>,
+}
+
+impl RouterAdvert {
+    pub fn new() -> Self {
+        Self {
+            hop_limit: 64,
+            lifetime: 1800,
+            ..Self::default()
+        }
+    }
+
+    /// Advertised prefixes
+    pub fn prefixes(&self) -> impl Iterator<Item = &PrefixInfo> {
+        self.options.iter().filter_map(|option| match option {
+            NdpOption::Prefix(info) => Some(info),
+            _ => None,
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        if bytes.len() < 12 {
+            return Err(parse_error("Router Advertisement cut short"));
+        }
+        Ok(Self {
+            hop_limit: bytes[0],
+            managed: bytes[1] & 0x80 != 0,
+            other: bytes[1] & 0x40 != 0,
+            lifetime: u16::from_be_bytes([bytes[2], bytes[3]]),
+            reachable: u32_at(bytes, 4),
+            retrans: u32_at(bytes, 8),
+            options: parse_options(&bytes[12..])?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, PaError> {
+        let flags = (self.managed as u8) << 7 | (self.other as u8) << 6;
+        let mut bytes = vec![self.hop_limit, flags];
+        bytes.extend_from_slice(&self.lifetime.to_be_bytes());
+        bytes.extend_from_slice(&self.reachable.to_be_bytes());
+        bytes.extend_from_slice(&self.retrans.to_be_bytes());
+        write_options(&self.options, &mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Hdr for RouterAdvert {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes()?.into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::RouterAdvert(self.clone())
+    }
+}
@@ -0,0 +1,638 @@
+use crate::dstructs::Packet;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+
+/// UDP ports flow exports are usually sent to
+pub mod netflow_port {
+    pub const NETFLOW: u16 = 2055;
+    pub const NETFLOW_ALT: u16 = 9995;
+    pub const NETFLOW_ALT2: u16 = 9996;
+    pub const IPFIX: u16 = 4739;
+}
+
+/// Information element ids shared by NetFlow v9 and IPFIX
+pub mod ie {
+    pub const IN_BYTES: u16 = 1;
+    pub const IN_PKTS: u16 = 2;
+    pub const PROTOCOL: u16 = 4;
+    pub const SRC_TOS: u16 = 5;
+    pub const TCP_FLAGS: u16 = 6;
+    pub const L4_SRC_PORT: u16 = 7;
+    pub const IPV4_SRC_ADDR: u16 = 8;
+    pub const SRC_MASK: u16 = 9;
+    pub const INPUT_SNMP: u16 = 10;
+    pub const L4_DST_PORT: u16 = 11;
+    pub const IPV4_DST_ADDR: u16 = 12;
+    pub const DST_MASK: u16 = 13;
+    pub const OUTPUT_SNMP: u16 = 14;
+    pub const IPV4_NEXT_HOP: u16 = 15;
+    pub const SRC_AS: u16 = 16;
+    pub const DST_AS: u16 = 17;
+    pub const LAST_SWITCHED: u16 = 21;
+    pub const FIRST_SWITCHED: u16 = 22;
+    pub const IPV6_SRC_ADDR: u16 = 27;
+    pub const IPV6_DST_ADDR: u16 = 28;
+    pub const SRC_VLAN: u16 = 58;
+    pub const FLOW_END_REASON: u16 = 136;
+    pub const FLOW_START_MILLISECONDS: u16 = 152;
+    pub const FLOW_END_MILLISECONDS: u16 = 153;
+}
+
+/// Field length marking a variable length IPFIX field
+pub const VARIABLE_LEN: u16 = 65535;
+
+fn parse_error(msg: &str) -> PaError {
+    PaError::new(msg, ErrorType::ParseError)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// One flow of a NetFlow v5 export
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct V5Record {
+    pub src_addr: [u8; 4],
+    pub dst_addr: [u8; 4],
+    pub next_hop: [u8; 4],
+    pub input: u16,
+    pub output: u16,
+    pub packets: u32,
+    pub bytes: u32,
+    /// Uptime in milliseconds at first packet
+    pub first: u32,
+    /// Uptime in milliseconds at last packet
+    pub last: u32,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub tcp_flags: u8,
+    pub proto: u8,
+    pub tos: u8,
+    pub src_as: u16,
+    pub dst_as: u16,
+    pub src_mask: u8,
+    pub dst_mask: u8,
+}
+
+impl V5Record {
+    const LEN: usize = 48;
+
+    fn create(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.src_addr);
+        data.extend_from_slice(&self.dst_addr);
+        data.extend_from_slice(&self.next_hop);
+        data.extend_from_slice(&self.input.to_be_bytes());
+        data.extend_from_slice(&self.output.to_be_bytes());
+        data.extend_from_slice(&self.packets.to_be_bytes());
+        data.extend_from_slice(&self.bytes.to_be_bytes());
+        data.extend_from_slice(&self.first.to_be_bytes());
+        data.extend_from_slice(&self.last.to_be_bytes());
+        data.extend_from_slice(&self.src_port.to_be_bytes());
+        data.extend_from_slice(&self.dst_port.to_be_bytes());
+        data.extend_from_slice(&[0, self.tcp_flags, self.proto, self.tos]);
+        data.extend_from_slice(&self.src_as.to_be_bytes());
+        data.extend_from_slice(&self.dst_as.to_be_bytes());
+        data.extend_from_slice(&[self.src_mask, self.dst_mask, 0, 0]);
+    }
+
+    fn parse(bytes: &[u8]) -> Self {
+        let addr = |at: usize| [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+        Self {
+            src_addr: addr(0),
+            dst_addr: addr(4),
+            next_hop: addr(8),
+            input: u16_at(bytes, 12),
+            output: u16_at(bytes, 14),
+            packets: u32_at(bytes, 16),
+            bytes: u32_at(bytes, 20),
+            first: u32_at(bytes, 24),
+            last: u32_at(bytes, 28),
+            src_port: u16_at(bytes, 32),
+            dst_port: u16_at(bytes, 34),
+            tcp_flags: bytes[37],
+            proto: bytes[38],
+            tos: bytes[39],
+            src_as: u16_at(bytes, 40),
+            dst_as: u16_at(bytes, 42),
+            src_mask: bytes[44],
+            dst_mask: bytes[45],
+        }
+    }
+}
+
+/// NetFlow v5 export, a fixed header followed by fixed size records
+///
+/// `count` of zero is written as the number of records, anything else is
+/// written as is.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NetFlowV5 {
+    pub count: u16,
+    pub sys_uptime: u32,
+    pub unix_secs: u32,
+    pub unix_nsecs: u32,
+    pub flow_sequence: u32,
+    pub engine_type: u8,
+    pub engine_id: u8,
+    pub sampling_interval: u16,
+    pub records: Vec<V5Record>,
+}
+
+impl NetFlowV5 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses export, records cut short are dropped
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        if bytes.len() < 24 || u16_at(bytes, 0) != 5 {
+            return Err(parse_error("Not a NetFlow v5 export"));
+        }
+        Ok(Self {
+            count: u16_at(bytes, 2),
+            sys_uptime: u32_at(bytes, 4),
+            unix_secs: u32_at(bytes, 8),
+            unix_nsecs: u32_at(bytes, 12),
+            flow_sequence: u32_at(bytes, 16),
+            engine_type: bytes[20],
+            engine_id: bytes[21],
+            sampling_interval: u16_at(bytes, 22),
+            records: bytes[24..]
+                .chunks_exact(V5Record::LEN)
+                .map(V5Record::parse)
+                .collect(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count = match self.count {
+            0 => self.records.len() as u16,
+            count => count,
+        };
+        let mut data = Vec::with_capacity(24 + self.records.len() * V5Record::LEN);
+        data.extend_from_slice(&5u16.to_be_bytes());
+        data.extend_from_slice(&count.to_be_bytes());
+        data.extend_from_slice(&self.sys_uptime.to_be_bytes());
+        data.extend_from_slice(&self.unix_secs.to_be_bytes());
+        data.extend_from_slice(&self.unix_nsecs.to_be_bytes());
+        data.extend_from_slice(&self.flow_sequence.to_be_bytes());
+        data.extend_from_slice(&[self.engine_type, self.engine_id]);
+        data.extend_from_slice(&self.sampling_interval.to_be_bytes());
+        for record in self.records.iter() {
+            record.create(&mut data);
+        }
+        data
+    }
+}
+
+impl Hdr for NetFlowV5 {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes().into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::NetFlowV5(self.clone())
+    }
+}
+
+/// Field of a template, `enterprise` is only carried by IPFIX
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FieldSpec {
+    pub id: u16,
+    /// Length in bytes, `VARIABLE_LEN` for variable length IPFIX fields
+    pub len: u16,
+    pub enterprise: Option<u32>,
+}
+
+impl FieldSpec {
+    pub fn new(id: u16, len: u16) -> Self {
+        Self {
+            id,
+            len,
+            enterprise: None,
+        }
+    }
+
+    /// Creates enterprise specific field, such as a reverse IPFIX element
+    /// of enterprise 29305
+    pub fn enterprise(id: u16, len: u16, enterprise: u32) -> Self {
+        Self {
+            id,
+            len,
+            enterprise: Some(enterprise),
+        }
+    }
+}
+
+/// Template or options template of NetFlow v9 or IPFIX
+///
+/// A template without fields withdraws template `id` in IPFIX, and with
+/// `id` equal to the id of its set withdraws every template of the set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    pub id: u16,
+    /// Number of leading `fields` which are scope fields, options
+    /// templates only
+    pub scope_fields: u16,
+    pub fields: Vec<FieldSpec>,
+}
+
+impl Template {
+    pub fn new(id: u16, fields: Vec<FieldSpec>) -> Self {
+        Self {
+            id,
+            scope_fields: 0,
+            fields,
+        }
+    }
+
+    /// Creates template withdrawal of template `id`
+    pub fn withdrawal(id: u16) -> Self {
+        Self::new(id, Vec::new())
+    }
+
+    /// Returns `true` if this withdraws template `id`
+    pub fn is_withdrawal(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Encodes one data record holding `values` in order of `fields`
+    pub fn encode(&self, values: &[&[u8]]) -> Result<Vec<u8>, PaError> {
+        if values.len() != self.fields.len() {
+            return Err(PaError::new(
+                "Number of values doesn't match template",
+                ErrorType::LengthError,
+            ));
+        }
+        let mut data = Vec::new();
+        for (field, value) in self.fields.iter().zip(values) {
+            if field.len != VARIABLE_LEN {
+                if value.len() != field.len as usize {
+                    return Err(PaError::new(
+                        format!("Value of field {} must be {} bytes", field.id, field.len),
+                        ErrorType::LengthError,
+                    ));
+                }
+            } else if value.len() < 255 {
+                data.push(value.len() as u8);
+            } else if value.len() <= u16::MAX as usize {
+                data.push(255);
+                data.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            } else {
+                return Err(PaError::new(
+                    "Variable length value too long",
+                    ErrorType::LengthError,
+                ));
+            }
+            data.extend_from_slice(value);
+        }
+        Ok(data)
+    }
+
+    /// Decodes data records of data set, trailing bytes too short for a
+    /// record are taken as padding
+    pub fn decode(&self, mut data: &[u8]) -> Vec<Vec<(FieldSpec, Vec<u8>)>> {
+        let min_len: usize = self
+            .fields
+            .iter()
+            .map(|field| match field.len {
+                VARIABLE_LEN => 1,
+                len => len as usize,
+            })
+            .sum();
+        let mut records = Vec::new();
+        if min_len == 0 {
+            return records;
+        }
+        'records: while data.len() >= min_len {
+            let mut record = Vec::with_capacity(self.fields.len());
+            for field in self.fields.iter() {
+                let mut len = field.len as usize;
+                if field.len == VARIABLE_LEN {
+                    let (prefix, value_len) = match data.first() {
+                        Some(255) if data.len() >= 3 => (3, u16_at(data, 1) as usize),
+                        Some(&short) if short < 255 => (1, short as usize),
+                        _ => break 'records,
+                    };
+                    data = &data[prefix..];
+                    len = value_len;
+                }
+                if data.len() < len {
+                    break 'records;
+                }
+                record.push((*field, data[..len].to_vec()));
+                data = &data[len..];
+            }
+            records.push(record);
+        }
+        records
+    }
+}
+
+/// Set of a NetFlow v9 or IPFIX message
+///
+/// Set ids of templates differ between versions and are filled in when the
+/// message is created.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FlowSet {
+    Template(Vec<Template>),
+    OptionsTemplate(Vec<Template>),
+    /// Records of template `id`, or of any other set id not known here
+    Data {
+        id: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl FlowSet {
+    fn create(&self, ipfix: bool, data: &mut Vec<u8>) {
+        let start = data.len();
+        let (id, templates, options): (u16, _, _) = match self {
+            FlowSet::Template(templates) => (if ipfix { 2 } else { 0 }, templates, false),
+            FlowSet::OptionsTemplate(templates) => (if ipfix { 3 } else { 1 }, templates, true),
+            FlowSet::Data { id, data: records } => {
+                data.extend_from_slice(&id.to_be_bytes());
+                data.extend_from_slice(&((4 + records.len()) as u16).to_be_bytes());
+                data.extend_from_slice(records);
+                return;
+            }
+        };
+        data.extend_from_slice(&id.to_be_bytes());
+        data.extend_from_slice(&[0, 0]);
+        for template in templates {
+            data.extend_from_slice(&template.id.to_be_bytes());
+            let count = template.fields.len() as u16;
+            let scope = template.scope_fields.min(count);
+            match (options, ipfix) {
+                (true, false) => {
+                    // Scope and option lengths are given in bytes
+                    data.extend_from_slice(&(scope * 4).to_be_bytes());
+                    data.extend_from_slice(&((count - scope) * 4).to_be_bytes());
+                }
+                (true, true) if count > 0 => {
+                    data.extend_from_slice(&count.to_be_bytes());
+                    data.extend_from_slice(&scope.to_be_bytes());
+                }
+                _ => data.extend_from_slice(&count.to_be_bytes()),
+            }
+            for field in template.fields.iter() {
+                match (field.enterprise, ipfix) {
+                    (Some(enterprise), true) => {
+                        data.extend_from_slice(&(field.id | 0x8000).to_be_bytes());
+                        data.extend_from_slice(&field.len.to_be_bytes());
+                        data.extend_from_slice(&enterprise.to_be_bytes());
+                    }
+                    _ => {
+                        data.extend_from_slice(&field.id.to_be_bytes());
+                        data.extend_from_slice(&field.len.to_be_bytes());
+                    }
+                }
+            }
+        }
+        if options && !ipfix {
+            // NetFlow v9 pads options template sets to 32 bits
+            while !(data.len() - start).is_multiple_of(4) {
+                data.push(0);
+            }
+        }
+        let len = (data.len() - start) as u16;
+        data[start + 2..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+
+    fn parse_fields(
+        body: &[u8],
+        at: &mut usize,
+        count: usize,
+        ipfix: bool,
+    ) -> Option<Vec<FieldSpec>> {
+        let mut fields = Vec::with_capacity(count);
+        for _ in 0..count {
+            if body.len() < *at + 4 {
+                return None;
+            }
+            let id = u16_at(body, *at);
+            let len = u16_at(body, *at + 2);
+            *at += 4;
+            if ipfix && id & 0x8000 != 0 {
+                if body.len() < *at + 4 {
+                    return None;
+                }
+                fields.push(FieldSpec::enterprise(id & 0x7fff, len, u32_at(body, *at)));
+                *at += 4;
+            } else {
+                fields.push(FieldSpec::new(id, len));
+            }
+        }
+        Some(fields)
+    }
+
+    fn parse(id: u16, body: &[u8], ipfix: bool) -> Result<Self, PaError> {
+        let (template_id, options_id) = if ipfix { (2, 3) } else { (0, 1) };
+        if id != template_id && id != options_id {
+            return Ok(FlowSet::Data {
+                id,
+                data: body.to_vec(),
+            });
+        }
+        let options = id == options_id;
+        let mut templates = Vec::new();
+        let mut at = 0;
+        while body.len() >= at + 4 {
+            let template = u16_at(body, at);
+            let count = u16_at(body, at + 2);
+            // Padding, as template ids start at 256
+            if template < 256 && !(ipfix && template == id) {
+                break;
+            }
+            at += 4;
+            let (scope, count) = match (options, ipfix) {
+                (true, false) => {
+                    if body.len() < at + 2 {
+                        return Err(parse_error("Options template cut short"));
+                    }
+                    let option_len = u16_at(body, at) as usize;
+                    at += 2;
+                    ((count / 4), count as usize / 4 + option_len / 4)
+                }
+                (true, true) if count > 0 => {
+                    if body.len() < at + 2 {
+                        return Err(parse_error("Options template cut short"));
+                    }
+                    let scope = u16_at(body, at);
+                    at += 2;
+                    (scope, count as usize)
+                }
+                _ => (0, count as usize),
+            };
+            let fields = Self::parse_fields(body, &mut at, count, ipfix)
+                .ok_or_else(|| parse_error("Template cut short"))?;
+            templates.push(Template {
+                id: template,
+                scope_fields: scope,
+                fields,
+            });
+        }
+        Ok(if options {
+            FlowSet::OptionsTemplate(templates)
+        } else {
+            FlowSet::Template(templates)
+        })
+    }
+}
+
+/// Splits `bytes` after message header into sets
+fn parse_sets(bytes: &[u8], ipfix: bool) -> Result<Vec<FlowSet>, PaError> {
+    let mut sets = Vec::new();
+    let mut at = 0;
+    while bytes.len() >= at + 4 {
+        let id = u16_at(bytes, at);
+        let len = u16_at(bytes, at + 2) as usize;
+        if len < 4 || bytes.len() < at + len {
+            return Err(parse_error("Set length exceeds message"));
+        }
+        sets.push(FlowSet::parse(id, &bytes[at + 4..at + len], ipfix)?);
+        at += len;
+    }
+    Ok(sets)
+}
+
+/// NetFlow v9 export according to [RFC 3954](https://datatracker.ietf.org/doc/html/rfc3954)
+///
+/// `count` is the number of template and data records, which can't be
+/// known for data sets, so it is written as is.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NetFlowV9 {
+    pub count: u16,
+    pub sys_uptime: u32,
+    pub unix_secs: u32,
+    pub sequence: u32,
+    pub source_id: u32,
+    pub sets: Vec<FlowSet>,
+}
+
+impl NetFlowV9 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        if bytes.len() < 20 || u16_at(bytes, 0) != 9 {
+            return Err(parse_error("Not a NetFlow v9 export"));
+        }
+        Ok(Self {
+            count: u16_at(bytes, 2),
+            sys_uptime: u32_at(bytes, 4),
+            unix_secs: u32_at(bytes, 8),
+            sequence: u32_at(bytes, 12),
+            source_id: u32_at(bytes, 16),
+            sets: parse_sets(&bytes[20..], false)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&9u16.to_be_bytes());
+        data.extend_from_slice(&self.count.to_be_bytes());
+        data.extend_from_slice(&self.sys_uptime.to_be_bytes());
+        data.extend_from_slice(&self.unix_secs.to_be_bytes());
+        data.extend_from_slice(&self.sequence.to_be_bytes());
+        data.extend_from_slice(&self.source_id.to_be_bytes());
+        for set in self.sets.iter() {
+            set.create(false, &mut data);
+        }
+        data
+    }
+}
+
+impl Hdr for NetFlowV9 {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes().into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::NetFlowV9(self.clone())
+    }
+}
+
+/// IPFIX message according to [RFC 7011](https://datatracker.ietf.org/doc/html/rfc7011)
+///
+/// `length` of zero is written as the length of the message, anything else
+/// is written as is.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ipfix {
+    pub length: u16,
+    pub export_time: u32,
+    pub sequence: u32,
+    pub domain_id: u32,
+    pub sets: Vec<FlowSet>,
+}
+
+impl Ipfix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses message, bytes past `length` are ignored
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        if bytes.len() < 16 || u16_at(bytes, 0) != 10 {
+            return Err(parse_error("Not an IPFIX message"));
+        }
+        let length = u16_at(bytes, 2);
+        if (length as usize) < 16 || length as usize > bytes.len() {
+            return Err(parse_error("IPFIX length exceeds message"));
+        }
+        Ok(Self {
+            length,
+            export_time: u32_at(bytes, 4),
+            sequence: u32_at(bytes, 8),
+            domain_id: u32_at(bytes, 12),
+            sets: parse_sets(&bytes[16..length as usize], true)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&10u16.to_be_bytes());
+        data.extend_from_slice(&self.length.to_be_bytes());
+        data.extend_from_slice(&self.export_time.to_be_bytes());
+        data.extend_from_slice(&self.sequence.to_be_bytes());
+        data.extend_from_slice(&self.domain_id.to_be_bytes());
+        for set in self.sets.iter() {
+            set.create(true, &mut data);
+        }
+        if self.length == 0 {
+            let len = data.len() as u16;
+            data[2..4].copy_from_slice(&len.to_be_bytes());
+        }
+        data
+    }
+}
+
+impl Hdr for Ipfix {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes().into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Ipfix(self.clone())
+    }
+}
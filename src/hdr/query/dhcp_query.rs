@@ -0,0 +1,82 @@
+use crate::hdr::Dhcp;
+use crate::{debug, proto::Proto, Pdu};
+
+macro_rules! ifeq {
+    ($lhs:expr, $rhs:expr) => {
+        if let Some(lhs) = $lhs {
+            if lhs != $rhs {
+                return false;
+            }
+        }
+    };
+}
+
+/// Used as query of finding particular DHCP messages
+///
+/// Members of structs are `Option<_>`
+/// * `None` - will match ANY data.
+/// * `Some(data)` - will match only to data similar to data.
+///
+/// `msg_type` never matches plain BOOTP messages.
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
+pub struct DhcpQuery {
+    pub op: Option<u8>,
+    pub xid: Option<u32>,
+    pub msg_type: Option<u8>,
+    pub chaddr: Option<[u8; 6]>,
+    pub giaddr: Option<[u8; 4]>,
+}
+
+impl DhcpQuery {
+    pub fn new() -> Self {
+        Self {
+            op: None,
+            xid: None,
+            msg_type: None,
+            chaddr: None,
+            giaddr: None,
+        }
+    }
+
+    /// Matches messages of type `msg_type`, e.g. `dhcp_msg::DISCOVER`
+    pub fn from(msg_type: u8) -> Self {
+        Self {
+            msg_type: Some(msg_type),
+            ..Self::new()
+        }
+    }
+}
+
+impl Default for DhcpQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq<Dhcp> for DhcpQuery {
+    fn eq(&self, rhs: &Dhcp) -> bool {
+        ifeq!(self.op, rhs.op);
+        ifeq!(self.xid, rhs.xid);
+        ifeq!(self.msg_type.map(Some), rhs.msg_type());
+        ifeq!(self.chaddr, rhs.mac());
+        ifeq!(self.giaddr, rhs.giaddr);
+        true
+    }
+}
+
+impl PartialEq<Pdu> for DhcpQuery {
+    fn eq(&self, other: &Pdu) -> bool {
+        if let Some(Proto::Dhcp(dhcp)) = other.headers.get(&7) {
+            debug!("DHCP message found in PDU Group");
+            if self == dhcp {
+                debug!("DHCP message matched with DHCP Query");
+                true
+            } else {
+                debug!("DHCP message not matched with DHCP Query");
+                false
+            }
+        } else {
+            false
+        }
+    }
+}
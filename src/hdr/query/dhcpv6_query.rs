@@ -0,0 +1,71 @@
+use crate::hdr::Dhcpv6;
+use crate::{debug, proto::Proto, Pdu};
+
+macro_rules! ifeq {
+    ($lhs:expr, $rhs:expr) => {
+        if let Some(lhs) = $lhs {
+            if lhs != $rhs {
+                return false;
+            }
+        }
+    };
+}
+
+/// Used as query of finding particular DHCPv6 messages
+///
+/// Members of structs are `Option<_>`
+/// * `None` - will match ANY data.
+/// * `Some(data)` - will match only to data similar to data.
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
+pub struct Dhcpv6Query {
+    pub msg_type: Option<u8>,
+    pub xid: Option<u32>,
+}
+
+impl Dhcpv6Query {
+    pub fn new() -> Self {
+        Self {
+            msg_type: None,
+            xid: None,
+        }
+    }
+
+    /// Matches messages of type `msg_type`, e.g. `dhcpv6_msg::SOLICIT`
+    pub fn from(msg_type: u8) -> Self {
+        Self {
+            msg_type: Some(msg_type),
+            ..Self::new()
+        }
+    }
+}
+
+impl Default for Dhcpv6Query {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq<Dhcpv6> for Dhcpv6Query {
+    fn eq(&self, rhs: &Dhcpv6) -> bool {
+        ifeq!(self.msg_type, rhs.msg_type);
+        ifeq!(self.xid, rhs.xid);
+        true
+    }
+}
+
+impl PartialEq<Pdu> for Dhcpv6Query {
+    fn eq(&self, other: &Pdu) -> bool {
+        if let Some(Proto::Dhcpv6(dhcpv6)) = other.headers.get(&7) {
+            debug!("DHCPv6 message found in PDU Group");
+            if self == dhcpv6 {
+                debug!("DHCPv6 message matched with DHCPv6 Query");
+                true
+            } else {
+                debug!("DHCPv6 message not matched with DHCPv6 Query");
+                false
+            }
+        } else {
+            false
+        }
+    }
+}
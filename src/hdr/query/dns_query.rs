@@ -0,0 +1,109 @@
+use crate::hdr::Dns;
+use crate::{debug, proto::Proto, Pdu};
+
+macro_rules! ifeq {
+    ($lhs:expr, $rhs:expr) => {
+        if let Some(lhs) = $lhs {
+            if lhs != $rhs {
+                return false;
+            }
+        }
+    };
+}
+
+/// Used as query of finding particular DNS messages
+///
+/// Members of structs are `Option<_>`
+/// * `None` - will match ANY data.
+/// * `Some(data)` - will match only to data similar to data.
+///
+/// `name`, `qtype` and `qclass` match if any question matches all of them.
+/// Names are compared ignoring case, and a name starting with `*.` matches
+/// every name below the rest of it.
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
+pub struct DnsQuery {
+    pub id: Option<u16>,
+    pub qr: Option<bool>,
+    pub opcode: Option<u8>,
+    pub name: Option<String>,
+    pub qtype: Option<u16>,
+    pub qclass: Option<u16>,
+}
+
+impl DnsQuery {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            qr: None,
+            opcode: None,
+            name: None,
+            qtype: None,
+            qclass: None,
+        }
+    }
+
+    /// Matches standard queries for `name` of type `qtype`
+    pub fn from(name: Option<&str>, qtype: Option<u16>) -> Self {
+        Self {
+            qr: Some(false),
+            opcode: Some(0),
+            name: name.map(|name| name.trim_end_matches('.').to_ascii_lowercase()),
+            qtype,
+            ..Self::new()
+        }
+    }
+}
+
+/// Compares `name` with `pattern` ignoring case and trailing dot, where a
+/// pattern starting with `*.` matches every name below the rest of it
+pub(crate) fn name_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.').to_ascii_lowercase();
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(parent) => name
+            .strip_suffix(parent)
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        None => name == pattern,
+    }
+}
+
+impl Default for DnsQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq<Dns> for DnsQuery {
+    fn eq(&self, rhs: &Dns) -> bool {
+        ifeq!(self.id, rhs.id);
+        ifeq!(self.qr, rhs.qr);
+        ifeq!(self.opcode, rhs.opcode);
+        if self.name.is_none() && self.qtype.is_none() && self.qclass.is_none() {
+            return true;
+        }
+        rhs.questions.iter().any(|question| {
+            self.name
+                .as_ref()
+                .is_none_or(|pattern| name_matches(pattern, &question.name))
+                && self.qtype.is_none_or(|qtype| qtype == question.qtype)
+                && self.qclass.is_none_or(|qclass| qclass == question.qclass)
+        })
+    }
+}
+
+impl PartialEq<Pdu> for DnsQuery {
+    fn eq(&self, other: &Pdu) -> bool {
+        if let Some(Proto::Dns(dns)) = other.headers.get(&7) {
+            debug!("DNS message found in PDU Group");
+            if self == dns {
+                debug!("DNS message matched with DNS Query");
+                true
+            } else {
+                debug!("DNS message not matched with DNS Query");
+                false
+            }
+        } else {
+            false
+        }
+    }
+}
@@ -97,8 +97,8 @@ impl IPv4Query {
             tos: Some(Bits::from(0, 8)),
             total_len: Some(Bits::from(0, 16)),
             id: Some(Bits::from(0, 16)),
-            flags: Some(Bits::from(0, 2)),
-            frag_offset: Some(Bits::from(0, 14)),
+            flags: Some(Bits::from(0, 3)),
+            frag_offset: Some(Bits::from(0, 13)),
             ttl: Some(Bits::from(64, 8)),
             proto: pro,
             hdr_checksum: Some(Bits::from(0, 16)),
@@ -0,0 +1,89 @@
+use crate::hdr::Mpls;
+use crate::{debug, proto::Proto, Pdu};
+
+macro_rules! ifeq {
+    ($lhs:expr, $rhs:expr) => {
+        if let Some(lhs) = $lhs {
+            if lhs != $rhs {
+                return false;
+            }
+        }
+    };
+}
+
+/// Used as query of finding particular MPLS frames
+///
+/// Members of structs are `Option<_>`
+/// * `None` - will match ANY data.
+/// * `Some(data)` - will match only to data similar to data.
+///
+/// `label` matches a label anywhere in the stack, `top` and `bottom` only
+/// the outermost and innermost one.
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
+pub struct MplsQuery {
+    pub label: Option<u32>,
+    pub top: Option<u32>,
+    pub bottom: Option<u32>,
+    /// Traffic class of top label
+    pub tc: Option<u8>,
+    /// Number of labels in the stack
+    pub depth: Option<usize>,
+}
+
+impl MplsQuery {
+    pub fn new() -> Self {
+        Self {
+            label: None,
+            top: None,
+            bottom: None,
+            tc: None,
+            depth: None,
+        }
+    }
+
+    /// Matches stacks holding `label`
+    pub fn from(label: u32) -> Self {
+        Self {
+            label: Some(label),
+            ..Self::new()
+        }
+    }
+}
+
+impl Default for MplsQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq<Mpls> for MplsQuery {
+    fn eq(&self, rhs: &Mpls) -> bool {
+        if let Some(label) = self.label {
+            if !rhs.labels.iter().any(|entry| entry.label == label) {
+                return false;
+            }
+        }
+        ifeq!(self.top.map(Some), rhs.labels.first().map(|e| e.label));
+        ifeq!(self.bottom.map(Some), rhs.labels.last().map(|e| e.label));
+        ifeq!(self.tc.map(Some), rhs.labels.first().map(|e| e.tc));
+        ifeq!(self.depth, rhs.labels.len());
+        true
+    }
+}
+
+impl PartialEq<Pdu> for MplsQuery {
+    fn eq(&self, other: &Pdu) -> bool {
+        if let Some(Proto::Mpls(mpls)) = other.headers.get(&3) {
+            debug!("MPLS label stack found in PDU Group");
+            if self == mpls {
+                debug!("MPLS label stack matched with MPLS Query");
+                true
+            } else {
+                debug!("MPLS label stack not matched with MPLS Query");
+                false
+            }
+        } else {
+            false
+        }
+    }
+}
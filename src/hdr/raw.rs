@@ -0,0 +1,42 @@
+use super::traits::Hdr;
+use crate::dstructs::Packet;
+use crate::error::PaError;
+use crate::proto::Proto;
+
+/// Bytes carried after the last header known to this library
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Raw {
+    pub data: Vec<u8>,
+}
+
+impl Raw {
+    pub fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    pub fn from(data: &[u8]) -> Self {
+        Self {
+            data: data.to_vec(),
+        }
+    }
+}
+
+impl Default for Raw {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hdr for Raw {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.data.clone().into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        Self { data: bytes.into() }
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Raw(self.clone())
+    }
+}
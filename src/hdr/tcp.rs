@@ -0,0 +1,196 @@
+use crate::dstructs::Bits;
+use crate::dstructs::Packet;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+
+pub mod tcp_flags {
+    pub const FIN: u16 = 0x001;
+    pub const SYN: u16 = 0x002;
+    pub const RST: u16 = 0x004;
+    pub const PSH: u16 = 0x008;
+    pub const ACK: u16 = 0x010;
+    pub const URG: u16 = 0x020;
+    pub const ECE: u16 = 0x040;
+    pub const CWR: u16 = 0x080;
+    pub const NS: u16 = 0x100;
+}
+
+/// TCP header according to [RFC 793](https://datatracker.ietf.org/doc/html/rfc793)
+///
+/// `options` hold raw option bytes, padded to a multiple of 4 when created.
+#[derive(Clone)]
+pub struct TcpHdr {
+    pub src_port: Bits,
+    pub dst_port: Bits,
+    pub seq_num: Bits,
+    pub ack_num: Bits,
+    pub data_offset: Bits,
+    pub reserved: Bits,
+    pub flags: Bits,
+    pub window: Bits,
+    pub checksum: Bits,
+    pub urgent_ptr: Bits,
+    pub options: Vec<u8>,
+}
+
+impl TcpHdr {
+    pub fn new() -> Self {
+        Self {
+            src_port: Bits::from(0, 16),
+            dst_port: Bits::from(0, 16),
+            seq_num: Bits::from(0, 32),
+            ack_num: Bits::from(0, 32),
+            data_offset: Bits::from(5, 4),
+            reserved: Bits::from(0, 3),
+            flags: Bits::from(0, 9),
+            window: Bits::from(64240, 16),
+            checksum: Bits::from(0, 16),
+            urgent_ptr: Bits::from(0, 16),
+            options: Vec::new(),
+        }
+    }
+
+    /// Creates header with ports and `flags` made of `tcp_flags` constants
+    pub fn from(src_port: u16, dst_port: u16, flags: u16) -> Self {
+        let mut hdr = Self::new();
+        hdr.src_port = Bits::from(src_port.into(), 16);
+        hdr.dst_port = Bits::from(dst_port.into(), 16);
+        hdr.flags = Bits::from(flags.into(), 9);
+        hdr
+    }
+
+    /// Returns `true` if every flag of `flags` is set
+    pub fn has_flags(&self, flags: u16) -> bool {
+        self.flags.to_usize() as u16 & flags == flags
+    }
+
+    /// Sets `options` and `data_offset` matching them
+    pub fn set_options(&mut self, options: &[u8]) -> Result<(), PaError> {
+        let mut options = options.to_vec();
+        while !options.len().is_multiple_of(4) {
+            options.push(0);
+        }
+        if options.len() > 40 {
+            return Err(PaError::new(
+                "TCP options longer than 40 bytes",
+                ErrorType::ConstructError,
+            ));
+        }
+        self.data_offset = Bits::from(5 + options.len() / 4, 4);
+        self.options = options;
+        Ok(())
+    }
+
+    pub fn length(&self) -> usize {
+        20 + self.options.len().div_ceil(4) * 4
+    }
+}
+
+impl Default for TcpHdr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hdr for TcpHdr {
+    fn create(&self) -> Result<Packet, PaError> {
+        let mut packet_data = Packet::new();
+        packet_data.append(self.src_port.clone().into());
+        packet_data.append(self.dst_port.clone().into());
+        packet_data.append(self.seq_num.clone().into());
+        packet_data.append(self.ack_num.clone().into());
+        packet_data.append(self.data_offset.clone().into());
+        packet_data.append(self.reserved.clone().into());
+        packet_data.append(self.flags.clone().into());
+        packet_data.append(self.window.clone().into());
+        packet_data.append(self.checksum.clone().into());
+        packet_data.append(self.urgent_ptr.clone().into());
+        for byte in self.options.iter() {
+            packet_data.push(*byte);
+        }
+        for _ in self.options.len()..self.length() - 20 {
+            packet_data.push(0);
+        }
+        Ok(packet_data)
+    }
+
+    /// Parses header, `bytes` must hold `data_offset` words
+    fn parse(bytes: Packet) -> Self {
+        let data_offset: Bits = bytes.get_bin_slice(96, 100).into();
+        let hdr_len = data_offset.to_usize().max(5) * 4;
+        Self {
+            src_port: bytes.get_bin_slice(0, 16).into(),
+            dst_port: bytes.get_bin_slice(16, 32).into(),
+            seq_num: bytes.get_bin_slice(32, 64).into(),
+            ack_num: bytes.get_bin_slice(64, 96).into(),
+            reserved: bytes.get_bin_slice(100, 103).into(),
+            flags: bytes.get_bin_slice(103, 112).into(),
+            window: bytes.get_bin_slice(112, 128).into(),
+            checksum: bytes.get_bin_slice(128, 144).into(),
+            urgent_ptr: bytes.get_bin_slice(144, 160).into(),
+            options: bytes.get_slice(160, hdr_len * 8),
+            data_offset,
+        }
+    }
+
+    fn get(&self) -> Proto {
+        Proto::TCP(self.clone())
+    }
+}
+
+impl PartialEq for TcpHdr {
+    fn eq(&self, other: &Self) -> bool {
+        self.src_port == other.src_port
+            && self.dst_port == other.dst_port
+            && self.seq_num == other.seq_num
+            && self.ack_num == other.ack_num
+            && self.data_offset == other.data_offset
+            && self.reserved == other.reserved
+            && self.flags == other.flags
+            && self.window == other.window
+            && self.checksum == other.checksum
+            && self.urgent_ptr == other.urgent_ptr
+            && self.options == other.options
+    }
+}
+
+impl std::fmt::Debug for TcpHdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(
+            format!(
+                "
+Source Port: {}
+Destination Port: {}
+Sequence Number: {}
+Acknowledgment Number: {}
+Data Offset: {}
+Reserved: {}
+Flags: {}
+Window: {}
+Checksum: {}
+Urgent Pointer: {}
+Options: {:?}",
+                self.src_port,
+                self.dst_port,
+                self.seq_num,
+                self.ack_num,
+                self.data_offset,
+                self.reserved,
+                self.flags,
+                self.window,
+                self.checksum,
+                self.urgent_ptr,
+                self.options,
+            )
+            .as_str(),
+        )
+    }
+}
+
+impl std::fmt::Display for TcpHdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let packet_vec: Vec<u8> = self.create().unwrap().into();
+        f.write_str(format!("{:?}", packet_vec).as_str())
+    }
+}
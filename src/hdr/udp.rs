@@ -0,0 +1,98 @@
+use crate::dstructs::Bits;
+use crate::dstructs::Packet;
+use crate::error::PaError;
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+
+/// UDP header according to [RFC 768](https://datatracker.ietf.org/doc/html/rfc768)
+#[derive(Clone)]
+pub struct UdpHdr {
+    pub src_port: Bits,
+    pub dst_port: Bits,
+    pub length: Bits,
+    pub checksum: Bits,
+}
+
+impl UdpHdr {
+    pub fn new() -> Self {
+        Self {
+            src_port: Bits::from(0, 16),
+            dst_port: Bits::from(0, 16),
+            length: Bits::from(0, 16),
+            checksum: Bits::from(0, 16),
+        }
+    }
+
+    pub fn from(src_port: u16, dst_port: u16) -> Self {
+        let mut hdr = Self::new();
+        hdr.src_port = Bits::from(src_port.into(), 16);
+        hdr.dst_port = Bits::from(dst_port.into(), 16);
+        hdr
+    }
+
+    pub fn length(&self) -> usize {
+        8
+    }
+}
+
+impl Default for UdpHdr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hdr for UdpHdr {
+    fn create(&self) -> Result<Packet, PaError> {
+        let mut packet_data = Packet::new();
+        packet_data.append(self.src_port.clone().into());
+        packet_data.append(self.dst_port.clone().into());
+        packet_data.append(self.length.clone().into());
+        packet_data.append(self.checksum.clone().into());
+        Ok(packet_data)
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        Self {
+            src_port: bytes.get_bin_slice(0, 16).into(),
+            dst_port: bytes.get_bin_slice(16, 32).into(),
+            length: bytes.get_bin_slice(32, 48).into(),
+            checksum: bytes.get_bin_slice(48, 64).into(),
+        }
+    }
+
+    fn get(&self) -> Proto {
+        Proto::UDP(self.clone())
+    }
+}
+
+impl PartialEq for UdpHdr {
+    fn eq(&self, other: &Self) -> bool {
+        self.src_port == other.src_port
+            && self.dst_port == other.dst_port
+            && self.length == other.length
+            && self.checksum == other.checksum
+    }
+}
+
+impl std::fmt::Debug for UdpHdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(
+            format!(
+                "
+Source Port: {}
+Destination Port: {}
+Length: {}
+Checksum: {}",
+                self.src_port, self.dst_port, self.length, self.checksum,
+            )
+            .as_str(),
+        )
+    }
+}
+
+impl std::fmt::Display for UdpHdr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let packet_vec: Vec<u8> = self.create().unwrap().into();
+        f.write_str(format!("{:?}", packet_vec).as_str())
+    }
+}
@@ -0,0 +1,68 @@
+use crate::dstructs::Packet;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::Hdr;
+use crate::proto::Proto;
+
+/// UDP port of VXLAN
+pub mod vxlan_port {
+    pub const VXLAN: u16 = 4789;
+}
+
+/// Flag telling the VNI is valid
+pub const VXLAN_VNI_VALID: u8 = 0x08;
+
+/// VXLAN header according to [RFC 7348](https://datatracker.ietf.org/doc/html/rfc7348),
+/// always followed by an Ethernet frame
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Vxlan {
+    pub flags: u8,
+    /// VXLAN network identifier of 24 bits
+    pub vni: u32,
+}
+
+impl Vxlan {
+    pub fn new(vni: u32) -> Self {
+        Self {
+            flags: VXLAN_VNI_VALID,
+            vni,
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        8
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PaError> {
+        if bytes.len() < 8 {
+            return Err(PaError::new(
+                "VXLAN header cut short",
+                ErrorType::ParseError,
+            ));
+        }
+        Ok(Self {
+            flags: bytes[0],
+            vni: u32::from_be_bytes([0, bytes[4], bytes[5], bytes[6]]),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.flags, 0, 0, 0];
+        bytes.extend_from_slice(&(self.vni << 8).to_be_bytes());
+        bytes
+    }
+}
+
+impl Hdr for Vxlan {
+    fn create(&self) -> Result<Packet, PaError> {
+        Ok(self.to_bytes().into())
+    }
+
+    fn parse(bytes: Packet) -> Self {
+        let bytes: Vec<u8> = bytes.into();
+        Self::from_bytes(&bytes).unwrap_or_default()
+    }
+
+    fn get(&self) -> Proto {
+        Proto::Vxlan(self.clone())
+    }
+}
@@ -0,0 +1,391 @@
+//! Information about local network interfaces
+//!
+//! Gives own MAC, IP addresses, MTU and flags of an interface, the default
+//! gateways and the kernel neighbour (ARP) table, so headers can be filled
+//! without hard coding local addresses. Routes and neighbours are read from
+//! `/proc/net`, so those helpers only work on Linux.
+
+use crate::error::{ErrorType, PaError};
+use crate::utility::parse_mac;
+use pnet_datalink::NetworkInterface;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+
+const IFF_UP: u32 = 0x1;
+const IFF_BROADCAST: u32 = 0x2;
+const IFF_LOOPBACK: u32 = 0x8;
+const IFF_POINTOPOINT: u32 = 0x10;
+const IFF_MULTICAST: u32 = 0x1000;
+
+/// Route is usable
+const RTF_UP: u32 = 0x1;
+/// Route goes through a gateway
+const RTF_GATEWAY: u32 = 0x2;
+/// Neighbour entry is complete
+const ATF_COM: u32 = 0x2;
+
+/// IPv4 address of an interface with its prefix length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv4Net {
+    pub addr: [u8; 4],
+    pub prefix: u8,
+}
+
+impl Ipv4Net {
+    /// Parses network in CIDR notation, e.g. `192.168.1.0/24`
+    ///
+    /// An address without prefix length is taken as `/32`.
+    pub fn parse(cidr: impl ToString) -> Result<Self, PaError> {
+        let cidr = cidr.to_string();
+        let (addr, prefix) = match cidr.split_once('/') {
+            Some((addr, prefix)) => (addr, prefix.trim().parse().ok()),
+            None => (cidr.as_str(), Some(32)),
+        };
+        let addr: Ipv4Addr = addr
+            .trim()
+            .parse()
+            .map_err(|_| PaError::new("Invalid IPv4 address in CIDR", ErrorType::ParseError))?;
+        match prefix {
+            Some(prefix) if prefix <= 32 => Ok(Self {
+                addr: addr.octets(),
+                prefix,
+            }),
+            _ => Err(PaError::new(
+                "Invalid prefix length in CIDR",
+                ErrorType::ParseError,
+            )),
+        }
+    }
+
+    /// Returns netmask made from prefix length
+    pub fn netmask(&self) -> [u8; 4] {
+        let mask = u32::MAX
+            .checked_shl(32 - self.prefix.min(32) as u32)
+            .unwrap_or(0);
+        mask.to_be_bytes()
+    }
+
+    /// Returns `true` if `ip` is in the same subnet
+    pub fn contains(&self, ip: [u8; 4]) -> bool {
+        let mask = u32::from_be_bytes(self.netmask());
+        u32::from_be_bytes(self.addr) & mask == u32::from_be_bytes(ip) & mask
+    }
+
+    /// Returns usable host addresses of the network
+    ///
+    /// Network and broadcast addresses are left out, except for `/31` and
+    /// `/32` networks which have none.
+    pub fn hosts(&self) -> impl Iterator<Item = [u8; 4]> {
+        let mask = u32::from_be_bytes(self.netmask());
+        let network = u32::from_be_bytes(self.addr) & mask;
+        let broadcast = network | !mask;
+        let (first, last) = if self.prefix >= 31 {
+            (network, broadcast)
+        } else {
+            (network + 1, broadcast - 1)
+        };
+        (first..=last).map(u32::to_be_bytes)
+    }
+}
+
+/// IPv6 address of an interface with its prefix length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv6Net {
+    pub addr: [u8; 16],
+    pub prefix: u8,
+}
+
+/// A local network interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interface {
+    pub name: String,
+    pub index: u32,
+    pub mac: Option<[u8; 6]>,
+    pub ipv4: Vec<Ipv4Net>,
+    pub ipv6: Vec<Ipv6Net>,
+    /// `None` if MTU could not be read
+    pub mtu: Option<u32>,
+    /// Raw `IFF_*` flags
+    pub flags: u32,
+}
+
+impl Interface {
+    /// Returns interface called `name`
+    pub fn by_name(name: impl ToString) -> Result<Self, PaError> {
+        let name = name.to_string();
+        interfaces()
+            .into_iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| PaError::new("Interface not found", ErrorType::InterfaceError))
+    }
+
+    /// Returns interface of the default IPv4 route
+    ///
+    /// Falls back to the first interface which is up, not loopback and has an
+    /// IP, same as `Channel::new`.
+    pub fn default_interface() -> Result<Self, PaError> {
+        let list = interfaces();
+        if let Ok(Some(gateway)) = default_gateway() {
+            if let Some(interf) = list.iter().find(|e| e.name == gateway.interface) {
+                return Ok(interf.clone());
+            }
+        }
+        list.into_iter()
+            .find(|e| e.is_up() && !e.is_loopback() && !(e.ipv4.is_empty() && e.ipv6.is_empty()))
+            .ok_or_else(|| {
+                PaError::new(
+                    "Error in getting default interface",
+                    ErrorType::InterfaceError,
+                )
+            })
+    }
+
+    /// Returns first IPv4 address of the interface
+    pub fn ip(&self) -> Option<[u8; 4]> {
+        self.ipv4.first().map(|net| net.addr)
+    }
+
+    /// Returns first IPv6 address of the interface
+    pub fn ipv6(&self) -> Option<[u8; 16]> {
+        self.ipv6.first().map(|net| net.addr)
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.flags & IFF_UP != 0
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.flags & IFF_BROADCAST != 0
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        self.flags & IFF_LOOPBACK != 0
+    }
+
+    pub fn is_point_to_point(&self) -> bool {
+        self.flags & IFF_POINTOPOINT != 0
+    }
+
+    pub fn is_multicast(&self) -> bool {
+        self.flags & IFF_MULTICAST != 0
+    }
+
+    /// Returns default IPv4 gateway if it is reached through this interface
+    pub fn gateway(&self) -> Result<Option<[u8; 4]>, PaError> {
+        Ok(default_gateway()?
+            .filter(|gw| gw.interface == self.name)
+            .map(|gw| gw.ip))
+    }
+
+    /// Looks up MAC of `ip` in the kernel neighbour table of this interface
+    pub fn neighbour(&self, ip: [u8; 4]) -> Result<Option<[u8; 6]>, PaError> {
+        Ok(arp_table()?
+            .into_iter()
+            .find(|e| e.ip == ip && e.interface == self.name && e.is_complete())
+            .map(|e| e.mac))
+    }
+
+    /// Looks up MAC of default gateway in the kernel neighbour table
+    pub fn gateway_mac(&self) -> Result<Option<[u8; 6]>, PaError> {
+        match self.gateway()? {
+            Some(ip) => self.neighbour(ip),
+            None => Ok(None),
+        }
+    }
+}
+
+impl From<&NetworkInterface> for Interface {
+    fn from(interf: &NetworkInterface) -> Self {
+        let mut ipv4 = Vec::new();
+        let mut ipv6 = Vec::new();
+        for net in interf.ips.iter() {
+            match net.ip() {
+                IpAddr::V4(ip) => ipv4.push(Ipv4Net {
+                    addr: ip.octets(),
+                    prefix: net.prefix(),
+                }),
+                IpAddr::V6(ip) => ipv6.push(Ipv6Net {
+                    addr: ip.octets(),
+                    prefix: net.prefix(),
+                }),
+            }
+        }
+        Self {
+            name: interf.name.clone(),
+            index: interf.index,
+            mac: interf.mac.map(|mac| mac.octets()),
+            ipv4,
+            ipv6,
+            mtu: read_mtu(&interf.name),
+            flags: interf.flags,
+        }
+    }
+}
+
+/// Returns every local interface
+pub fn interfaces() -> Vec<Interface> {
+    pnet_datalink::interfaces()
+        .iter()
+        .map(Interface::from)
+        .collect()
+}
+
+fn read_mtu(name: &str) -> Option<u32> {
+    fs::read_to_string(format!("/sys/class/net/{}/mtu", name))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn read_proc(path: &str) -> Result<String, PaError> {
+    fs::read_to_string(path)
+        .map_err(|e| PaError::new(format!("{}: {}", path, e), ErrorType::InterfaceError))
+}
+
+/// Default IPv4 route
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gateway {
+    pub interface: String,
+    pub ip: [u8; 4],
+    pub metric: u32,
+}
+
+/// Default IPv6 route
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gateway6 {
+    pub interface: String,
+    pub ip: [u8; 16],
+    pub metric: u32,
+}
+
+/// Returns default IPv4 gateway with lowest metric
+pub fn default_gateway() -> Result<Option<Gateway>, PaError> {
+    Ok(parse_route_table(&read_proc("/proc/net/route")?))
+}
+
+/// Returns default IPv6 gateway with lowest metric
+pub fn default_gateway_v6() -> Result<Option<Gateway6>, PaError> {
+    Ok(parse_ipv6_route_table(&read_proc("/proc/net/ipv6_route")?))
+}
+
+/// Finds default gateway in contents of `/proc/net/route`
+pub fn parse_route_table(table: &str) -> Option<Gateway> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 {
+                return None;
+            }
+            let dest = u32::from_str_radix(fields[1], 16).ok()?;
+            let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+            let flags = u32::from_str_radix(fields[3], 16).ok()?;
+            let metric = fields[6].parse().ok()?;
+            let mask = u32::from_str_radix(fields[7], 16).ok()?;
+            if dest != 0 || mask != 0 || flags & (RTF_UP | RTF_GATEWAY) != RTF_UP | RTF_GATEWAY {
+                return None;
+            }
+            // Addresses are printed as host order integers
+            Some(Gateway {
+                interface: fields[0].to_string(),
+                ip: gateway.to_le_bytes(),
+                metric,
+            })
+        })
+        .min_by_key(|gw| gw.metric)
+}
+
+/// Finds default gateway in contents of `/proc/net/ipv6_route`
+pub fn parse_ipv6_route_table(table: &str) -> Option<Gateway6> {
+    table
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 {
+                return None;
+            }
+            let dest = parse_hex_ipv6(fields[0])?;
+            let prefix = u8::from_str_radix(fields[1], 16).ok()?;
+            let next_hop = parse_hex_ipv6(fields[4])?;
+            let metric = u32::from_str_radix(fields[5], 16).ok()?;
+            let flags = u32::from_str_radix(fields[8], 16).ok()?;
+            if dest != [0; 16]
+                || prefix != 0
+                || next_hop == [0; 16]
+                || flags & (RTF_UP | RTF_GATEWAY) != RTF_UP | RTF_GATEWAY
+            {
+                return None;
+            }
+            Some(Gateway6 {
+                interface: fields[9].to_string(),
+                ip: next_hop,
+                metric,
+            })
+        })
+        .min_by_key(|gw| gw.metric)
+}
+
+fn parse_hex_ipv6(hex: &str) -> Option<[u8; 16]> {
+    if hex.len() != 32 {
+        return None;
+    }
+    let mut addr = [0; 16];
+    for (i, byte) in addr.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(addr)
+}
+
+/// Entry of kernel neighbour table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbour {
+    pub ip: [u8; 4],
+    pub mac: [u8; 6],
+    pub interface: String,
+    /// Raw `ATF_*` flags
+    pub flags: u32,
+}
+
+impl Neighbour {
+    /// Returns `false` for entries still being resolved or failed
+    pub fn is_complete(&self) -> bool {
+        self.flags & ATF_COM != 0
+    }
+}
+
+/// Returns kernel neighbour table
+pub fn arp_table() -> Result<Vec<Neighbour>, PaError> {
+    Ok(parse_arp_table(&read_proc("/proc/net/arp")?))
+}
+
+/// Looks up MAC of `ip` in kernel neighbour table on any interface
+pub fn lookup_mac(ip: [u8; 4]) -> Result<Option<[u8; 6]>, PaError> {
+    Ok(arp_table()?
+        .into_iter()
+        .find(|e| e.ip == ip && e.is_complete())
+        .map(|e| e.mac))
+}
+
+/// Parses contents of `/proc/net/arp`
+pub fn parse_arp_table(table: &str) -> Vec<Neighbour> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return None;
+            }
+            let ip: Ipv4Addr = fields[0].parse().ok()?;
+            let flags = u32::from_str_radix(fields[2].trim_start_matches("0x"), 16).ok()?;
+            Some(Neighbour {
+                ip: ip.octets(),
+                mac: parse_mac(fields[3]).ok()?,
+                interface: fields[5].to_string(),
+                flags,
+            })
+        })
+        .collect()
+}
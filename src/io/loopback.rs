@@ -0,0 +1,48 @@
+use super::PacketIo;
+use crate::error::{ErrorType, PaError};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+/// One end of an in-memory link
+///
+/// Frames sent on one end of a pair are received on the other end. Useful to
+/// test code built on `PacketIo` without root privileges or a real interface.
+pub struct LoopbackIo {
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+}
+
+impl LoopbackIo {
+    /// Returns two connected ends
+    pub fn pair() -> (Self, Self) {
+        let (tx_a, rx_a) = channel();
+        let (tx_b, rx_b) = channel();
+        (Self { tx: tx_a, rx: rx_b }, Self { tx: tx_b, rx: rx_a })
+    }
+}
+
+impl PacketIo for LoopbackIo {
+    fn send(&mut self, frame: &[u8]) -> Result<usize, PaError> {
+        self.tx
+            .send(frame.to_vec())
+            .map_err(|_| PaError::new("Other end of loopback closed", ErrorType::ChannelError))?;
+        Ok(frame.len())
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, PaError> {
+        self.rx
+            .recv()
+            .map_err(|_| PaError::new("Other end of loopback closed", ErrorType::ChannelError))
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, PaError> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(PaError::new(
+                "Other end of loopback closed",
+                ErrorType::ChannelError,
+            )),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Contains `PacketIo` trait and its implementations
+//!
+//! Anything able to send and receive raw frames can implement `PacketIo`,
+//! which lets `Pdu`, `auto_reply` and the other helpers of this library run on
+//! a real interface, an in-memory pair, a pcap file or a TAP device.
+
+use crate::error::PaError;
+use std::time::Duration;
+
+mod loopback;
+pub use loopback::*;
+#[cfg(feature = "pcap")]
+mod pcap;
+#[cfg(feature = "pcap")]
+pub use self::pcap::*;
+#[cfg(target_os = "linux")]
+mod tap;
+#[cfg(target_os = "linux")]
+pub use tap::*;
+
+/// Sends and receives raw frames
+pub trait PacketIo {
+    /// Sends one frame, returns number of bytes sent
+    fn send(&mut self, frame: &[u8]) -> Result<usize, PaError>;
+
+    /// Blocks until one frame is received
+    fn recv(&mut self) -> Result<Vec<u8>, PaError>;
+
+    /// Waits at most `timeout` for one frame
+    ///
+    /// Returns `Ok(None)` if nothing was received in time.
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, PaError>;
+}
+
+impl<T: PacketIo + ?Sized> PacketIo for &mut T {
+    fn send(&mut self, frame: &[u8]) -> Result<usize, PaError> {
+        (**self).send(frame)
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, PaError> {
+        (**self).recv()
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, PaError> {
+        (**self).recv_timeout(timeout)
+    }
+}
+
+impl<T: PacketIo + ?Sized> PacketIo for Box<T> {
+    fn send(&mut self, frame: &[u8]) -> Result<usize, PaError> {
+        (**self).send(frame)
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, PaError> {
+        (**self).recv()
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, PaError> {
+        (**self).recv_timeout(timeout)
+    }
+}
@@ -0,0 +1,87 @@
+use super::PacketIo;
+use crate::error::{ErrorType, PaError};
+use pcap_file::{PcapReader, PcapWriter};
+use std::fs::File;
+use std::time::{Duration, Instant};
+
+/// Reads received frames from a pcap file and writes sent frames to another
+///
+/// Either side is optional. Receiving without a source, or after the source
+/// is exhausted, returns an error from `recv` and `Ok(None)` from
+/// `recv_timeout`. Sending without a sink only counts bytes.
+pub struct PcapIo {
+    source: Option<PcapReader<File>>,
+    sink: Option<PcapWriter<File>>,
+    time_start: Instant,
+}
+
+fn pcap_error(err: impl ToString) -> PaError {
+    PaError::new(err.to_string(), ErrorType::PcapFileError)
+}
+
+impl PcapIo {
+    pub fn new(source: Option<&str>, sink: Option<&str>) -> Result<Self, PaError> {
+        let source = match source {
+            Some(path) => {
+                let file = File::open(path).map_err(pcap_error)?;
+                Some(PcapReader::new(file).map_err(pcap_error)?)
+            }
+            None => None,
+        };
+        let sink = match sink {
+            Some(path) => {
+                let file = File::create(path).map_err(pcap_error)?;
+                Some(PcapWriter::new(file).map_err(pcap_error)?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            source,
+            sink,
+            time_start: Instant::now(),
+        })
+    }
+
+    /// Only receives, from frames stored in `path`
+    pub fn open(path: &str) -> Result<Self, PaError> {
+        Self::new(Some(path), None)
+    }
+
+    /// Only sends, into new pcap file at `path`
+    pub fn create(path: &str) -> Result<Self, PaError> {
+        Self::new(None, Some(path))
+    }
+
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>, PaError> {
+        match self.source.as_mut().and_then(|source| source.next()) {
+            Some(packet) => Ok(Some(packet.map_err(pcap_error)?.data.into_owned())),
+            None => Ok(None),
+        }
+    }
+}
+
+impl PacketIo for PcapIo {
+    fn send(&mut self, frame: &[u8]) -> Result<usize, PaError> {
+        if let Some(sink) = self.sink.as_mut() {
+            let elapsed = self.time_start.elapsed();
+            sink.write(
+                elapsed.as_secs() as u32,
+                elapsed.subsec_nanos(),
+                frame,
+                frame.len() as u32,
+            )
+            .map_err(pcap_error)?;
+        }
+        Ok(frame.len())
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, PaError> {
+        self.next_frame()?
+            .ok_or_else(|| PaError::new("No more frames in pcap file", ErrorType::PcapFileError))
+    }
+
+    fn recv_timeout(&mut self, _timeout: Duration) -> Result<Option<Vec<u8>>, PaError> {
+        self.next_frame()
+    }
+}
@@ -0,0 +1,78 @@
+use super::PacketIo;
+use crate::error::{ErrorType, PaError};
+use crate::sys;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+/// Linux TAP device
+///
+/// Frames sent by this side come out of the kernel interface, and frames the
+/// kernel transmits on the interface are received here. The interface has to
+/// be brought up (and optionally bridged) by the caller, e.g. with `ip link`.
+pub struct TapIo {
+    file: File,
+    name: String,
+    buffer: Vec<u8>,
+}
+
+impl TapIo {
+    /// Creates or attaches to TAP interface called `name`
+    pub fn open(name: impl ToString) -> Result<Self, PaError> {
+        let name = name.to_string();
+        if name.len() >= libc::IFNAMSIZ {
+            return Err(PaError::new(
+                "Interface name too long",
+                ErrorType::InterfaceError,
+            ));
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/net/tun")?;
+
+        let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+        for (dst, src) in ifr.ifr_name.iter_mut().zip(name.bytes()) {
+            *dst = src as libc::c_char;
+        }
+        ifr.ifr_ifru.ifru_flags = (libc::IFF_TAP | libc::IFF_NO_PI) as libc::c_short;
+
+        let ret = unsafe { libc::ioctl(file.as_raw_fd(), libc::TUNSETIFF, &mut ifr) };
+        if ret < 0 {
+            return Err(PaError::new(
+                std::io::Error::last_os_error().to_string(),
+                ErrorType::InterfaceError,
+            ));
+        }
+
+        Ok(Self {
+            file,
+            name,
+            buffer: vec![0; 65536],
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PacketIo for TapIo {
+    fn send(&mut self, frame: &[u8]) -> Result<usize, PaError> {
+        Ok(self.file.write(frame)?)
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, PaError> {
+        let len = self.file.read(&mut self.buffer)?;
+        Ok(self.buffer[..len].to_vec())
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, PaError> {
+        if !sys::poll_readable(&self.file, sys::poll_timeout(timeout))? {
+            return Ok(None);
+        }
+        self.recv().map(Some)
+    }
+}
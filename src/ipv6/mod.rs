@@ -0,0 +1,13 @@
+//! IPv6 router and DHCPv6 server emulators built on `RouterAdvert` and
+//! `Dhcpv6`
+//!
+//! `Router` sends Router Advertisements with prefixes, MTU and DNS servers
+//! at intervals and answers Router Solicitations, so hosts configure
+//! themselves through SLAAC. `Dhcpv6Server` hands out addresses and
+//! delegated prefixes for managed networks, on its own or alongside a
+//! `Router`. Both run on any `PacketIo` sending Ethernet frames.
+
+mod router;
+pub use router::*;
+mod server;
+pub use server::*;
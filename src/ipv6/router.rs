@@ -0,0 +1,260 @@
+use crate::dstructs::Bits;
+use crate::error::PaError;
+use crate::hdr::{
+    eth_type, icmpv6_type, link_local, multicast_mac, EthHdr, IPv6Hdr, Icmpv6Hdr, NdpOption,
+    PrefixInfo, RouterAdvert, ALL_NODES,
+};
+use crate::io::PacketIo;
+use crate::ipv6::Dhcpv6Server;
+use crate::proto::Proto;
+use crate::Pdu;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Options of `Router`
+#[derive(Debug, Clone)]
+pub struct RouterOpts {
+    pub mac: [u8; 6],
+    /// Link-local source of advertisements
+    pub ip: [u8; 16],
+    /// Hop limit hosts should use, 0 for unspecified
+    pub hop_limit: u8,
+    /// Tells hosts to get addresses through DHCPv6
+    pub managed: bool,
+    /// Tells hosts to get other configuration through DHCPv6
+    pub other: bool,
+    /// How long hosts may use the router as default router, zero for never
+    pub lifetime: Duration,
+    pub prefixes: Vec<PrefixInfo>,
+    pub mtu: Option<u32>,
+    /// Recursive DNS servers
+    pub rdnss: Vec<[u8; 16]>,
+    pub rdnss_lifetime: Duration,
+    /// Time between two unsolicited advertisements
+    pub interval: Duration,
+    /// Sends advertisement with zero lifetimes when stopping, so hosts drop
+    /// the router and its prefixes
+    pub withdraw: bool,
+}
+
+impl RouterOpts {
+    pub fn new(mac: [u8; 6]) -> Self {
+        Self {
+            mac,
+            ip: link_local(mac),
+            hop_limit: 64,
+            managed: false,
+            other: false,
+            lifetime: Duration::from_secs(1800),
+            prefixes: Vec::new(),
+            mtu: None,
+            rdnss: Vec::new(),
+            rdnss_lifetime: Duration::from_secs(1800),
+            interval: Duration::from_secs(10),
+            withdraw: true,
+        }
+    }
+}
+
+/// Summary returned after `Router::run`
+#[derive(Debug, Clone, Default)]
+pub struct RouterStats {
+    /// Unsolicited advertisements sent
+    pub adverts: usize,
+    /// Advertisements sent in answer to Router Solicitations
+    pub solicited: usize,
+    /// Replies sent by the DHCPv6 server
+    pub dhcp: usize,
+}
+
+/// IPv6 router sending Router Advertisements, optionally with a DHCPv6
+/// server answering on the same link
+///
+/// Whether it acts as the legit router of a test network or as a rogue one
+/// only depends on its options, e.g. a rogue router advertises a short
+/// interval, a long lifetime and its own prefixes and DNS servers.
+pub struct Router {
+    pub opts: RouterOpts,
+    /// Answers DHCPv6 messages when set, as needed by `managed` and `other`
+    pub dhcp: Option<Dhcpv6Server>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Router {
+    pub fn new(opts: RouterOpts) -> Self {
+        Self {
+            opts,
+            dhcp: None,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Returns flag stopping `run` when set, usable from another thread
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// Returns advertisement carrying every option
+    pub fn advert(&self) -> RouterAdvert {
+        let mut advert = RouterAdvert::new();
+        advert.hop_limit = self.opts.hop_limit;
+        advert.managed = self.opts.managed;
+        advert.other = self.opts.other;
+        advert.lifetime = self.opts.lifetime.as_secs().min(u16::MAX as u64) as u16;
+        advert
+            .options
+            .push(NdpOption::SourceLinkAddr(self.opts.mac));
+        if let Some(mtu) = self.opts.mtu {
+            advert.options.push(NdpOption::Mtu(mtu));
+        }
+        advert
+            .options
+            .extend(self.opts.prefixes.iter().cloned().map(NdpOption::Prefix));
+        if !self.opts.rdnss.is_empty() {
+            advert.options.push(NdpOption::Rdnss {
+                lifetime: self.opts.rdnss_lifetime.as_secs() as u32,
+                servers: self.opts.rdnss.clone(),
+            });
+        }
+        advert
+    }
+
+    /// Returns advertisement with zero lifetimes, withdrawing router,
+    /// prefixes and DNS servers
+    pub fn withdrawal(&self) -> RouterAdvert {
+        let mut advert = self.advert();
+        advert.lifetime = 0;
+        for option in advert.options.iter_mut() {
+            match option {
+                NdpOption::Prefix(info) => {
+                    info.valid = 0;
+                    info.preferred = 0;
+                }
+                NdpOption::Rdnss { lifetime, .. } => *lifetime = 0,
+                _ => {}
+            }
+        }
+        advert
+    }
+
+    /// Wraps `advert` into a frame sent to `dst_mac` and `dst_ip`
+    pub fn advert_frame(&self, advert: RouterAdvert, dst_mac: [u8; 6], dst_ip: [u8; 16]) -> Pdu {
+        let mut ip = IPv6Hdr::new();
+        // Hosts drop Neighbor Discovery messages which may have been routed
+        ip.hop_limit = Bits::from(255, 8);
+        ip.src_ip_addr = self.opts.ip;
+        ip.dst_ip_addr = dst_ip;
+        Pdu::new()
+            .header(EthHdr::from_raw(
+                self.opts.mac,
+                dst_mac,
+                eth_type::IPv6 as u16,
+            ))
+            .header(ip)
+            .header(Icmpv6Hdr::new(icmpv6_type::ROUTER_ADVERT))
+            .header(advert)
+    }
+
+    /// Crafts reply to Router Solicitation or DHCPv6 message in `pdu`, or
+    /// `None` if it needs no reply
+    ///
+    /// Solicitations from the unspecified address are answered to all
+    /// nodes, others to the soliciting host.
+    pub fn reply(&self, pdu: Pdu) -> Option<Pdu> {
+        if let Some(Proto::Dhcpv6(_)) = pdu.headers.get(&7) {
+            return self.dhcp.as_ref()?.reply(pdu);
+        }
+        let (eth, ip) = match (
+            pdu.headers.get(&2),
+            pdu.headers.get(&3),
+            pdu.headers.get(&7),
+        ) {
+            (Some(Proto::Eth(eth)), Some(Proto::IPv6(ip)), Some(Proto::RouterSolicit(_))) => {
+                (eth, ip)
+            }
+            _ => return None,
+        };
+        Some(match ip.src_ip_addr {
+            src if src == [0; 16] => {
+                self.advert_frame(self.advert(), multicast_mac(ALL_NODES), ALL_NODES)
+            }
+            src => self.advert_frame(self.advert(), eth.src_hw_addr, src),
+        })
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    fn advertise_loop<T: PacketIo>(
+        &self,
+        io: &mut T,
+        rounds: Option<usize>,
+        stats: &mut RouterStats,
+    ) -> Result<(), PaError> {
+        let mut advert = self.advert_frame(self.advert(), multicast_mac(ALL_NODES), ALL_NODES);
+        advert.build()?;
+        while !self.stopped() && rounds != Some(stats.adverts) {
+            advert.send_on(io)?;
+            stats.adverts += 1;
+
+            let next_round = Instant::now() + self.opts.interval;
+            while let Some(left) = next_round.checked_duration_since(Instant::now()) {
+                if self.stopped() {
+                    break;
+                }
+                // Wake up regularly to notice a stop request
+                let frame = match io.recv_timeout(left.min(Duration::from_millis(100)))? {
+                    Some(frame) => frame,
+                    None => continue,
+                };
+                let pdu = Pdu::parse(&frame);
+                let dhcp = matches!(pdu.headers.get(&7), Some(Proto::Dhcpv6(_)));
+                let mut reply = match self.reply(pdu) {
+                    Some(reply) => reply,
+                    None => continue,
+                };
+                reply.build()?;
+                reply.send_on(io)?;
+                if dhcp {
+                    stats.dhcp += 1;
+                } else {
+                    stats.solicited += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn withdraw<T: PacketIo>(&self, io: &mut T) -> Result<(), PaError> {
+        let mut withdrawal =
+            self.advert_frame(self.withdrawal(), multicast_mac(ALL_NODES), ALL_NODES);
+        withdrawal.build()?;
+        withdrawal.send_on(io)?;
+        Ok(())
+    }
+
+    /// Advertises every `opts.interval` and answers solicitations and
+    /// DHCPv6 messages in between, until stopped or `rounds` unsolicited
+    /// advertisements were sent
+    ///
+    /// With `opts.withdraw` set, the router is withdrawn before returning,
+    /// also when sending failed midway.
+    pub fn run<T: PacketIo>(
+        &self,
+        io: &mut T,
+        rounds: Option<usize>,
+    ) -> Result<RouterStats, PaError> {
+        let mut stats = RouterStats::default();
+        let result = self.advertise_loop(io, rounds, &mut stats);
+        let withdrawn = if self.opts.withdraw {
+            self.withdraw(io)
+        } else {
+            Ok(())
+        };
+        result?;
+        withdrawn?;
+        Ok(stats)
+    }
+}
@@ -0,0 +1,433 @@
+use crate::error::PaError;
+use crate::hdr::{
+    dhcpv6_msg, dhcpv6_opt, dhcpv6_port, dhcpv6_status, duid_ll, eth_type, link_local, Dhcpv6,
+    Dhcpv6Option, Dhcpv6Query, EthHdr, IPv6Hdr, UdpHdr,
+};
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::{auto_reply, Pdu, QueryHdr, Rules};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Pool of prefixes delegated through IA_PD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdPool {
+    /// Prefix the delegated ones are cut from
+    pub prefix: [u8; 16],
+    pub prefix_len: u8,
+    /// Length of each delegated prefix
+    pub delegated_len: u8,
+}
+
+impl PdPool {
+    /// Delegated prefix number `index`, `None` past the end of the pool
+    fn nth(&self, index: u128) -> Option<[u8; 16]> {
+        let bits = self.delegated_len.checked_sub(self.prefix_len)?;
+        if self.delegated_len > 128 || (bits < 128 && index >> bits != 0) {
+            return None;
+        }
+        let step = 1u128.checked_shl(128 - self.delegated_len as u32)?;
+        let base = u128::from_be_bytes(self.prefix) & !(step - 1);
+        Some(base.wrapping_add(index * step).to_be_bytes())
+    }
+
+    /// Checks if `prefix` is one of the delegated prefixes
+    fn contains(&self, prefix: [u8; 16]) -> bool {
+        let first = match self.nth(0) {
+            Some(first) => u128::from_be_bytes(first),
+            None => return false,
+        };
+        let offset = u128::from_be_bytes(prefix).wrapping_sub(first);
+        let shift = 128 - self.delegated_len as u32;
+        offset.checked_shr(shift).and_then(|index| self.nth(index)) == Some(prefix)
+    }
+}
+
+/// Options of `Dhcpv6Server`
+#[derive(Debug, Clone)]
+pub struct Dhcpv6Opts {
+    pub server_mac: [u8; 6],
+    /// Link-local source of replies
+    pub server_ip: [u8; 16],
+    /// DUID sent as server identifier
+    pub duid: Vec<u8>,
+    /// First address handed out through IA_NA
+    pub pool_start: [u8; 16],
+    /// Last address handed out through IA_NA
+    pub pool_end: [u8; 16],
+    /// Prefixes handed out through IA_PD, none if `None`
+    pub pd_pool: Option<PdPool>,
+    pub dns_servers: Vec<[u8; 16]>,
+    pub domains: Vec<String>,
+    pub preferred: Duration,
+    pub valid: Duration,
+    /// Preference advertised to clients, 255 makes them skip waiting for
+    /// other servers
+    pub preference: u8,
+    /// Binds on Solicit when the client asks for rapid commit
+    pub rapid_commit: bool,
+}
+
+impl Dhcpv6Opts {
+    pub fn new(server_mac: [u8; 6], pool_start: [u8; 16], pool_end: [u8; 16]) -> Self {
+        Self {
+            server_mac,
+            server_ip: link_local(server_mac),
+            duid: duid_ll(server_mac),
+            pool_start,
+            pool_end,
+            pd_pool: None,
+            dns_servers: Vec::new(),
+            domains: Vec::new(),
+            preferred: Duration::from_secs(3600),
+            valid: Duration::from_secs(7200),
+            preference: 0,
+            rapid_commit: true,
+        }
+    }
+}
+
+/// State of a `Binding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingState {
+    Bound,
+    /// Declined by the client as in use, not handed out until it expires
+    Declined,
+}
+
+/// Address or delegated prefix bound to an IA of a client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    /// DUID of client
+    pub client: Vec<u8>,
+    pub iaid: u32,
+    pub addr: [u8; 16],
+    /// Length of delegated prefix, `None` for addresses
+    pub prefix_len: Option<u8>,
+    pub state: BindingState,
+    pub expires: Instant,
+}
+
+/// Stateful DHCPv6 server handing out addresses and delegated prefixes
+///
+/// Clones share their bindings. Relayed messages aren't supported.
+#[derive(Debug, Clone)]
+pub struct Dhcpv6Server {
+    pub opts: Dhcpv6Opts,
+    bindings: Arc<Mutex<Vec<Binding>>>,
+}
+
+fn status(code: u16, message: &str) -> Dhcpv6Option {
+    Dhcpv6Option::StatusCode {
+        code,
+        message: message.to_string(),
+    }
+}
+
+impl Dhcpv6Server {
+    pub fn new(opts: Dhcpv6Opts) -> Self {
+        Self {
+            opts,
+            bindings: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Current bindings, including expired ones not yet handed out again
+    pub fn bindings(&self) -> Vec<Binding> {
+        self.bindings.lock().unwrap().clone()
+    }
+
+    fn in_pool(&self, addr: [u8; 16]) -> bool {
+        let addr = u128::from_be_bytes(addr);
+        u128::from_be_bytes(self.opts.pool_start) <= addr
+            && addr <= u128::from_be_bytes(self.opts.pool_end)
+    }
+
+    fn taken(bindings: &[Binding], addr: [u8; 16], client: &[u8], iaid: u32, now: Instant) -> bool {
+        bindings.iter().any(|binding| {
+            binding.addr == addr
+                && binding.expires > now
+                && (binding.client != client
+                    || binding.iaid != iaid
+                    || binding.state == BindingState::Declined)
+        })
+    }
+
+    /// Picks address or prefix for IA `iaid` of `client`: the one bound to
+    /// it, the hinted one or the first free one of the pool
+    fn pick(
+        &self,
+        bindings: &[Binding],
+        client: &[u8],
+        iaid: u32,
+        prefix: bool,
+        hint: Option<[u8; 16]>,
+        now: Instant,
+    ) -> Option<[u8; 16]> {
+        let held = bindings
+            .iter()
+            .find(|b| {
+                b.client == client
+                    && b.iaid == iaid
+                    && b.prefix_len.is_some() == prefix
+                    && b.state == BindingState::Bound
+            })
+            .map(|b| b.addr);
+        let free = |addr: &[u8; 16]| !Self::taken(bindings, *addr, client, iaid, now);
+        if prefix {
+            let pool = self.opts.pd_pool.as_ref()?;
+            held.into_iter()
+                .chain(hint.filter(|hint| pool.contains(*hint)))
+                .chain((0..).map_while(|i| pool.nth(i)))
+                .find(free)
+        } else {
+            let start = u128::from_be_bytes(self.opts.pool_start);
+            let end = u128::from_be_bytes(self.opts.pool_end);
+            held.into_iter()
+                .chain(hint.filter(|hint| self.in_pool(*hint)))
+                .chain((start..=end).map(u128::to_be_bytes))
+                .find(free)
+        }
+    }
+
+    fn lifetimes(&self) -> (u32, u32) {
+        (
+            self.opts.preferred.as_secs() as u32,
+            self.opts.valid.as_secs() as u32,
+        )
+    }
+
+    /// Answers IA_NA and IA_PD options of `msg`, binding them if `bind`
+    fn assign(&self, msg: &Dhcpv6, client: &[u8], bind: bool) -> Vec<Dhcpv6Option> {
+        let now = Instant::now();
+        let (preferred, valid) = self.lifetimes();
+        let (t1, t2) = (preferred / 2, preferred / 5 * 4);
+        let mut bindings = self.bindings.lock().unwrap();
+        let mut answers = Vec::new();
+        for ia in msg.options.iter() {
+            let (iaid, prefix) = match ia {
+                Dhcpv6Option::IaNa { iaid, .. } => (*iaid, false),
+                Dhcpv6Option::IaPd { iaid, .. } => (*iaid, true),
+                _ => continue,
+            };
+            let hint = ia.options().iter().find_map(|option| match option {
+                Dhcpv6Option::IaAddr { addr, .. } => Some(*addr),
+                Dhcpv6Option::IaPrefix { prefix, .. } => Some(*prefix),
+                _ => None,
+            });
+            let picked = self.pick(&bindings, client, iaid, prefix, hint, now);
+            let options = match (picked, &self.opts.pd_pool) {
+                (Some(addr), _) if !prefix => vec![Dhcpv6Option::IaAddr {
+                    addr,
+                    preferred,
+                    valid,
+                    options: Vec::new(),
+                }],
+                (Some(addr), Some(pool)) => vec![Dhcpv6Option::IaPrefix {
+                    preferred,
+                    valid,
+                    prefix_len: pool.delegated_len,
+                    prefix: addr,
+                    options: Vec::new(),
+                }],
+                _ if prefix => vec![status(dhcpv6_status::NO_PREFIX_AVAIL, "No prefixes left")],
+                _ => vec![status(dhcpv6_status::NO_ADDRS_AVAIL, "No addresses left")],
+            };
+            if let (true, Some(addr)) = (bind, picked) {
+                bindings.retain(|b| {
+                    b.client != client
+                        || b.iaid != iaid
+                        || b.prefix_len.is_some() != prefix
+                        || b.state == BindingState::Declined
+                });
+                bindings.push(Binding {
+                    client: client.to_vec(),
+                    iaid,
+                    addr,
+                    prefix_len: self
+                        .opts
+                        .pd_pool
+                        .as_ref()
+                        .filter(|_| prefix)
+                        .map(|pool| pool.delegated_len),
+                    state: BindingState::Bound,
+                    expires: now + self.opts.valid,
+                });
+            }
+            answers.push(if prefix {
+                Dhcpv6Option::IaPd {
+                    iaid,
+                    t1,
+                    t2,
+                    options,
+                }
+            } else {
+                Dhcpv6Option::IaNa {
+                    iaid,
+                    t1,
+                    t2,
+                    options,
+                }
+            });
+        }
+        answers
+    }
+
+    /// Removes bindings of `client` listed in IAs of `msg`, marking them
+    /// declined if `decline`
+    fn unbind(&self, msg: &Dhcpv6, client: &[u8], decline: bool) {
+        let expires = Instant::now() + self.opts.valid;
+        let mut bindings = self.bindings.lock().unwrap();
+        for ia in msg.options.iter() {
+            let iaid = match ia {
+                Dhcpv6Option::IaNa { iaid, .. } | Dhcpv6Option::IaPd { iaid, .. } => *iaid,
+                _ => continue,
+            };
+            for option in ia.options() {
+                let addr = match option {
+                    Dhcpv6Option::IaAddr { addr, .. } => *addr,
+                    Dhcpv6Option::IaPrefix { prefix, .. } => *prefix,
+                    _ => continue,
+                };
+                let owned = |b: &Binding| b.client == client && b.iaid == iaid && b.addr == addr;
+                if decline {
+                    bindings.iter_mut().filter(|b| owned(b)).for_each(|b| {
+                        b.state = BindingState::Declined;
+                        b.expires = expires;
+                    });
+                } else {
+                    bindings.retain(|b| !owned(b));
+                }
+            }
+        }
+    }
+
+    fn config_options(&self) -> Vec<Dhcpv6Option> {
+        let mut options = Vec::new();
+        if !self.opts.dns_servers.is_empty() {
+            options.push(Dhcpv6Option::DnsServers(self.opts.dns_servers.clone()));
+        }
+        if !self.opts.domains.is_empty() {
+            options.push(Dhcpv6Option::DomainList(self.opts.domains.clone()));
+        }
+        options
+    }
+
+    /// Crafts reply to client message `msg`, `None` if it must be dropped
+    ///
+    /// Request, Renew and Rebind all bind the requested IAs, so a client
+    /// unknown to the server is given new addresses instead of NoBinding.
+    pub fn handle(&self, msg: &Dhcpv6) -> Option<Dhcpv6> {
+        let ours = msg
+            .server_id()
+            .map(|duid| duid == self.opts.duid.as_slice());
+        let client = msg.client_id().map(<[u8]>::to_vec);
+        let mut reply = match (msg.msg_type, ours, &client) {
+            (dhcpv6_msg::INFORMATION_REQUEST, Some(false), _) => return None,
+            (dhcpv6_msg::INFORMATION_REQUEST, _, _) => msg.reply(dhcpv6_msg::REPLY),
+            (_, _, None) => return None,
+            (dhcpv6_msg::SOLICIT, None, Some(client)) => {
+                let commit =
+                    self.opts.rapid_commit && msg.option(dhcpv6_opt::RAPID_COMMIT).is_some();
+                let mut reply = if commit {
+                    msg.reply(dhcpv6_msg::REPLY)
+                } else {
+                    msg.reply(dhcpv6_msg::ADVERTISE)
+                };
+                reply.options.extend(self.assign(msg, client, commit));
+                if commit {
+                    reply.options.push(Dhcpv6Option::RapidCommit);
+                } else if self.opts.preference != 0 {
+                    reply
+                        .options
+                        .push(Dhcpv6Option::Preference(self.opts.preference));
+                }
+                reply
+            }
+            (dhcpv6_msg::REQUEST, Some(true), Some(client))
+            | (dhcpv6_msg::RENEW, Some(true), Some(client))
+            | (dhcpv6_msg::REBIND, None, Some(client)) => {
+                let mut reply = msg.reply(dhcpv6_msg::REPLY);
+                reply.options.extend(self.assign(msg, client, true));
+                reply
+            }
+            (dhcpv6_msg::RELEASE, Some(true), Some(client))
+            | (dhcpv6_msg::DECLINE, Some(true), Some(client)) => {
+                self.unbind(msg, client, msg.msg_type == dhcpv6_msg::DECLINE);
+                let mut reply = msg.reply(dhcpv6_msg::REPLY);
+                reply.options.push(status(dhcpv6_status::SUCCESS, ""));
+                reply
+            }
+            (dhcpv6_msg::CONFIRM, None, Some(_)) => {
+                let on_link = msg
+                    .options
+                    .iter()
+                    .flat_map(|ia| ia.options())
+                    .all(|option| match option {
+                        Dhcpv6Option::IaAddr { addr, .. } => self.in_pool(*addr),
+                        _ => true,
+                    });
+                let mut reply = msg.reply(dhcpv6_msg::REPLY);
+                reply.options.push(if on_link {
+                    status(dhcpv6_status::SUCCESS, "")
+                } else {
+                    status(dhcpv6_status::NOT_ON_LINK, "Not on link")
+                });
+                reply
+            }
+            _ => return None,
+        };
+        reply
+            .options
+            .insert(0, Dhcpv6Option::ServerId(self.opts.duid.clone()));
+        reply.options.extend(self.config_options());
+        Some(reply)
+    }
+
+    /// Crafts reply frame to DHCPv6 message in `pdu`, or `None` if it needs
+    /// no reply
+    ///
+    /// Replies are sent to the link-local address and MAC of the client.
+    pub fn reply(&self, pdu: Pdu) -> Option<Pdu> {
+        let (eth, ip, msg) = match (
+            pdu.headers.get(&2),
+            pdu.headers.get(&3),
+            pdu.headers.get(&7),
+        ) {
+            (Some(Proto::Eth(eth)), Some(Proto::IPv6(ip)), Some(Proto::Dhcpv6(msg))) => {
+                (eth, ip, msg)
+            }
+            _ => return None,
+        };
+        let reply = self.handle(msg)?;
+        let mut ipv6 = IPv6Hdr::new();
+        ipv6.src_ip_addr = self.opts.server_ip;
+        ipv6.dst_ip_addr = ip.src_ip_addr;
+        let frame = Pdu::new()
+            .header(EthHdr::from_raw(
+                self.opts.server_mac,
+                eth.src_hw_addr,
+                eth_type::IPv6 as u16,
+            ))
+            .header(ipv6)
+            .header(UdpHdr::from(dhcpv6_port::SERVER, dhcpv6_port::CLIENT))
+            .header(reply);
+        Some(frame)
+    }
+
+    /// Rules answering client messages, as taken by `Channel::auto_reply`
+    pub fn rules(&self) -> Rules {
+        let server = self.clone();
+        let mut rules = Rules::new();
+        rules.add_rule(QueryHdr::Dhcpv6(Dhcpv6Query::new()), move |pdu| {
+            server.reply(pdu)
+        });
+        rules
+    }
+
+    /// Answers client messages received on `io` until `limit` replies are
+    /// sent. Returns number of replies sent.
+    pub fn run<T: PacketIo>(&self, io: &mut T, limit: Option<usize>) -> Result<usize, PaError> {
+        auto_reply(io, &self.rules(), limit)
+    }
+}
@@ -0,0 +1,191 @@
+//! Contains `L3Channel`, sending and receiving packets at IP layer
+//!
+//! Packets sent on `L3Channel` start at IP header, the kernel does the route
+//! lookup and neighbour resolution and adds the link layer header.
+
+use crate::dstructs::Bits;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::{Hdr, IPv6Hdr};
+use crate::io::PacketIo;
+use crate::sys;
+use std::convert::TryInto;
+use std::os::unix::io::{AsRawFd, OwnedFd};
+use std::time::Duration;
+
+/// Largest packet read from the socket at once
+const MAX_PACKET_LEN: usize = 65535;
+
+/// IP version used by `L3Channel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpVersion {
+    V4,
+    V6,
+}
+
+/// Raw IP channel built on `SOCK_RAW` with header included
+///
+/// Sends any IPv4 (or IPv6) packet and receives packets of one IP protocol,
+/// e.g. `ip_proto::ICMP`. Received IPv4 packets start at IP header. Kernel
+/// does not pass IPv6 header to raw sockets, so it is rebuilt from ancillary
+/// data and received IPv6 packets also start at IP header.
+pub struct L3Channel {
+    send_fd: OwnedFd,
+    recv_fd: OwnedFd,
+    version: IpVersion,
+    proto: u8,
+    buffer: Vec<u8>,
+}
+
+impl L3Channel {
+    /// Opens IPv4 channel receiving packets of IP protocol `proto`
+    pub fn new(proto: u8) -> Result<Self, PaError> {
+        let send_fd = sys::raw_socket(libc::AF_INET, libc::IPPROTO_RAW)?;
+        sys::set_option(&send_fd, libc::IPPROTO_IP, libc::IP_HDRINCL, 1)?;
+        let recv_fd = sys::raw_socket(libc::AF_INET, proto.into())?;
+        Ok(Self {
+            send_fd,
+            recv_fd,
+            version: IpVersion::V4,
+            proto,
+            buffer: vec![0; MAX_PACKET_LEN],
+        })
+    }
+
+    /// Opens IPv6 channel receiving packets with next header `proto`
+    pub fn new_v6(proto: u8) -> Result<Self, PaError> {
+        let send_fd = sys::raw_socket(libc::AF_INET6, libc::IPPROTO_RAW)?;
+        sys::set_option(&send_fd, libc::IPPROTO_IPV6, libc::IPV6_HDRINCL, 1)?;
+        let recv_fd = sys::raw_socket(libc::AF_INET6, proto.into())?;
+        sys::set_option(&recv_fd, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, 1)?;
+        sys::set_option(&recv_fd, libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT, 1)?;
+        Ok(Self {
+            send_fd,
+            recv_fd,
+            version: IpVersion::V6,
+            proto,
+            buffer: vec![0; MAX_PACKET_LEN],
+        })
+    }
+
+    pub fn version(&self) -> IpVersion {
+        self.version
+    }
+
+    fn send_v4(&self, packet: &[u8]) -> Result<usize, PaError> {
+        let mut addr: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+        addr.sin_family = libc::AF_INET as libc::sa_family_t;
+        addr.sin_addr.s_addr = u32::from_ne_bytes(packet[16..20].try_into().unwrap());
+        let ret = unsafe {
+            libc::sendto(
+                self.send_fd.as_raw_fd(),
+                packet.as_ptr() as *const libc::c_void,
+                packet.len(),
+                0,
+                &addr as *const libc::sockaddr_in as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(ret as usize)
+    }
+
+    fn send_v6(&self, packet: &[u8]) -> Result<usize, PaError> {
+        let mut addr: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+        addr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+        addr.sin6_addr.s6_addr = packet[24..40].try_into().unwrap();
+        let ret = unsafe {
+            libc::sendto(
+                self.send_fd.as_raw_fd(),
+                packet.as_ptr() as *const libc::c_void,
+                packet.len(),
+                0,
+                &addr as *const libc::sockaddr_in6 as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(ret as usize)
+    }
+
+    fn recv_v6(&mut self) -> Result<Vec<u8>, PaError> {
+        let mut src: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+        let mut control = [0u8; 128];
+        let mut iov = libc::iovec {
+            iov_base: self.buffer.as_mut_ptr() as *mut libc::c_void,
+            iov_len: self.buffer.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_name = &mut src as *mut libc::sockaddr_in6 as *mut libc::c_void;
+        msg.msg_namelen = std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len() as _;
+
+        let len = unsafe { libc::recvmsg(self.recv_fd.as_raw_fd(), &mut msg, 0) };
+        if len < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let len = len as usize;
+
+        let mut hdr = IPv6Hdr::new();
+        hdr.next_hdr = Bits::from(self.proto.into(), 8);
+        hdr.payload_len = Bits::from(len, 16);
+        hdr.src_ip_addr = src.sin6_addr.s6_addr;
+
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let (level, cmsg_type) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type) };
+            if level == libc::IPPROTO_IPV6 && cmsg_type == libc::IPV6_PKTINFO {
+                let info = unsafe {
+                    std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::in6_pktinfo)
+                };
+                hdr.dst_ip_addr = info.ipi6_addr.s6_addr;
+            } else if level == libc::IPPROTO_IPV6 && cmsg_type == libc::IPV6_HOPLIMIT {
+                let hop_limit = unsafe {
+                    std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int)
+                };
+                hdr.hop_limit = Bits::from(hop_limit as usize & 0xff, 8);
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+
+        let mut packet: Vec<u8> = hdr.create()?.into();
+        packet.extend_from_slice(&self.buffer[..len]);
+        Ok(packet)
+    }
+}
+
+impl PacketIo for L3Channel {
+    fn send(&mut self, packet: &[u8]) -> Result<usize, PaError> {
+        match (self.version, packet.first().map(|b| b >> 4)) {
+            (IpVersion::V4, Some(4)) if packet.len() >= 20 => self.send_v4(packet),
+            (IpVersion::V6, Some(6)) if packet.len() >= 40 => self.send_v6(packet),
+            _ => Err(PaError::new(
+                "Packet does not start with IP header of channel version",
+                ErrorType::ConstructError,
+            )),
+        }
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, PaError> {
+        match self.version {
+            IpVersion::V4 => {
+                let len = sys::recv(&self.recv_fd, &mut self.buffer)?;
+                Ok(self.buffer[..len].to_vec())
+            }
+            IpVersion::V6 => self.recv_v6(),
+        }
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, PaError> {
+        if !sys::poll_readable(&self.recv_fd, sys::poll_timeout(timeout))? {
+            return Ok(None);
+        }
+        self.recv().map(Some)
+    }
+}
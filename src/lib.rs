@@ -1,13 +1,41 @@
 mod error;
 pub use error::*;
+pub mod arp;
+pub mod dhcp;
+pub mod dns;
+pub mod flow;
+pub mod frag;
 pub mod hdr;
+pub mod iface;
+pub mod io;
+pub mod ipv6;
 mod pdu;
 pub mod proto;
 mod query;
+#[cfg(feature = "pcap")]
+pub mod replay;
 pub use query::*;
+mod link;
 pub mod macros;
+pub mod ping;
 mod sock;
+pub mod tcp;
+pub mod traceroute;
 pub mod utility;
 pub use pdu::*;
 pub use sock::*;
+#[cfg(all(feature = "async", target_os = "linux"))]
+mod async_sock;
+#[cfg(target_os = "linux")]
+mod l3_sock;
+#[cfg(target_os = "linux")]
+mod sys;
+#[cfg(all(feature = "async", target_os = "linux"))]
+pub use async_sock::*;
+#[cfg(target_os = "linux")]
+pub use l3_sock::*;
+#[cfg(target_os = "linux")]
+mod ring_sock;
+#[cfg(target_os = "linux")]
+pub use ring_sock::*;
 pub mod dstructs;
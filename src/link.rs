@@ -0,0 +1,155 @@
+//! Sending IP probes either at IP layer or wrapped in Ethernet
+
+use crate::arp::ArpResolver;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::EthHdr;
+use crate::iface::Interface;
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::Pdu;
+use std::convert::TryInto;
+use std::time::{Duration, Instant};
+
+/// Start of the IPv4 packet quoted in an ICMP error
+pub(crate) struct Quote {
+    pub(crate) proto: u8,
+    pub(crate) dst: [u8; 4],
+    /// First 8 bytes following IPv4 header, the least ICMP errors quote
+    pub(crate) l4: [u8; 8],
+}
+
+impl Quote {
+    /// Reads quote from body of ICMP error `pdu`
+    pub(crate) fn parse(pdu: &Pdu) -> Option<Self> {
+        let quote = match pdu.headers.get(&7) {
+            Some(Proto::Raw(raw)) => &raw.data,
+            _ => return None,
+        };
+        let ihl = (*quote.first()? & 0xf) as usize * 4;
+        if ihl < 20 || quote.len() < ihl + 8 {
+            return None;
+        }
+        Some(Self {
+            proto: quote[9],
+            dst: quote[16..20].try_into().unwrap(),
+            l4: quote[ihl..ihl + 8].try_into().unwrap(),
+        })
+    }
+
+    /// Returns 16 bit word of `l4` at byte `at`, such as a port
+    pub(crate) fn word(&self, at: usize) -> u16 {
+        u16::from_be_bytes([self.l4[at], self.l4[at + 1]])
+    }
+
+    /// Returns sequence number of quoted TCP segment
+    pub(crate) fn seq(&self) -> u32 {
+        u32::from_be_bytes(self.l4[4..8].try_into().unwrap())
+    }
+}
+
+/// Link used by probing tools such as `ping` and `traceroute`
+///
+/// Without resolver packets start at IP header, as on `L3Channel`. With a
+/// resolver they are Ethernet frames, as on `Channel`, and the destination
+/// MAC is resolved through it.
+pub(crate) struct IpLink<'a> {
+    resolver: Option<&'a mut ArpResolver>,
+}
+
+impl<'a> IpLink<'a> {
+    pub(crate) fn new(resolver: Option<&'a mut ArpResolver>) -> Self {
+        Self { resolver }
+    }
+
+    /// Builds `pdu` holding IPv4 and upper layers, then sends it
+    ///
+    /// An unset IPv4 source is left for the kernel to fill at IP layer and
+    /// taken from the resolver on Ethernet.
+    pub(crate) fn send<T: PacketIo>(&mut self, io: &mut T, mut pdu: Pdu) -> Result<Pdu, PaError> {
+        match self.resolver.as_deref_mut() {
+            Some(resolver) => {
+                if let Some(Proto::IPv4(ipv4)) = pdu.headers.get_mut(&3) {
+                    if ipv4.src_ip_addr == [0; 4] {
+                        ipv4.src_ip_addr = resolver.src_ip;
+                    }
+                }
+                pdu.set_header(EthHdr::from_raw([0; 6], [0; 6], 0x0800));
+                pdu.build_resolved(io, resolver)?;
+            }
+            None => pdu.build()?,
+        }
+        pdu.send_on(io)?;
+        Ok(pdu)
+    }
+
+    /// Returns `ip` or, if unset, the source address packets will carry
+    ///
+    /// Needed when upper layer checksums depend on it before the kernel gets
+    /// to fill it in.
+    pub(crate) fn src_ip(&self, ip: [u8; 4]) -> Result<[u8; 4], PaError> {
+        if ip != [0; 4] {
+            return Ok(ip);
+        }
+        if let Some(resolver) = self.resolver.as_deref() {
+            return Ok(resolver.src_ip);
+        }
+        Interface::default_interface()?.ip().ok_or_else(|| {
+            PaError::new(
+                "Default interface has no IPv4 address",
+                ErrorType::InterfaceError,
+            )
+        })
+    }
+
+    /// Returns those of `ips` packets can be sent to
+    ///
+    /// On Ethernet the next hops of all `ips` are resolved at once, with
+    /// requests `interval` apart, and addresses whose next hop did not
+    /// answer are left out.
+    pub(crate) fn reachable<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        ips: &[[u8; 4]],
+        interval: Duration,
+    ) -> Result<Vec<[u8; 4]>, PaError> {
+        let resolver = match self.resolver.as_deref_mut() {
+            Some(resolver) => resolver,
+            None => return Ok(ips.to_vec()),
+        };
+        let mut hops: Vec<[u8; 4]> = ips.iter().map(|ip| resolver.next_hop(*ip)).collect();
+        hops.sort_unstable();
+        hops.dedup();
+        resolver.resolve_all(io, &hops, interval)?;
+        Ok(ips
+            .iter()
+            .copied()
+            .filter(|ip| resolver.cached(resolver.next_hop(*ip)).is_some())
+            .collect())
+    }
+
+    pub(crate) fn parse(&self, frame: &[u8]) -> Pdu {
+        if self.resolver.is_some() {
+            Pdu::parse(frame)
+        } else {
+            Pdu::parse_ip(frame)
+        }
+    }
+
+    /// Passes every `Pdu` received until `until` to `on_recv` with its arrival time
+    pub(crate) fn recv_until<T: PacketIo>(
+        &self,
+        io: &mut T,
+        until: Instant,
+        mut on_recv: impl FnMut(Pdu, Instant),
+    ) -> Result<(), PaError> {
+        while let Some(left) = until.checked_duration_since(Instant::now()) {
+            let frame = match io.recv_timeout(left)? {
+                Some(frame) => frame,
+                None => break,
+            };
+            let now = Instant::now();
+            on_recv(self.parse(&frame), now);
+        }
+        Ok(())
+    }
+}
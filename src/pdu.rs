@@ -1,94 +1,576 @@
-use crate::dstructs::Packet;
+use crate::dstructs::{Bits, Packet};
 use crate::error::PaError;
 use crate::hdr::*;
+use crate::io::PacketIo;
 use crate::proto::{EthType, Proto};
 use crate::sock::Channel;
+use crate::utility::{checksum, pseudo_checksum};
 use std::collections::HashMap;
 
+/// Returns key under which `proto` is stored in `Pdu::headers`
+fn layer(proto: &Proto) -> Option<u8> {
+    match proto {
+        Proto::Eth(_) => Some(2),
+        Proto::Arp(_) | Proto::IPv4(_) | Proto::IPv6(_) | Proto::Mpls(_) => Some(3),
+        Proto::ICMP(_) | Proto::Icmpv6(_) | Proto::UDP(_) | Proto::TCP(_) | Proto::Gre(_) => {
+            Some(4)
+        }
+        Proto::Dns(_)
+        | Proto::Dhcp(_)
+        | Proto::Dhcpv6(_)
+        | Proto::RouterSolicit(_)
+        | Proto::RouterAdvert(_)
+        | Proto::NetFlowV5(_)
+        | Proto::NetFlowV9(_)
+        | Proto::Ipfix(_)
+        | Proto::Erspan(_)
+        | Proto::Vxlan(_)
+        | Proto::Geneve(_)
+        | Proto::Raw(_) => Some(7),
+        _ => None,
+    }
+}
+
+/// Returns length of tunnel header `proto` within the payload it was
+/// parsed from, and EtherType of the frame following it
+fn tunnel(proto: &Proto) -> Option<(usize, u16)> {
+    match proto {
+        // GRE is parsed as layer 4, its payload starts after it
+        Proto::Gre(gre) => Some((0, gre.protocol)),
+        Proto::Erspan(erspan) => Some((erspan.length(), gre_proto::TEB)),
+        Proto::Vxlan(vxlan) => Some((vxlan.length(), gre_proto::TEB)),
+        Proto::Geneve(geneve) => Some((geneve.length(), geneve.protocol)),
+        _ => None,
+    }
+}
+
+/// Number of tunnel headers whose frame is parsed into a nested `inner`,
+/// frames carried deeper are kept as `Raw`
+const MAX_NESTING: usize = 8;
+
+/// Returns EtherType announcing `inner` in a tunnel header
+fn inner_type(inner: &Pdu) -> u16 {
+    match (inner.headers.get(&2), inner.headers.get(&3)) {
+        (Some(_), _) => gre_proto::TEB,
+        (None, Some(Proto::IPv4(_))) => eth_type::IPv4 as u16,
+        (None, Some(Proto::IPv6(_))) => eth_type::IPv6 as u16,
+        _ => 0,
+    }
+}
+
+/// Parses body of known ICMPv6 messages, ERSPAN following GRE and payload
+/// of UDP or TCP sent to or from a well known port, `None` keeps it as `Raw`
+fn parse_app(l4: &Proto, payload: &[u8]) -> Option<Proto> {
+    let (src, dst, tcp) = match l4 {
+        Proto::Gre(gre) => {
+            return match gre.protocol {
+                gre_proto::ERSPAN_II | gre_proto::ERSPAN_III => {
+                    Erspan::from_bytes(payload).ok().map(Proto::Erspan)
+                }
+                _ => None,
+            };
+        }
+        Proto::Icmpv6(icmp) => {
+            return match icmp.icmp_type {
+                icmpv6_type::ROUTER_SOLICIT => RouterSolicit::from_bytes(payload)
+                    .ok()
+                    .map(Proto::RouterSolicit),
+                icmpv6_type::ROUTER_ADVERT => RouterAdvert::from_bytes(payload)
+                    .ok()
+                    .map(Proto::RouterAdvert),
+                _ => None,
+            };
+        }
+        Proto::UDP(udp) => (udp.src_port.to_usize(), udp.dst_port.to_usize(), false),
+        Proto::TCP(tcp) => (tcp.src_port.to_usize(), tcp.dst_port.to_usize(), true),
+        _ => return None,
+    };
+    let on = |port: u16| src == port as usize || dst == port as usize;
+    if on(dns_port::DNS) || on(dns_port::MDNS) {
+        // Over TCP only segments holding one whole message are parsed
+        let msg = if !tcp {
+            payload
+        } else if payload.len() >= 2
+            && u16::from_be_bytes([payload[0], payload[1]]) as usize == payload.len() - 2
+        {
+            &payload[2..]
+        } else {
+            return None;
+        };
+        return Dns::from_bytes(msg).ok().map(Proto::Dns);
+    }
+    if !tcp && (on(dhcp_port::SERVER) || on(dhcp_port::CLIENT)) {
+        return Dhcp::from_bytes(payload).ok().map(Proto::Dhcp);
+    }
+    if !tcp && (on(dhcpv6_port::SERVER) || on(dhcpv6_port::CLIENT)) {
+        return Dhcpv6::from_bytes(payload).ok().map(Proto::Dhcpv6);
+    }
+    if !tcp && on(vxlan_port::VXLAN) {
+        return Vxlan::from_bytes(payload).ok().map(Proto::Vxlan);
+    }
+    if !tcp && on(geneve_port::GENEVE) {
+        return Geneve::from_bytes(payload).ok().map(Proto::Geneve);
+    }
+    if !tcp
+        && (on(netflow_port::NETFLOW)
+            || on(netflow_port::NETFLOW_ALT)
+            || on(netflow_port::NETFLOW_ALT2)
+            || on(netflow_port::IPFIX))
+    {
+        return match payload.get(..2) {
+            Some([0, 5]) => NetFlowV5::from_bytes(payload).ok().map(Proto::NetFlowV5),
+            Some([0, 9]) => NetFlowV9::from_bytes(payload).ok().map(Proto::NetFlowV9),
+            Some([0, 10]) => Ipfix::from_bytes(payload).ok().map(Proto::Ipfix),
+            _ => None,
+        };
+    }
+    None
+}
+
 pub struct Pdu {
     pub headers: HashMap<u8, Proto>,
+    /// Frame carried by a GRE, ERSPAN, VXLAN, Geneve or MPLS header, starting at
+    /// Ethernet or IP
+    pub inner: Option<Box<Pdu>>,
     pub buffer: Vec<u8>,
+    /// Bytes following L4 header when they were not kept as `Raw`
+    l4_payload: Option<Vec<u8>>,
 }
 
 impl Pdu {
     pub fn new() -> Self {
         Self {
             headers: HashMap::with_capacity(6),
+            inner: None,
             buffer: Vec::new(),
+            l4_payload: None,
+        }
+    }
+
+    /// Returns bytes following L4 header, also when they were parsed into an
+    /// application layer or inner frame, empty without payload
+    pub fn payload(&self) -> &[u8] {
+        match (self.headers.get(&7), &self.l4_payload) {
+            (Some(Proto::Raw(raw)), _) => &raw.data,
+            (_, Some(payload)) => payload,
+            _ => &[],
         }
     }
 
     pub fn parse(bits: &[u8]) -> Self {
+        Self::parse_nested(bits, 0)
+    }
+
+    /// Parses frame carried under `depth` tunnel headers
+    fn parse_nested(bits: &[u8], depth: usize) -> Self {
         let mut pack = Self::new();
+        if bits.len() < 14 {
+            return pack;
+        }
         let eth_hdr = EthHdr::parse((&bits[0..14]).into());
         pack.headers.insert(2, Proto::Eth(eth_hdr.clone()));
         match eth_hdr.get_data_type() {
             EthType::Arp => {
-                let arp_hdr = ArpHdr::parse((&bits[14..]).into());
-                pack.headers.insert(3, Proto::Arp(arp_hdr));
-            }
-            EthType::IPv4 => {
-                let ipv4_hdr = IPv4Hdr::parse((&bits[14..]).into());
-                pack.headers.insert(3, Proto::IPv4(ipv4_hdr));
+                if bits.len() >= 42 {
+                    let arp_hdr = ArpHdr::parse((&bits[14..42]).into());
+                    pack.headers.insert(3, Proto::Arp(arp_hdr));
+                }
             }
+            EthType::IPv4 | EthType::IPv6 => pack.parse_ip_layer(&bits[14..], depth),
+            EthType::Mpls => pack.parse_mpls(&bits[14..], depth),
             EthType::Unknown => {}
         };
 
         pack
     }
 
-    pub fn header(mut self, hdr: impl Hdr) -> Self {
-        match hdr.get() {
-            Proto::Arp(arp_hdr) => self.headers.insert(3, Proto::Arp(arp_hdr)),
-            Proto::Eth(eth_hdr) => self.headers.insert(2, Proto::Eth(eth_hdr)),
-            Proto::IPv4(ipv4_hdr) => self.headers.insert(3, Proto::IPv4(ipv4_hdr)),
+    /// Parses packet starting at IP layer, as received on `L3Channel`
+    pub fn parse_ip(bits: &[u8]) -> Self {
+        Self::parse_ip_nested(bits, 0)
+    }
+
+    fn parse_ip_nested(bits: &[u8], depth: usize) -> Self {
+        let mut pack = Self::new();
+        pack.parse_ip_layer(bits, depth);
+        pack
+    }
+
+    fn parse_ip_layer(&mut self, bits: &[u8], depth: usize) {
+        match bits.first().map(|b| b >> 4) {
+            Some(4) if bits.len() >= 20 => {
+                let ipv4_hdr = IPv4Hdr::parse((&bits[0..20]).into());
+                let hdr_len = ipv4_hdr.ihl.to_usize() * 4;
+                let total_len = ipv4_hdr.total_len.to_usize().min(bits.len());
+                let proto = ipv4_hdr.proto.to_usize() as u8;
+                let first_frag = ipv4_hdr.frag_offset.to_usize() == 0;
+                self.headers.insert(3, Proto::IPv4(ipv4_hdr));
+                if hdr_len >= 20 && hdr_len < total_len {
+                    let payload = &bits[hdr_len..total_len];
+                    // Only first fragment starts with upper layer header
+                    if first_frag {
+                        self.parse_payload(proto, payload, depth);
+                    } else {
+                        self.headers.insert(7, Proto::Raw(Raw::from(payload)));
+                    }
+                }
+            }
+            Some(6) if bits.len() >= 40 => {
+                let ipv6_hdr = IPv6Hdr::parse((&bits[0..40]).into());
+                let total_len = (40 + ipv6_hdr.payload_len.to_usize()).min(bits.len());
+                let next_hdr = ipv6_hdr.next_hdr.to_usize() as u8;
+                self.headers.insert(3, Proto::IPv6(ipv6_hdr));
+                if total_len > 40 {
+                    self.parse_payload(next_hdr, &bits[40..total_len], depth);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Parses label stack and the packet it carries, guessed from the labels
+    /// and first nibble following them
+    fn parse_mpls(&mut self, bits: &[u8], depth: usize) {
+        let mpls = match Mpls::from_bytes(bits) {
+            Ok(mpls) => mpls,
+            Err(_) => return,
+        };
+        let payload = &bits[mpls.length()..];
+        self.inner = match mpls.payload_type(payload) {
+            _ if depth >= MAX_NESTING => None,
+            gre_proto::TEB if payload.len() >= 14 => {
+                Some(Box::new(Pdu::parse_nested(payload, depth + 1)))
+            }
+            gre_proto::TEB => None,
+            _ if !payload.is_empty() => Some(Box::new(Pdu::parse_ip_nested(payload, depth + 1))),
             _ => None,
         };
+        if self.inner.is_none() && !payload.is_empty() {
+            self.headers.insert(7, Proto::Raw(Raw::from(payload)));
+        }
+        self.headers.insert(3, Proto::Mpls(mpls));
+    }
+
+    /// Parses data carried by IP protocol `proto`
+    fn parse_payload(&mut self, proto: u8, bits: &[u8], depth: usize) {
+        let hdr_len = match proto {
+            ip_proto::ICMP if bits.len() >= 8 => {
+                self.headers
+                    .insert(4, Proto::ICMP(IcmpHdr::parse((&bits[0..8]).into())));
+                8
+            }
+            ip_proto::ICMPV6 if bits.len() >= 4 => {
+                self.headers
+                    .insert(4, Proto::Icmpv6(Icmpv6Hdr::parse((&bits[0..4]).into())));
+                4
+            }
+            ip_proto::GRE => match Gre::from_bytes(bits) {
+                Ok(gre) => {
+                    let hdr_len = gre.length();
+                    self.headers.insert(4, Proto::Gre(gre));
+                    hdr_len
+                }
+                Err(_) => 0,
+            },
+            ip_proto::UDP if bits.len() >= 8 => {
+                self.headers
+                    .insert(4, Proto::UDP(UdpHdr::parse((&bits[0..8]).into())));
+                8
+            }
+            ip_proto::TCP if bits.len() >= 20 => {
+                let hdr_len = (bits[12] >> 4) as usize * 4;
+                if hdr_len < 20 || hdr_len > bits.len() {
+                    0
+                } else {
+                    self.headers
+                        .insert(4, Proto::TCP(TcpHdr::parse((&bits[0..hdr_len]).into())));
+                    hdr_len
+                }
+            }
+            _ => 0,
+        };
+        if bits.len() > hdr_len {
+            let payload = &bits[hdr_len..];
+            let mut app = match self.headers.get(&4) {
+                Some(l4) => parse_app(l4, payload),
+                None => None,
+            };
+            // Tunnels carry a frame following their header
+            let mut tunnel = match (&app, self.headers.get(&4)) {
+                (Some(app), _) => tunnel(app),
+                (None, Some(l4)) => tunnel(l4),
+                _ => None,
+            };
+            if tunnel.is_some() && depth >= MAX_NESTING {
+                // Too deep to follow, the whole payload is kept raw
+                app = None;
+                tunnel = None;
+            }
+            self.inner = match tunnel {
+                Some((len, gre_proto::TEB)) if payload.len() > len => {
+                    Some(Box::new(Pdu::parse_nested(&payload[len..], depth + 1)))
+                }
+                Some((len, ethertype))
+                    if payload.len() > len
+                        && (ethertype as usize == eth_type::IPv4
+                            || ethertype as usize == eth_type::IPv6) =>
+                {
+                    Some(Box::new(Pdu::parse_ip_nested(&payload[len..], depth + 1)))
+                }
+                _ => None,
+            };
+            match app {
+                Some(app) => {
+                    self.headers.insert(7, app);
+                    self.l4_payload = Some(payload.to_vec());
+                }
+                None if self.inner.is_none() => {
+                    self.headers.insert(7, Proto::Raw(Raw::from(payload)));
+                }
+                None => self.l4_payload = Some(payload.to_vec()),
+            }
+        }
+    }
 
+    pub fn header(mut self, hdr: impl Hdr) -> Self {
+        self.set_header(hdr);
         self
     }
 
     pub fn set_header(&mut self, hdr: impl Hdr) {
-        match hdr.get() {
-            Proto::Arp(arp_hdr) => self.headers.insert(3, Proto::Arp(arp_hdr)),
-            Proto::Eth(eth_hdr) => self.headers.insert(2, Proto::Eth(eth_hdr)),
-            Proto::IPv4(ipv4_hdr) => self.headers.insert(3, Proto::IPv4(ipv4_hdr)),
-            _ => None,
-        };
+        let proto = hdr.get();
+        if let Some(layer) = layer(&proto) {
+            self.headers.insert(layer, proto);
+        }
     }
 
+    /// Creates `buffer` from headers
+    ///
+    /// Length, protocol and checksum fields left to `0` are filled in. A
+    /// `Pdu` without Ethernet header is built starting at next layer. An
+    /// `inner` frame is built and appended to its tunnel header.
     pub fn build(&mut self) -> Result<(), PaError> {
-        match self.headers.get(&3) {
-            Some(hdr) => match hdr {
-                Proto::Arp(arp) => match self.headers.get(&2) {
-                    Some(hdr2) => match hdr2 {
-                        Proto::Eth(eth) => {
-                            self.buffer = eth.encapsulate(arp.clone())?;
-                        }
-                        _ => {
-                            self.buffer = vec![];
-                        }
-                    },
+        let (inner, inner_type) = match self.inner.as_mut() {
+            Some(inner) => {
+                inner.build()?;
+                (inner.buffer.clone(), inner_type(inner))
+            }
+            None => (Vec::new(), 0),
+        };
+        let mut payload: Vec<u8> = match self.headers.get(&7) {
+            Some(Proto::Raw(raw)) => raw.data.clone(),
+            Some(Proto::Dns(dns)) => {
+                let mut msg = dns.to_bytes()?;
+                if let Some(Proto::TCP(_)) = self.headers.get(&4) {
+                    // DNS over TCP is preceded by message length
+                    let len = (msg.len() as u16).to_be_bytes();
+                    msg.splice(0..0, len.iter().copied());
+                }
+                msg
+            }
+            Some(Proto::Dhcp(dhcp)) => dhcp.to_bytes()?,
+            Some(Proto::Dhcpv6(dhcpv6)) => dhcpv6.to_bytes()?,
+            Some(Proto::RouterSolicit(rs)) => rs.to_bytes()?,
+            Some(Proto::RouterAdvert(ra)) => ra.to_bytes()?,
+            Some(Proto::NetFlowV5(netflow)) => netflow.to_bytes(),
+            Some(Proto::NetFlowV9(netflow)) => netflow.to_bytes(),
+            Some(Proto::Ipfix(ipfix)) => ipfix.to_bytes(),
+            Some(Proto::Erspan(erspan)) => erspan.to_bytes(),
+            Some(Proto::Vxlan(vxlan)) => vxlan.to_bytes(),
+            Some(Proto::Geneve(geneve)) => {
+                let mut geneve = geneve.clone();
+                if geneve.protocol == 0 {
+                    geneve.protocol = inner_type;
+                }
+                geneve.to_bytes()?
+            }
+            _ => Vec::new(),
+        };
+        if let Some(Proto::Erspan(_)) | Some(Proto::Vxlan(_)) | Some(Proto::Geneve(_)) | None =
+            self.headers.get(&7)
+        {
+            payload.extend_from_slice(&inner);
+        }
+        let (payload, proto) = self.build_l4(payload, inner_type)?;
+
+        let mut data: Vec<u8> = match self.headers.get(&3) {
+            Some(Proto::Arp(arp)) => arp.create()?.into(),
+            Some(Proto::IPv4(ipv4)) => {
+                let mut ipv4 = ipv4.clone();
+                if let (0, Some(proto)) = (ipv4.proto.to_usize(), proto) {
+                    ipv4.proto = Bits::from(proto.into(), 8);
+                }
+                if ipv4.total_len.to_usize() == 0 {
+                    ipv4.total_len = Bits::from(20 + payload.len(), 16);
+                }
+                if ipv4.hdr_checksum.to_usize() == 0 {
+                    ipv4.set_checksum()?;
+                }
+                ipv4.create()?.into()
+            }
+            Some(Proto::IPv6(ipv6)) => {
+                let mut ipv6 = ipv6.clone();
+                match (ipv6.next_hdr.to_usize() as u8, proto) {
+                    (0, Some(proto)) if proto != ip_proto::ICMP => {
+                        ipv6.next_hdr = Bits::from(proto.into(), 8);
+                    }
                     _ => {}
-                },
-                Proto::IPv4(ipv4) => match self.headers.get(&2) {
-                    Some(hdr2) => match hdr2 {
-                        Proto::Eth(eth) => {
-                            self.buffer = eth.encapsulate(ipv4.clone())?;
-                        }
-                        _ => {
-                            self.buffer = vec![];
-                        }
-                    },
-                    _ => {} /* Currently in Development */
-                },
-                _ => {}
-            },
-            _ => {}
+                }
+                if ipv6.payload_len.to_usize() == 0 {
+                    ipv6.payload_len = Bits::from(payload.len(), 16);
+                }
+                ipv6.create()?.into()
+            }
+            Some(Proto::Mpls(mpls)) => mpls.to_bytes(),
+            _ => Vec::new(),
         };
+        data.extend_from_slice(&payload);
+
+        if let Some(Proto::Eth(eth)) = self.headers.get(&2) {
+            data = eth.encapsulate_raw(data)?;
+        }
+
+        self.buffer = data;
         Ok(())
     }
 
+    /// Returns layer 4 header followed by `payload`, and its IP protocol
+    ///
+    /// `inner_type` is the EtherType of a frame carried by GRE.
+    fn build_l4(
+        &self,
+        mut payload: Vec<u8>,
+        inner_type: u16,
+    ) -> Result<(Vec<u8>, Option<u8>), PaError> {
+        let addrs: Option<(&[u8], &[u8])> = match self.headers.get(&3) {
+            Some(Proto::IPv4(ipv4)) => Some((&ipv4.src_ip_addr, &ipv4.dst_ip_addr)),
+            Some(Proto::IPv6(ipv6)) => Some((&ipv6.src_ip_addr, &ipv6.dst_ip_addr)),
+            _ => None,
+        };
+
+        let (mut segment, proto): (Vec<u8>, u8) = match self.headers.get(&4) {
+            Some(Proto::ICMP(icmp)) => {
+                let mut icmp = icmp.clone();
+                if icmp.checksum.to_usize() == 0 {
+                    icmp.set_checksum(&payload)?;
+                }
+                (icmp.create()?.into(), ip_proto::ICMP)
+            }
+            Some(Proto::Icmpv6(icmp)) => {
+                let mut segment: Vec<u8> = icmp.create()?.into();
+                if let (0, Some((src, dst))) = (icmp.checksum, addrs) {
+                    segment.extend_from_slice(&payload);
+                    let sum = pseudo_checksum(src, dst, ip_proto::ICMPV6, &segment);
+                    segment.truncate(icmp.length());
+                    segment[2..4].copy_from_slice(&sum.to_be_bytes());
+                }
+                (segment, ip_proto::ICMPV6)
+            }
+            Some(Proto::Gre(gre)) => {
+                let mut gre = gre.clone();
+                if gre.protocol == 0 {
+                    gre.protocol = match self.headers.get(&7) {
+                        Some(Proto::Erspan(erspan)) => erspan.gre_protocol(),
+                        _ => inner_type,
+                    };
+                }
+                let mut segment = gre.to_bytes();
+                if gre.checksum == Some(0) {
+                    segment.extend_from_slice(&payload);
+                    let sum = checksum(&segment);
+                    segment.truncate(gre.length());
+                    segment[4..6].copy_from_slice(&sum.to_be_bytes());
+                }
+                (segment, ip_proto::GRE)
+            }
+            Some(Proto::UDP(udp)) => {
+                let mut udp = udp.clone();
+                if udp.length.to_usize() == 0 {
+                    udp.length = Bits::from(udp.length() + payload.len(), 16);
+                }
+                let mut segment: Vec<u8> = udp.create()?.into();
+                if let (0, Some((src, dst))) = (udp.checksum.to_usize(), addrs) {
+                    segment.extend_from_slice(&payload);
+                    let sum = match pseudo_checksum(src, dst, ip_proto::UDP, &segment) {
+                        0 => 0xffff,
+                        sum => sum,
+                    };
+                    segment.truncate(udp.length());
+                    segment[6..8].copy_from_slice(&sum.to_be_bytes());
+                }
+                (segment, ip_proto::UDP)
+            }
+            Some(Proto::TCP(tcp)) => {
+                let mut segment: Vec<u8> = tcp.create()?.into();
+                if let (0, Some((src, dst))) = (tcp.checksum.to_usize(), addrs) {
+                    segment.extend_from_slice(&payload);
+                    let sum = pseudo_checksum(src, dst, ip_proto::TCP, &segment);
+                    segment.truncate(tcp.length());
+                    segment[16..18].copy_from_slice(&sum.to_be_bytes());
+                }
+                (segment, ip_proto::TCP)
+            }
+            _ => return Ok((payload, None)),
+        };
+        segment.append(&mut payload);
+        Ok((segment, Some(proto)))
+    }
+
+    /// Checks if this `Pdu` looks like a reply to `request`
+    ///
+    /// ARP replies must resolve the requested address, IPv4 and IPv6 packets
+    /// must come back from the destination of `request`, or from anyone when
+    /// an IPv6 `request` went to a multicast group, other frames must be
+    /// addressed to the sender of `request`. ICMP and ICMPv6 echo replies must
+    /// also carry the identifier and sequence number of the request.
+    pub fn answers(&self, request: &Pdu) -> bool {
+        if let (Some(Proto::ICMP(reply)), Some(Proto::ICMP(req))) =
+            (self.headers.get(&4), request.headers.get(&4))
+        {
+            if req.icmp_type.to_usize() == icmp_type::ECHO_REQUEST as usize
+                && (reply.icmp_type.to_usize() != icmp_type::ECHO_REPLY as usize
+                    || reply.id != req.id
+                    || reply.seq != req.seq)
+            {
+                return false;
+            }
+        }
+        if let (Some(Proto::Icmpv6(reply)), Some(Proto::Icmpv6(req))) =
+            (self.headers.get(&4), request.headers.get(&4))
+        {
+            // Identifier and sequence number start the echo body
+            let id_seq = |pdu: &Pdu| match pdu.headers.get(&7) {
+                Some(Proto::Raw(raw)) => raw.data.get(..4).map(<[u8]>::to_vec),
+                _ => None,
+            };
+            if req.icmp_type == icmpv6_type::ECHO_REQUEST
+                && (reply.icmp_type != icmpv6_type::ECHO_REPLY || id_seq(self) != id_seq(request))
+            {
+                return false;
+            }
+        }
+        match (self.headers.get(&3), request.headers.get(&3)) {
+            (Some(Proto::Arp(reply)), Some(Proto::Arp(req))) => {
+                reply.opr.to_usize() == REP as usize
+                    && reply.src_proto_addr == req.dst_proto_addr
+                    && reply.dst_proto_addr == req.src_proto_addr
+            }
+            (Some(Proto::IPv4(reply)), Some(Proto::IPv4(req))) => {
+                reply.src_ip_addr == req.dst_ip_addr && reply.dst_ip_addr == req.src_ip_addr
+            }
+            (Some(Proto::IPv6(reply)), Some(Proto::IPv6(req))) => {
+                (reply.src_ip_addr == req.dst_ip_addr || req.dst_ip_addr[0] == 0xff)
+                    && reply.dst_ip_addr == req.src_ip_addr
+            }
+            (_, Some(_)) => false,
+            _ => match (self.headers.get(&2), request.headers.get(&2)) {
+                (Some(Proto::Eth(reply)), Some(Proto::Eth(req))) => {
+                    reply.dst_hw_addr == req.src_hw_addr
+                }
+                _ => false,
+            },
+        }
+    }
+
     pub fn send_and_recv(&self, interface_name: Option<String>) -> Result<Packet, PaError> {
         let mut c;
         if interface_name.is_none() {
@@ -96,11 +578,7 @@ impl Pdu {
         } else {
             c = Channel::from(interface_name.unwrap())?;
         }
-        //TODO: Check if received raw data is really a response of your send data.
-        c.send_packet(&self.buffer);
-        let bits = c.recv();
-        println!("{:?}", bits);
-        Ok(bits.into())
+        self.send_and_recv_on(&mut c)
     }
 
     pub fn send(&self, interface_name: Option<String>) -> Result<usize, PaError> {
@@ -110,7 +588,19 @@ impl Pdu {
         } else {
             c = Channel::from(interface_name.unwrap())?;
         }
-        c.send_packet(&self.buffer);
-        Ok(self.buffer.len())
+        self.send_on(&mut c)
+    }
+
+    /// Sends built buffer on any `PacketIo`
+    pub fn send_on<T: PacketIo>(&self, io: &mut T) -> Result<usize, PaError> {
+        io.send(&self.buffer)
+    }
+
+    /// Sends built buffer on any `PacketIo` and returns next received frame
+    pub fn send_and_recv_on<T: PacketIo>(&self, io: &mut T) -> Result<Packet, PaError> {
+        //TODO: Check if received raw data is really a response of your send data.
+        io.send(&self.buffer)?;
+        let bits = io.recv()?;
+        Ok(bits.into())
     }
 }
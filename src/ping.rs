@@ -0,0 +1,425 @@
+//! ICMP echo (ping) with RTT statistics and subnet sweep
+//!
+//! Functions without suffix send packets starting at IP header, as expected
+//! by `L3Channel`. The `_eth` variants send Ethernet frames, as expected by
+//! `Channel`, and find destination MACs through an `ArpResolver`.
+
+use crate::arp::ArpResolver;
+use crate::dstructs::Bits;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::{icmp_type, IPv4Hdr, IcmpHdr, Raw};
+use crate::iface::Ipv4Net;
+use crate::io::PacketIo;
+use crate::link::IpLink;
+use crate::proto::Proto;
+use crate::utility::parse_ip;
+use crate::Pdu;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Options of `ping` and `ping_sweep`
+#[derive(Debug, Clone)]
+pub struct PingOpts {
+    /// Number of echo requests sent to each target
+    pub count: usize,
+    /// Time between two requests to the same target
+    pub interval: Duration,
+    /// Requests sent per second by sweeps, `None` sends as fast as possible
+    pub rate: Option<f64>,
+    /// How long a reply is awaited
+    pub timeout: Duration,
+    pub ttl: u8,
+    pub tos: u8,
+    /// Set Don't Fragment flag
+    pub dont_frag: bool,
+    /// Number of payload bytes after ICMP header
+    pub size: usize,
+    /// Bytes repeated to fill payload
+    pub pattern: Vec<u8>,
+    /// ICMP identifier of requests
+    pub id: u16,
+    /// Source IP, `0.0.0.0` lets kernel (or resolver for `_eth` variants) pick it
+    pub src_ip: [u8; 4],
+}
+
+impl PingOpts {
+    pub fn new() -> Self {
+        Self {
+            count: 4,
+            interval: Duration::from_secs(1),
+            rate: Some(1000.0),
+            timeout: Duration::from_secs(1),
+            ttl: 64,
+            tos: 0,
+            dont_frag: false,
+            size: 56,
+            pattern: (0..=255).collect(),
+            id: std::process::id() as u16,
+            src_ip: [0; 4],
+        }
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        if self.pattern.is_empty() {
+            return vec![0; self.size];
+        }
+        self.pattern
+            .iter()
+            .copied()
+            .cycle()
+            .take(self.size)
+            .collect()
+    }
+}
+
+impl Default for PingOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Result of one echo request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probe {
+    pub seq: u16,
+    /// `None` if no reply came within timeout
+    pub rtt: Option<Duration>,
+    /// TTL of reply
+    pub ttl: Option<u8>,
+    /// Size of ICMP reply
+    pub bytes: usize,
+}
+
+/// Round trip time summary, as printed by `ping`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RttStats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    /// Standard deviation of RTTs
+    pub mdev: Duration,
+}
+
+impl RttStats {
+    /// Returns summary of `rtts`, `None` if it is empty
+    pub fn from(rtts: &[Duration]) -> Option<Self> {
+        if rtts.is_empty() {
+            return None;
+        }
+        let secs: Vec<f64> = rtts.iter().map(Duration::as_secs_f64).collect();
+        let n = secs.len() as f64;
+        let avg = secs.iter().sum::<f64>() / n;
+        let sq_avg = secs.iter().map(|s| s * s).sum::<f64>() / n;
+        Some(Self {
+            min: *rtts.iter().min().unwrap(),
+            avg: Duration::from_secs_f64(avg),
+            max: *rtts.iter().max().unwrap(),
+            mdev: Duration::from_secs_f64((sq_avg - avg * avg).max(0.0).sqrt()),
+        })
+    }
+}
+
+/// Result of `ping`
+#[derive(Debug, Clone)]
+pub struct PingStats {
+    pub target: [u8; 4],
+    pub probes: Vec<Probe>,
+    pub transmitted: usize,
+    pub received: usize,
+    pub rtt: Option<RttStats>,
+}
+
+impl PingStats {
+    fn from(target: [u8; 4], probes: Vec<Probe>) -> Self {
+        let rtts: Vec<Duration> = probes.iter().filter_map(|probe| probe.rtt).collect();
+        Self {
+            target,
+            transmitted: probes.len(),
+            received: rtts.len(),
+            rtt: RttStats::from(&rtts),
+            probes,
+        }
+    }
+
+    /// Returns percentage of requests without reply
+    pub fn loss(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        (self.transmitted - self.received) as f64 * 100.0 / self.transmitted as f64
+    }
+}
+
+impl fmt::Display for PingStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} packets transmitted, {} received, {}% packet loss",
+            self.transmitted,
+            self.received,
+            self.loss().round()
+        )?;
+        if let Some(rtt) = self.rtt {
+            let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+            write!(
+                f,
+                "\nrtt min/avg/max/mdev = {:.3}/{:.3}/{:.3}/{:.3} ms",
+                ms(rtt.min),
+                ms(rtt.avg),
+                ms(rtt.max),
+                ms(rtt.mdev)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Host which answered `ping_sweep`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SweepResult {
+    pub ip: [u8; 4],
+    pub rtt: Duration,
+    pub ttl: u8,
+}
+
+/// Echo reply read from a received frame
+struct Reply {
+    src: [u8; 4],
+    seq: u16,
+    ttl: u8,
+    bytes: usize,
+}
+
+/// Sends echo requests either at IP layer or wrapped in Ethernet
+struct Pinger<'a> {
+    opts: &'a PingOpts,
+    link: IpLink<'a>,
+    payload: Vec<u8>,
+}
+
+impl<'a> Pinger<'a> {
+    fn new(opts: &'a PingOpts, resolver: Option<&'a mut ArpResolver>) -> Self {
+        Self {
+            opts,
+            link: IpLink::new(resolver),
+            payload: opts.payload(),
+        }
+    }
+
+    fn send_request<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        target: [u8; 4],
+        seq: u16,
+    ) -> Result<(), PaError> {
+        let mut ipv4 = IPv4Hdr::new();
+        ipv4.src_ip_addr = self.opts.src_ip;
+        ipv4.dst_ip_addr = target;
+        ipv4.ttl = Bits::from(self.opts.ttl.into(), 8);
+        ipv4.tos = Bits::from(self.opts.tos.into(), 8);
+        ipv4.id = Bits::from(seq.into(), 16);
+        if self.opts.dont_frag {
+            ipv4.flags = Bits::from(0b010, 3);
+        }
+
+        let pdu = Pdu::new()
+            .header(ipv4)
+            .header(IcmpHdr::echo_request(self.opts.id, seq))
+            .header(Raw::from(&self.payload));
+        self.link.send(io, pdu)?;
+        Ok(())
+    }
+
+    fn parse_reply(&self, pdu: &Pdu) -> Option<Reply> {
+        let (ipv4, icmp) = match (pdu.headers.get(&3), pdu.headers.get(&4)) {
+            (Some(Proto::IPv4(ipv4)), Some(Proto::ICMP(icmp))) => (ipv4, icmp),
+            _ => return None,
+        };
+        if icmp.icmp_type.to_usize() != icmp_type::ECHO_REPLY as usize
+            || icmp.id.to_usize() != self.opts.id as usize
+        {
+            return None;
+        }
+        let payload_len = match pdu.headers.get(&7) {
+            Some(Proto::Raw(raw)) => raw.data.len(),
+            _ => 0,
+        };
+        Some(Reply {
+            src: ipv4.src_ip_addr,
+            seq: icmp.seq.to_usize() as u16,
+            ttl: ipv4.ttl.to_usize() as u8,
+            bytes: icmp.length() + payload_len,
+        })
+    }
+
+    /// Passes replies received until `until` to `on_reply`
+    fn collect<T: PacketIo>(
+        &self,
+        io: &mut T,
+        until: Instant,
+        mut on_reply: impl FnMut(Reply, Instant),
+    ) -> Result<(), PaError> {
+        self.link.recv_until(io, until, |pdu, now| {
+            if let Some(reply) = self.parse_reply(&pdu) {
+                on_reply(reply, now);
+            }
+        })
+    }
+
+    fn ping<T: PacketIo>(&mut self, io: &mut T, target: [u8; 4]) -> Result<PingStats, PaError> {
+        let mut probes: Vec<Probe> = Vec::with_capacity(self.opts.count);
+        let mut sent: Vec<Instant> = Vec::with_capacity(self.opts.count);
+        let timeout = self.opts.timeout;
+        let on_reply = |reply: Reply, now: Instant, probes: &mut Vec<Probe>, sent: &[Instant]| {
+            // Sequence numbers wrap, so replies belong to the latest probe
+            // carrying theirs
+            let last = sent.len() - 1;
+            let back = (last as u16).wrapping_sub(reply.seq) as usize;
+            if reply.src != target || back > last {
+                return;
+            }
+            let i = last - back;
+            if probes[i].rtt.is_some() {
+                return;
+            }
+            let rtt = now - sent[i];
+            if rtt <= timeout {
+                probes[i].rtt = Some(rtt);
+                probes[i].ttl = Some(reply.ttl);
+                probes[i].bytes = reply.bytes;
+            }
+        };
+
+        for seq in 0..self.opts.count {
+            sent.push(Instant::now());
+            self.send_request(io, target, seq as u16)?;
+            probes.push(Probe {
+                seq: seq as u16,
+                rtt: None,
+                ttl: None,
+                bytes: 0,
+            });
+
+            let wait = if seq + 1 == self.opts.count {
+                timeout
+            } else {
+                self.opts.interval
+            };
+            self.collect(io, sent[seq] + wait, |reply, now| {
+                on_reply(reply, now, &mut probes, &sent)
+            })?;
+        }
+
+        Ok(PingStats::from(target, probes))
+    }
+
+    fn sweep<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        cidr: impl ToString,
+    ) -> Result<Vec<SweepResult>, PaError> {
+        let net = Ipv4Net::parse(cidr)?;
+        let interval = match self.opts.rate {
+            Some(rate) if rate > 0.0 => Duration::from_secs_f64(1.0 / rate),
+            _ => Duration::from_secs(0),
+        };
+        let mut results: BTreeMap<[u8; 4], SweepResult> = BTreeMap::new();
+        let mut pending: Vec<[u8; 4]> = net.hosts().collect();
+
+        for seq in 0..self.opts.count.max(1) {
+            let mut sent: HashMap<[u8; 4], Instant> = HashMap::new();
+            let on_reply = |reply: Reply,
+                            now: Instant,
+                            results: &mut BTreeMap<[u8; 4], SweepResult>,
+                            sent: &HashMap<[u8; 4], Instant>| {
+                if reply.seq != seq as u16 || results.contains_key(&reply.src) {
+                    return;
+                }
+                if let Some(sent_at) = sent.get(&reply.src) {
+                    let result = SweepResult {
+                        ip: reply.src,
+                        rtt: now - *sent_at,
+                        ttl: reply.ttl,
+                    };
+                    results.insert(reply.src, result);
+                }
+            };
+
+            let targets = self.link.reachable(io, &pending, interval)?;
+            let mut next_send = Instant::now();
+            for ip in targets.iter() {
+                self.collect(io, next_send, |reply, now| {
+                    on_reply(reply, now, &mut results, &sent)
+                })?;
+                sent.insert(*ip, Instant::now());
+                match self.send_request(io, *ip, seq as u16) {
+                    Ok(()) => {}
+                    // Hosts which do not resolve on Ethernet are down
+                    Err(PaError {
+                        err_type: ErrorType::TimeoutError,
+                        ..
+                    }) => {}
+                    Err(err) => return Err(err),
+                }
+                next_send = Instant::now() + interval;
+            }
+            self.collect(io, Instant::now() + self.opts.timeout, |reply, now| {
+                on_reply(reply, now, &mut results, &sent)
+            })?;
+
+            pending.retain(|ip| !results.contains_key(ip));
+            if pending.is_empty() {
+                break;
+            }
+        }
+
+        Ok(results.into_values().collect())
+    }
+}
+
+/// Sends `opts.count` echo requests to `target` at IP layer
+pub fn ping<T: PacketIo>(
+    io: &mut T,
+    target: impl ToString,
+    opts: &PingOpts,
+) -> Result<PingStats, PaError> {
+    Pinger::new(opts, None).ping(io, parse_ip(target)?)
+}
+
+/// Sends `opts.count` echo requests to `target` in Ethernet frames
+pub fn ping_eth<T: PacketIo>(
+    io: &mut T,
+    resolver: &mut ArpResolver,
+    target: impl ToString,
+    opts: &PingOpts,
+) -> Result<PingStats, PaError> {
+    Pinger::new(opts, Some(resolver)).ping(io, parse_ip(target)?)
+}
+
+/// Finds hosts of network `cidr` answering echo requests at IP layer
+///
+/// Hosts are swept in up to `opts.count` rounds. Each round sends one
+/// request to every host which has not answered yet, `opts.rate` requests
+/// per second, then waits `opts.timeout` for late replies. Results are
+/// sorted by IP address.
+pub fn ping_sweep<T: PacketIo>(
+    io: &mut T,
+    cidr: impl ToString,
+    opts: &PingOpts,
+) -> Result<Vec<SweepResult>, PaError> {
+    Pinger::new(opts, None).sweep(io, cidr)
+}
+
+/// Same as `ping_sweep` with Ethernet frames
+///
+/// Next hops of all hosts are resolved at once before each round. Hosts
+/// whose next hop does not answer ARP are taken as down.
+pub fn ping_sweep_eth<T: PacketIo>(
+    io: &mut T,
+    resolver: &mut ArpResolver,
+    cidr: impl ToString,
+    opts: &PingOpts,
+) -> Result<Vec<SweepResult>, PaError> {
+    Pinger::new(opts, Some(resolver)).sweep(io, cidr)
+}
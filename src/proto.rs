@@ -6,13 +6,33 @@ pub enum Proto {
     Arp(ArpHdr),
     Eth(EthHdr),
     IPv4(IPv4Hdr),
-    ICMP,
+    IPv6(IPv6Hdr),
+    Mpls(Mpls),
+    ICMP(IcmpHdr),
+    Icmpv6(Icmpv6Hdr),
+    UDP(UdpHdr),
+    TCP(TcpHdr),
+    Gre(Gre),
+    Dns(Dns),
+    Dhcp(Dhcp),
+    Dhcpv6(Dhcpv6),
+    RouterSolicit(RouterSolicit),
+    RouterAdvert(RouterAdvert),
+    NetFlowV5(NetFlowV5),
+    NetFlowV9(NetFlowV9),
+    Ipfix(Ipfix),
+    Erspan(Erspan),
+    Vxlan(Vxlan),
+    Geneve(Geneve),
+    Raw(Raw),
     Unknown,
 }
 
 pub enum EthType {
     Arp,
     IPv4,
+    IPv6,
+    Mpls,
     Unknown,
 }
 
@@ -1,4 +1,4 @@
-use crate::hdr::{ArpQuery, EthQuery, IPv4Query};
+use crate::hdr::{ArpQuery, DhcpQuery, Dhcpv6Query, DnsQuery, EthQuery, IPv4Query, MplsQuery};
 use crate::Pdu;
 use std::collections::HashMap;
 
@@ -7,10 +7,17 @@ pub enum QueryHdr {
     IPv4(IPv4Query),
     Eth(EthQuery),
     Arp(ArpQuery),
+    Dns(DnsQuery),
+    Dhcp(DhcpQuery),
+    Dhcpv6(Dhcpv6Query),
+    Mpls(MplsQuery),
 }
 
+/// Crafts reply to a matched `Pdu`, `None` leaves it unanswered
+pub type ReplyFn = Box<dyn Fn(Pdu) -> Option<Pdu> + Send + Sync>;
+
 pub struct Rules {
-    pub rules: HashMap<QueryHdr, fn(Pdu) -> Pdu>,
+    pub rules: HashMap<QueryHdr, ReplyFn>,
 }
 
 impl Rules {
@@ -20,7 +27,33 @@ impl Rules {
         }
     }
 
-    pub fn add_rule(&mut self, query: QueryHdr, callback: fn(Pdu) -> Pdu) {
-        self.rules.insert(query, callback);
+    /// Adds rule replying with `callback`, which may be a closure holding
+    /// state such as a zone to answer from
+    pub fn add_rule(
+        &mut self,
+        query: QueryHdr,
+        callback: impl Fn(Pdu) -> Option<Pdu> + Send + Sync + 'static,
+    ) {
+        self.rules.insert(query, Box::new(callback));
+    }
+
+    /// Returns callback of first query matching `pdu`
+    pub fn find(&self, pdu: &Pdu) -> Option<&ReplyFn> {
+        self.rules.iter().find_map(|(key, value)| {
+            let matched = match key {
+                QueryHdr::Arp(query) => query == pdu,
+                QueryHdr::Eth(query) => query == pdu,
+                QueryHdr::IPv4(query) => query == pdu,
+                QueryHdr::Dns(query) => query == pdu,
+                QueryHdr::Dhcp(query) => query == pdu,
+                QueryHdr::Dhcpv6(query) => query == pdu,
+                QueryHdr::Mpls(query) => query == pdu,
+            };
+            if matched {
+                Some(value)
+            } else {
+                None
+            }
+        })
     }
 }
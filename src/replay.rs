@@ -0,0 +1,299 @@
+//! Replays frames stored in pcap files onto a `Channel`
+//!
+//! Frames can be sent with their original timing, scaled timing, fixed rate
+//! or as fast as possible. MAC and IPv4 addresses can be rewritten on the fly
+//! through a `RewriteMap`, in which case the affected checksums are recomputed.
+
+use crate::dstructs::Bits;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::{ip_proto, ArpHdr, EthHdr, Hdr, IPv4Hdr};
+use crate::io::PacketIo;
+use crate::proto::EthType;
+use crate::sock::Channel;
+use crate::utility::{checksum, parse_ip, parse_mac, pseudo_checksum};
+use pcap_file::PcapReader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Decides how long to wait between two frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    /// Keep the inter packet gaps found in the capture
+    Original,
+    /// Keep the original gaps but play them `n` times faster
+    Multiplier(f64),
+    /// Send a fixed number of packets per second
+    Pps(f64),
+    /// Send at a fixed rate in megabits per second
+    Mbps(f64),
+    /// Send as fast as possible
+    Topspeed,
+}
+
+/// Mapping table of addresses to be replaced in replayed frames
+#[derive(Debug, Clone, Default)]
+pub struct RewriteMap {
+    pub mac: HashMap<[u8; 6], [u8; 6]>,
+    pub ip: HashMap<[u8; 4], [u8; 4]>,
+}
+
+impl RewriteMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_mac(&mut self, from: impl ToString, to: impl ToString) -> Result<(), PaError> {
+        self.mac.insert(parse_mac(from)?, parse_mac(to)?);
+        Ok(())
+    }
+
+    pub fn add_ip(&mut self, from: impl ToString, to: impl ToString) -> Result<(), PaError> {
+        self.ip.insert(parse_ip(from)?, parse_ip(to)?);
+        Ok(())
+    }
+
+    fn map_mac(&self, mac: &mut [u8; 6]) -> bool {
+        match self.mac.get(mac) {
+            Some(new_mac) => {
+                *mac = *new_mac;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn map_ip(&self, ip: &mut [u8; 4]) -> bool {
+        match self.ip.get(ip) {
+            Some(new_ip) => {
+                *ip = *new_ip;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Rewrites addresses in `frame` in place
+    ///
+    /// Ethernet and ARP addresses are rewritten through `EthHdr` and `ArpHdr`.
+    /// For IPv4 the header checksum is recomputed through `IPv4Hdr`, and the
+    /// TCP/UDP checksum is recomputed when the whole segment is present.
+    /// Returns `true` if anything in the frame was changed.
+    pub fn rewrite(&self, frame: &mut [u8]) -> Result<bool, PaError> {
+        if frame.len() < 14 {
+            return Ok(false);
+        }
+
+        let mut eth_hdr = EthHdr::parse((&frame[0..14]).into());
+        let mut changed = self.map_mac(&mut eth_hdr.src_hw_addr);
+        changed |= self.map_mac(&mut eth_hdr.dst_hw_addr);
+        if changed {
+            let raw: Vec<u8> = eth_hdr.create()?.into();
+            frame[0..14].copy_from_slice(&raw);
+        }
+
+        match eth_hdr.get_data_type() {
+            EthType::Arp if frame.len() >= 42 => {
+                let mut arp_hdr = ArpHdr::parse((&frame[14..42]).into());
+                let mut arp_changed = self.map_mac(&mut arp_hdr.src_hw_addr);
+                arp_changed |= self.map_mac(&mut arp_hdr.dst_hw_addr);
+                arp_changed |= self.map_ip(&mut arp_hdr.src_proto_addr);
+                arp_changed |= self.map_ip(&mut arp_hdr.dst_proto_addr);
+                if arp_changed {
+                    let raw: Vec<u8> = arp_hdr.create()?.into();
+                    frame[14..42].copy_from_slice(&raw);
+                }
+                changed |= arp_changed;
+            }
+            EthType::IPv4 if frame.len() >= 34 => {
+                changed |= self.rewrite_ipv4(&mut frame[14..])?;
+            }
+            _ => {}
+        }
+
+        Ok(changed)
+    }
+
+    fn rewrite_ipv4(&self, packet: &mut [u8]) -> Result<bool, PaError> {
+        let mut ipv4_hdr = IPv4Hdr::parse((&packet[0..20]).into());
+        let mut changed = self.map_ip(&mut ipv4_hdr.src_ip_addr);
+        changed |= self.map_ip(&mut ipv4_hdr.dst_ip_addr);
+        if !changed {
+            return Ok(false);
+        }
+
+        let hdr_len = ipv4_hdr.ihl.to_usize() * 4;
+        if hdr_len < 20 || hdr_len > packet.len() {
+            return Err(PaError::new(
+                "Invalid IPv4 header length in frame",
+                ErrorType::ParseError,
+            ));
+        }
+
+        if hdr_len == 20 {
+            ipv4_hdr.set_checksum()?;
+            let raw: Vec<u8> = ipv4_hdr.create()?.into();
+            packet[0..20].copy_from_slice(&raw);
+        } else {
+            // Options are not modelled by `IPv4Hdr`, so they are kept as they are
+            ipv4_hdr.hdr_checksum = Bits::from(0, 16);
+            let raw: Vec<u8> = ipv4_hdr.create()?.into();
+            packet[0..20].copy_from_slice(&raw);
+            let sum = checksum(&packet[0..hdr_len]);
+            packet[10..12].copy_from_slice(&sum.to_be_bytes());
+        }
+
+        let total_len = ipv4_hdr.total_len.to_usize();
+        let is_fragment =
+            ipv4_hdr.frag_offset.to_usize() != 0 || ipv4_hdr.flags.to_usize() & 1 != 0;
+        if is_fragment || total_len > packet.len() || total_len < hdr_len {
+            return Ok(true);
+        }
+
+        let csum_offset = match ipv4_hdr.proto.to_usize() as u8 {
+            ip_proto::TCP => 16,
+            ip_proto::UDP => 6,
+            _ => return Ok(true),
+        };
+        let segment = &mut packet[hdr_len..total_len];
+        if segment.len() < csum_offset + 2 {
+            return Ok(true);
+        }
+        if ipv4_hdr.proto.to_usize() as u8 == ip_proto::UDP && segment[6..8] == [0, 0] {
+            // UDP checksum is optional over IPv4 and was not used
+            return Ok(true);
+        }
+
+        segment[csum_offset..csum_offset + 2].copy_from_slice(&[0, 0]);
+        let mut sum = pseudo_checksum(
+            &ipv4_hdr.src_ip_addr,
+            &ipv4_hdr.dst_ip_addr,
+            ipv4_hdr.proto.to_usize() as u8,
+            segment,
+        );
+        if sum == 0 && csum_offset == 6 {
+            sum = 0xffff;
+        }
+        segment[csum_offset..csum_offset + 2].copy_from_slice(&sum.to_be_bytes());
+
+        Ok(true)
+    }
+}
+
+/// Options of `replay_pcap`
+#[derive(Debug, Clone)]
+pub struct ReplayOpts {
+    pub timing: Timing,
+    /// Number of times the capture is played. `0` will loop forever.
+    pub loops: usize,
+    pub rewrite: Option<RewriteMap>,
+}
+
+impl ReplayOpts {
+    pub fn new() -> Self {
+        Self {
+            timing: Timing::Original,
+            loops: 1,
+            rewrite: None,
+        }
+    }
+}
+
+impl Default for ReplayOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Summary returned after a replay is finished
+#[derive(Debug, Clone, Default)]
+pub struct ReplayStats {
+    pub packets: usize,
+    pub bytes: usize,
+    pub rewritten: usize,
+    pub elapsed: Duration,
+}
+
+/// Returns time at which the next frame should leave, relative to start of replay
+///
+/// `prev` holds the offset and length of the frame sent before this one.
+fn next_offset(timing: Timing, since_first: Duration, prev: Option<(Duration, usize)>) -> Duration {
+    let (sent, len) = match prev {
+        Some(prev) => prev,
+        None => return Duration::from_secs(0),
+    };
+    match timing {
+        Timing::Original => since_first,
+        Timing::Multiplier(n) if n > 0.0 => since_first.div_f64(n),
+        Timing::Pps(pps) if pps > 0.0 => sent + Duration::from_secs_f64(1.0 / pps),
+        Timing::Mbps(mbps) if mbps > 0.0 => {
+            sent + Duration::from_secs_f64((len * 8) as f64 / (mbps * 1_000_000.0))
+        }
+        _ => Duration::from_secs(0),
+    }
+}
+
+impl Channel {
+    /// Sends every frame of pcap file at `pcap_path` on this channel
+    pub fn replay_pcap(
+        &mut self,
+        pcap_path: impl ToString,
+        opts: &ReplayOpts,
+    ) -> Result<ReplayStats, PaError> {
+        replay_pcap(self, pcap_path, opts)
+    }
+}
+
+/// Sends every frame of pcap file at `pcap_path` on `io`
+pub fn replay_pcap<T: PacketIo>(
+    io: &mut T,
+    pcap_path: impl ToString,
+    opts: &ReplayOpts,
+) -> Result<ReplayStats, PaError> {
+    let pcap_path = pcap_path.to_string();
+    let mut stats = ReplayStats::default();
+    let time_start = Instant::now();
+    let mut loop_count = 0;
+
+    while opts.loops == 0 || loop_count < opts.loops {
+        let pcap = File::open(&pcap_path)
+            .map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+        let pcap_reader = PcapReader::new(pcap)
+            .map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+
+        let loop_start = Instant::now();
+        let mut first_ts: Option<Duration> = None;
+        let mut prev: Option<(Duration, usize)> = None;
+
+        for packet in pcap_reader {
+            let packet =
+                packet.map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+            let ts = packet.header.timestamp();
+            let first = *first_ts.get_or_insert(ts);
+            let since_first = ts.checked_sub(first).unwrap_or_default();
+
+            let mut frame = packet.data.into_owned();
+            let offset = next_offset(opts.timing, since_first, prev);
+            if let Some(wait) = offset.checked_sub(loop_start.elapsed()) {
+                sleep(wait);
+            }
+            prev = Some((offset, frame.len()));
+
+            if let Some(rewrite) = &opts.rewrite {
+                if rewrite.rewrite(&mut frame)? {
+                    stats.rewritten += 1;
+                }
+            }
+
+            io.send(&frame)?;
+            stats.packets += 1;
+            stats.bytes += frame.len();
+        }
+
+        loop_count += 1;
+    }
+
+    stats.elapsed = time_start.elapsed();
+    Ok(stats)
+}
@@ -0,0 +1,393 @@
+//! Contains `RingChannel`, a layer 2 channel on `PACKET_MMAP` rings
+//!
+//! Frames are exchanged with the kernel through memory mapped TX and RX rings
+//! (`TPACKET_V3`) instead of one system call and one copy per frame. Use
+//! `send_batch` to queue many frames with a single system call and
+//! `recv_batch` to borrow received frames straight from the ring.
+
+use crate::error::{ErrorType, PaError};
+use crate::io::PacketIo;
+use crate::sock::Channel;
+use crate::sys;
+use std::collections::VecDeque;
+use std::os::unix::io::{AsRawFd, OwnedFd};
+use std::ptr;
+use std::sync::atomic::{fence, Ordering};
+use std::time::Duration;
+
+/// Value of `tpacket_versions::TPACKET_V3`
+const TPACKET_V3: libc::c_int = 2;
+
+/// Offset of frame data from start of a TX slot, `TPACKET_ALIGN(sizeof(tpacket3_hdr))`
+const TX_DATA_OFFSET: usize = 48;
+
+/// Sizes of the rings shared with the kernel
+#[derive(Debug, Clone, Copy)]
+pub struct RingConfig {
+    /// Size of one block, must be a multiple of page size
+    pub block_size: u32,
+    pub rx_block_nr: u32,
+    pub tx_block_nr: u32,
+    /// Size of one TX slot, must divide `block_size`
+    pub tx_frame_size: u32,
+    /// Time after which kernel hands over a partly filled RX block
+    pub rx_block_timeout: Duration,
+}
+
+impl RingConfig {
+    pub fn new() -> Self {
+        Self {
+            block_size: 1 << 20,
+            rx_block_nr: 16,
+            tx_block_nr: 16,
+            tx_frame_size: 2048,
+            rx_block_timeout: Duration::from_millis(10),
+        }
+    }
+}
+
+impl Default for RingConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Layer 2 channel on memory mapped `TPACKET_V3` rings
+pub struct RingChannel {
+    fd: OwnedFd,
+    map: *mut u8,
+    map_len: usize,
+    config: RingConfig,
+    rx_block: u32,
+    tx_frame: u32,
+    tx_frame_nr: u32,
+    rx_queue: VecDeque<Vec<u8>>,
+}
+
+fn set_ring_option<T>(fd: &OwnedFd, name: libc::c_int, value: &T) -> Result<(), PaError> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_PACKET,
+            name,
+            value as *const T as *const libc::c_void,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(PaError::new(
+            std::io::Error::last_os_error().to_string(),
+            ErrorType::ChannelError,
+        ));
+    }
+    Ok(())
+}
+
+impl RingChannel {
+    /// Opens ring channel on interface called `interface` with default sizes
+    pub fn from(interface: impl ToString) -> Result<Self, PaError> {
+        Self::with_config(interface, RingConfig::new())
+    }
+
+    pub fn with_config(interface: impl ToString, config: RingConfig) -> Result<Self, PaError> {
+        let interface = interface.to_string();
+        let interf = Channel::get_interface_list()
+            .into_iter()
+            .find(|e| e.name == interface)
+            .ok_or_else(|| PaError::new("Interface not found", ErrorType::InterfaceError))?;
+        if config.tx_frame_size as usize <= TX_DATA_OFFSET
+            || !config.block_size.is_multiple_of(config.tx_frame_size)
+        {
+            return Err(PaError::new(
+                "TX frame size must divide block size",
+                ErrorType::ChannelError,
+            ));
+        }
+
+        let fd = sys::packet_socket(interf.index, false)?;
+        sys::set_option(&fd, libc::SOL_PACKET, libc::PACKET_VERSION, TPACKET_V3)?;
+
+        let rx_req = libc::tpacket_req3 {
+            tp_block_size: config.block_size,
+            tp_block_nr: config.rx_block_nr,
+            tp_frame_size: config.tx_frame_size,
+            tp_frame_nr: config.block_size / config.tx_frame_size * config.rx_block_nr,
+            tp_retire_blk_tov: config.rx_block_timeout.as_millis() as u32,
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        set_ring_option(&fd, libc::PACKET_RX_RING, &rx_req)?;
+
+        let tx_frame_nr = config.block_size / config.tx_frame_size * config.tx_block_nr;
+        let tx_req = libc::tpacket_req3 {
+            tp_block_size: config.block_size,
+            tp_block_nr: config.tx_block_nr,
+            tp_frame_size: config.tx_frame_size,
+            tp_frame_nr: tx_frame_nr,
+            tp_retire_blk_tov: 0,
+            tp_sizeof_priv: 0,
+            tp_feature_req_word: 0,
+        };
+        set_ring_option(&fd, libc::PACKET_TX_RING, &tx_req)?;
+
+        let map_len = config.block_size as usize
+            * (config.rx_block_nr as usize + config.tx_block_nr as usize);
+        let map = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                map_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_LOCKED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(PaError::new(
+                std::io::Error::last_os_error().to_string(),
+                ErrorType::ChannelError,
+            ));
+        }
+
+        Ok(Self {
+            fd,
+            map: map as *mut u8,
+            map_len,
+            config,
+            rx_block: 0,
+            tx_frame: 0,
+            tx_frame_nr,
+            rx_queue: VecDeque::new(),
+        })
+    }
+
+    fn rx_block_ptr(&self, index: u32) -> *mut libc::tpacket_block_desc {
+        unsafe {
+            self.map
+                .add(index as usize * self.config.block_size as usize)
+                .cast()
+        }
+    }
+
+    fn tx_frame_ptr(&self, index: u32) -> *mut libc::tpacket3_hdr {
+        let frames_per_block = self.config.block_size / self.config.tx_frame_size;
+        let block = (self.config.rx_block_nr + index / frames_per_block) as usize;
+        let offset = block * self.config.block_size as usize
+            + (index % frames_per_block) as usize * self.config.tx_frame_size as usize;
+        unsafe { self.map.add(offset).cast() }
+    }
+
+    /// Queues `frames` on TX ring and asks kernel to send them
+    ///
+    /// Waits for free slots if the ring is full. Returns number of frames sent.
+    pub fn send_batch(&mut self, frames: &[&[u8]]) -> Result<usize, PaError> {
+        let max_len = self.config.tx_frame_size as usize - TX_DATA_OFFSET;
+        let mut queued = 0;
+        for frame in frames {
+            if frame.len() > max_len {
+                return Err(PaError::new(
+                    "Frame larger than TX ring slot",
+                    ErrorType::LengthError,
+                ));
+            }
+
+            let hdr = self.tx_frame_ptr(self.tx_frame);
+            loop {
+                let status = unsafe { ptr::read_volatile(&(*hdr).tp_status) };
+                if status == libc::TP_STATUS_AVAILABLE || status == libc::TP_STATUS_WRONG_FORMAT {
+                    break;
+                }
+                if queued > 0 {
+                    self.kick()?;
+                    queued = 0;
+                }
+                self.wait(libc::POLLOUT, -1)?;
+            }
+
+            unsafe {
+                let data = (hdr as *mut u8).add(TX_DATA_OFFSET);
+                ptr::copy_nonoverlapping(frame.as_ptr(), data, frame.len());
+                (*hdr).tp_next_offset = 0;
+                (*hdr).tp_len = frame.len() as u32;
+                (*hdr).tp_snaplen = frame.len() as u32;
+                fence(Ordering::Release);
+                ptr::write_volatile(&mut (*hdr).tp_status, libc::TP_STATUS_SEND_REQUEST);
+            }
+            queued += 1;
+            self.tx_frame = (self.tx_frame + 1) % self.tx_frame_nr;
+        }
+
+        if queued > 0 {
+            self.kick()?;
+        }
+        Ok(frames.len())
+    }
+
+    fn kick(&self) -> Result<(), PaError> {
+        let ret = unsafe { libc::send(self.fd.as_raw_fd(), ptr::null(), 0, libc::MSG_DONTWAIT) };
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::WouldBlock {
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn wait(&self, events: libc::c_short, timeout_ms: libc::c_int) -> Result<bool, PaError> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events,
+            revents: 0,
+        };
+        let ret = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.kind() {
+                // A signal interrupting the wait counts as timeout
+                std::io::ErrorKind::Interrupted => Ok(false),
+                _ => Err(err.into()),
+            };
+        }
+        Ok(ret > 0)
+    }
+
+    /// Waits at most `timeout` for next block of received frames
+    ///
+    /// Frames of returned `RxBatch` are borrowed from RX ring. The block is
+    /// handed back to the kernel when the batch is dropped.
+    pub fn recv_batch(&mut self, timeout: Duration) -> Result<Option<RxBatch<'_>>, PaError> {
+        let block = self.rx_block_ptr(self.rx_block);
+        if !Self::block_ready(block) {
+            self.wait(libc::POLLIN | libc::POLLERR, sys::poll_timeout(timeout))?;
+            if !Self::block_ready(block) {
+                return Ok(None);
+            }
+        }
+        fence(Ordering::Acquire);
+        self.rx_block = (self.rx_block + 1) % self.config.rx_block_nr;
+
+        let (num_pkts, first) = unsafe {
+            let hdr = &(*block).hdr.bh1;
+            (hdr.num_pkts, hdr.offset_to_first_pkt)
+        };
+        Ok(Some(RxBatch {
+            block,
+            num_pkts,
+            first_offset: first,
+            _channel: std::marker::PhantomData,
+        }))
+    }
+
+    fn block_ready(block: *mut libc::tpacket_block_desc) -> bool {
+        let status = unsafe { ptr::read_volatile(&(*block).hdr.bh1.block_status) };
+        status & libc::TP_STATUS_USER != 0
+    }
+}
+
+impl Drop for RingChannel {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.map as *mut libc::c_void, self.map_len);
+        }
+    }
+}
+
+/// One block of frames received on `RingChannel`
+pub struct RxBatch<'a> {
+    block: *mut libc::tpacket_block_desc,
+    num_pkts: u32,
+    first_offset: u32,
+    _channel: std::marker::PhantomData<&'a mut RingChannel>,
+}
+
+impl<'a> RxBatch<'a> {
+    pub fn len(&self) -> usize {
+        self.num_pkts as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_pkts == 0
+    }
+
+    /// Iterates over frames of this block
+    pub fn iter(&self) -> RxFrames<'_> {
+        RxFrames {
+            next: unsafe { (self.block as *const u8).add(self.first_offset as usize) },
+            remaining: self.num_pkts,
+            _batch: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'b RxBatch<'a> {
+    type Item = &'b [u8];
+    type IntoIter = RxFrames<'b>;
+
+    fn into_iter(self) -> RxFrames<'b> {
+        self.iter()
+    }
+}
+
+impl<'a> Drop for RxBatch<'a> {
+    fn drop(&mut self) {
+        fence(Ordering::Release);
+        unsafe {
+            ptr::write_volatile(
+                &mut (*self.block).hdr.bh1.block_status,
+                libc::TP_STATUS_KERNEL,
+            );
+        }
+    }
+}
+
+/// Iterator over frames borrowed from an `RxBatch`
+pub struct RxFrames<'b> {
+    next: *const u8,
+    remaining: u32,
+    _batch: std::marker::PhantomData<&'b [u8]>,
+}
+
+impl<'b> Iterator for RxFrames<'b> {
+    type Item = &'b [u8];
+
+    fn next(&mut self) -> Option<&'b [u8]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        unsafe {
+            let hdr = self.next as *const libc::tpacket3_hdr;
+            let data = self.next.add((*hdr).tp_mac as usize);
+            let frame = std::slice::from_raw_parts(data, (*hdr).tp_snaplen as usize);
+            self.next = self.next.add((*hdr).tp_next_offset as usize);
+            Some(frame)
+        }
+    }
+}
+
+impl PacketIo for RingChannel {
+    fn send(&mut self, frame: &[u8]) -> Result<usize, PaError> {
+        self.send_batch(&[frame])?;
+        Ok(frame.len())
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, PaError> {
+        loop {
+            if let Some(frame) = self.recv_timeout(Duration::from_secs(1))? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, PaError> {
+        if self.rx_queue.is_empty() {
+            let frames: Vec<Vec<u8>> = match self.recv_batch(timeout)? {
+                Some(batch) => batch.iter().map(|frame| frame.to_vec()).collect(),
+                None => return Ok(None),
+            };
+            self.rx_queue.extend(frames);
+        }
+        Ok(self.rx_queue.pop_front())
+    }
+}
@@ -1,17 +1,24 @@
+use crate::debug;
 use crate::error::*;
-use crate::{debug, QueryHdr};
+use crate::iface::Interface;
+use crate::io::PacketIo;
 use crate::{Pdu, Rules};
+use std::io::ErrorKind;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "pcap")]
 use pcap_file::PcapWriter;
 #[cfg(feature = "pcap")]
-use std::{fs::File, time::Instant};
+use std::fs::File;
 
 use pnet_datalink::{
     channel, interfaces, Channel as PChannel, Config, DataLinkReceiver, DataLinkSender,
     NetworkInterface,
 };
 
+/// How often a blocked receive wakes up to check its deadline
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
 pub struct Channel {
     rx: Box<dyn DataLinkReceiver>,
     tx: Box<dyn DataLinkSender>,
@@ -23,6 +30,21 @@ impl Channel {
         self.tx.send_to(packet, Some(self.interf.clone()));
     }
 
+    fn open(interf: &NetworkInterface) -> Result<Self, PaError> {
+        let config = Config {
+            read_timeout: Some(POLL_INTERVAL),
+            ..Config::default()
+        };
+        match channel(interf, config)? {
+            PChannel::Ethernet(tx, rx) => Ok(Channel {
+                tx,
+                rx,
+                interf: interf.clone(),
+            }),
+            _ => Err(PaError::new("Unknown Channel", ErrorType::ChannelError)),
+        }
+    }
+
     pub fn new() -> Result<Self, PaError> {
         let interfaces_list = interfaces();
         let default_interface: Option<&NetworkInterface> = interfaces_list
@@ -30,15 +52,7 @@ impl Channel {
             .find(|e| e.is_up() && !e.is_loopback() && !e.ips.is_empty());
 
         return if let Some(default_interface) = default_interface {
-            let my_channel = channel(default_interface, Config::default())?;
-            match my_channel {
-                PChannel::Ethernet(tx, rx) => Ok(Channel {
-                    tx,
-                    rx,
-                    interf: default_interface.clone(),
-                }),
-                _ => Err(PaError::new("Unknown Channel", ErrorType::ChannelError)),
-            }
+            Self::open(default_interface)
         } else {
             Err(PaError::new(
                 "Error in getting default interface",
@@ -68,26 +82,16 @@ impl Channel {
 
         let interf_selected = interf_selected.unwrap();
 
-        let my_channel = channel(&interf_selected, Config::default())?;
-        return match my_channel {
-            PChannel::Ethernet(tx, rx) => Ok(Channel {
-                tx,
-                rx,
-                interf: interf_selected.clone(),
-            }),
-            _ => Err(PaError::new("Unknown Channel", ErrorType::ChannelError)),
-        };
+        Self::open(interf_selected)
     }
 
-    pub fn recv(&mut self) -> Vec<u8> {
-        let mut bits: Vec<u8> = Vec::new();
-        if let Ok(byte) = self.rx.next() {
-            for bit in byte {
-                bits.push(*bit);
-            }
-        }
+    /// Returns details of the interface this channel is opened on
+    pub fn interface(&self) -> Interface {
+        Interface::from(&self.interf)
+    }
 
-        bits
+    pub fn recv(&mut self) -> Vec<u8> {
+        PacketIo::recv(self).unwrap_or_default()
     }
 
     #[cfg(feature = "pcap")]
@@ -147,59 +151,80 @@ impl Channel {
     }
 
     pub fn auto_reply(&mut self, rules: Rules, limit: Option<usize>) {
-        let mut total_send = 0;
-        let mut reply: Pdu;
+        if let Err(err) = auto_reply(self, &rules, limit) {
+            debug!("Stopped replying: {:?}", err);
+        }
+    }
+}
+
+impl PacketIo for Channel {
+    fn send(&mut self, frame: &[u8]) -> Result<usize, PaError> {
+        match self.tx.send_to(frame, Some(self.interf.clone())) {
+            Some(Ok(())) => Ok(frame.len()),
+            Some(Err(err)) => Err(err.into()),
+            None => Err(PaError::new(
+                "Unable to send frame",
+                ErrorType::ChannelError,
+            )),
+        }
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, PaError> {
         loop {
-            if limit.is_some() {
-                if limit.unwrap() == total_send {
-                    break;
+            match self.rx.next() {
+                Ok(frame) => return Ok(frame.to_vec()),
+                // Signals interrupt the wait, which is retried like a timeout
+                Err(err) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::Interrupted) => {
+                    continue
                 }
+                Err(err) => return Err(err.into()),
             }
-            let mut matched: bool = false;
-            let recvd = self.recv();
-            let pdu = Pdu::parse(&recvd);
-            for (key, value) in &rules.rules {
-                match key {
-                    QueryHdr::Arp(query) => {
-                        if query == &pdu {
-                            matched = true;
-                            reply = value(pdu);
-                            reply.build();
-                            self.send_packet(&reply.buffer);
-                            debug!("Send crafted response for ARP Query");
-                            break;
-                        }
-                    }
-                    QueryHdr::Eth(query) => {
-                        debug!("Got Eth, matching with query");
-                        if query == &pdu {
-                            debug!("Eth matched with Eth Query");
-                            matched = true;
-                            reply = value(pdu);
-                            reply.build();
-                            self.send_packet(&reply.buffer);
-                            debug!("Send crafted response for Eth Query");
-                            break;
-                        }
-                    }
-                    QueryHdr::IPv4(query) => {
-                        debug!("Got IPv4, matching with query");
-                        if query == &pdu {
-                            debug!("Eth matched with IPv4 Query");
-                            matched = true;
-                            reply = value(pdu);
-                            reply.build();
-                            self.send_packet(&reply.buffer);
-                            debug!("Send crafted response for IPv4 Query");
-                            break;
-                        }
-                    }
+        }
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, PaError> {
+        let time_start = Instant::now();
+        while time_start.elapsed() < timeout {
+            match self.rx.next() {
+                Ok(frame) => return Ok(Some(frame.to_vec())),
+                Err(err) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::Interrupted) => {
+                    continue
                 }
+                Err(err) => return Err(err.into()),
             }
+        }
+        Ok(None)
+    }
+}
 
-            if matched {
-                total_send += 1;
-            }
+/// Replies to received frames according to `rules`
+///
+/// Every received frame is matched against queries in `rules`. On first
+/// match, the callback of that query crafts the reply which is sent back,
+/// unless it returns `None` to leave the frame unanswered.
+/// Stops after `limit` replies, or never if `limit` is `None`. Returns
+/// number of replies sent.
+pub fn auto_reply<T: PacketIo>(
+    io: &mut T,
+    rules: &Rules,
+    limit: Option<usize>,
+) -> Result<usize, PaError> {
+    let mut total_send = 0;
+    while limit != Some(total_send) {
+        let recvd = io.recv()?;
+        if recvd.len() < 14 {
+            continue;
+        }
+        let pdu = Pdu::parse(&recvd);
+        let callback = rules.find(&pdu);
+
+        if let Some(mut reply) = callback.and_then(|callback| callback(pdu)) {
+            reply.build()?;
+            io.send(&reply.buffer)?;
+            debug!("Send crafted response for matched Query");
+            total_send += 1;
         }
     }
+
+    Ok(total_send)
 }
@@ -0,0 +1,151 @@
+//! Thin wrappers around the raw socket system calls used by this library
+
+use crate::error::{ErrorType, PaError};
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+const ETH_P_ALL: u16 = 0x0003;
+
+fn last_error(err_type: ErrorType) -> PaError {
+    PaError::new(io::Error::last_os_error().to_string(), err_type)
+}
+
+/// Opens `AF_PACKET` socket bound to interface with index `ifindex`
+pub(crate) fn packet_socket(ifindex: u32, nonblocking: bool) -> Result<OwnedFd, PaError> {
+    let mut sock_type = libc::SOCK_RAW | libc::SOCK_CLOEXEC;
+    if nonblocking {
+        sock_type |= libc::SOCK_NONBLOCK;
+    }
+    let fd = unsafe { libc::socket(libc::AF_PACKET, sock_type, i32::from(ETH_P_ALL.to_be())) };
+    if fd < 0 {
+        return Err(last_error(ErrorType::ChannelError));
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as u16;
+    addr.sll_protocol = ETH_P_ALL.to_be();
+    addr.sll_ifindex = ifindex as i32;
+    let ret = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(last_error(ErrorType::InterfaceError));
+    }
+
+    Ok(fd)
+}
+
+/// Opens two connected sockets keeping frame boundaries, used as an in-memory link
+#[cfg(feature = "async")]
+pub(crate) fn socket_pair(nonblocking: bool) -> Result<(OwnedFd, OwnedFd), PaError> {
+    let mut sock_type = libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC;
+    if nonblocking {
+        sock_type |= libc::SOCK_NONBLOCK;
+    }
+    let mut fds = [0; 2];
+    let ret = unsafe { libc::socketpair(libc::AF_UNIX, sock_type, 0, fds.as_mut_ptr()) };
+    if ret < 0 {
+        return Err(last_error(ErrorType::ChannelError));
+    }
+    unsafe { Ok((OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+}
+
+#[cfg(feature = "async")]
+pub(crate) fn send(fd: &impl AsRawFd, frame: &[u8]) -> io::Result<usize> {
+    let ret = unsafe {
+        libc::send(
+            fd.as_raw_fd(),
+            frame.as_ptr() as *const libc::c_void,
+            frame.len(),
+            0,
+        )
+    };
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+pub(crate) fn recv(fd: &impl AsRawFd, buffer: &mut [u8]) -> io::Result<usize> {
+    let ret = unsafe {
+        libc::recv(
+            fd.as_raw_fd(),
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            buffer.len(),
+            0,
+        )
+    };
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+/// Opens raw IP socket, `domain` is `AF_INET` or `AF_INET6`
+pub(crate) fn raw_socket(domain: libc::c_int, proto: libc::c_int) -> Result<OwnedFd, PaError> {
+    let fd = unsafe { libc::socket(domain, libc::SOCK_RAW | libc::SOCK_CLOEXEC, proto) };
+    if fd < 0 {
+        return Err(last_error(ErrorType::ChannelError));
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Sets integer socket option
+pub(crate) fn set_option(
+    fd: &impl AsRawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> Result<(), PaError> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(last_error(ErrorType::ChannelError));
+    }
+    Ok(())
+}
+
+/// Converts `timeout` to milliseconds taken by `poll`
+///
+/// Rounds up, so timeouts below 1 ms still wait, and clamps long ones which
+/// would otherwise turn negative and wait forever.
+pub(crate) fn poll_timeout(timeout: Duration) -> libc::c_int {
+    let ms = timeout.as_nanos().div_ceil(1_000_000);
+    ms.min(libc::c_int::MAX as u128) as libc::c_int
+}
+
+/// Waits at most `timeout_ms` for `fd` to become readable
+///
+/// A signal interrupting the wait counts as timeout.
+pub(crate) fn poll_readable(fd: &impl AsRawFd, timeout_ms: libc::c_int) -> io::Result<bool> {
+    let mut pfd = libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ret = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+    if ret < 0 {
+        let err = io::Error::last_os_error();
+        match err.kind() {
+            io::ErrorKind::Interrupted => Ok(false),
+            _ => Err(err),
+        }
+    } else {
+        Ok(ret > 0)
+    }
+}
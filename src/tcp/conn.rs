@@ -0,0 +1,757 @@
+use super::{seq_le, seq_lt};
+use crate::arp::ArpResolver;
+use crate::dstructs::Bits;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::{ip_proto, tcp_flags, Hdr, IPv4Hdr, Raw, TcpHdr};
+use crate::io::PacketIo;
+use crate::link::IpLink;
+use crate::proto::Proto;
+use crate::utility::{parse_ip, pseudo_checksum, random_u32};
+use crate::Pdu;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Connection states of [RFC 793](https://datatracker.ietf.org/doc/html/rfc793)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+/// Options of `TcpConn`
+#[derive(Debug, Clone)]
+pub struct TcpOpts {
+    /// Local IP, `0.0.0.0` takes it from resolver or default interface
+    pub src_ip: [u8; 4],
+    /// Local port of clients, random if `None`
+    pub src_port: Option<u16>,
+    /// Largest segment sent, also announced in SYN
+    pub mss: u16,
+    /// Receive buffer size, advertised as window
+    pub window: u16,
+    /// Retransmission timeout, doubled on every retry of a segment
+    pub rto: Duration,
+    /// Retries of a segment before giving up on connection
+    pub max_retries: u32,
+    /// Longest wait of `connect`, `accept`, `flush` and `close`
+    pub timeout: Duration,
+}
+
+impl TcpOpts {
+    pub fn new() -> Self {
+        Self {
+            src_ip: [0; 4],
+            src_port: None,
+            mss: 1460,
+            window: 65535,
+            rto: Duration::from_secs(1),
+            max_retries: 5,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Default for TcpOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Called with every outgoing segment before it is built
+///
+/// Fields left to `0`, such as checksum, are filled in after the hook runs,
+/// so setting them sends them as they are. Returning `false` drops the
+/// segment.
+pub type SegmentHook<'a> = Box<dyn FnMut(&mut Pdu) -> bool + 'a>;
+
+/// Returns MSS option of `tcp` if present
+fn mss_option(tcp: &TcpHdr) -> Option<u16> {
+    let options = &tcp.options;
+    let mut i = 0;
+    while i < options.len() {
+        match options[i] {
+            0 => break,
+            1 => i += 1,
+            kind => {
+                let len = *options.get(i + 1)? as usize;
+                if kind == 2 && len == 4 && i + 4 <= options.len() {
+                    return Some(u16::from_be_bytes([options[i + 2], options[i + 3]]));
+                }
+                i += len.max(2);
+            }
+        }
+    }
+    None
+}
+
+fn valid_checksum(ipv4: &IPv4Hdr, tcp: &TcpHdr, data: &[u8]) -> bool {
+    let mut segment: Vec<u8> = match tcp.create() {
+        Ok(packet) => packet.into(),
+        Err(_) => return false,
+    };
+    segment.extend_from_slice(data);
+    pseudo_checksum(
+        &ipv4.src_ip_addr,
+        &ipv4.dst_ip_addr,
+        ip_proto::TCP,
+        &segment,
+    ) == 0
+}
+
+/// Segment sent and not yet acknowledged
+struct Unacked {
+    seq: u32,
+    flags: u16,
+    data: Vec<u8>,
+    sent: Instant,
+    retries: u32,
+}
+
+impl Unacked {
+    /// Sequence space taken, SYN and FIN count as one
+    fn len(&self) -> u32 {
+        let syn_fin = self.flags & (tcp_flags::SYN | tcp_flags::FIN);
+        self.data.len() as u32 + syn_fin.count_ones()
+    }
+}
+
+/// Userspace TCP endpoint running over a `PacketIo`
+///
+/// Kernel never sees this connection, so segments can be sent the way a
+/// test needs through `set_hook` and `inject`. Data is retransmitted after
+/// `opts.rto` with exponential backoff and in-flight data never exceeds the
+/// window advertised by peer. MSS is the only TCP option supported, there
+/// is no window scaling or SACK. Zero windows are not probed either, so
+/// sending stalls if the window update of peer gets lost.
+///
+/// The kernel answers segments of ports it does not know with RST, so on a
+/// real interface such RSTs need to be filtered, for example with iptables.
+pub struct TcpConn<'a> {
+    opts: TcpOpts,
+    link: IpLink<'a>,
+    hook: Option<SegmentHook<'a>>,
+    state: TcpState,
+    local: ([u8; 4], u16),
+    remote: ([u8; 4], u16),
+    snd_una: u32,
+    snd_nxt: u32,
+    snd_wnd: u32,
+    snd_mss: usize,
+    irs: u32,
+    rcv_nxt: u32,
+    unacked: VecDeque<Unacked>,
+    send_buf: VecDeque<u8>,
+    fin_queued: bool,
+    fin_sent: bool,
+    fin_received: bool,
+    recv_buf: Vec<u8>,
+    /// Segments received ahead of `rcv_nxt`, keyed by offset from `irs`
+    out_of_order: BTreeMap<u32, Vec<u8>>,
+    reset: bool,
+}
+
+impl<'a> TcpConn<'a> {
+    fn with_link(
+        link: IpLink<'a>,
+        opts: TcpOpts,
+        state: TcpState,
+        local_port: u16,
+        remote: ([u8; 4], u16),
+    ) -> Result<Self, PaError> {
+        let iss = random_u32();
+        Ok(Self {
+            local: (link.src_ip(opts.src_ip)?, local_port),
+            remote,
+            snd_mss: opts.mss as usize,
+            opts,
+            link,
+            hook: None,
+            state,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_wnd: 0,
+            irs: 0,
+            rcv_nxt: 0,
+            unacked: VecDeque::new(),
+            send_buf: VecDeque::new(),
+            fin_queued: false,
+            fin_sent: false,
+            fin_received: false,
+            recv_buf: Vec::new(),
+            out_of_order: BTreeMap::new(),
+            reset: false,
+        })
+    }
+
+    fn new_client(
+        link: IpLink<'a>,
+        remote_ip: impl ToString,
+        remote_port: u16,
+        opts: TcpOpts,
+    ) -> Result<Self, PaError> {
+        let local_port = opts
+            .src_port
+            .unwrap_or_else(|| 32768 + (random_u32() % 28232) as u16);
+        let remote = (parse_ip(remote_ip)?, remote_port);
+        Self::with_link(link, opts, TcpState::Closed, local_port, remote)
+    }
+
+    /// Creates client of `remote_ip:remote_port`, packets start at IP header
+    pub fn client(
+        remote_ip: impl ToString,
+        remote_port: u16,
+        opts: TcpOpts,
+    ) -> Result<Self, PaError> {
+        Self::new_client(IpLink::new(None), remote_ip, remote_port, opts)
+    }
+
+    /// Same as `client` with Ethernet frames sent to the next hop
+    pub fn client_eth(
+        resolver: &'a mut ArpResolver,
+        remote_ip: impl ToString,
+        remote_port: u16,
+        opts: TcpOpts,
+    ) -> Result<Self, PaError> {
+        Self::new_client(IpLink::new(Some(resolver)), remote_ip, remote_port, opts)
+    }
+
+    /// Creates server listening on `local_port`, packets start at IP header
+    pub fn listen(local_port: u16, opts: TcpOpts) -> Result<Self, PaError> {
+        Self::with_link(
+            IpLink::new(None),
+            opts,
+            TcpState::Listen,
+            local_port,
+            ([0; 4], 0),
+        )
+    }
+
+    /// Same as `listen` with Ethernet frames sent to the next hop
+    pub fn listen_eth(
+        resolver: &'a mut ArpResolver,
+        local_port: u16,
+        opts: TcpOpts,
+    ) -> Result<Self, PaError> {
+        Self::with_link(
+            IpLink::new(Some(resolver)),
+            opts,
+            TcpState::Listen,
+            local_port,
+            ([0; 4], 0),
+        )
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    /// Returns local IP and port
+    pub fn local(&self) -> ([u8; 4], u16) {
+        self.local
+    }
+
+    /// Returns remote IP and port, zero for a server before a SYN arrived
+    pub fn remote(&self) -> ([u8; 4], u16) {
+        self.remote
+    }
+
+    /// Next sequence number to send
+    pub fn snd_nxt(&self) -> u32 {
+        self.snd_nxt
+    }
+
+    /// Next sequence number expected from peer
+    pub fn rcv_nxt(&self) -> u32 {
+        self.rcv_nxt
+    }
+
+    /// Bytes sent and not acknowledged yet
+    pub fn in_flight(&self) -> u32 {
+        self.snd_nxt.wrapping_sub(self.snd_una)
+    }
+
+    /// Last window advertised by peer
+    pub fn peer_window(&self) -> u32 {
+        self.snd_wnd
+    }
+
+    /// Sets hook called with every outgoing segment, see `SegmentHook`
+    pub fn set_hook(&mut self, hook: impl FnMut(&mut Pdu) -> bool + 'a) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_hook(&mut self) {
+        self.hook = None;
+    }
+
+    /// Window advertised to peer
+    fn rcv_wnd(&self) -> u32 {
+        (self.opts.window as usize).saturating_sub(self.recv_buf.len()) as u32
+    }
+
+    /// Creates segment of this connection, acknowledging `rcv_nxt` if
+    /// `flags` holds ACK
+    pub fn segment(&self, flags: u16, seq: u32, data: &[u8]) -> Pdu {
+        let mut ipv4 = IPv4Hdr::new();
+        ipv4.src_ip_addr = self.local.0;
+        ipv4.dst_ip_addr = self.remote.0;
+        let mut tcp = TcpHdr::from(self.local.1, self.remote.1, flags);
+        tcp.seq_num = Bits::from(seq as usize, 32);
+        if flags & tcp_flags::ACK != 0 {
+            tcp.ack_num = Bits::from(self.rcv_nxt as usize, 32);
+        }
+        tcp.window = Bits::from(self.rcv_wnd().min(65535) as usize, 16);
+        if flags & tcp_flags::SYN != 0 {
+            let mss = self.opts.mss.to_be_bytes();
+            // Four bytes never exceed limit of options
+            tcp.set_options(&[2, 4, mss[0], mss[1]]).unwrap();
+        }
+        let pdu = Pdu::new().header(ipv4).header(tcp);
+        if data.is_empty() {
+            pdu
+        } else {
+            pdu.header(Raw::from(data))
+        }
+    }
+
+    /// Sends `pdu` through hook without tracking it
+    ///
+    /// Meant for segments the connection would never send itself, created
+    /// with `segment` and changed as needed.
+    pub fn inject<T: PacketIo>(&mut self, io: &mut T, mut pdu: Pdu) -> Result<(), PaError> {
+        if let Some(hook) = self.hook.as_mut() {
+            if !hook(&mut pdu) {
+                return Ok(());
+            }
+        }
+        self.link.send(io, pdu)?;
+        Ok(())
+    }
+
+    fn emit<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        flags: u16,
+        seq: u32,
+        data: &[u8],
+    ) -> Result<(), PaError> {
+        let pdu = self.segment(flags, seq, data);
+        self.inject(io, pdu)
+    }
+
+    /// Sends segment at `snd_nxt` and keeps it for retransmission
+    fn send_tracked<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        flags: u16,
+        data: Vec<u8>,
+    ) -> Result<(), PaError> {
+        self.emit(io, flags, self.snd_nxt, &data)?;
+        let segment = Unacked {
+            seq: self.snd_nxt,
+            flags,
+            data,
+            sent: Instant::now(),
+            retries: 0,
+        };
+        self.snd_nxt = self.snd_nxt.wrapping_add(segment.len());
+        self.unacked.push_back(segment);
+        Ok(())
+    }
+
+    /// Sends queued data fitting in peer window, then FIN if queued
+    fn transmit<T: PacketIo>(&mut self, io: &mut T) -> Result<(), PaError> {
+        if !matches!(
+            self.state,
+            TcpState::Established | TcpState::CloseWait | TcpState::FinWait1 | TcpState::LastAck
+        ) {
+            return Ok(());
+        }
+        while !self.send_buf.is_empty() {
+            let in_flight = self.in_flight();
+            if in_flight >= self.snd_wnd {
+                break;
+            }
+            let len = self
+                .snd_mss
+                .min((self.snd_wnd - in_flight) as usize)
+                .min(self.send_buf.len());
+            let data: Vec<u8> = self.send_buf.drain(..len).collect();
+            self.send_tracked(io, tcp_flags::PSH | tcp_flags::ACK, data)?;
+        }
+        if self.send_buf.is_empty() && self.fin_queued && !self.fin_sent {
+            self.fin_sent = true;
+            self.send_tracked(io, tcp_flags::FIN | tcp_flags::ACK, Vec::new())?;
+        }
+        Ok(())
+    }
+
+    fn retransmit_at(&self) -> Option<Instant> {
+        self.unacked
+            .front()
+            .map(|segment| segment.sent + self.opts.rto * 2u32.saturating_pow(segment.retries))
+    }
+
+    /// Retransmits oldest segment if its timeout passed
+    fn on_timer<T: PacketIo>(&mut self, io: &mut T) -> Result<(), PaError> {
+        match self.retransmit_at() {
+            Some(at) if at <= Instant::now() => {}
+            _ => return Ok(()),
+        }
+        let segment = self.unacked.front_mut().unwrap();
+        segment.retries += 1;
+        segment.sent = Instant::now();
+        if segment.retries > self.opts.max_retries {
+            self.state = TcpState::Closed;
+            self.unacked.clear();
+            return Err(PaError::new(
+                "Retransmission limit reached",
+                ErrorType::TimeoutError,
+            ));
+        }
+        let (flags, seq, data) = (segment.flags, segment.seq, segment.data.clone());
+        self.emit(io, flags, seq, &data)
+    }
+
+    /// Processes acknowledgment `ack` and window of peer
+    fn on_ack(&mut self, ack: u32, window: u32) {
+        if seq_lt(ack, self.snd_una) || seq_lt(self.snd_nxt, ack) {
+            return;
+        }
+        self.snd_wnd = window;
+        self.snd_una = ack;
+        while let Some(front) = self.unacked.front_mut() {
+            let end = front.seq.wrapping_add(front.len());
+            if seq_le(end, ack) {
+                self.unacked.pop_front();
+                continue;
+            }
+            if seq_lt(front.seq, ack) {
+                let acked = ack.wrapping_sub(front.seq) as usize;
+                front.data.drain(..acked.min(front.data.len()));
+                front.seq = ack;
+                front.retries = 0;
+            }
+            break;
+        }
+        if self.fin_sent && self.unacked.is_empty() {
+            self.state = match self.state {
+                TcpState::FinWait1 => TcpState::FinWait2,
+                TcpState::Closing => TcpState::TimeWait,
+                TcpState::LastAck => TcpState::Closed,
+                state => state,
+            };
+        }
+    }
+
+    /// Adds data at `seq` to receive buffer, keeping data received first
+    /// where segments overlap
+    fn on_data(&mut self, seq: u32, data: &[u8]) {
+        let (mut seq, mut data) = (seq, data);
+        if seq_lt(seq, self.rcv_nxt) {
+            let old = self.rcv_nxt.wrapping_sub(seq) as usize;
+            if old >= data.len() {
+                return;
+            }
+            seq = self.rcv_nxt;
+            data = &data[old..];
+        }
+        let room = self.rcv_nxt.wrapping_add(self.rcv_wnd()).wrapping_sub(seq) as i32;
+        if room <= 0 {
+            return;
+        }
+        data = &data[..data.len().min(room as usize)];
+
+        if seq != self.rcv_nxt {
+            let offset = seq.wrapping_sub(self.irs);
+            let entry = self.out_of_order.entry(offset).or_default();
+            if entry.len() < data.len() {
+                *entry = data.to_vec();
+            }
+            return;
+        }
+        self.recv_buf.extend_from_slice(data);
+        self.rcv_nxt = self.rcv_nxt.wrapping_add(data.len() as u32);
+
+        while let Some((&offset, _)) = self.out_of_order.iter().next() {
+            let seq = self.irs.wrapping_add(offset);
+            if seq_lt(self.rcv_nxt, seq) {
+                break;
+            }
+            let data = self.out_of_order.remove(&offset).unwrap();
+            let old = self.rcv_nxt.wrapping_sub(seq) as usize;
+            if old < data.len() {
+                self.recv_buf.extend_from_slice(&data[old..]);
+                self.rcv_nxt = self.rcv_nxt.wrapping_add((data.len() - old) as u32);
+            }
+        }
+    }
+
+    fn on_reset(&mut self) {
+        self.reset = true;
+        self.state = TcpState::Closed;
+        self.unacked.clear();
+        self.send_buf.clear();
+    }
+
+    /// Processes one received packet
+    fn process<T: PacketIo>(&mut self, io: &mut T, pdu: &Pdu) -> Result<(), PaError> {
+        let (ipv4, tcp) = match (pdu.headers.get(&3), pdu.headers.get(&4)) {
+            (Some(Proto::IPv4(ipv4)), Some(Proto::TCP(tcp))) => (ipv4, tcp),
+            _ => return Ok(()),
+        };
+        let data = pdu.payload();
+        let src = (ipv4.src_ip_addr, tcp.src_port.to_usize() as u16);
+        if (ipv4.dst_ip_addr, tcp.dst_port.to_usize() as u16) != self.local
+            || (self.state != TcpState::Listen && src != self.remote)
+            || !valid_checksum(ipv4, tcp, data)
+        {
+            return Ok(());
+        }
+        let seq = tcp.seq_num.to_usize() as u32;
+        let ack = tcp.ack_num.to_usize() as u32;
+        let window = tcp.window.to_usize() as u32;
+        let flag = |flags| tcp.has_flags(flags);
+
+        match self.state {
+            TcpState::Closed => return Ok(()),
+            TcpState::Listen => {
+                if flag(tcp_flags::SYN) && !flag(tcp_flags::ACK) && !flag(tcp_flags::RST) {
+                    self.remote = src;
+                    self.irs = seq;
+                    self.rcv_nxt = seq.wrapping_add(1);
+                    self.snd_wnd = window;
+                    if let Some(mss) = mss_option(tcp) {
+                        self.snd_mss = self.snd_mss.min(mss as usize);
+                    }
+                    self.state = TcpState::SynReceived;
+                    self.send_tracked(io, tcp_flags::SYN | tcp_flags::ACK, Vec::new())?;
+                }
+                return Ok(());
+            }
+            TcpState::SynSent => {
+                if flag(tcp_flags::ACK) && ack != self.snd_nxt {
+                    return Ok(());
+                }
+                if flag(tcp_flags::RST) {
+                    if flag(tcp_flags::ACK) {
+                        self.on_reset();
+                    }
+                } else if flag(tcp_flags::SYN | tcp_flags::ACK) {
+                    self.irs = seq;
+                    self.rcv_nxt = seq.wrapping_add(1);
+                    if let Some(mss) = mss_option(tcp) {
+                        self.snd_mss = self.snd_mss.min(mss as usize);
+                    }
+                    self.on_ack(ack, window);
+                    self.state = TcpState::Established;
+                    self.emit(io, tcp_flags::ACK, self.snd_nxt, &[])?;
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let in_window =
+            seq_le(self.rcv_nxt, seq) && seq_le(seq, self.rcv_nxt.wrapping_add(self.rcv_wnd()));
+        if flag(tcp_flags::RST) {
+            if in_window {
+                self.on_reset();
+            }
+            return Ok(());
+        }
+        if flag(tcp_flags::SYN) {
+            // Peer missed our ACK of its SYN
+            if self.state != TcpState::SynReceived {
+                self.emit(io, tcp_flags::ACK, self.snd_nxt, &[])?;
+            }
+            return Ok(());
+        }
+        if !flag(tcp_flags::ACK) {
+            return Ok(());
+        }
+        if self.state == TcpState::SynReceived {
+            if ack != self.snd_nxt {
+                return Ok(());
+            }
+            self.state = TcpState::Established;
+        }
+        self.on_ack(ack, window);
+
+        let mut ack_needed = false;
+        if !data.is_empty() {
+            self.on_data(seq, data);
+            ack_needed = true;
+        }
+        if flag(tcp_flags::FIN) {
+            if seq.wrapping_add(data.len() as u32) == self.rcv_nxt && !self.fin_received {
+                self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+                self.fin_received = true;
+                self.state = match self.state {
+                    TcpState::Established => TcpState::CloseWait,
+                    TcpState::FinWait1 => TcpState::Closing,
+                    TcpState::FinWait2 => TcpState::TimeWait,
+                    state => state,
+                };
+            }
+            ack_needed = true;
+        }
+        if ack_needed {
+            self.emit(io, tcp_flags::ACK, self.snd_nxt, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Waits at most `timeout` for one packet, processes it, then
+    /// retransmits and sends queued data as needed
+    pub fn poll<T: PacketIo>(&mut self, io: &mut T, timeout: Duration) -> Result<(), PaError> {
+        let mut wait = timeout;
+        if let Some(at) = self.retransmit_at() {
+            wait = wait.min(at.saturating_duration_since(Instant::now()));
+        }
+        if let Some(frame) = io.recv_timeout(wait)? {
+            let pdu = self.link.parse(&frame);
+            self.process(io, &pdu)?;
+        }
+        self.on_timer(io)?;
+        self.transmit(io)
+    }
+
+    /// Polls until `done` holds, failing after `timeout` or on reset
+    fn wait_for<T: PacketIo>(
+        &mut self,
+        io: &mut T,
+        timeout: Duration,
+        what: &str,
+        done: impl Fn(&Self) -> bool,
+    ) -> Result<(), PaError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.reset {
+                return Err(PaError::new(
+                    "Connection reset by peer",
+                    ErrorType::ChannelError,
+                ));
+            }
+            if done(self) {
+                return Ok(());
+            }
+            match deadline.checked_duration_since(Instant::now()) {
+                Some(left) => self.poll(io, left)?,
+                None => {
+                    return Err(PaError::new(
+                        format!("Timed out waiting for {}", what),
+                        ErrorType::TimeoutError,
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Opens connection with the 3-way handshake
+    pub fn connect<T: PacketIo>(&mut self, io: &mut T) -> Result<(), PaError> {
+        if self.state != TcpState::Closed || self.reset {
+            return Err(PaError::new(
+                "Connection already used",
+                ErrorType::ChannelError,
+            ));
+        }
+        self.state = TcpState::SynSent;
+        self.send_tracked(io, tcp_flags::SYN, Vec::new())?;
+        self.wait_for(io, self.opts.timeout, "SYN/ACK", |conn| {
+            conn.state == TcpState::Established
+        })
+    }
+
+    /// Waits for a client to complete the 3-way handshake
+    pub fn accept<T: PacketIo>(&mut self, io: &mut T) -> Result<(), PaError> {
+        if self.state != TcpState::Listen {
+            return Err(PaError::new(
+                "Connection is not listening",
+                ErrorType::ChannelError,
+            ));
+        }
+        self.wait_for(io, self.opts.timeout, "connection", |conn| {
+            conn.state == TcpState::Established
+        })
+    }
+
+    /// Queues `data` and sends as much as peer window allows
+    pub fn send<T: PacketIo>(&mut self, io: &mut T, data: &[u8]) -> Result<(), PaError> {
+        if !matches!(self.state, TcpState::Established | TcpState::CloseWait) || self.fin_queued {
+            return Err(PaError::new(
+                "Connection is not open for sending",
+                ErrorType::ChannelError,
+            ));
+        }
+        self.send_buf.extend(data);
+        self.transmit(io)
+    }
+
+    /// Waits until all data sent is acknowledged
+    pub fn flush<T: PacketIo>(&mut self, io: &mut T) -> Result<(), PaError> {
+        self.wait_for(io, self.opts.timeout, "acknowledgment", |conn| {
+            conn.send_buf.is_empty() && conn.unacked.is_empty()
+        })
+    }
+
+    /// Returns data received so far, waiting at most `timeout` for some
+    ///
+    /// Empty data means peer closed its side.
+    pub fn recv<T: PacketIo>(&mut self, io: &mut T, timeout: Duration) -> Result<Vec<u8>, PaError> {
+        self.wait_for(io, timeout, "data", |conn| {
+            !conn.recv_buf.is_empty() || conn.fin_received
+        })?;
+        let was_small = (self.rcv_wnd() as usize) < self.snd_mss;
+        let data = std::mem::take(&mut self.recv_buf);
+        // Let peer know window opened again
+        if was_small && !matches!(self.state, TcpState::Closed | TcpState::TimeWait) {
+            self.emit(io, tcp_flags::ACK, self.snd_nxt, &[])?;
+        }
+        Ok(data)
+    }
+
+    /// Sends FIN after queued data and waits for the close to complete
+    ///
+    /// When peer did not close its side yet, waits for its FIN. `TimeWait`
+    /// is left to the caller, nothing is resent from it.
+    pub fn close<T: PacketIo>(&mut self, io: &mut T) -> Result<(), PaError> {
+        self.state = match self.state {
+            TcpState::Established | TcpState::SynReceived => TcpState::FinWait1,
+            TcpState::CloseWait => TcpState::LastAck,
+            TcpState::Listen | TcpState::SynSent => {
+                self.state = TcpState::Closed;
+                return Ok(());
+            }
+            state => state,
+        };
+        self.fin_queued = true;
+        self.transmit(io)?;
+        self.wait_for(io, self.opts.timeout, "close", |conn| {
+            matches!(conn.state, TcpState::Closed | TcpState::TimeWait)
+        })
+    }
+
+    /// Sends RST and drops connection
+    pub fn abort<T: PacketIo>(&mut self, io: &mut T) -> Result<(), PaError> {
+        if !matches!(
+            self.state,
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::TimeWait
+        ) {
+            self.emit(io, tcp_flags::RST, self.snd_nxt, &[])?;
+        }
+        self.state = TcpState::Closed;
+        self.unacked.clear();
+        self.send_buf.clear();
+        Ok(())
+    }
+}
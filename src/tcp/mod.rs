@@ -0,0 +1,19 @@
+//! TCP tools built on `TcpHdr`
+//!
+//! Like `arp`, everything here runs on any `PacketIo`.
+
+mod conn;
+pub use conn::*;
+mod scan;
+pub use scan::*;
+mod stream;
+pub use stream::*;
+
+/// Returns `true` if sequence number `a` comes before `b`
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    a == b || seq_lt(a, b)
+}
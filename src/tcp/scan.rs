@@ -0,0 +1,312 @@
+use crate::arp::ArpResolver;
+use crate::dstructs::Bits;
+use crate::error::{ErrorType, PaError};
+use crate::hdr::{icmp_type, ip_proto, tcp_flags, IPv4Hdr, TcpHdr};
+use crate::io::PacketIo;
+use crate::link::{IpLink, Quote};
+use crate::proto::Proto;
+use crate::utility::{parse_ip, random_u32};
+use crate::Pdu;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// State of a port found by `syn_scan`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortState {
+    /// Answered with SYN/ACK
+    Open,
+    /// Answered with RST
+    Closed,
+    /// Did not answer or an ICMP unreachable came back
+    Filtered,
+}
+
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+        })
+    }
+}
+
+/// Result of one port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortResult {
+    pub port: u16,
+    pub state: PortState,
+    /// Time from SYN to the answer, `None` if nothing answered
+    pub rtt: Option<Duration>,
+}
+
+/// Options of `syn_scan`
+#[derive(Debug, Clone)]
+pub struct SynScanOpts {
+    /// SYNs sent per second, `None` sends as fast as possible
+    pub rate: Option<f64>,
+    /// Number of extra rounds sent to ports which did not answer
+    pub retries: usize,
+    /// How long answers are awaited after last SYN of a round
+    pub timeout: Duration,
+    /// Source IP, `0.0.0.0` takes it from resolver or default interface
+    pub src_ip: [u8; 4],
+    /// Send RST to ports answering SYN/ACK, so half-open connections are
+    /// torn down
+    pub reset: bool,
+}
+
+impl SynScanOpts {
+    pub fn new() -> Self {
+        Self {
+            rate: Some(1000.0),
+            retries: 1,
+            timeout: Duration::from_secs(1),
+            src_ip: [0; 4],
+            reset: true,
+        }
+    }
+}
+
+impl Default for SynScanOpts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses port list such as `"22,80,8000-8080"`
+///
+/// Returned ports are sorted and unique.
+pub fn parse_ports(ports: impl ToString) -> Result<Vec<u16>, PaError> {
+    let ports = ports.to_string();
+    let parse = |port: &str| {
+        port.trim().parse::<u16>().map_err(|_| {
+            PaError::new(
+                format!("Invalid port {:?}", port.trim()).as_str(),
+                ErrorType::ParseError,
+            )
+        })
+    };
+    let mut list = BTreeSet::new();
+    for part in ports.split(',').filter(|part| !part.trim().is_empty()) {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if first > last {
+                    return Err(PaError::new(
+                        format!("Invalid port range {:?}", part.trim()).as_str(),
+                        ErrorType::ParseError,
+                    ));
+                }
+                list.extend(first..=last);
+            }
+            None => {
+                list.insert(parse(part)?);
+            }
+        }
+    }
+    Ok(list.into_iter().collect())
+}
+
+/// First source port of SYNs, as in the Linux ephemeral range
+const SRC_PORT_FIRST: u16 = 32768;
+const SRC_PORT_COUNT: u32 = 28232;
+
+/// SYN waiting for an answer, keyed by its source and destination port
+struct Syn {
+    seq: u32,
+    sent: Instant,
+}
+
+struct SynScanner<'a> {
+    opts: &'a SynScanOpts,
+    link: IpLink<'a>,
+    src_ip: [u8; 4],
+    target: [u8; 4],
+    pending: HashMap<(u16, u16), Syn>,
+    results: BTreeMap<u16, PortResult>,
+}
+
+impl SynScanner<'_> {
+    fn segment(&self, src_port: u16, dst_port: u16, flags: u16, seq: u32) -> Pdu {
+        let mut ipv4 = IPv4Hdr::new();
+        ipv4.src_ip_addr = self.src_ip;
+        ipv4.dst_ip_addr = self.target;
+        let mut tcp = TcpHdr::from(src_port, dst_port, flags);
+        tcp.seq_num = Bits::from(seq as usize, 32);
+        Pdu::new().header(ipv4).header(tcp)
+    }
+
+    /// Returns source port starting at a random one, not used by a SYN to
+    /// `port` which is still awaiting an answer
+    fn free_src_port(&self, port: u16) -> Result<u16, PaError> {
+        let first = random_u32() % SRC_PORT_COUNT;
+        for i in 0..SRC_PORT_COUNT {
+            let src_port = SRC_PORT_FIRST + ((first + i) % SRC_PORT_COUNT) as u16;
+            match self.pending.get(&(src_port, port)) {
+                Some(syn) if syn.sent.elapsed() < self.opts.timeout => continue,
+                _ => return Ok(src_port),
+            }
+        }
+        Err(PaError::new(
+            format!("No free source port to probe port {}", port),
+            ErrorType::ConstructError,
+        ))
+    }
+
+    /// Sends SYN to `port` from a random source port and sequence number
+    fn send_syn<T: PacketIo>(&mut self, io: &mut T, port: u16) -> Result<(), PaError> {
+        let src_port = self.free_src_port(port)?;
+        let seq = random_u32();
+        self.link
+            .send(io, self.segment(src_port, port, tcp_flags::SYN, seq))?;
+        let sent = Instant::now();
+        self.pending.insert((src_port, port), Syn { seq, sent });
+        Ok(())
+    }
+
+    fn answer(&mut self, key: (u16, u16), state: PortState, at: Instant) {
+        if let Some(syn) = self.pending.remove(&key) {
+            let port = key.1;
+            let rtt = Some(at - syn.sent);
+            self.results.insert(port, PortResult { port, state, rtt });
+        }
+    }
+
+    /// Handles a received packet, returns RST to send if it was SYN/ACK
+    fn process(&mut self, pdu: &Pdu, at: Instant) -> Option<Pdu> {
+        let ipv4 = match pdu.headers.get(&3) {
+            Some(Proto::IPv4(ipv4)) if ipv4.dst_ip_addr == self.src_ip => ipv4,
+            _ => return None,
+        };
+        match pdu.headers.get(&4)? {
+            Proto::TCP(tcp) if ipv4.src_ip_addr == self.target => {
+                let key = (
+                    tcp.dst_port.to_usize() as u16,
+                    tcp.src_port.to_usize() as u16,
+                );
+                let syn = self.pending.get(&key)?;
+                let ack = tcp.ack_num.to_usize() as u32;
+                if ack != syn.seq.wrapping_add(1) {
+                    return None;
+                }
+                if tcp.has_flags(tcp_flags::RST) {
+                    self.answer(key, PortState::Closed, at);
+                    None
+                } else if tcp.has_flags(tcp_flags::SYN | tcp_flags::ACK) {
+                    self.answer(key, PortState::Open, at);
+                    self.opts
+                        .reset
+                        .then(|| self.segment(key.0, key.1, tcp_flags::RST, ack))
+                } else {
+                    None
+                }
+            }
+            Proto::ICMP(icmp) if icmp.icmp_type.to_usize() as u8 == icmp_type::DEST_UNREACHABLE => {
+                let quote = Quote::parse(pdu)?;
+                if quote.proto != ip_proto::TCP || quote.dst != self.target {
+                    return None;
+                }
+                let key = (quote.word(0), quote.word(2));
+                let syn = self.pending.get(&key)?;
+                if quote.seq() == syn.seq {
+                    self.answer(key, PortState::Filtered, at);
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Processes packets received until `until`
+    fn collect<T: PacketIo>(&mut self, io: &mut T, until: Instant) -> Result<(), PaError> {
+        let mut received = Vec::new();
+        self.link
+            .recv_until(io, until, |pdu, at| received.push((pdu, at)))?;
+        for (pdu, at) in received {
+            if let Some(rst) = self.process(&pdu, at) {
+                self.link.send(io, rst)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn scan<T: PacketIo>(mut self, io: &mut T, ports: &[u16]) -> Result<Vec<PortResult>, PaError> {
+        let interval = match self.opts.rate {
+            Some(rate) if rate > 0.0 => Duration::from_secs_f64(1.0 / rate),
+            _ => Duration::from_secs(0),
+        };
+
+        let mut left: Vec<u16> = ports.to_vec();
+        left.sort_unstable();
+        left.dedup();
+        // SYNs of earlier rounds stay pending, so late SYN/ACKs are still reset
+        for _ in 0..=self.opts.retries {
+            let mut next_send = Instant::now();
+            for port in left.iter() {
+                self.collect(io, next_send)?;
+                self.send_syn(io, *port)?;
+                next_send = Instant::now() + interval;
+            }
+            self.collect(io, Instant::now() + self.opts.timeout)?;
+
+            left.retain(|port| !self.results.contains_key(port));
+            if left.is_empty() {
+                break;
+            }
+        }
+
+        for port in left {
+            self.results.insert(
+                port,
+                PortResult {
+                    port,
+                    state: PortState::Filtered,
+                    rtt: None,
+                },
+            );
+        }
+        Ok(self.results.into_values().collect())
+    }
+}
+
+fn scanner<'a>(
+    opts: &'a SynScanOpts,
+    link: IpLink<'a>,
+    target: impl ToString,
+) -> Result<SynScanner<'a>, PaError> {
+    Ok(SynScanner {
+        src_ip: link.src_ip(opts.src_ip)?,
+        target: parse_ip(target)?,
+        opts,
+        link,
+        pending: HashMap::new(),
+        results: BTreeMap::new(),
+    })
+}
+
+/// Scans TCP `ports` of `target` with packets starting at IP header
+///
+/// Each SYN comes from a random source port with a random sequence number.
+/// Ports which did not answer are probed again in up to `opts.retries` more
+/// rounds and are reported filtered after that. Results are sorted by port.
+pub fn syn_scan<T: PacketIo>(
+    io: &mut T,
+    target: impl ToString,
+    ports: &[u16],
+    opts: &SynScanOpts,
+) -> Result<Vec<PortResult>, PaError> {
+    scanner(opts, IpLink::new(None), target)?.scan(io, ports)
+}
+
+/// Same as `syn_scan` with Ethernet frames sent to the next hop, as on `Channel`
+pub fn syn_scan_eth<T: PacketIo>(
+    io: &mut T,
+    resolver: &mut ArpResolver,
+    target: impl ToString,
+    ports: &[u16],
+    opts: &SynScanOpts,
+) -> Result<Vec<PortResult>, PaError> {
+    scanner(opts, IpLink::new(Some(resolver)), target)?.scan(io, ports)
+}
@@ -0,0 +1,369 @@
+use super::seq_lt;
+#[cfg(feature = "pcap")]
+use crate::error::ErrorType;
+use crate::error::PaError;
+use crate::hdr::tcp_flags;
+use crate::io::PacketIo;
+use crate::proto::Proto;
+use crate::Pdu;
+#[cfg(feature = "pcap")]
+use pcap_file::PcapReader;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(feature = "pcap")]
+use std::fs::File;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// Side of a connection which sent data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Client,
+    Server,
+}
+
+/// Connection tracked by `StreamReassembler`
+///
+/// Client is the side which sent SYN, or the first packet seen when
+/// capture started after the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamKey {
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+}
+
+/// Receives events of `StreamReassembler`, every method does nothing by
+/// default
+pub trait StreamHandler {
+    /// First packet of connection seen
+    fn on_open(&mut self, _key: &StreamKey, _ts: Duration) {}
+
+    /// Next bytes of stream sent by `side`, in order and without repeats
+    fn on_data(&mut self, _key: &StreamKey, _side: Side, _data: &[u8], _ts: Duration) {}
+
+    /// `len` bytes sent by `side` were never seen and are skipped
+    fn on_gap(&mut self, _key: &StreamKey, _side: Side, _len: u32, _ts: Duration) {}
+
+    /// Both sides sent FIN and all data before it was delivered
+    fn on_close(&mut self, _key: &StreamKey, _ts: Duration) {}
+
+    /// `side` sent RST
+    fn on_reset(&mut self, _key: &StreamKey, _side: Side, _ts: Duration) {}
+
+    /// Nothing seen for `StreamReassembler::timeout`, or capture ended
+    fn on_timeout(&mut self, _key: &StreamKey, _ts: Duration) {}
+}
+
+/// Data sent by one side
+#[derive(Default)]
+struct HalfStream {
+    /// Next sequence number to deliver, `None` until first segment
+    next: Option<u32>,
+    /// Segments ahead of `next`, keyed by sequence number
+    pending: BTreeMap<u32, Vec<u8>>,
+    pending_bytes: usize,
+    fin: Option<u32>,
+    closed: bool,
+}
+
+impl HalfStream {
+    /// Delivers data of `pending` which became contiguous
+    fn drain(&mut self, mut deliver: impl FnMut(&[u8])) {
+        let mut next = match self.next {
+            Some(next) => next,
+            None => return,
+        };
+        while let Some((&seq, _)) = self.pending.iter().find(|(&seq, _)| !seq_lt(next, seq)) {
+            let data = self.pending.remove(&seq).unwrap();
+            self.pending_bytes -= data.len();
+            let old = next.wrapping_sub(seq) as usize;
+            if old < data.len() {
+                deliver(&data[old..]);
+                next = next.wrapping_add((data.len() - old) as u32);
+            }
+        }
+        if self.fin == Some(next) && !self.closed {
+            self.closed = true;
+            next = next.wrapping_add(1);
+        }
+        self.next = Some(next);
+    }
+
+    /// Skips to first pending segment, returns length of gap skipped
+    fn skip_gap(&mut self) -> Option<u32> {
+        let next = self.next?;
+        let first = self
+            .pending
+            .keys()
+            .copied()
+            .min_by_key(|seq| seq.wrapping_sub(next))?;
+        self.next = Some(first);
+        Some(first.wrapping_sub(next))
+    }
+
+    /// Adds segment, returns `true` if buffered data exceeds `limit`
+    fn add(&mut self, seq: u32, data: &[u8], fin: bool, limit: usize) -> bool {
+        let next = *self.next.get_or_insert(seq);
+        if fin {
+            self.fin = Some(seq.wrapping_add(data.len() as u32));
+        }
+        let (seq, data) = if seq_lt(seq, next) {
+            // Retransmission, keep what was delivered first
+            let old = next.wrapping_sub(seq) as usize;
+            if old >= data.len() {
+                return false;
+            }
+            (next, &data[old..])
+        } else {
+            (seq, data)
+        };
+        if data.is_empty() {
+            return false;
+        }
+        match self.pending.get(&seq) {
+            Some(known) if known.len() >= data.len() => {}
+            known => {
+                let known = known.map_or(0, Vec::len);
+                self.pending_bytes += data.len() - known;
+                self.pending.insert(seq, data.to_vec());
+            }
+        }
+        self.pending_bytes > limit
+    }
+}
+
+struct Stream {
+    client: HalfStream,
+    server: HalfStream,
+    last_seen: Duration,
+}
+
+impl Stream {
+    fn half(&mut self, side: Side) -> &mut HalfStream {
+        match side {
+            Side::Client => &mut self.client,
+            Side::Server => &mut self.server,
+        }
+    }
+}
+
+/// Puts TCP segments of captured or live traffic back into byte streams
+///
+/// Segments are ordered by sequence number, which may wrap around.
+/// Retransmitted and overlapping bytes are delivered once, as first seen.
+/// Data behind a missing segment is held until the gap is filled, the
+/// connection ends or more than `max_buffer` bytes wait, then the gap is
+/// reported with `on_gap` and skipped.
+pub struct StreamReassembler {
+    /// Connections idle this long are dropped with `on_timeout`
+    pub timeout: Duration,
+    /// Bytes held behind a gap per direction before it is skipped
+    pub max_buffer: usize,
+    streams: HashMap<StreamKey, Stream>,
+    last_expire: Duration,
+}
+
+impl StreamReassembler {
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(120),
+            max_buffer: 1 << 20,
+            streams: HashMap::new(),
+            last_expire: Duration::from_secs(0),
+        }
+    }
+
+    /// Number of connections being tracked
+    pub fn streams(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Delivers all data held by half of `key`, skipping gaps
+    fn flush<H: StreamHandler>(
+        stream: &mut Stream,
+        key: &StreamKey,
+        side: Side,
+        ts: Duration,
+        handler: &mut H,
+    ) {
+        let half = stream.half(side);
+        while !half.pending.is_empty() {
+            half.drain(|data| handler.on_data(key, side, data, ts));
+            if let Some(gap) = half.skip_gap() {
+                handler.on_gap(key, side, gap, ts);
+            }
+        }
+    }
+
+    /// Removes connections idle at `ts` reporting them with `on_timeout`
+    pub fn expire<H: StreamHandler>(&mut self, ts: Duration, handler: &mut H) {
+        self.last_expire = ts;
+        let timeout = self.timeout;
+        let expired: Vec<StreamKey> = self
+            .streams
+            .iter()
+            .filter(|(_, stream)| ts.saturating_sub(stream.last_seen) >= timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            let mut stream = self.streams.remove(&key).unwrap();
+            Self::flush(&mut stream, &key, Side::Client, ts, handler);
+            Self::flush(&mut stream, &key, Side::Server, ts, handler);
+            handler.on_timeout(&key, ts);
+        }
+    }
+
+    /// Delivers what is left of every connection at end of capture and
+    /// reports them with `on_timeout`
+    pub fn finish<H: StreamHandler>(&mut self, handler: &mut H) {
+        let ts = self
+            .streams
+            .values()
+            .map(|stream| stream.last_seen + self.timeout)
+            .max()
+            .unwrap_or_default();
+        self.expire(ts, handler);
+    }
+
+    /// Processes one Ethernet frame seen at `ts`
+    pub fn process_frame<H: StreamHandler>(&mut self, frame: &[u8], ts: Duration, handler: &mut H) {
+        self.process(&Pdu::parse(frame), ts, handler)
+    }
+
+    /// Processes one parsed packet seen at `ts`, packets other than TCP over
+    /// IPv4 or IPv6 are ignored
+    pub fn process<H: StreamHandler>(&mut self, pdu: &Pdu, ts: Duration, handler: &mut H) {
+        if ts.saturating_sub(self.last_expire) >= Duration::from_secs(1) {
+            self.expire(ts, handler);
+        }
+        let (src, dst): (IpAddr, IpAddr) = match pdu.headers.get(&3) {
+            Some(Proto::IPv4(ipv4)) => (ipv4.src_ip_addr.into(), ipv4.dst_ip_addr.into()),
+            Some(Proto::IPv6(ipv6)) => (ipv6.src_ip_addr.into(), ipv6.dst_ip_addr.into()),
+            _ => return,
+        };
+        let tcp = match pdu.headers.get(&4) {
+            Some(Proto::TCP(tcp)) => tcp,
+            _ => return,
+        };
+        let data = pdu.payload();
+        let src = SocketAddr::new(src, tcp.src_port.to_usize() as u16);
+        let dst = SocketAddr::new(dst, tcp.dst_port.to_usize() as u16);
+        let flag = |flags| tcp.has_flags(flags);
+
+        let forward = StreamKey {
+            client: src,
+            server: dst,
+        };
+        let backward = StreamKey {
+            client: dst,
+            server: src,
+        };
+        let (key, side) = if self.streams.contains_key(&forward) {
+            (forward, Side::Client)
+        } else if self.streams.contains_key(&backward) {
+            (backward, Side::Server)
+        } else if flag(tcp_flags::RST) || (data.is_empty() && !flag(tcp_flags::SYN)) {
+            // Nothing to track, such as last ACK of a closed connection
+            return;
+        } else {
+            // SYN/ACK seen first comes from server
+            let (key, side) = if flag(tcp_flags::SYN | tcp_flags::ACK) {
+                (backward, Side::Server)
+            } else {
+                (forward, Side::Client)
+            };
+            self.streams.insert(
+                key,
+                Stream {
+                    client: HalfStream::default(),
+                    server: HalfStream::default(),
+                    last_seen: ts,
+                },
+            );
+            handler.on_open(&key, ts);
+            (key, side)
+        };
+
+        let stream = self.streams.get_mut(&key).unwrap();
+        stream.last_seen = ts;
+        if flag(tcp_flags::RST) {
+            Self::flush(stream, &key, Side::Client, ts, handler);
+            Self::flush(stream, &key, Side::Server, ts, handler);
+            handler.on_reset(&key, side, ts);
+            self.streams.remove(&key);
+            return;
+        }
+
+        let mut seq = tcp.seq_num.to_usize() as u32;
+        let half = stream.half(side);
+        if flag(tcp_flags::SYN) {
+            seq = seq.wrapping_add(1);
+            if half.next.is_some() {
+                // Retransmitted SYN
+                return;
+            }
+            half.next = Some(seq);
+        }
+        if half.add(seq, data, flag(tcp_flags::FIN), self.max_buffer) {
+            half.drain(|data| handler.on_data(&key, side, data, ts));
+            while half.pending_bytes > self.max_buffer {
+                if let Some(gap) = half.skip_gap() {
+                    handler.on_gap(&key, side, gap, ts);
+                }
+                half.drain(|data| handler.on_data(&key, side, data, ts));
+            }
+        }
+        half.drain(|data| handler.on_data(&key, side, data, ts));
+
+        if stream.client.closed && stream.server.closed {
+            handler.on_close(&key, ts);
+            self.streams.remove(&key);
+        }
+    }
+
+    /// Reassembles frames received on `io` until `limit` frames were
+    /// processed, timestamps count from the call
+    pub fn run<T: PacketIo, H: StreamHandler>(
+        &mut self,
+        io: &mut T,
+        limit: Option<usize>,
+        handler: &mut H,
+    ) -> Result<usize, PaError> {
+        let time_start = Instant::now();
+        let mut count = 0;
+        while limit != Some(count) {
+            let frame = io.recv()?;
+            self.process_frame(&frame, time_start.elapsed(), handler);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Reassembles every frame of pcap file at `pcap_path` using its
+    /// timestamps, then calls `finish`
+    #[cfg(feature = "pcap")]
+    pub fn reassemble_pcap<H: StreamHandler>(
+        &mut self,
+        pcap_path: impl ToString,
+        handler: &mut H,
+    ) -> Result<usize, PaError> {
+        let pcap = File::open(pcap_path.to_string())
+            .map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+        let pcap_reader = PcapReader::new(pcap)
+            .map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+        let mut count = 0;
+        for packet in pcap_reader {
+            let packet =
+                packet.map_err(|e| PaError::new(e.to_string(), ErrorType::PcapFileError))?;
+            self.process_frame(&packet.data, packet.header.timestamp(), handler);
+            count += 1;
+        }
+        self.finish(handler);
+        Ok(count)
+    }
+}
+
+impl Default for StreamReassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,344 @@
+//! Traceroute with UDP, ICMP echo or TCP SYN probes
+//!
+//! Probes of one trace keep the fields load balancers hash on constant, as
+//! done by Paris traceroute, so every probe follows the same path. Each probe
+//! is told apart by a field left out of the flow: UDP checksum, ICMP sequence
+//! number or TCP sequence number. Routers quote that field back in their
+//! ICMP errors, which is how replies are matched to probes.
+
+use crate::arp::ArpResolver;
+use crate::dstructs::Bits;
+use crate::error::PaError;
+use crate::hdr::{icmp_type, ip_proto, tcp_flags, Hdr, IPv4Hdr, IcmpHdr, Raw, TcpHdr, UdpHdr};
+use crate::io::PacketIo;
+use crate::link::{IpLink, Quote};
+use crate::proto::Proto;
+use crate::utility::{ip_to_string, parse_ip, pseudo_checksum};
+use crate::Pdu;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Protocol of traceroute probes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceProto {
+    Udp,
+    Icmp,
+    Tcp,
+}
+
+/// Options of `traceroute`
+#[derive(Debug, Clone)]
+pub struct TraceOpts {
+    pub proto: TraceProto,
+    pub first_ttl: u8,
+    pub max_ttl: u8,
+    /// Probes sent to each hop
+    pub probes: usize,
+    /// How long replies of a hop are awaited
+    pub timeout: Duration,
+    pub src_port: u16,
+    /// Destination port of UDP and TCP probes
+    pub dst_port: u16,
+    /// ICMP identifier of echo probes
+    pub id: u16,
+    /// Source IP, `0.0.0.0` lets kernel (or resolver for `_eth` variant) pick it
+    pub src_ip: [u8; 4],
+}
+
+impl TraceOpts {
+    pub fn new(proto: TraceProto) -> Self {
+        let pid = std::process::id() as u16;
+        Self {
+            proto,
+            first_ttl: 1,
+            max_ttl: 30,
+            probes: 3,
+            timeout: Duration::from_secs(1),
+            src_port: 32768 + pid % 16384,
+            dst_port: match proto {
+                TraceProto::Tcp => 80,
+                _ => 33434,
+            },
+            id: pid,
+            src_ip: [0; 4],
+        }
+    }
+}
+
+impl Default for TraceOpts {
+    fn default() -> Self {
+        Self::new(TraceProto::Udp)
+    }
+}
+
+/// Answer to one probe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopProbe {
+    /// Address which answered, `None` if nothing came within timeout
+    pub addr: Option<[u8; 4]>,
+    pub rtt: Option<Duration>,
+}
+
+/// Answers of one TTL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceHop {
+    pub ttl: u8,
+    pub probes: Vec<HopProbe>,
+    /// Set when destination itself answered at this TTL
+    pub reached: bool,
+}
+
+/// Result of `traceroute`
+#[derive(Debug, Clone)]
+pub struct TraceResult {
+    pub target: [u8; 4],
+    pub hops: Vec<TraceHop>,
+    pub reached: bool,
+}
+
+impl fmt::Display for TraceResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, hop) in self.hops.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:2}", hop.ttl)?;
+            let mut last_addr = None;
+            for probe in hop.probes.iter() {
+                match (probe.addr, probe.rtt) {
+                    (Some(addr), Some(rtt)) => {
+                        if last_addr != Some(addr) {
+                            write!(f, "  {}", ip_to_string(&addr))?;
+                            last_addr = Some(addr);
+                        }
+                        write!(f, "  {:.3} ms", rtt.as_secs_f64() * 1000.0)?;
+                    }
+                    _ => write!(f, "  *")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds two 16 bit numbers in one's complement
+fn ones_add(a: u16, b: u16) -> u16 {
+    let sum = a as u32 + b as u32;
+    ((sum & 0xffff) + (sum >> 16)) as u16
+}
+
+/// Answer matched to a probe
+struct Answer {
+    probe: u16,
+    addr: [u8; 4],
+    reached: bool,
+}
+
+struct Tracer<'a> {
+    opts: &'a TraceOpts,
+    link: IpLink<'a>,
+    src_ip: [u8; 4],
+    target: [u8; 4],
+}
+
+impl<'a> Tracer<'a> {
+    fn new(opts: &'a TraceOpts, link: IpLink<'a>, target: [u8; 4]) -> Result<Self, PaError> {
+        // UDP and TCP checksums cover the source, it can't be left to the kernel
+        let src_ip = match opts.proto {
+            TraceProto::Icmp => opts.src_ip,
+            _ => link.src_ip(opts.src_ip)?,
+        };
+        Ok(Self {
+            opts,
+            link,
+            src_ip,
+            target,
+        })
+    }
+
+    fn ip_hdr(&self, ttl: u8, probe: u16) -> IPv4Hdr {
+        let mut ipv4 = IPv4Hdr::new();
+        ipv4.src_ip_addr = self.src_ip;
+        ipv4.dst_ip_addr = self.target;
+        ipv4.ttl = Bits::from(ttl.into(), 8);
+        ipv4.id = Bits::from(probe.into(), 16);
+        ipv4
+    }
+
+    /// Sends probe number `probe` with `ttl`
+    fn send_probe<T: PacketIo>(&mut self, io: &mut T, ttl: u8, probe: u16) -> Result<(), PaError> {
+        let pdu = Pdu::new().header(self.ip_hdr(ttl, probe));
+        let pdu = match self.opts.proto {
+            TraceProto::Udp => {
+                let mut udp = UdpHdr::from(self.opts.src_port, self.opts.dst_port);
+                udp.length = Bits::from(10, 16);
+                // Two payload bytes are chosen so that checksum equals `probe`
+                let mut segment: Vec<u8> = udp.create()?.into();
+                segment.extend_from_slice(&[0, 0]);
+                let sum = pseudo_checksum(&self.src_ip, &self.target, ip_proto::UDP, &segment);
+                let fill = ones_add(!probe, sum);
+                udp.checksum = Bits::from(probe.into(), 16);
+                pdu.header(udp).header(Raw::from(&fill.to_be_bytes()))
+            }
+            TraceProto::Icmp => {
+                // Payload makes up for the sequence number, keeping checksum constant
+                pdu.header(IcmpHdr::echo_request(self.opts.id, probe))
+                    .header(Raw::from(&(!probe).to_be_bytes()))
+            }
+            TraceProto::Tcp => {
+                let mut tcp = TcpHdr::from(self.opts.src_port, self.opts.dst_port, tcp_flags::SYN);
+                tcp.seq_num = Bits::from(probe.into(), 32);
+                pdu.header(tcp)
+            }
+        };
+        self.link.send(io, pdu)?;
+        Ok(())
+    }
+
+    /// Returns probe number quoted in an ICMP error, checking it is ours
+    fn quoted_probe(&self, quote: &Quote) -> Option<u16> {
+        if quote.dst != self.target {
+            return None;
+        }
+        match (self.opts.proto, quote.proto) {
+            (TraceProto::Udp, ip_proto::UDP) | (TraceProto::Tcp, ip_proto::TCP)
+                if quote.word(0) != self.opts.src_port || quote.word(2) != self.opts.dst_port =>
+            {
+                None
+            }
+            (TraceProto::Udp, ip_proto::UDP) => Some(quote.word(6)),
+            (TraceProto::Tcp, ip_proto::TCP) => Some(quote.seq() as u16),
+            (TraceProto::Icmp, ip_proto::ICMP) if quote.word(4) == self.opts.id => {
+                Some(quote.word(6))
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_answer(&self, pdu: &Pdu) -> Option<Answer> {
+        let ipv4 = match pdu.headers.get(&3) {
+            Some(Proto::IPv4(ipv4)) => ipv4,
+            _ => return None,
+        };
+        let addr = ipv4.src_ip_addr;
+        match (pdu.headers.get(&4), self.opts.proto) {
+            (Some(Proto::ICMP(icmp)), proto) => {
+                let kind = icmp.icmp_type.to_usize() as u8;
+                if kind == icmp_type::ECHO_REPLY {
+                    let matches = proto == TraceProto::Icmp
+                        && addr == self.target
+                        && icmp.id.to_usize() as u16 == self.opts.id;
+                    return matches.then(|| Answer {
+                        probe: icmp.seq.to_usize() as u16,
+                        addr,
+                        reached: true,
+                    });
+                }
+                if kind != icmp_type::TIME_EXCEEDED && kind != icmp_type::DEST_UNREACHABLE {
+                    return None;
+                }
+                Some(Answer {
+                    probe: self.quoted_probe(&Quote::parse(pdu)?)?,
+                    addr,
+                    reached: kind == icmp_type::DEST_UNREACHABLE && addr == self.target,
+                })
+            }
+            (Some(Proto::TCP(tcp)), TraceProto::Tcp) => {
+                let answers = addr == self.target
+                    && tcp.src_port.to_usize() as u16 == self.opts.dst_port
+                    && tcp.dst_port.to_usize() as u16 == self.opts.src_port
+                    && (tcp.has_flags(tcp_flags::SYN | tcp_flags::ACK)
+                        || tcp.has_flags(tcp_flags::RST));
+                answers.then(|| Answer {
+                    probe: (tcp.ack_num.to_usize() as u32).wrapping_sub(1) as u16,
+                    addr,
+                    reached: true,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn trace<T: PacketIo>(&mut self, io: &mut T) -> Result<TraceResult, PaError> {
+        let mut result = TraceResult {
+            target: self.target,
+            hops: Vec::new(),
+            reached: false,
+        };
+        let mut next_probe: u16 = 1;
+
+        for ttl in self.opts.first_ttl.max(1)..=self.opts.max_ttl {
+            let mut hop = TraceHop {
+                ttl,
+                probes: vec![
+                    HopProbe {
+                        addr: None,
+                        rtt: None,
+                    };
+                    self.opts.probes
+                ],
+                reached: false,
+            };
+            let mut sent: HashMap<u16, (usize, Instant)> = HashMap::new();
+            for i in 0..self.opts.probes {
+                sent.insert(next_probe, (i, Instant::now()));
+                self.send_probe(io, ttl, next_probe)?;
+                next_probe = next_probe.wrapping_add(1).max(1);
+            }
+
+            let mut answered = 0;
+            let deadline = Instant::now() + self.opts.timeout;
+            while answered < self.opts.probes {
+                let mut answers: Vec<(Answer, Instant)> = Vec::new();
+                let until = deadline.min(Instant::now() + Duration::from_millis(10));
+                self.link.recv_until(io, until, |pdu, now| {
+                    if let Some(answer) = self.parse_answer(&pdu) {
+                        answers.push((answer, now));
+                    }
+                })?;
+                for (answer, now) in answers {
+                    if let Some((i, sent_at)) = sent.remove(&answer.probe) {
+                        hop.probes[i] = HopProbe {
+                            addr: Some(answer.addr),
+                            rtt: Some(now - sent_at),
+                        };
+                        hop.reached |= answer.reached;
+                        answered += 1;
+                    }
+                }
+                if Instant::now() >= deadline {
+                    break;
+                }
+            }
+
+            let reached = hop.reached;
+            result.hops.push(hop);
+            if reached {
+                result.reached = true;
+                break;
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Traces route to `target` with packets starting at IP header
+pub fn traceroute<T: PacketIo>(
+    io: &mut T,
+    target: impl ToString,
+    opts: &TraceOpts,
+) -> Result<TraceResult, PaError> {
+    Tracer::new(opts, IpLink::new(None), parse_ip(target)?)?.trace(io)
+}
+
+/// Traces route to `target` with Ethernet frames sent to the next hop
+pub fn traceroute_eth<T: PacketIo>(
+    io: &mut T,
+    resolver: &mut ArpResolver,
+    target: impl ToString,
+    opts: &TraceOpts,
+) -> Result<TraceResult, PaError> {
+    Tracer::new(opts, IpLink::new(Some(resolver)), parse_ip(target)?)?.trace(io)
+}
@@ -3,6 +3,9 @@
 
 use crate::error::{ErrorType, PaError};
 use crate::proto::EthType;
+use std::cell::Cell;
+use std::net::Ipv6Addr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Parses IP Address in string to array of bytes of length 4
 pub fn parse_ip<T: ToString>(ip_addr: T) -> Result<[u8; 4], PaError> {
@@ -40,6 +43,21 @@ pub fn parse_mac<T: ToString>(mac_addr: T) -> Result<[u8; 6], PaError> {
     }
 }
 
+/// Parses IPv6 Address in string to array of bytes of length 16
+pub fn parse_ipv6<T: ToString>(ip_addr: T) -> Result<[u8; 16], PaError> {
+    match ip_addr.to_string().trim().parse::<Ipv6Addr>() {
+        Ok(addr) => Ok(addr.octets()),
+        Err(err) => Err(PaError::new(err.to_string(), ErrorType::ParseError)),
+    }
+}
+
+/// Parses IPv6 address in array of bytes to String eg "fe80::1"
+pub fn ipv6_to_string(ip_addr: &[u8]) -> String {
+    let mut octets = [0; 16];
+    octets.copy_from_slice(&ip_addr[0..16]);
+    Ipv6Addr::from(octets).to_string()
+}
+
 /// Parses IP address in array of bytes to String eg "192.168.1.1"
 pub fn ip_to_string(ip_addr: &[u8]) -> String {
     format!(
@@ -57,10 +75,71 @@ pub fn mac_to_string(mac_addr: &[u8]) -> String {
     string_mac_addr.trim_end_matches(":").to_string()
 }
 
+/// Computes the 16 bit one's complement checksum used by IPv4, ICMP, UDP and TCP
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = 0;
+    for chunk in data.chunks(2) {
+        let word = if chunk.len() == 2 {
+            u16::from_be_bytes([chunk[0], chunk[1]])
+        } else {
+            u16::from_be_bytes([chunk[0], 0])
+        };
+        sum += u32::from(word);
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Computes TCP/UDP checksum of `segment` including the IP pseudo header
+///
+/// `src` and `dst` are both IPv4 or both IPv6 addresses.
+pub fn pseudo_checksum(src: &[u8], dst: &[u8], proto: u8, segment: &[u8]) -> u16 {
+    let mut data: Vec<u8> = Vec::with_capacity(40 + segment.len());
+    data.extend_from_slice(src);
+    data.extend_from_slice(dst);
+    if src.len() == 16 {
+        data.extend_from_slice(&(segment.len() as u32).to_be_bytes());
+        data.extend_from_slice(&[0, 0, 0, proto]);
+    } else {
+        data.extend_from_slice(&[0, proto]);
+        data.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+    }
+    data.extend_from_slice(segment);
+    checksum(&data)
+}
+
+/// Returns a pseudo random number, good for ports and sequence numbers but
+/// not for anything cryptographic
+pub fn random_u32() -> u32 {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new({
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or(0);
+            (nanos ^ (u64::from(std::process::id()) << 32)) | 1
+        });
+    }
+    STATE.with(|state| {
+        // xorshift64*
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as u32
+    })
+}
+
 /// Get Ethernet type from 16 byte unsigned integer
 pub fn from_ethtype(ethtype: u16) -> EthType {
     match ethtype {
         0x806 => EthType::Arp,
+        0x800 => EthType::IPv4,
+        0x86dd => EthType::IPv6,
+        0x8847 | 0x8848 => EthType::Mpls,
         _ => EthType::Unknown,
     }
 }
@@ -0,0 +1,514 @@
+mod common;
+
+use common::{reply_to_arp, PEER_MAC};
+use pakit::arp::{
+    arp_scan, gratuitous_arp, ArpEvent, ArpEventKind, ArpResolver, ArpSpoofer, ArpWatch, Host,
+    ScanOpts, WatchOpts,
+};
+use pakit::hdr::{ip_proto, ArpHdr, ArpQuery, EthHdr, IPv4Hdr};
+use pakit::iface::Ipv4Net;
+use pakit::io::{LoopbackIo, PacketIo};
+use pakit::proto::Proto;
+use pakit::{auto_reply, ErrorType, PaError, Pdu, QueryHdr, Rules};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const HOST_MAC: [u8; 6] = [0xaa; 6];
+
+/// Answers `count` ARP requests for `ip`, then hands `io` back
+fn responder(
+    mut io: LoopbackIo,
+    ip: &'static str,
+    count: usize,
+) -> thread::JoinHandle<(usize, LoopbackIo)> {
+    thread::spawn(move || {
+        let mut rules = Rules::new();
+        rules.add_rule(
+            QueryHdr::Arp(ArpQuery::from(None, None, None, Some(ip)).unwrap()),
+            reply_to_arp,
+        );
+        (auto_reply(&mut io, &rules, Some(count)).unwrap(), io)
+    })
+}
+
+fn resolver() -> ArpResolver {
+    let mut resolver = ArpResolver::new(HOST_MAC, [192, 168, 1, 100]);
+    resolver.timeout = Duration::from_millis(50);
+    resolver
+}
+
+#[test]
+fn resolve_and_cache() {
+    let (mut host, peer) = LoopbackIo::pair();
+    let handle = responder(peer, "192.168.1.1", 1);
+
+    let mut resolver = resolver();
+    let ip = [192, 168, 1, 1];
+    assert_eq!(resolver.resolve_mac(&mut host, ip).unwrap(), PEER_MAC);
+    let (answered, mut peer) = handle.join().unwrap();
+    assert_eq!(answered, 1);
+    // Nobody answers anymore, so this can only come from cache
+    assert_eq!(resolver.resolve_mac(&mut host, ip).unwrap(), PEER_MAC);
+    assert!(peer
+        .recv_timeout(Duration::from_millis(10))
+        .unwrap()
+        .is_none());
+
+    // Expired entry is requested again and times out
+    resolver.ttl = Duration::from_secs(0);
+    assert_eq!(resolver.cached(ip), None);
+    let err = resolver.resolve_mac(&mut host, ip).unwrap_err();
+    assert!(matches!(err.err_type, ErrorType::TimeoutError));
+    assert!(peer
+        .recv_timeout(Duration::from_millis(10))
+        .unwrap()
+        .is_some());
+}
+
+#[test]
+fn build_resolved_through_gateway() {
+    let (mut host, peer) = LoopbackIo::pair();
+    let handle = responder(peer, "192.168.1.1", 1);
+
+    let mut resolver = resolver();
+    resolver.subnet = Some(Ipv4Net {
+        addr: [192, 168, 1, 100],
+        prefix: 24,
+    });
+    resolver.gateway = Some([192, 168, 1, 1]);
+    assert_eq!(resolver.next_hop([192, 168, 1, 7]), [192, 168, 1, 7]);
+    assert_eq!(resolver.next_hop([8, 8, 8, 8]), [192, 168, 1, 1]);
+
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw([0; 6], [0; 6], 0x0800))
+        .header(IPv4Hdr::from("192.168.1.100", "8.8.8.8", ip_proto::UDP).unwrap());
+    pdu.build_resolved(&mut host, &mut resolver).unwrap();
+    handle.join().unwrap();
+
+    assert_eq!(pdu.buffer[0..6], PEER_MAC);
+    assert_eq!(pdu.buffer[6..12], HOST_MAC);
+    match Pdu::parse(&pdu.buffer).headers.get(&3) {
+        Some(Proto::IPv4(ipv4)) => assert_eq!(ipv4.dst_ip_addr, [8, 8, 8, 8]),
+        _ => panic!("IPv4 header missing"),
+    }
+
+    // Headers of other kinds are built untouched
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(HOST_MAC, [0; 6], 0x0806))
+        .header(ArpHdr::new());
+    pdu.build_resolved(&mut host, &mut resolver).unwrap();
+    assert_eq!(pdu.buffer[0..6], [0; 6]);
+}
+
+/// Answers ARP requests like a subnet where
+/// - `.1` answers from a VMware MAC
+/// - `.2` answers from two MACs
+/// - `.3` only answers second request
+fn scan_responder(mut io: LoopbackIo) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut asked_3 = 0;
+        while let Ok(Some(frame)) = io.recv_timeout(Duration::from_millis(300)) {
+            let request = Pdu::parse(&frame);
+            let ip = match request.headers.get(&3) {
+                Some(Proto::Arp(arp)) => arp.dst_proto_addr,
+                _ => continue,
+            };
+            let macs: Vec<[u8; 6]> = match ip[3] {
+                1 => vec![[0x00, 0x50, 0x56, 1, 1, 1]],
+                2 => vec![[0x02, 0, 0, 0, 0, 2], [0x02, 0, 0, 0, 0, 22]],
+                3 => {
+                    asked_3 += 1;
+                    if asked_3 < 2 {
+                        continue;
+                    }
+                    vec![[0x02, 0, 0, 0, 0, 3]]
+                }
+                _ => continue,
+            };
+            for mac in macs {
+                let mut reply = reply_to_arp(Pdu::parse(&frame)).unwrap();
+                if let Some(Proto::Arp(arp)) = reply.headers.get_mut(&3) {
+                    arp.src_hw_addr = mac;
+                }
+                reply.build().unwrap();
+                reply.send_on(&mut io).unwrap();
+            }
+        }
+    })
+}
+
+#[test]
+fn scan_subnet() {
+    let (mut host, peer) = LoopbackIo::pair();
+    let handle = scan_responder(peer);
+
+    let mut opts = ScanOpts::new(HOST_MAC, [192, 168, 1, 100]);
+    opts.rate = None;
+    opts.timeout = Duration::from_millis(50);
+    let results = arp_scan(&mut host, "192.168.1.0/29", &opts).unwrap();
+    drop(host);
+    handle.join().unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].ip, [192, 168, 1, 1]);
+    assert_eq!(results[0].vendor, Some("VMware"));
+    assert!(!results[0].duplicate);
+    assert_eq!(results[1].ip, [192, 168, 1, 2]);
+    assert!(results[1].duplicate);
+    assert_eq!(results[1].replies, 2);
+    assert_eq!(results[1].other_macs, vec![[0x02, 0, 0, 0, 0, 22]]);
+    assert_eq!(results[2].ip, [192, 168, 1, 3]);
+    assert_eq!(results[2].mac, [0x02, 0, 0, 0, 0, 3]);
+
+    assert!(arp_scan(&mut LoopbackIo::pair().0, "10.0.0.0/33", &opts).is_err());
+}
+
+#[test]
+fn spoof_forward_and_restore() {
+    let a = Host::new([192, 168, 1, 1], [0x02, 0, 0, 0, 0, 1]);
+    let b = Host::new([192, 168, 1, 2], [0x02, 0, 0, 0, 0, 2]);
+    let mut spoofer = ArpSpoofer::new(HOST_MAC, a, b);
+    spoofer.opts.interval = Duration::from_millis(20);
+    spoofer.opts.forward = true;
+    spoofer.opts.restore_count = 2;
+    let stop = spoofer.stop_handle();
+
+    let (mut host, mut peer) = LoopbackIo::pair();
+    let handle = thread::spawn(move || spoofer.run(&mut host).unwrap());
+
+    let arp_of = |frame: &[u8]| match Pdu::parse(frame).headers.remove(&3) {
+        Some(Proto::Arp(arp)) => Some(arp),
+        _ => None,
+    };
+
+    // First round tells `a` that `b` is at our MAC and the other way round
+    let to_a = arp_of(&peer.recv().unwrap()).unwrap();
+    assert_eq!(to_a.src_proto_addr, b.ip);
+    assert_eq!(to_a.src_hw_addr, HOST_MAC);
+    assert_eq!(to_a.dst_hw_addr, a.mac);
+    let to_b = arp_of(&peer.recv().unwrap()).unwrap();
+    assert_eq!(to_b.src_proto_addr, a.ip);
+    assert_eq!(to_b.dst_hw_addr, b.mac);
+
+    let mut from_a = Pdu::new()
+        .header(EthHdr::from_raw(a.mac, HOST_MAC, 0x0800))
+        .header(IPv4Hdr::from("192.168.1.1", "192.168.1.2", ip_proto::UDP).unwrap());
+    from_a.build().unwrap();
+    from_a.send_on(&mut peer).unwrap();
+    let forwarded = loop {
+        let frame = peer.recv().unwrap();
+        if arp_of(&frame).is_none() {
+            break frame;
+        }
+    };
+    assert_eq!(forwarded[0..6], b.mac);
+    assert_eq!(forwarded[6..12], HOST_MAC);
+    assert_eq!(forwarded[12..], from_a.buffer[12..]);
+
+    stop.store(true, Ordering::SeqCst);
+    let stats = handle.join().unwrap();
+    assert!(stats.poisoned >= 1);
+    assert_eq!(stats.forwarded, 1);
+    assert_eq!(stats.restored, 2);
+
+    // Last four frames restore the real addresses
+    let mut frames = Vec::new();
+    while let Ok(Some(frame)) = peer.recv_timeout(Duration::from_millis(10)) {
+        frames.push(frame);
+    }
+    let restore: Vec<ArpHdr> = frames[frames.len() - 4..]
+        .iter()
+        .map(|frame| arp_of(frame).unwrap())
+        .collect();
+    assert_eq!(restore[0].src_hw_addr, b.mac);
+    assert_eq!(restore[0].dst_hw_addr, a.mac);
+    assert_eq!(restore[1].src_hw_addr, a.mac);
+    assert_eq!(restore[1].dst_hw_addr, b.mac);
+
+    let garp = gratuitous_arp(HOST_MAC, [192, 168, 1, 100]).unwrap();
+    let garp = arp_of(&garp.buffer).unwrap();
+    assert_eq!(garp.src_proto_addr, garp.dst_proto_addr);
+}
+
+#[test]
+fn spoof_gratuitous_and_own_traffic() {
+    let a = Host::new([192, 168, 1, 1], [0x02, 0, 0, 0, 0, 1]);
+    let b = Host::new([192, 168, 1, 2], [0x02, 0, 0, 0, 0, 2]);
+    let mut spoofer = ArpSpoofer::new(HOST_MAC, a, b);
+    spoofer.our_ips.push([192, 168, 1, 100].into());
+    spoofer.opts.interval = Duration::from_millis(20);
+    spoofer.opts.forward = true;
+    spoofer.opts.gratuitous = true;
+    spoofer.opts.restore_count = 1;
+    let stop = spoofer.stop_handle();
+
+    let (mut host, mut peer) = LoopbackIo::pair();
+    let handle = thread::spawn(move || spoofer.run(&mut host).unwrap());
+
+    let arp_of = |frame: &[u8]| match Pdu::parse(frame).headers.remove(&3) {
+        Some(Proto::Arp(arp)) => Some(arp),
+        _ => None,
+    };
+
+    // Poisoning starts with broadcast announcements of both hosts
+    for ip in [b.ip, a.ip].iter() {
+        let frame = peer.recv().unwrap();
+        assert_eq!(frame[0..6], [0xff; 6]);
+        let garp = arp_of(&frame).unwrap();
+        assert_eq!(garp.src_proto_addr, *ip);
+        assert_eq!(garp.dst_proto_addr, *ip);
+        assert_eq!(garp.src_hw_addr, HOST_MAC);
+    }
+
+    // Traffic to our own address is left to the kernel
+    let mut to_us = Pdu::new()
+        .header(EthHdr::from_raw(a.mac, HOST_MAC, 0x0800))
+        .header(IPv4Hdr::from("192.168.1.1", "192.168.1.100", ip_proto::UDP).unwrap());
+    to_us.build().unwrap();
+    to_us.send_on(&mut peer).unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    stop.store(true, Ordering::SeqCst);
+    let stats = handle.join().unwrap();
+    assert_eq!(stats.forwarded, 0);
+
+    let mut frames = Vec::new();
+    while let Ok(Some(frame)) = peer.recv_timeout(Duration::from_millis(10)) {
+        frames.push(frame);
+    }
+    assert!(frames.iter().all(|frame| arp_of(frame).is_some()));
+    // Restoring also announces both hosts at their real MAC
+    let restore: Vec<ArpHdr> = frames[frames.len() - 4..]
+        .iter()
+        .map(|frame| arp_of(frame).unwrap())
+        .collect();
+    assert_eq!(restore[2].src_hw_addr, b.mac);
+    assert_eq!(restore[2].dst_hw_addr, [0xff; 6]);
+    assert_eq!(restore[3].src_hw_addr, a.mac);
+    assert_eq!(restore[3].src_proto_addr, a.ip);
+}
+
+/// Serializes tests changing the SIGINT disposition of the process
+static SIGINT_LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+#[cfg(unix)]
+fn spoof_keeps_sigint_handler() {
+    let _lock = SIGINT_LOCK.lock().unwrap();
+    let disposition = || unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        libc::sigaction(libc::SIGINT, std::ptr::null(), &mut action);
+        action.sa_sigaction
+    };
+    unsafe {
+        let mut ignore: libc::sigaction = std::mem::zeroed();
+        ignore.sa_sigaction = libc::SIG_IGN;
+        libc::sigaction(libc::SIGINT, &ignore, std::ptr::null_mut());
+    }
+
+    let a = Host::new([192, 168, 1, 1], [0x02, 0, 0, 0, 0, 1]);
+    let b = Host::new([192, 168, 1, 2], [0x02, 0, 0, 0, 0, 2]);
+    let mut spoofer = ArpSpoofer::new(HOST_MAC, a, b);
+    spoofer.opts.catch_ctrl_c = true;
+    spoofer.opts.restore_count = 1;
+    spoofer.stop_handle().store(true, Ordering::SeqCst);
+    spoofer.run(&mut LoopbackIo::pair().0).unwrap();
+    assert_eq!(disposition(), libc::SIG_IGN);
+
+    unsafe {
+        let mut default: libc::sigaction = std::mem::zeroed();
+        default.sa_sigaction = libc::SIG_DFL;
+        libc::sigaction(libc::SIGINT, &default, std::ptr::null_mut());
+    }
+}
+
+/// Datagram socket whose waits fail when a signal interrupts them, as those
+/// of pnet do
+#[cfg(unix)]
+struct SocketIo(UnixDatagram);
+
+#[cfg(unix)]
+impl PacketIo for SocketIo {
+    fn send(&mut self, frame: &[u8]) -> Result<usize, PaError> {
+        Ok(self.0.send(frame)?)
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, PaError> {
+        self.0.set_read_timeout(None)?;
+        let mut buffer = vec![0; 1514];
+        let len = self.0.recv(&mut buffer)?;
+        buffer.truncate(len);
+        Ok(buffer)
+    }
+
+    fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, PaError> {
+        self.0
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut buffer = vec![0; 1514];
+        match self.0.recv(&mut buffer) {
+            Ok(len) => {
+                buffer.truncate(len);
+                Ok(Some(buffer))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn spoof_stops_on_interrupted_wait() {
+    use std::os::unix::thread::JoinHandleExt;
+
+    let _lock = SIGINT_LOCK.lock().unwrap();
+    let (sock, _peer) = UnixDatagram::pair().unwrap();
+    let a = Host::new([192, 168, 1, 1], [0x02, 0, 0, 0, 0, 1]);
+    let b = Host::new([192, 168, 1, 2], [0x02, 0, 0, 0, 0, 2]);
+    let mut spoofer = ArpSpoofer::new(HOST_MAC, a, b);
+    spoofer.opts.catch_ctrl_c = true;
+    spoofer.opts.forward = true;
+    spoofer.opts.restore_count = 1;
+    let handle = thread::spawn(move || spoofer.run(&mut SocketIo(sock)));
+
+    // Ctrl-C lands on the thread waiting for frames to forward
+    while !handle.is_finished() {
+        thread::sleep(Duration::from_millis(50));
+        unsafe {
+            libc::pthread_kill(handle.as_pthread_t(), libc::SIGINT);
+        }
+    }
+    let stats = handle.join().unwrap().unwrap();
+    assert_eq!((stats.poisoned, stats.restored), (1, 1));
+}
+
+fn arp_frame(eth_src: [u8; 6], opr: u16, src: ([u8; 6], [u8; 4]), dst_ip: [u8; 4]) -> Vec<u8> {
+    let mut arp = ArpHdr::new();
+    if opr == 2 {
+        arp.set_arp_reply();
+    }
+    arp.src_hw_addr = src.0;
+    arp.src_proto_addr = src.1;
+    arp.dst_proto_addr = dst_ip;
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(eth_src, [0xff; 6], 0x0806))
+        .header(arp);
+    pdu.build().unwrap();
+    pdu.buffer
+}
+
+#[test]
+fn watch_anomalies() {
+    let mut opts = WatchOpts::new();
+    opts.gratuitous_threshold = 3;
+    opts.flap_threshold = 2;
+    let mut watch = ArpWatch::with_opts(opts);
+    let ms = Duration::from_millis;
+    let kinds = |events: Vec<ArpEvent>| -> Vec<ArpEventKind> {
+        events.into_iter().map(|event| event.kind).collect()
+    };
+
+    let me = ([0x02, 0, 0, 0, 0, 1], [10, 0, 0, 1]);
+    let gw = ([0x02, 0, 0, 0, 0, 2], [10, 0, 0, 2]);
+    let evil = ([0x02, 0, 0, 0, 0, 66], [10, 0, 0, 2]);
+
+    // Request and its answer only introduce both stations
+    let events = watch.process(&arp_frame(me.0, 1, me, gw.1), ms(0));
+    assert_eq!(
+        kinds(events),
+        vec![ArpEventKind::NewStation {
+            ip: me.1,
+            mac: me.0
+        }]
+    );
+    let events = watch.process(&arp_frame(gw.0, 2, gw, me.1), ms(10));
+    assert_eq!(
+        kinds(events),
+        vec![ArpEventKind::NewStation {
+            ip: gw.1,
+            mac: gw.0
+        }]
+    );
+
+    // Second answer to the same request comes from another MAC
+    let events = kinds(watch.process(&arp_frame(evil.0, 2, evil, me.1), ms(20)));
+    assert_eq!(
+        events[0],
+        ArpEventKind::DuplicateIp {
+            ip: gw.1,
+            macs: vec![gw.0, evil.0]
+        }
+    );
+    assert_eq!(
+        events[1],
+        ArpEventKind::BindingChanged {
+            ip: gw.1,
+            old_mac: gw.0,
+            new_mac: evil.0
+        }
+    );
+
+    // Reply long after request timed out, moving binding back
+    let events = kinds(watch.process(&arp_frame(gw.0, 2, gw, me.1), ms(10_000)));
+    assert_eq!(
+        events[0],
+        ArpEventKind::UnsolicitedReply {
+            ip: gw.1,
+            mac: gw.0,
+            dst_ip: me.1
+        }
+    );
+    assert!(matches!(
+        events[2],
+        ArpEventKind::MacFlapping { changes: 2, .. }
+    ));
+    assert_eq!(watch.bindings()[&gw.1].mac, gw.0);
+
+    // Ethernet source does not match sender MAC
+    let events = kinds(watch.process(&arp_frame(evil.0, 1, me, gw.1), ms(10_010)));
+    assert_eq!(
+        events,
+        vec![ArpEventKind::SenderMismatch {
+            ip: me.1,
+            eth_mac: evil.0,
+            arp_mac: me.0
+        }]
+    );
+
+    // Third gratuitous ARP within window is a flood
+    let mut floods = 0;
+    for i in 0..4 {
+        let events = watch.process(&arp_frame(me.0, 2, me, me.1), ms(11_000 + i));
+        floods += kinds(events)
+            .iter()
+            .filter(|kind| matches!(kind, ArpEventKind::GratuitousFlood { count: 3, .. }))
+            .count();
+    }
+    assert_eq!(floods, 1);
+}
+
+#[test]
+fn watch_on_io() {
+    let (mut host, mut peer) = LoopbackIo::pair();
+    let (tx, rx) = std::sync::mpsc::channel();
+    peer.send(&arp_frame(
+        [0xcc; 6],
+        1,
+        ([0xcc; 6], [10, 0, 0, 9]),
+        [10, 0, 0, 1],
+    ))
+    .unwrap();
+    let count = ArpWatch::new()
+        .run_with_sender(&mut host, Some(1), tx)
+        .unwrap();
+    assert_eq!(count, 1);
+    let event = rx.recv().unwrap();
+    assert_eq!(
+        event.kind.to_string(),
+        "new station 10.0.0.9 at cc:cc:cc:cc:cc:cc"
+    );
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use common::{arp_request, reply_to_arp};
+use futures_util::{SinkExt, StreamExt};
+use pakit::hdr::ArpQuery;
+use pakit::proto::Proto;
+use pakit::{AsyncChannel, QueryHdr, Rules};
+use std::time::Duration;
+
+#[tokio::test]
+async fn stream_and_sink() {
+    let (mut a, mut b) = AsyncChannel::pair().unwrap();
+    a.send(arp_request()).await.unwrap();
+    let pdu = b.next().await.unwrap().unwrap();
+    assert!(matches!(pdu.headers.get(&3), Some(Proto::Arp(_))));
+}
+
+#[tokio::test]
+async fn sr1_with_auto_reply() {
+    let (mut host, mut responder) = AsyncChannel::pair().unwrap();
+    let responder = tokio::spawn(async move {
+        let mut rules = Rules::new();
+        rules.add_rule(
+            QueryHdr::Arp(ArpQuery::from(None, None, None, Some("192.168.1.101")).unwrap()),
+            reply_to_arp,
+        );
+        responder.auto_reply(&rules, Some(1)).await.unwrap()
+    });
+
+    let reply = host
+        .sr1(&arp_request(), Duration::from_secs(1))
+        .await
+        .unwrap()
+        .unwrap();
+    match reply.headers.get(&3) {
+        Some(Proto::Arp(arp)) => assert_eq!(arp.src_hw_addr, [0xbb; 6]),
+        _ => panic!("Expected ARP reply"),
+    }
+    assert_eq!(responder.await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn sr1_times_out() {
+    let (mut host, _peer) = AsyncChannel::pair().unwrap();
+    let reply = host
+        .sr1(&arp_request(), Duration::from_millis(50))
+        .await
+        .unwrap();
+    assert!(reply.is_none());
+}
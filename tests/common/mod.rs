@@ -0,0 +1,39 @@
+//! Helpers shared by integration tests
+#![allow(dead_code)]
+
+use pakit::hdr::{ArpHdr, EthHdr};
+use pakit::Pdu;
+
+/// MAC of the peer answering ARP requests
+pub const PEER_MAC: [u8; 6] = [0xbb; 6];
+
+/// Built ARP request for 192.168.1.101 from 192.168.1.100
+pub fn arp_request() -> Pdu {
+    let mut pdu = Pdu::new()
+        .header(
+            ArpHdr::from(
+                "aa:aa:aa:aa:aa:aa",
+                "192.168.1.100",
+                "00:00:00:00:00:00",
+                "192.168.1.101",
+            )
+            .unwrap(),
+        )
+        .header(EthHdr::from("aa:aa:aa:aa:aa:aa", "ff:ff:ff:ff:ff:ff", 0x0806).unwrap());
+    pdu.build().unwrap();
+    pdu
+}
+
+/// Answers ARP request `pdu` from `PEER_MAC`
+pub fn reply_to_arp(mut pdu: Pdu) -> Option<Pdu> {
+    let mut arp = pdu.headers.remove(&3).unwrap().unwrap_arp().unwrap();
+    arp.set_arp_reply();
+    arp.dst_hw_addr = arp.src_hw_addr;
+    arp.src_hw_addr = PEER_MAC;
+    std::mem::swap(&mut arp.src_proto_addr, &mut arp.dst_proto_addr);
+    Some(
+        Pdu::new()
+            .header(EthHdr::from_raw(PEER_MAC, arp.dst_hw_addr, 0x0806))
+            .header(arp),
+    )
+}
@@ -0,0 +1,369 @@
+use pakit::dhcp::{spoofed_mac, ClientOpts, DhcpClient, DhcpServer, LeaseState, ServerOpts};
+use pakit::hdr::{
+    bootp_op, dhcp_msg, dhcp_opt, relay_sub, Bootp, Dhcp, DhcpOption, DhcpQuery, EthHdr, IPv4Hdr,
+    UdpHdr, BROADCAST, DHCP_MAGIC,
+};
+use pakit::io::{LoopbackIo, PacketIo};
+use pakit::proto::Proto;
+use pakit::{auto_reply, Pdu, QueryHdr, Rules};
+use std::thread;
+use std::time::Duration;
+
+const MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x42];
+
+fn relay_info() -> DhcpOption {
+    DhcpOption::RelayAgent(vec![
+        (relay_sub::CIRCUIT_ID, b"Gi0/1.100".to_vec()),
+        (relay_sub::REMOTE_ID, MAC.to_vec()),
+    ])
+}
+
+fn request() -> Dhcp {
+    let mut dhcp = Dhcp::request(dhcp_msg::REQUEST, 0xdead_beef, MAC);
+    dhcp.flags = BROADCAST;
+    dhcp.giaddr = [10, 0, 0, 1];
+    dhcp.hops = 1;
+    dhcp.options.extend(vec![
+        DhcpOption::ClientId {
+            htype: 1,
+            id: MAC.to_vec(),
+        },
+        DhcpOption::RequestedIp([10, 0, 0, 50]),
+        DhcpOption::ServerId([10, 0, 0, 2]),
+        DhcpOption::ParamRequest(vec![
+            dhcp_opt::SUBNET_MASK,
+            dhcp_opt::ROUTER,
+            dhcp_opt::DNS,
+            dhcp_opt::DOMAIN_NAME,
+        ]),
+        DhcpOption::Raw {
+            code: 60,
+            data: b"pakit".to_vec(),
+        },
+        relay_info(),
+    ]);
+    dhcp
+}
+
+#[test]
+fn messages_round_trip() {
+    let dhcp = request();
+    let bytes = dhcp.to_bytes().unwrap();
+    assert_eq!(bytes.len(), 300);
+    assert_eq!(&bytes[..4], &[bootp_op::REQUEST, 1, 6, 1]);
+    assert_eq!(&bytes[236..243], &[99, 130, 83, 99, 53, 1, 3]);
+    assert_eq!(Dhcp::from_bytes(&bytes).unwrap(), dhcp);
+
+    // Every message type, with server options on replies
+    for msg_type in dhcp_msg::DISCOVER..=dhcp_msg::INFORM {
+        let mut reply = dhcp.reply(msg_type);
+        assert_eq!(reply.option(dhcp_opt::RELAY_AGENT), Some(&relay_info()));
+        reply.yiaddr = [10, 0, 0, 50];
+        reply.sname = b"server".to_vec();
+        reply.options.extend(vec![
+            DhcpOption::ServerId([10, 0, 0, 2]),
+            DhcpOption::LeaseTime(3600),
+            DhcpOption::SubnetMask([255, 255, 255, 0]),
+            DhcpOption::Router(vec![[10, 0, 0, 1]]),
+            DhcpOption::Dns(vec![[10, 0, 0, 53], [9, 9, 9, 9]]),
+            DhcpOption::DomainName("lab.example".into()),
+        ]);
+        let parsed = Dhcp::from_bytes(&reply.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed, reply);
+        assert_eq!(parsed.msg_type(), Some(msg_type));
+        assert_eq!(
+            (parsed.op, parsed.xid, parsed.mac()),
+            (bootp_op::REPLY, 0xdead_beef, MAC)
+        );
+    }
+
+    // Long option is split and joined again
+    let mut long = Dhcp::request(dhcp_msg::INFORM, 1, MAC);
+    long.options.push(DhcpOption::DomainName("a".repeat(300)));
+    let bytes = long.to_bytes().unwrap();
+    assert_eq!(&bytes[243..245], &[dhcp_opt::DOMAIN_NAME, 255]);
+    assert_eq!(&bytes[500..502], &[dhcp_opt::DOMAIN_NAME, 45]);
+    assert_eq!(Dhcp::from_bytes(&bytes).unwrap(), long);
+
+    // Plain BOOTP without magic cookie
+    let mut bootp = Bootp::new();
+    bootp.file = b"pxelinux.0".to_vec();
+    let bytes = bootp.to_bytes().unwrap();
+    assert_eq!(bytes.len(), 300);
+    assert_eq!(&bytes[236..240], &[0; 4]);
+    let parsed = Bootp::from_bytes(&bytes).unwrap();
+    assert_eq!((parsed.msg_type(), parsed), (None, bootp));
+}
+
+#[test]
+fn malformed_options() {
+    let mut bytes = Dhcp::new().to_bytes().unwrap();
+    bytes.truncate(236);
+    bytes.extend_from_slice(&DHCP_MAGIC);
+    // Padding, then options which don't fit their type
+    bytes.extend_from_slice(&[0, 0, 53, 2, 1, 1, 3, 3, 10, 0, 0, 61, 1, 1]);
+    // Relay agent sub-option overrunning the option
+    bytes.extend_from_slice(&[82, 4, 1, 5, 0, 0, 255]);
+    let dhcp = Dhcp::from_bytes(&bytes).unwrap();
+    assert_eq!(
+        dhcp.options,
+        vec![
+            DhcpOption::Raw {
+                code: 53,
+                data: vec![1, 1]
+            },
+            DhcpOption::Raw {
+                code: 3,
+                data: vec![10, 0, 0]
+            },
+            DhcpOption::Raw {
+                code: 61,
+                data: vec![1]
+            },
+            DhcpOption::Raw {
+                code: 82,
+                data: vec![1, 5, 0, 0]
+            },
+        ]
+    );
+    assert_eq!(dhcp.msg_type(), None);
+
+    // Option running past the message, and message shorter than BOOTP
+    bytes.truncate(bytes.len() - 2);
+    assert!(Dhcp::from_bytes(&bytes).is_err());
+    assert!(Dhcp::from_bytes(&bytes[..200]).is_err());
+
+    let mut dhcp = Dhcp::new();
+    dhcp.sname = vec![b'x'; 65];
+    assert!(dhcp.to_bytes().is_err());
+}
+
+fn frame(dhcp: Dhcp) -> Pdu {
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(MAC, [0xff; 6], 0x0800))
+        .header(IPv4Hdr::from("0.0.0.0", "255.255.255.255", 0).unwrap())
+        .header(UdpHdr::from(68, 67))
+        .header(dhcp);
+    pdu.build().unwrap();
+    pdu
+}
+
+fn offer(pdu: Pdu) -> Option<Pdu> {
+    let dhcp = match pdu.headers.get(&7) {
+        Some(Proto::Dhcp(dhcp)) => dhcp,
+        _ => panic!("Query not matched on DHCP"),
+    };
+    let mut offer = dhcp.reply(dhcp_msg::OFFER);
+    offer.yiaddr = [10, 0, 0, 50];
+    Some(
+        Pdu::new()
+            .header(EthHdr::from_raw([0xbb; 6], dhcp.mac(), 0x0800))
+            .header(IPv4Hdr::from("10.0.0.2", "255.255.255.255", 0).unwrap())
+            .header(UdpHdr::from(67, 68))
+            .header(offer),
+    )
+}
+
+#[test]
+fn rules_answer_discover() {
+    let (mut host, mut server) = LoopbackIo::pair();
+    let handle = thread::spawn(move || {
+        let mut rules = Rules::new();
+        rules.add_rule(QueryHdr::Dhcp(DhcpQuery::from(dhcp_msg::DISCOVER)), offer);
+        auto_reply(&mut server, &rules, Some(1)).unwrap()
+    });
+
+    frame(request()).send_on(&mut host).unwrap();
+    frame(Bootp::new()).send_on(&mut host).unwrap();
+    frame(Dhcp::request(dhcp_msg::DISCOVER, 7, MAC))
+        .send_on(&mut host)
+        .unwrap();
+    let reply = Pdu::parse(&host.recv().unwrap());
+    match reply.headers.get(&7) {
+        Some(Proto::Dhcp(dhcp)) => {
+            assert_eq!(dhcp.msg_type(), Some(dhcp_msg::OFFER));
+            assert_eq!((dhcp.xid, dhcp.yiaddr), (7, [10, 0, 0, 50]));
+        }
+        _ => panic!("Expected DHCP reply"),
+    }
+    assert_eq!(handle.join().unwrap(), 1);
+
+    let mut query = DhcpQuery::new();
+    query.giaddr = Some([10, 0, 0, 1]);
+    query.chaddr = Some(MAC);
+    assert!(query == request());
+    assert!(query != Bootp::new());
+    assert!(DhcpQuery::from(dhcp_msg::REQUEST) == request());
+}
+
+fn server() -> DhcpServer {
+    let mut opts = ServerOpts::new([0xbb; 6], [10, 0, 0, 2], [10, 0, 0, 10], [10, 0, 0, 12]);
+    opts.options = vec![
+        DhcpOption::SubnetMask([255, 255, 255, 0]),
+        DhcpOption::Router(vec![[10, 0, 0, 1]]),
+    ];
+    let mut server = DhcpServer::new(opts);
+    server.reserve(MAC, [10, 0, 0, 100]);
+    server
+}
+
+fn select(mac: [u8; 6], ip: [u8; 4], server_id: [u8; 4]) -> Dhcp {
+    let mut request = Dhcp::request(dhcp_msg::REQUEST, 2, mac);
+    request.options.push(DhcpOption::RequestedIp(ip));
+    request.options.push(DhcpOption::ServerId(server_id));
+    request
+}
+
+#[test]
+fn server_leases_from_pool() {
+    let server = server();
+    let discover = |mac: [u8; 6]| {
+        server
+            .handle(&Dhcp::request(dhcp_msg::DISCOVER, 1, mac))
+            .map(|offer| offer.yiaddr)
+    };
+
+    let offer = server
+        .handle(&Dhcp::request(dhcp_msg::DISCOVER, 1, spoofed_mac(1)))
+        .unwrap();
+    assert_eq!(
+        (offer.msg_type(), offer.yiaddr),
+        (Some(dhcp_msg::OFFER), [10, 0, 0, 10])
+    );
+    assert_eq!(
+        offer.option(dhcp_opt::LEASE_TIME),
+        Some(&DhcpOption::LeaseTime(3600))
+    );
+    assert_eq!(
+        offer.option(dhcp_opt::SUBNET_MASK),
+        Some(&DhcpOption::SubnetMask([255, 255, 255, 0]))
+    );
+    assert_eq!(
+        server.lease(spoofed_mac(1)).unwrap().state,
+        LeaseState::Offered
+    );
+    let ack = server
+        .handle(&select(spoofed_mac(1), [10, 0, 0, 10], [10, 0, 0, 2]))
+        .unwrap();
+    assert_eq!(
+        (ack.msg_type(), ack.yiaddr),
+        (Some(dhcp_msg::ACK), [10, 0, 0, 10])
+    );
+    assert_eq!(
+        server.lease(spoofed_mac(1)).unwrap().state,
+        LeaseState::Bound
+    );
+
+    // Same address again, reservation outside the pool, then exhaustion
+    assert_eq!(discover(spoofed_mac(1)), Some([10, 0, 0, 10]));
+    assert_eq!(discover(MAC), Some([10, 0, 0, 100]));
+    assert_eq!(discover(spoofed_mac(2)), Some([10, 0, 0, 11]));
+    assert_eq!(discover(spoofed_mac(3)), Some([10, 0, 0, 12]));
+    assert_eq!(discover(spoofed_mac(4)), None);
+    let nak = server
+        .handle(&select(spoofed_mac(4), [10, 0, 0, 10], [10, 0, 0, 2]))
+        .unwrap();
+    assert_eq!(nak.msg_type(), Some(dhcp_msg::NAK));
+
+    // Client choosing another server frees its offer
+    assert!(server
+        .handle(&select(spoofed_mac(3), [10, 0, 0, 12], [10, 0, 0, 3]))
+        .is_none());
+    assert_eq!(discover(spoofed_mac(4)), Some([10, 0, 0, 12]));
+
+    // Declined address stays out of the pool, released one comes back
+    let mut decline = Dhcp::request(dhcp_msg::DECLINE, 3, spoofed_mac(2));
+    decline
+        .options
+        .push(DhcpOption::RequestedIp([10, 0, 0, 11]));
+    assert!(server.handle(&decline).is_none());
+    let mut release = Dhcp::request(dhcp_msg::RELEASE, 4, spoofed_mac(1));
+    release.ciaddr = [10, 0, 0, 10];
+    assert!(server.handle(&release).is_none());
+    assert_eq!(server.lease(spoofed_mac(1)), None);
+    assert_eq!(discover(spoofed_mac(5)), Some([10, 0, 0, 10]));
+    let states: Vec<_> = server
+        .leases()
+        .iter()
+        .map(|lease| (lease.ip, lease.state))
+        .collect();
+    assert_eq!(
+        states,
+        vec![
+            ([10, 0, 0, 10], LeaseState::Offered),
+            ([10, 0, 0, 11], LeaseState::Declined),
+            ([10, 0, 0, 12], LeaseState::Offered),
+            ([10, 0, 0, 100], LeaseState::Offered),
+        ]
+    );
+
+    let mut inform = Dhcp::request(dhcp_msg::INFORM, 5, spoofed_mac(9));
+    inform.ciaddr = [10, 0, 0, 200];
+    let ack = server.handle(&inform).unwrap();
+    assert_eq!(
+        (ack.yiaddr, ack.option(dhcp_opt::LEASE_TIME)),
+        ([0; 4], None)
+    );
+    assert!(ack.option(dhcp_opt::ROUTER).is_some());
+
+    // Replies through a relay go back to it on the server port
+    let reply = server.reply(Pdu::parse(&frame(request()).buffer)).unwrap();
+    match (
+        reply.headers.get(&3),
+        reply.headers.get(&4),
+        reply.headers.get(&7),
+    ) {
+        (Some(Proto::IPv4(ip)), Some(Proto::UDP(udp)), Some(Proto::Dhcp(dhcp))) => {
+            assert_eq!(ip.dst_ip_addr, [10, 0, 0, 1]);
+            assert_eq!(udp.dst_port.to_usize(), 67);
+            assert_eq!(dhcp.option(dhcp_opt::RELAY_AGENT), Some(&relay_info()));
+        }
+        _ => panic!("Expected DHCP reply"),
+    }
+}
+
+#[test]
+fn clients_exhaust_pool() {
+    let (mut host, mut peer) = LoopbackIo::pair();
+    let server = server();
+    let runner = server.clone();
+    // An OFFER and an ACK for each of three addresses
+    let handle = thread::spawn(move || (runner.run(&mut peer, Some(6)).unwrap(), peer));
+
+    let mut opts = ClientOpts::new();
+    opts.timeout = Duration::from_millis(100);
+    opts.retries = 0;
+    let mut client = DhcpClient::with_opts(opts);
+    let leases = client
+        .acquire_all(&mut host, (1..=5).map(spoofed_mac))
+        .unwrap();
+    let (sent, mut peer) = handle.join().unwrap();
+    assert_eq!(sent, 6);
+
+    let ips: Vec<[u8; 4]> = leases.iter().map(|lease| lease.ip).collect();
+    assert_eq!(ips, vec![[10, 0, 0, 10], [10, 0, 0, 11], [10, 0, 0, 12]]);
+    assert_eq!(leases[0].server_mac, [0xbb; 6]);
+    assert_eq!(leases[0].lease_time, Some(3600));
+    assert_eq!(
+        leases[0].ack.option(dhcp_opt::ROUTER),
+        Some(&DhcpOption::Router(vec![[10, 0, 0, 1]]))
+    );
+    let bound: Vec<_> = server
+        .leases()
+        .iter()
+        .map(|lease| (lease.mac, lease.state))
+        .collect();
+    assert_eq!(
+        bound,
+        (1..=3)
+            .map(|i| (spoofed_mac(i), LeaseState::Bound))
+            .collect::<Vec<_>>()
+    );
+
+    client.release(&mut host, &leases[0]).unwrap();
+    // DISCOVERs of clients left without address come first, then RELEASE
+    while let Some(frame) = peer.recv_timeout(Duration::from_millis(10)).unwrap() {
+        assert!(server.reply(Pdu::parse(&frame)).is_none());
+    }
+    assert_eq!(server.lease(spoofed_mac(1)), None);
+}
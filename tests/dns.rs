@@ -0,0 +1,446 @@
+use pakit::dns::{DnsResponder, Zone};
+use pakit::hdr::{
+    dns_class, dns_rcode, dns_type, Dns, DnsQuery, DnsRecord, EthHdr, IPv4Hdr, RData, TcpHdr,
+    UdpHdr,
+};
+use pakit::io::{LoopbackIo, PacketIo};
+use pakit::proto::Proto;
+use pakit::{auto_reply, Pdu, QueryHdr, Rules};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn response() -> Dns {
+    let mut dns = Dns::query(0x1234, "www.example.com", dns_type::A).reply();
+    dns.aa = true;
+    dns.answers = vec![
+        DnsRecord::new(
+            "www.example.com",
+            60,
+            RData::CNAME("web.example.com".into()),
+        ),
+        DnsRecord::new("web.example.com", 60, RData::A([192, 0, 2, 1])),
+        DnsRecord::new(
+            "web.example.com",
+            60,
+            RData::AAAA([0x20, 1, 0xd, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+        ),
+        DnsRecord::new(
+            "example.com",
+            300,
+            RData::MX {
+                preference: 10,
+                exchange: "mail.example.com".into(),
+            },
+        ),
+        DnsRecord::new(
+            "example.com",
+            300,
+            RData::TXT(vec![b"v=spf1 -all".to_vec(), Vec::new()]),
+        ),
+        DnsRecord::new(
+            "1.2.0.192.in-addr.arpa",
+            60,
+            RData::PTR("web.example.com".into()),
+        ),
+        DnsRecord::new(
+            "_sip._udp.example.com",
+            60,
+            RData::SRV {
+                priority: 1,
+                weight: 5,
+                port: 5060,
+                target: "sip.example.com".into(),
+            },
+        ),
+        DnsRecord::new(
+            "example.com",
+            60,
+            RData::CAA {
+                flags: 0,
+                tag: "issue".into(),
+                value: b"ca.example.net".to_vec(),
+            },
+        ),
+    ];
+    dns.authorities = vec![
+        DnsRecord::new("example.com", 60, RData::NS("ns1.example.com".into())),
+        DnsRecord::new(
+            "example.com",
+            60,
+            RData::SOA {
+                mname: "ns1.example.com".into(),
+                rname: "hostmaster.example.com".into(),
+                serial: 2024010101,
+                refresh: 7200,
+                retry: 900,
+                expire: 1209600,
+                minimum: 300,
+            },
+        ),
+    ];
+    let mut unknown = DnsRecord::new("example.com", 60, RData::Raw(vec![1, 2, 3]));
+    unknown.rtype = 65280;
+    let mut opt = DnsRecord::opt(1232, true);
+    opt.data = RData::OPT(vec![(10, vec![0xaa; 8])]);
+    dns.additionals = vec![unknown, opt];
+    dns
+}
+
+#[test]
+fn records_round_trip_compressed() {
+    let dns = response();
+    let bytes = dns.to_bytes().unwrap();
+    assert_eq!(&bytes[..4], &[0x12, 0x34, 0x85, 0x80]);
+    assert_eq!(&bytes[4..12], &[0, 1, 0, 8, 0, 2, 0, 2]);
+    // Answer owner points at question name
+    assert_eq!(&bytes[33..35], &[0xc0, 12]);
+    // "example.com" is written once, SRV target is not compressed
+    let count = |needle: &[u8]| bytes.windows(needle.len()).filter(|w| *w == needle).count();
+    assert_eq!(count(b"\x07example\x03com"), 2);
+    assert_eq!(count(b"\x03sip\x07example\x03com"), 1);
+
+    let parsed = Dns::from_bytes(&bytes).unwrap();
+    assert_eq!(parsed, dns);
+    let edns = parsed.edns().unwrap();
+    assert_eq!((edns.class, edns.ttl), (1232, 0x8000));
+
+    // Labels with dots and unprintable bytes survive
+    let odd = Dns::query(1, "a\\.b.\\000x.example", dns_type::TXT);
+    let bytes = odd.to_bytes().unwrap();
+    assert_eq!(&bytes[12..19], b"\x03a.b\x02\x00x");
+    assert_eq!(Dns::from_bytes(&bytes).unwrap(), odd);
+
+    assert!(Dns::query(1, &"a".repeat(64), dns_type::A)
+        .to_bytes()
+        .is_err());
+    let long = vec!["a".repeat(63); 4].join(".");
+    assert!(Dns::query(1, &long, dns_type::A).to_bytes().is_err());
+}
+
+#[test]
+fn hostile_messages() {
+    let header = |qd: u8, an: u8| vec![0, 1, 0x81, 0x80, 0, qd, 0, an, 0, 0, 0, 0];
+
+    // Pointer to itself and pointer ahead
+    for name in [vec![0xc0, 12], vec![0xc0, 14, 0, 0]].iter() {
+        let mut msg = header(1, 0);
+        msg.extend_from_slice(name);
+        msg.extend_from_slice(&[0, 1, 0, 1]);
+        assert!(Dns::from_bytes(&msg).is_err());
+    }
+
+    // Raw record holding `n` pointers, each pointing at the one before,
+    // and a record named by the last of them
+    let chain = |n: usize| {
+        let mut msg = header(0, 2);
+        msg.extend_from_slice(&[0, 0xff, 0, 0, 1, 0, 0, 0, 60]);
+        msg.extend_from_slice(&((n * 2) as u16).to_be_bytes());
+        msg.extend_from_slice(&[0xc0, 12]);
+        for i in 1..n {
+            msg.extend_from_slice(&(0xc000 | (23 + (i - 1) * 2) as u16).to_be_bytes());
+        }
+        msg.extend_from_slice(&(0xc000 | (23 + (n - 1) * 2) as u16).to_be_bytes());
+        msg.extend_from_slice(&[0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1]);
+        Dns::from_bytes(&msg)
+    };
+    assert_eq!(chain(10).unwrap().answers[1].data, RData::A([192, 0, 2, 1]));
+    assert!(chain(100).is_err());
+
+    // Name growing past 255 bytes through pointers
+    let mut msg = header(2, 0);
+    msg.extend(std::iter::repeat_n([63u8].iter().chain(&[b'a'; 63]).copied(), 3).flatten());
+    msg.extend_from_slice(&[0, 0, 1, 0, 1]);
+    msg.extend(std::iter::once(63u8).chain([b'b'; 63]));
+    msg.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1]);
+    let err = Dns::from_bytes(&msg).unwrap_err();
+    assert!(format!("{:?}", err).contains("too long"));
+
+    // Reserved label type and truncated record
+    let mut msg = header(1, 0);
+    msg.extend_from_slice(&[0x41, 0, 0, 1, 0, 1]);
+    assert!(Dns::from_bytes(&msg).is_err());
+    let mut msg = header(0, 1);
+    msg.extend_from_slice(&[0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0]);
+    assert!(Dns::from_bytes(&msg).is_err());
+
+    // A record of wrong length is kept raw
+    let mut msg = header(0, 1);
+    msg.extend_from_slice(&[0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 3, 1, 2, 3]);
+    let dns = Dns::from_bytes(&msg).unwrap();
+    assert_eq!(dns.answers[0].data, RData::Raw(vec![1, 2, 3]));
+}
+
+fn transport(dns: Dns, tcp: bool) -> Pdu {
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw([0xaa; 6], [0xbb; 6], 0x0800))
+        .header(IPv4Hdr::from("10.0.0.1", "10.0.0.53", 0).unwrap())
+        .header(dns);
+    if tcp {
+        pdu.set_header(TcpHdr::from(40000, 53, 0x018));
+    } else {
+        pdu.set_header(UdpHdr::from(40000, 53));
+    }
+    pdu.build().unwrap();
+    pdu
+}
+
+#[test]
+fn dns_over_udp_and_tcp() {
+    let dns = response();
+    let len = dns.to_bytes().unwrap().len();
+    let udp = transport(dns.clone(), false);
+    assert_eq!(udp.buffer.len(), 14 + 20 + 8 + len);
+    match Pdu::parse(&udp.buffer).headers.get(&7) {
+        Some(Proto::Dns(parsed)) => assert_eq!(parsed, &dns),
+        _ => panic!("DNS not parsed over UDP"),
+    }
+
+    let tcp = transport(dns.clone(), true);
+    let payload = &tcp.buffer[14 + 20 + 20..];
+    assert_eq!(&payload[..2], &(len as u16).to_be_bytes());
+    match Pdu::parse(&tcp.buffer).headers.get(&7) {
+        Some(Proto::Dns(parsed)) => assert_eq!(parsed, &dns),
+        _ => panic!("DNS not parsed over TCP"),
+    }
+    assert_eq!(Pdu::parse(&tcp.buffer).payload(), payload);
+    // Partial segment stays raw
+    let mut cut = tcp.buffer.clone();
+    cut.truncate(cut.len() - 10);
+    let ip_len = (cut.len() - 14) as u16;
+    cut[16..18].copy_from_slice(&ip_len.to_be_bytes());
+    assert!(matches!(
+        Pdu::parse(&cut).headers.get(&7),
+        Some(Proto::Raw(_))
+    ));
+}
+
+fn answer(pdu: Pdu) -> Option<Pdu> {
+    let (eth, ipv4, udp, dns) = match (
+        pdu.headers.get(&2),
+        pdu.headers.get(&3),
+        pdu.headers.get(&4),
+        pdu.headers.get(&7),
+    ) {
+        (
+            Some(Proto::Eth(eth)),
+            Some(Proto::IPv4(ipv4)),
+            Some(Proto::UDP(udp)),
+            Some(Proto::Dns(dns)),
+        ) => (eth, ipv4, udp, dns),
+        _ => panic!("Query not matched on DNS"),
+    };
+    let mut reply = dns.reply();
+    let name = reply.questions[0].name.clone();
+    reply
+        .answers
+        .push(DnsRecord::new(&name, 30, RData::A([10, 6, 6, 6])));
+    let mut ip = IPv4Hdr::new();
+    ip.src_ip_addr = ipv4.dst_ip_addr;
+    ip.dst_ip_addr = ipv4.src_ip_addr;
+    Some(
+        Pdu::new()
+            .header(EthHdr::from_raw(eth.dst_hw_addr, eth.src_hw_addr, 0x0800))
+            .header(ip)
+            .header(UdpHdr::from(
+                udp.dst_port.to_usize() as u16,
+                udp.src_port.to_usize() as u16,
+            ))
+            .header(reply),
+    )
+}
+
+#[test]
+fn rules_answer_matching_queries() {
+    let (mut host, mut responder) = LoopbackIo::pair();
+    let handle = thread::spawn(move || {
+        let mut rules = Rules::new();
+        rules.add_rule(
+            QueryHdr::Dns(DnsQuery::from(Some("*.Example.com"), Some(dns_type::A))),
+            answer,
+        );
+        auto_reply(&mut responder, &rules, Some(1)).unwrap()
+    });
+
+    // Wrong type, name above the wildcard, then a match
+    let queries = [
+        Dns::query(1, "www.example.com", dns_type::AAAA),
+        Dns::query(2, "example.com", dns_type::A),
+        Dns::query(3, "WWW.example.com.", dns_type::A),
+    ];
+    for query in queries.iter() {
+        transport(query.clone(), false).send_on(&mut host).unwrap();
+    }
+    let reply = Pdu::parse(&host.recv().unwrap());
+    let dns = match reply.headers.get(&7) {
+        Some(Proto::Dns(dns)) => dns,
+        _ => panic!("Expected DNS reply"),
+    };
+    assert_eq!(dns.id, 3);
+    assert!(dns.qr);
+    assert_eq!(dns.rcode, dns_rcode::NO_ERROR);
+    assert_eq!(dns.answers[0].data, RData::A([10, 6, 6, 6]));
+    assert_eq!(handle.join().unwrap(), 1);
+
+    let mut query = DnsQuery::new();
+    query.id = Some(2);
+    assert!(query == queries[1]);
+    assert!(query != queries[0]);
+}
+
+fn zone() -> Zone {
+    let mut zone = Zone::new();
+    zone.add(DnsRecord::new("www.lab", 300, RData::A([10, 0, 0, 80])));
+    zone.add(DnsRecord::new(
+        "www.lab",
+        300,
+        RData::TXT(vec![b"web".to_vec()]),
+    ));
+    zone.add(DnsRecord::new(
+        "alias.lab",
+        60,
+        RData::CNAME("www.lab".into()),
+    ));
+    zone.add(DnsRecord::new("*.lab", 30, RData::A([10, 0, 0, 1])));
+    zone.add(DnsRecord::new("*.dev.lab", 30, RData::AAAA([0xfd; 16])));
+    for i in 0..40 {
+        zone.add(DnsRecord::new("big.lab", 30, RData::A([10, 1, 0, i])));
+    }
+    zone.fail("broken.lab", dns_rcode::SERV_FAIL);
+    zone.fail("*.gone.lab", dns_rcode::NX_DOMAIN);
+    zone
+}
+
+#[test]
+fn responder_answers_from_zone() {
+    let mut responder = DnsResponder::new(zone());
+    let ask = |responder: &DnsResponder, name: &str, qtype: u16| {
+        responder.respond(&Dns::query(7, name, qtype))
+    };
+
+    let reply = ask(&responder, "WWW.lab.", dns_type::A);
+    assert!(reply.qr && reply.aa && !reply.tc);
+    assert_eq!((reply.id, reply.rcode), (7, dns_rcode::NO_ERROR));
+    assert_eq!(
+        reply.answers,
+        vec![DnsRecord::new("WWW.lab.", 300, RData::A([10, 0, 0, 80]))]
+    );
+    assert_eq!(ask(&responder, "www.lab", dns_type::ANY).answers.len(), 2);
+    // Existing name without data of the type
+    let reply = ask(&responder, "www.lab", dns_type::MX);
+    assert_eq!((reply.rcode, reply.answers.len()), (dns_rcode::NO_ERROR, 0));
+
+    // CNAME is followed, wildcards answer with the name asked for
+    let answers = ask(&responder, "alias.lab", dns_type::A).answers;
+    assert_eq!(answers[0].data, RData::CNAME("www.lab".into()));
+    assert_eq!(answers[1].data, RData::A([10, 0, 0, 80]));
+    let answers = ask(&responder, "host.lab", dns_type::A).answers;
+    assert_eq!(
+        answers,
+        vec![DnsRecord::new("host.lab", 30, RData::A([10, 0, 0, 1]))]
+    );
+    // Most specific wildcard only
+    assert_eq!(ask(&responder, "a.dev.lab", dns_type::A).answers.len(), 0);
+    assert_eq!(
+        ask(&responder, "a.dev.lab", dns_type::AAAA).answers.len(),
+        1
+    );
+
+    // Unknown names and injected failures
+    assert_eq!(
+        ask(&responder, "lab", dns_type::A).rcode,
+        dns_rcode::NX_DOMAIN
+    );
+    assert_eq!(
+        ask(&responder, "other", dns_type::A).rcode,
+        dns_rcode::NX_DOMAIN
+    );
+    let reply = ask(&responder, "broken.lab", dns_type::A);
+    assert_eq!(
+        (reply.rcode, reply.answers.len()),
+        (dns_rcode::SERV_FAIL, 0)
+    );
+    assert_eq!(
+        ask(&responder, "x.gone.lab", dns_type::A).rcode,
+        dns_rcode::NX_DOMAIN
+    );
+    let mut chaos = Dns::query(1, "www.lab", dns_type::TXT);
+    chaos.questions[0].qclass = dns_class::CH;
+    assert_eq!(responder.respond(&chaos).rcode, dns_rcode::REFUSED);
+
+    // Answers over 512 bytes need EDNS0
+    let reply = ask(&responder, "big.lab", dns_type::A);
+    assert!(reply.tc && reply.answers.is_empty());
+    let mut query = Dns::query(8, "big.lab", dns_type::A);
+    query.additionals.push(DnsRecord::opt(4096, false));
+    let reply = responder.respond(&query);
+    assert!(!reply.tc);
+    assert_eq!(reply.answers.len(), 40);
+    assert_eq!(reply.edns().unwrap().class, 1232);
+
+    responder.opts.ttl = Some(0);
+    assert!(responder
+        .respond(&query)
+        .answers
+        .iter()
+        .all(|record| record.ttl == 0));
+    responder.opts.truncate = true;
+    let reply = responder.respond(&query);
+    assert!(reply.tc && reply.answers.is_empty());
+    assert_eq!(reply.additionals.len(), 1);
+}
+
+#[test]
+fn responder_replies_on_loopback() {
+    let (mut host, mut peer) = LoopbackIo::pair();
+    let mut responder = DnsResponder::new(zone());
+    responder.opts.delay = Duration::from_millis(50);
+    let handle = thread::spawn(move || responder.run(&mut peer, Some(1)).unwrap());
+
+    // Query over TCP is not answered
+    let start = Instant::now();
+    transport(Dns::query(1, "www.lab", dns_type::A), true)
+        .send_on(&mut host)
+        .unwrap();
+    // Neither is multicast DNS
+    let mut mdns = Pdu::new()
+        .header(EthHdr::from_raw(
+            [0xaa; 6],
+            [1, 0, 0x5e, 0, 0, 0xfb],
+            0x0800,
+        ))
+        .header(IPv4Hdr::from("10.0.0.1", "224.0.0.251", 0).unwrap())
+        .header(UdpHdr::from(5353, 5353))
+        .header(Dns::query(3, "www.lab", dns_type::A));
+    mdns.build().unwrap();
+    mdns.send_on(&mut host).unwrap();
+    transport(Dns::query(2, "gone", dns_type::A), false)
+        .send_on(&mut host)
+        .unwrap();
+    let reply = Pdu::parse(&host.recv().unwrap());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(handle.join().unwrap(), 1);
+
+    match (
+        reply.headers.get(&2),
+        reply.headers.get(&3),
+        reply.headers.get(&4),
+        reply.headers.get(&7),
+    ) {
+        (
+            Some(Proto::Eth(eth)),
+            Some(Proto::IPv4(ipv4)),
+            Some(Proto::UDP(udp)),
+            Some(Proto::Dns(dns)),
+        ) => {
+            assert_eq!((eth.src_hw_addr, eth.dst_hw_addr), ([0xbb; 6], [0xaa; 6]));
+            assert_eq!(ipv4.src_ip_addr, [10, 0, 0, 53]);
+            assert_eq!(ipv4.dst_ip_addr, [10, 0, 0, 1]);
+            assert_eq!(
+                (udp.src_port.to_usize(), udp.dst_port.to_usize()),
+                (53, 40000)
+            );
+            assert_eq!((dns.id, dns.rcode), (2, dns_rcode::NX_DOMAIN));
+        }
+        _ => panic!("Expected DNS reply over UDP"),
+    }
+}
@@ -0,0 +1,204 @@
+use pakit::flow::{CsvExporter, Flow, FlowEnd, FlowTable, JsonExporter, CSV_HEADER};
+use pakit::hdr::{tcp_flags, EthHdr, IPv4Hdr, Raw, TcpHdr, UdpHdr};
+use pakit::Pdu;
+use std::time::Duration;
+
+fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+fn tcp(src: &str, dst: &str, src_port: u16, dst_port: u16, flags: u16) -> Vec<u8> {
+    let eth = EthHdr::from_raw([0xaa; 6], [0xbb; 6], 0x0800);
+    let mut pdu = Pdu::new()
+        .header(eth)
+        .header(IPv4Hdr::from(src, dst, 0).unwrap())
+        .header(TcpHdr::from(src_port, dst_port, flags));
+    pdu.build().unwrap();
+    pdu.buffer
+}
+
+/// UDP frame with 802.1Q tags of `vlans`, outermost first
+fn udp(vlans: &[u16], src_port: u16, dst_port: u16, data: &[u8]) -> Vec<u8> {
+    let eth = EthHdr::from_raw([0xaa; 6], [0xbb; 6], 0x0800);
+    let mut pdu = Pdu::new()
+        .header(eth)
+        .header(IPv4Hdr::from("10.0.0.1", "10.0.0.2", 0).unwrap())
+        .header(UdpHdr::from(src_port, dst_port))
+        .header(Raw::from(data));
+    pdu.build().unwrap();
+    let mut frame = pdu.buffer[..12].to_vec();
+    for vlan in vlans {
+        frame.extend_from_slice(&[0x81, 0x00]);
+        frame.extend_from_slice(&vlan.to_be_bytes());
+    }
+    frame.extend_from_slice(&pdu.buffer[12..]);
+    frame
+}
+
+#[test]
+fn bidirectional_flows() {
+    let mut table = FlowTable::new();
+    let mut done: Vec<Flow> = Vec::new();
+
+    let handshake = [
+        tcp("10.0.0.1", "10.0.0.2", 4000, 80, tcp_flags::SYN),
+        tcp(
+            "10.0.0.2",
+            "10.0.0.1",
+            80,
+            4000,
+            tcp_flags::SYN | tcp_flags::ACK,
+        ),
+        tcp("10.0.0.1", "10.0.0.2", 4000, 80, tcp_flags::ACK),
+        tcp(
+            "10.0.0.1",
+            "10.0.0.2",
+            4000,
+            80,
+            tcp_flags::FIN | tcp_flags::ACK,
+        ),
+    ];
+    for (i, frame) in handshake.iter().enumerate() {
+        table.process_frame(frame, secs(i as u64), |flow| done.push(flow));
+    }
+    table.process_frame(&udp(&[10], 53, 53, b"query"), secs(4), |flow| {
+        done.push(flow)
+    });
+    table.process_frame(&udp(&[], 53, 53, b"untagged"), secs(4), |flow| {
+        done.push(flow)
+    });
+    assert_eq!(table.flows(), 3);
+    table.process_frame(
+        &tcp(
+            "10.0.0.2",
+            "10.0.0.1",
+            80,
+            4000,
+            tcp_flags::FIN | tcp_flags::ACK,
+        ),
+        secs(5),
+        |flow| done.push(flow),
+    );
+    assert_eq!(table.flows(), 2);
+
+    let flow = &done[0];
+    assert_eq!(flow.end, FlowEnd::Finished);
+    assert_eq!(flow.key.src.to_string(), "10.0.0.1");
+    assert_eq!(
+        (flow.key.src_port, flow.key.dst_port, flow.key.proto),
+        (4000, 80, 6)
+    );
+    assert_eq!((flow.packets, flow.bytes), (3, 120));
+    assert_eq!((flow.rev_packets, flow.rev_bytes), (2, 80));
+    assert_eq!(
+        flow.tcp_flags,
+        tcp_flags::SYN | tcp_flags::ACK | tcp_flags::FIN
+    );
+    assert_eq!(flow.duration(), secs(5));
+
+    // Q-in-Q frame keeps outer VLAN, other side of tagged flow is merged
+    table.process_frame(&udp(&[10, 20], 53, 53, b"reply"), secs(6), |flow| {
+        done.push(flow)
+    });
+    table.process_frame(&udp(&[20], 53, 53, b"other vlan"), secs(6), |flow| {
+        done.push(flow)
+    });
+    table.finish(|flow| done.push(flow));
+    let vlans: Vec<(Option<u16>, u64, FlowEnd)> = done[1..]
+        .iter()
+        .map(|flow| (flow.key.vlan, flow.packets, flow.end))
+        .collect();
+    assert_eq!(
+        vlans,
+        vec![
+            (None, 1, FlowEnd::Flushed),
+            (Some(10), 2, FlowEnd::Flushed),
+            (Some(20), 1, FlowEnd::Flushed),
+        ]
+    );
+}
+
+#[test]
+fn timeouts_and_eviction() {
+    let mut table = FlowTable::new();
+    table.idle_timeout = secs(10);
+    table.active_timeout = secs(60);
+    table.max_flows = 2;
+    let mut done: Vec<Flow> = Vec::new();
+    let ends = |done: &[Flow]| -> Vec<(u16, FlowEnd)> {
+        done.iter()
+            .map(|flow| (flow.key.src_port, flow.end))
+            .collect()
+    };
+
+    // Port 1 talks every 5 seconds, so it only ends by active timeout
+    for t in 0..13 {
+        table.process_frame(&udp(&[], 1, 9, b""), secs(t * 5), |flow| done.push(flow));
+        if t == 1 {
+            table.process_frame(&udp(&[], 2, 9, b""), secs(5), |flow| done.push(flow));
+        }
+    }
+    assert_eq!(ends(&done), vec![(2, FlowEnd::Idle), (1, FlowEnd::Active)]);
+    // Packet which hit active timeout starts next flow
+    assert_eq!(table.flows(), 1);
+
+    done.clear();
+    table.idle_timeout = secs(100);
+    for port in 1..=3 {
+        table.process_frame(&udp(&[], port, 9, b""), secs(100 + port as u64), |flow| {
+            done.push(flow)
+        });
+    }
+    assert_eq!(ends(&done), vec![(1, FlowEnd::Evicted)]);
+    assert_eq!(table.flows(), 2);
+}
+
+#[test]
+fn timestamps_out_of_order() {
+    let mut table = FlowTable::new();
+    let mut done: Vec<Flow> = Vec::new();
+    let ms = Duration::from_millis;
+
+    // Frames of several interfaces merged into one capture
+    table.process_frame(&udp(&[], 1, 9, b""), ms(10_000), |flow| done.push(flow));
+    table.process_frame(&udp(&[], 1, 9, b""), ms(9_500), |flow| done.push(flow));
+    table.process_frame(&udp(&[], 2, 9, b""), ms(9_800), |flow| done.push(flow));
+    table.finish(|flow| done.push(flow));
+
+    assert_eq!(done.len(), 2);
+    // Flow of port 2 was last seen first
+    assert_eq!(done[0].key.src_port, 2);
+    assert_eq!(done[1].first_seen, ms(9_500));
+    assert_eq!(done[1].last_seen, ms(10_000));
+    assert_eq!(done[1].duration(), ms(500));
+    assert_eq!(done[1].packets, 2);
+}
+
+#[test]
+fn csv_and_json_export() {
+    let mut table = FlowTable::new();
+    let mut flows = Vec::new();
+    table.process_frame(
+        &udp(&[7], 5000, 53, b"abc"),
+        Duration::from_millis(1500),
+        |f| flows.push(f),
+    );
+    table.finish(|f| flows.push(f));
+
+    let mut csv = CsvExporter::new(Vec::new());
+    csv.export(&flows[0]).unwrap();
+    let csv = String::from_utf8(csv.into_inner().unwrap()).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], CSV_HEADER);
+    assert_eq!(
+        lines[1],
+        "10.0.0.1,10.0.0.2,5000,53,17,7,1.500000,1.500000,1,31,0,0,0,0,flushed"
+    );
+
+    let mut json = JsonExporter::new(Vec::new());
+    json.export(&flows[0]).unwrap();
+    let json = String::from_utf8(json.into_inner().unwrap()).unwrap();
+    assert!(json.starts_with("{\"src\":\"10.0.0.1\",\"dst\":\"10.0.0.2\",\"src_port\":5000,"));
+    assert!(json.contains("\"vlan\":7,\"first_seen\":1.500000,"));
+    assert!(json.ends_with("\"end\":\"flushed\"}\n"));
+}
@@ -0,0 +1,227 @@
+use pakit::dstructs::Bits;
+use pakit::frag::{
+    fragment, fragment_with, FragOpts, FragOrder, OverlapPolicy, Reassembler, MORE_FRAGMENTS,
+};
+use pakit::hdr::{EthHdr, IPv4Hdr, Raw, UdpHdr};
+use pakit::proto::Proto;
+use pakit::Pdu;
+use std::time::Duration;
+
+fn datagram(len: usize) -> Pdu {
+    let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+    let mut ipv4 = IPv4Hdr::from("10.0.0.1", "10.0.0.2", 0).unwrap();
+    ipv4.id = Bits::from(0x4242, 16);
+    let mut pdu = Pdu::new()
+        .header(ipv4)
+        .header(UdpHdr::from(5000, 9000))
+        .header(Raw::from(&data));
+    pdu.build().unwrap();
+    pdu
+}
+
+fn ipv4(pdu: &Pdu) -> &IPv4Hdr {
+    match pdu.headers.get(&3) {
+        Some(Proto::IPv4(ipv4)) => ipv4,
+        _ => panic!("IPv4 header missing"),
+    }
+}
+
+/// Builds fragment of datagram `id` at byte `offset` filled with `fill`
+fn frag(id: usize, offset: usize, len: usize, more: bool, fill: u8) -> Vec<u8> {
+    let mut ipv4 = IPv4Hdr::from("10.0.0.1", "10.0.0.2", 17).unwrap();
+    ipv4.id = Bits::from(id, 16);
+    ipv4.frag_offset = Bits::from(offset / 8, 13);
+    ipv4.flags = Bits::from(if more { MORE_FRAGMENTS } else { 0 }, 3);
+    let mut pdu = Pdu::new().header(ipv4).header(Raw::from(&vec![fill; len]));
+    pdu.build().unwrap();
+    pdu.buffer
+}
+
+fn reassemble(policy: OverlapPolicy, frags: &[Vec<u8>]) -> Vec<u8> {
+    let mut reassembler = Reassembler::new(policy);
+    let mut done = None;
+    for frag in frags {
+        done = reassembler.process(frag, Duration::from_secs(1));
+    }
+    done.unwrap().buffer[20..].to_vec()
+}
+
+#[test]
+fn fragment_and_reassemble() {
+    let pdu = datagram(3000);
+    let frags = fragment(&pdu, 1500).unwrap();
+    assert_eq!(frags.len(), 3);
+    let offsets: Vec<usize> = frags
+        .iter()
+        .map(|f| ipv4(f).frag_offset.to_usize())
+        .collect();
+    assert_eq!(offsets, vec![0, 185, 370]);
+    let more: Vec<usize> = frags.iter().map(|f| ipv4(f).flags.to_usize()).collect();
+    assert_eq!(more, vec![MORE_FRAGMENTS, MORE_FRAGMENTS, 0]);
+    assert!(frags.iter().all(|f| f.buffer.len() <= 1500));
+    assert_eq!(frags[2].buffer.len(), 20 + 3008 - 2960);
+    assert_eq!(pakit::utility::checksum(&frags[1].buffer[..20]), 0);
+    // Later fragments are not parsed as UDP
+    assert!(Pdu::parse_ip(&frags[1].buffer).headers.get(&4).is_none());
+
+    let mut opts = FragOpts::new();
+    opts.order = FragOrder::Random;
+    let mut reassembler = Reassembler::new(OverlapPolicy::Linux);
+    let frags = fragment_with(&pdu, 1000, &opts).unwrap();
+    assert_eq!(frags.len(), 4);
+    let mut done = Vec::new();
+    for frag in frags.iter() {
+        done.extend(reassembler.process(&frag.buffer, Duration::from_secs(1)));
+    }
+    assert_eq!(done.len(), 1);
+    assert_eq!(done[0].buffer, pdu.buffer);
+    match done[0].headers.get(&4) {
+        Some(Proto::UDP(udp)) => assert_eq!(udp.dst_port.to_usize(), 9000),
+        _ => panic!("UDP header missing"),
+    }
+    assert_eq!(reassembler.pending(), 0);
+
+    let mut dont_frag = datagram(3000);
+    if let Some(Proto::IPv4(ipv4)) = dont_frag.headers.get_mut(&3) {
+        ipv4.flags = Bits::from(0b010, 3);
+    }
+    dont_frag.build().unwrap();
+    assert!(fragment(&dont_frag, 1500).is_err());
+}
+
+#[test]
+fn evasion_fragments() {
+    let eth = EthHdr::from_raw([0xaa; 6], [0xbb; 6], 0x0800);
+    let mut pdu = datagram(100).header(eth);
+    pdu.build().unwrap();
+
+    let mut opts = FragOpts::new();
+    opts.first_size = Some(1);
+    opts.overlap = 4;
+    opts.overlap_fill = Some(b'X');
+    let frags = fragment_with(&pdu, 60, &opts).unwrap();
+    // 8 byte first fragment splits UDP header, then 40 byte pieces
+    // repeating 8 bytes each
+    let layout: Vec<(usize, usize)> = frags
+        .iter()
+        .map(|f| (ipv4(f).frag_offset.to_usize() * 8, f.buffer.len() - 34))
+        .collect();
+    assert_eq!(layout, vec![(0, 8), (0, 40), (32, 40), (64, 40), (96, 12)]);
+    assert!(frags
+        .iter()
+        .all(|f| matches!(f.headers.get(&2), Some(Proto::Eth(_)))));
+
+    let frames: Vec<Vec<u8>> = frags.iter().map(|f| f.buffer.clone()).collect();
+    let reassemble_frames = |policy| {
+        let mut reassembler = Reassembler::new(policy);
+        let mut done = None;
+        for frame in frames.iter() {
+            done = reassembler.process_frame(frame, Duration::from_secs(1));
+        }
+        done.unwrap()
+    };
+    // Original data of every overlap comes first
+    let done = reassemble_frames(OverlapPolicy::First);
+    assert!(matches!(done.headers.get(&2), Some(Proto::Eth(_))));
+    assert_eq!(done.buffer, pdu.buffer);
+
+    let done = reassemble_frames(OverlapPolicy::Last);
+    let data = &done.buffer[34..];
+    for start in [0, 32, 64, 96].iter() {
+        assert_eq!(&data[*start..start + 8], b"XXXXXXXX");
+    }
+    assert_eq!(&data[8..32], &pdu.buffer[42..66]);
+}
+
+#[test]
+fn overlap_policies() {
+    // Second fragment starts before first, third starts where first does
+    let frags = vec![
+        frag(1, 8, 16, true, b'1'),
+        frag(1, 0, 16, true, b'2'),
+        frag(1, 8, 16, true, b'3'),
+        frag(1, 24, 8, false, b'4'),
+    ];
+    let expect = |layout: &[(u8, usize)]| -> Vec<u8> {
+        layout
+            .iter()
+            .flat_map(|(b, n)| std::iter::repeat(*b).take(*n))
+            .collect()
+    };
+    let first = expect(&[(b'2', 8), (b'1', 16), (b'4', 8)]);
+    assert_eq!(reassemble(OverlapPolicy::First, &frags), first);
+    assert_eq!(reassemble(OverlapPolicy::Windows, &frags), first);
+    assert_eq!(
+        reassemble(OverlapPolicy::Last, &frags),
+        expect(&[(b'2', 8), (b'3', 16), (b'4', 8)])
+    );
+    assert_eq!(
+        reassemble(OverlapPolicy::Bsd, &frags),
+        expect(&[(b'2', 16), (b'1', 8), (b'4', 8)])
+    );
+    assert_eq!(
+        reassemble(OverlapPolicy::Linux, &frags),
+        expect(&[(b'2', 16), (b'3', 8), (b'4', 8)])
+    );
+
+    // Fragment fully covering an older one replaces it on Windows
+    let frags = vec![
+        frag(2, 8, 8, true, b'1'),
+        frag(2, 0, 24, true, b'2'),
+        frag(2, 24, 8, false, b'3'),
+    ];
+    assert_eq!(
+        reassemble(OverlapPolicy::Windows, &frags),
+        expect(&[(b'2', 24), (b'3', 8)])
+    );
+    assert_eq!(
+        reassemble(OverlapPolicy::First, &frags),
+        expect(&[(b'2', 8), (b'1', 8), (b'2', 8), (b'3', 8)])
+    );
+}
+
+#[test]
+fn reassembly_timeout() {
+    let mut reassembler = Reassembler::new(OverlapPolicy::First);
+    assert!(reassembler
+        .process(&frag(3, 0, 16, true, 0), Duration::from_secs(1))
+        .is_none());
+    assert_eq!(reassembler.pending(), 1);
+    assert_eq!(reassembler.expire(Duration::from_secs(20)), 0);
+    // Rest of datagram arrives too late and starts a new one
+    assert!(reassembler
+        .process(&frag(3, 16, 8, false, 0), Duration::from_secs(40))
+        .is_none());
+    assert_eq!(reassembler.pending(), 1);
+    assert_eq!(reassembler.expire(Duration::from_secs(80)), 1);
+}
+
+#[test]
+fn reassembly_limits() {
+    let mut reassembler = Reassembler::new(OverlapPolicy::First);
+    reassembler.max_pending = 4;
+    for id in 0..10 {
+        let ts = Duration::from_millis(id as u64);
+        assert!(reassembler.process(&frag(id, 0, 16, true, 0), ts).is_none());
+    }
+    assert_eq!(reassembler.pending(), 4);
+    // Oldest datagrams made room for the latest ones
+    let ts = Duration::from_millis(10);
+    assert!(reassembler.process(&frag(0, 16, 8, false, 0), ts).is_none());
+    assert!(reassembler.process(&frag(9, 16, 8, false, 0), ts).is_some());
+
+    let mut reassembler = Reassembler::new(OverlapPolicy::First);
+    reassembler.max_fragments = 8;
+    let ts = Duration::from_secs(1);
+    assert!(reassembler.process(&frag(1, 0, 8, true, 0), ts).is_none());
+    for _ in 0..20 {
+        assert!(reassembler.process(&frag(1, 8, 8, true, 1), ts).is_none());
+    }
+    // First fragment was dropped for the flood following it
+    assert!(reassembler.process(&frag(1, 16, 8, false, 2), ts).is_none());
+    let done = reassembler.process(&frag(1, 0, 8, true, 0), ts).unwrap();
+    assert_eq!(
+        done.buffer[20..],
+        [vec![0; 8], vec![1; 8], vec![2; 8]].concat()[..]
+    );
+}
@@ -35,3 +35,28 @@ fn ipv4_create_parse() {
     let hdr2 = IPv4Hdr::parse(raw_hdrs.into());
     assert_eq!(hdr, hdr2);
 }
+
+#[test]
+fn ipv4_checksum() {
+    use pakit::hdr::Hdr;
+    use pakit::hdr::{ip_proto, IPv4Hdr};
+    use pakit::utility::checksum;
+
+    let mut hdr = IPv4Hdr::from("192.168.1.1", "192.168.10.2", ip_proto::UDP).unwrap();
+    hdr.set_checksum().unwrap();
+    let raw: Vec<u8> = hdr.create().unwrap().into();
+    assert_eq!(checksum(&raw), 0);
+    assert_eq!(IPv4Hdr::parse(raw.into()).ttl.to_usize(), 64);
+}
+
+#[test]
+fn ipv6_create_parse() {
+    use pakit::hdr::Hdr;
+    use pakit::hdr::{ip_proto, IPv6Hdr};
+
+    let hdr = IPv6Hdr::from("fe80::1", "2001:db8::2", ip_proto::UDP).unwrap();
+    let raw_hdrs = hdr.create().unwrap();
+    let hdr2 = IPv6Hdr::parse(raw_hdrs.into());
+    assert_eq!(hdr, hdr2);
+    assert_eq!(hdr2.src_ip_addr[0..2], [0xfe, 0x80]);
+}
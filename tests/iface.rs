@@ -0,0 +1,59 @@
+use pakit::iface::*;
+
+#[test]
+fn route_tables() {
+    let route =
+        "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+                 eth1\t00000000\t0102A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0\n\
+                 eth0\t00000000\t0101A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n\
+                 eth0\t0001A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n";
+    let gateway = parse_route_table(route).unwrap();
+    assert_eq!(gateway.interface, "eth0");
+    assert_eq!(gateway.ip, [192, 168, 1, 1]);
+    assert_eq!(gateway.metric, 100);
+
+    let route6 = "fe800000000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000100 00000001 00000000 00000001     eth0\n\
+                  00000000000000000000000000000000 00 00000000000000000000000000000000 00 fe800000000000000000000000000001 00000400 00000001 00000000 00450003     eth0\n";
+    let gateway = parse_ipv6_route_table(route6).unwrap();
+    assert_eq!(gateway.interface, "eth0");
+    assert_eq!(gateway.ip[0..2], [0xfe, 0x80]);
+    assert_eq!(gateway.ip[15], 1);
+    assert_eq!(gateway.metric, 0x400);
+
+    assert_eq!(parse_route_table(""), None);
+}
+
+#[test]
+fn neighbour_table() {
+    let arp = "IP address       HW type     Flags       HW address            Mask     Device\n\
+               192.168.1.1      0x1         0x2         aa:bb:cc:dd:ee:ff     *        eth0\n\
+               192.168.1.7      0x1         0x0         00:00:00:00:00:00     *        eth0\n";
+    let table = parse_arp_table(arp);
+    assert_eq!(table.len(), 2);
+    assert_eq!(table[0].ip, [192, 168, 1, 1]);
+    assert_eq!(table[0].mac, [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]);
+    assert_eq!(table[0].interface, "eth0");
+    assert!(table[0].is_complete());
+    assert!(!table[1].is_complete());
+}
+
+#[test]
+fn loopback_interface() {
+    let lo = Interface::by_name("lo").unwrap();
+    assert!(lo.is_loopback());
+    assert_eq!(lo.ip(), Some([127, 0, 0, 1]));
+    assert_eq!(lo.ipv4[0].prefix, 8);
+    assert!(lo.ipv4[0].contains([127, 1, 2, 3]));
+    assert_eq!(lo.ipv4[0].netmask(), [255, 0, 0, 0]);
+    assert!(Interface::by_name("no-such-iface0").is_err());
+}
+
+#[test]
+fn cidr_hosts() {
+    let net = Ipv4Net::parse("10.0.0.77/30").unwrap();
+    let hosts: Vec<[u8; 4]> = net.hosts().collect();
+    assert_eq!(hosts, vec![[10, 0, 0, 77], [10, 0, 0, 78]]);
+    assert_eq!(Ipv4Net::parse("10.0.0.1").unwrap().hosts().count(), 1);
+    assert_eq!(Ipv4Net::parse("10.0.0.0/24").unwrap().hosts().count(), 254);
+    assert!(Ipv4Net::parse("10.0.0/24").is_err());
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{arp_request, reply_to_arp};
+use pakit::hdr::ArpQuery;
+use pakit::io::{LoopbackIo, PacketIo};
+use pakit::proto::Proto;
+use pakit::{auto_reply, Pdu, QueryHdr, Rules};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn loopback_send_recv() {
+    let (mut a, mut b) = LoopbackIo::pair();
+    let pdu = arp_request();
+    assert_eq!(pdu.send_on(&mut a).unwrap(), pdu.buffer.len());
+    assert_eq!(b.recv().unwrap(), pdu.buffer);
+    assert!(b.recv_timeout(Duration::from_millis(10)).unwrap().is_none());
+}
+
+#[test]
+fn auto_reply_on_loopback() {
+    let (mut host, mut responder) = LoopbackIo::pair();
+    let handle = thread::spawn(move || {
+        let mut rules = Rules::new();
+        rules.add_rule(
+            QueryHdr::Arp(ArpQuery::from(None, None, None, Some("192.168.1.101")).unwrap()),
+            reply_to_arp,
+        );
+        auto_reply(&mut responder, &rules, Some(1)).unwrap()
+    });
+
+    let reply = arp_request().send_and_recv_on(&mut host).unwrap();
+    let reply: Vec<u8> = reply.into();
+    let reply = Pdu::parse(&reply);
+    match reply.headers.get(&3) {
+        Some(Proto::Arp(arp)) => {
+            assert_eq!(arp.src_hw_addr, [0xbb; 6]);
+            assert_eq!(arp.src_proto_addr, [192, 168, 1, 101]);
+            assert_eq!(arp.opr.to_usize(), 2);
+        }
+        _ => panic!("Expected ARP reply"),
+    }
+    assert_eq!(handle.join().unwrap(), 1);
+}
+
+#[test]
+#[cfg(feature = "pcap")]
+fn pcap_sink_and_source() {
+    use pakit::io::PcapIo;
+
+    let path = std::env::temp_dir().join("pakit_io_test.pcap");
+    let path = path.to_str().unwrap();
+    let pdu = arp_request();
+    {
+        let mut sink = PcapIo::create(path).unwrap();
+        pdu.send_on(&mut sink).unwrap();
+        pdu.send_on(&mut sink).unwrap();
+    }
+
+    let mut source = PcapIo::open(path).unwrap();
+    assert_eq!(source.recv().unwrap(), pdu.buffer);
+    assert_eq!(
+        source.recv_timeout(Duration::from_secs(1)).unwrap(),
+        Some(pdu.buffer.clone())
+    );
+    assert!(source
+        .recv_timeout(Duration::from_secs(1))
+        .unwrap()
+        .is_none());
+    assert!(source.recv().is_err());
+}
@@ -0,0 +1,470 @@
+use pakit::hdr::{
+    dhcpv6_msg, dhcpv6_opt, dhcpv6_port, dhcpv6_status, duid_ll, eth_type, icmpv6_type, ip_proto,
+    link_local, multicast_mac, Dhcpv6, Dhcpv6Option, EthHdr, IPv6Hdr, Icmpv6Hdr, NdpOption,
+    PrefixInfo, Raw, RouterAdvert, RouterSolicit, UdpHdr, ALL_DHCP_SERVERS, ALL_NODES, ALL_ROUTERS,
+};
+use pakit::io::{LoopbackIo, PacketIo};
+use pakit::ipv6::{BindingState, Dhcpv6Opts, Dhcpv6Server, PdPool, Router, RouterOpts};
+use pakit::proto::Proto;
+use pakit::utility::{parse_ipv6, pseudo_checksum};
+use pakit::Pdu;
+use std::thread;
+use std::time::Duration;
+
+const ROUTER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const HOST_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x42];
+
+fn ip(addr: &str) -> [u8; 16] {
+    parse_ipv6(addr).unwrap()
+}
+
+fn router() -> Router {
+    let mut opts = RouterOpts::new(ROUTER_MAC);
+    opts.prefixes.push(PrefixInfo::new(ip("2001:db8:1::"), 64));
+    opts.mtu = Some(1480);
+    opts.rdnss.push(ip("2001:db8:1::53"));
+    opts.interval = Duration::from_millis(300);
+    Router::new(opts)
+}
+
+/// Checks ICMPv6 or UDP checksum of built IPv6 `frame`
+fn checksum_valid(frame: &[u8], proto: u8) -> bool {
+    pseudo_checksum(&frame[22..38], &frame[38..54], proto, &frame[54..]) == 0
+}
+
+#[test]
+fn router_advert_round_trip() {
+    let mut advert = router().advert();
+    advert.managed = true;
+    advert.options.push(NdpOption::Raw {
+        kind: 31,
+        data: vec![0; 6],
+    });
+    let bytes = advert.to_bytes().unwrap();
+    // 12 fixed bytes, link address 8, MTU 8, prefix 32, RDNSS 24, raw 8
+    assert_eq!(bytes.len(), 92);
+    assert_eq!(&bytes[..4], &[64, 0x80, 0x07, 0x08]);
+    assert_eq!(&bytes[12..20], &[1, 1, 2, 0, 0, 0, 0, 1]);
+    assert_eq!(RouterAdvert::from_bytes(&bytes).unwrap(), advert);
+    assert_eq!(
+        advert.prefixes().collect::<Vec<_>>(),
+        vec![&PrefixInfo::new(ip("2001:db8:1::"), 64)]
+    );
+
+    // Zero length and cut options fail, unknown sizes stay raw
+    assert!(RouterAdvert::from_bytes(&[64, 0, 7, 8, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0]).is_err());
+    assert!(RouterAdvert::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    let mut odd = bytes[..12].to_vec();
+    odd.extend_from_slice(&[5, 2, 0, 0, 0, 0, 5, 0xdc, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(
+        RouterAdvert::from_bytes(&odd).unwrap().options,
+        vec![NdpOption::Raw {
+            kind: 5,
+            data: vec![0, 0, 0, 0, 5, 0xdc, 0, 0, 0, 0, 0, 0, 0, 0],
+        }]
+    );
+
+    assert_eq!(multicast_mac(ALL_NODES), [0x33, 0x33, 0, 0, 0, 1]);
+    assert_eq!(link_local(HOST_MAC), ip("fe80::ff:fe00:42"));
+}
+
+#[test]
+fn router_advert_frame_parses() {
+    let mut pdu = router().advert_frame(router().advert(), multicast_mac(ALL_NODES), ALL_NODES);
+    pdu.build().unwrap();
+    assert_eq!(pdu.buffer[20], ip_proto::ICMPV6);
+    assert_eq!(pdu.buffer[21], 255);
+    assert_eq!(pdu.buffer[54], icmpv6_type::ROUTER_ADVERT);
+    assert!(checksum_valid(&pdu.buffer, ip_proto::ICMPV6));
+
+    let parsed = Pdu::parse(&pdu.buffer);
+    match parsed.headers.get(&4) {
+        Some(Proto::Icmpv6(icmp)) => assert_eq!(icmp.icmp_type, icmpv6_type::ROUTER_ADVERT),
+        _ => panic!("ICMPv6 header not parsed"),
+    }
+    match parsed.headers.get(&7) {
+        Some(Proto::RouterAdvert(advert)) => assert_eq!(advert, &router().advert()),
+        _ => panic!("Router Advertisement not parsed"),
+    }
+
+    // Other ICMPv6 messages keep their body raw
+    let mut echo = Pdu::new()
+        .header(IPv6Hdr::from("fe80::1", "fe80::2", 0).unwrap())
+        .header(Icmpv6Hdr::new(icmpv6_type::ECHO_REQUEST))
+        .header(Raw::from(&[0, 1, 0, 1][..]));
+    echo.build().unwrap();
+    match Pdu::parse_ip(&echo.buffer).headers.get(&7) {
+        Some(Proto::Raw(raw)) => assert_eq!(raw.data, vec![0, 1, 0, 1]),
+        _ => panic!("Echo body not kept raw"),
+    }
+}
+
+fn solicit_frame(src: [u8; 16]) -> Pdu {
+    let mut rs = RouterSolicit::new();
+    if src != [0; 16] {
+        rs.options.push(NdpOption::SourceLinkAddr(HOST_MAC));
+    }
+    let mut ipv6 = IPv6Hdr::new();
+    ipv6.src_ip_addr = src;
+    ipv6.dst_ip_addr = ALL_ROUTERS;
+    Pdu::new()
+        .header(EthHdr::from_raw(
+            HOST_MAC,
+            multicast_mac(ALL_ROUTERS),
+            eth_type::IPv6 as u16,
+        ))
+        .header(ipv6)
+        .header(Icmpv6Hdr::new(icmpv6_type::ROUTER_SOLICIT))
+        .header(rs)
+}
+
+#[test]
+fn router_answers_solicitations() {
+    let router = router();
+    let mut rs = solicit_frame(link_local(HOST_MAC));
+    rs.build().unwrap();
+    assert!(checksum_valid(&rs.buffer, ip_proto::ICMPV6));
+    let mut reply = router.reply(Pdu::parse(&rs.buffer)).unwrap();
+    reply.build().unwrap();
+    assert_eq!(&reply.buffer[..6], &HOST_MAC);
+    assert_eq!(&reply.buffer[38..54], &link_local(HOST_MAC));
+
+    let mut rs = solicit_frame([0; 16]);
+    rs.build().unwrap();
+    let mut reply = router.reply(Pdu::parse(&rs.buffer)).unwrap();
+    reply.build().unwrap();
+    assert_eq!(&reply.buffer[..6], &multicast_mac(ALL_NODES));
+    assert_eq!(&reply.buffer[38..54], &ALL_NODES);
+
+    let withdrawal = router.withdrawal();
+    assert_eq!(withdrawal.lifetime, 0);
+    assert!(withdrawal.prefixes().all(|info| info.valid == 0));
+}
+
+fn echo(src: &str, dst: &str, icmp_type: u8, seq: u8) -> Pdu {
+    let mut pdu = Pdu::new()
+        .header(IPv6Hdr::from(src, dst, 0).unwrap())
+        .header(Icmpv6Hdr::new(icmp_type))
+        .header(Raw::from(&[0, 7, 0, seq, 0xaa, 0xbb][..]));
+    pdu.build().unwrap();
+    Pdu::parse_ip(&pdu.buffer)
+}
+
+#[test]
+fn ipv6_replies_answer_requests() {
+    let request = echo("2001:db8::1", "2001:db8::2", icmpv6_type::ECHO_REQUEST, 1);
+    let reply = |src, seq| echo(src, "2001:db8::1", icmpv6_type::ECHO_REPLY, seq);
+    assert!(reply("2001:db8::2", 1).answers(&request));
+    assert!(!reply("2001:db8::2", 2).answers(&request));
+    assert!(!reply("2001:db8::3", 1).answers(&request));
+    assert!(!echo("2001:db8::2", "2001:db8::1", icmpv6_type::ECHO_REQUEST, 1).answers(&request));
+
+    // Anyone may answer a request sent to a multicast group
+    let mut rs = solicit_frame(link_local(HOST_MAC));
+    rs.build().unwrap();
+    let mut reply = router().reply(Pdu::parse(&rs.buffer)).unwrap();
+    reply.build().unwrap();
+    assert!(Pdu::parse(&reply.buffer).answers(&Pdu::parse(&rs.buffer)));
+}
+
+fn solicit() -> Dhcpv6 {
+    let mut msg = Dhcpv6::new(dhcpv6_msg::SOLICIT, 0x00ab_cdef);
+    msg.options.extend(vec![
+        Dhcpv6Option::ClientId(duid_ll(HOST_MAC)),
+        Dhcpv6Option::ElapsedTime(0),
+        Dhcpv6Option::Oro(vec![dhcpv6_opt::DNS_SERVERS, dhcpv6_opt::DOMAIN_LIST]),
+        Dhcpv6Option::IaNa {
+            iaid: 1,
+            t1: 0,
+            t2: 0,
+            options: Vec::new(),
+        },
+        Dhcpv6Option::IaPd {
+            iaid: 2,
+            t1: 0,
+            t2: 0,
+            options: vec![Dhcpv6Option::IaPrefix {
+                preferred: 0,
+                valid: 0,
+                prefix_len: 56,
+                prefix: [0; 16],
+                options: Vec::new(),
+            }],
+        },
+    ]);
+    msg
+}
+
+#[test]
+fn dhcpv6_round_trip() {
+    let mut msg = solicit();
+    msg.options.extend(vec![
+        Dhcpv6Option::RapidCommit,
+        Dhcpv6Option::DnsServers(vec![ip("2001:db8::53")]),
+        Dhcpv6Option::DomainList(vec!["lab.example".to_string(), "test".to_string()]),
+        Dhcpv6Option::StatusCode {
+            code: dhcpv6_status::SUCCESS,
+            message: "ok".to_string(),
+        },
+        Dhcpv6Option::Preference(255),
+        Dhcpv6Option::Raw {
+            code: 39,
+            data: b"\x00host".to_vec(),
+        },
+    ]);
+    let bytes = msg.to_bytes().unwrap();
+    assert_eq!(&bytes[..4], &[dhcpv6_msg::SOLICIT, 0xab, 0xcd, 0xef]);
+    assert_eq!(Dhcpv6::from_bytes(&bytes).unwrap(), msg);
+
+    // Options of wrong size stay raw, cut options and relay messages fail
+    let odd = [1, 0, 0, 1, 0, 8, 0, 1, 0];
+    assert_eq!(
+        Dhcpv6::from_bytes(&odd).unwrap().options,
+        vec![Dhcpv6Option::Raw {
+            code: dhcpv6_opt::ELAPSED_TIME,
+            data: vec![0],
+        }]
+    );
+    assert!(Dhcpv6::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Dhcpv6::from_bytes(&[12, 0, 0, 0]).is_err());
+
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(
+            HOST_MAC,
+            multicast_mac(ALL_DHCP_SERVERS),
+            eth_type::IPv6 as u16,
+        ))
+        .header(IPv6Hdr::from("fe80::ff:fe00:42", "ff02::1:2", 0).unwrap())
+        .header(UdpHdr::from(dhcpv6_port::CLIENT, dhcpv6_port::SERVER))
+        .header(msg.clone());
+    pdu.build().unwrap();
+    assert!(checksum_valid(&pdu.buffer, ip_proto::UDP));
+    match Pdu::parse(&pdu.buffer).headers.get(&7) {
+        Some(Proto::Dhcpv6(parsed)) => assert_eq!(parsed, &msg),
+        _ => panic!("DHCPv6 not parsed"),
+    }
+}
+
+fn server() -> Dhcpv6Server {
+    let mut opts = Dhcpv6Opts::new(ROUTER_MAC, ip("2001:db8:1::100"), ip("2001:db8:1::101"));
+    opts.pd_pool = Some(PdPool {
+        prefix: ip("2001:db8:ff00::"),
+        prefix_len: 48,
+        delegated_len: 56,
+    });
+    opts.dns_servers.push(ip("2001:db8:1::53"));
+    opts.domains.push("lab.example".to_string());
+    opts.rapid_commit = false;
+    Dhcpv6Server::new(opts)
+}
+
+fn ia_addr(ia: Option<&Dhcpv6Option>) -> Option<[u8; 16]> {
+    ia?.options().iter().find_map(|option| match option {
+        Dhcpv6Option::IaAddr { addr, .. } => Some(*addr),
+        Dhcpv6Option::IaPrefix { prefix, .. } => Some(*prefix),
+        _ => None,
+    })
+}
+
+#[test]
+fn dhcpv6_server_binds() {
+    let server = server();
+    let advertise = server.handle(&solicit()).unwrap();
+    assert_eq!(advertise.msg_type, dhcpv6_msg::ADVERTISE);
+    assert_eq!(advertise.xid, 0x00ab_cdef);
+    assert_eq!(advertise.client_id(), Some(&duid_ll(HOST_MAC)[..]));
+    assert_eq!(advertise.server_id(), Some(&duid_ll(ROUTER_MAC)[..]));
+    assert_eq!(
+        ia_addr(advertise.option(dhcpv6_opt::IA_NA)),
+        Some(ip("2001:db8:1::100"))
+    );
+    assert_eq!(
+        ia_addr(advertise.option(dhcpv6_opt::IA_PD)),
+        Some(ip("2001:db8:ff00::"))
+    );
+    assert_eq!(
+        advertise.option(dhcpv6_opt::DNS_SERVERS),
+        Some(&Dhcpv6Option::DnsServers(vec![ip("2001:db8:1::53")]))
+    );
+    assert!(server.bindings().is_empty());
+
+    // Request must name this server
+    let mut request = solicit();
+    request.msg_type = dhcpv6_msg::REQUEST;
+    assert!(server.handle(&request).is_none());
+    request
+        .options
+        .push(Dhcpv6Option::ServerId(duid_ll(ROUTER_MAC)));
+    let reply = server.handle(&request).unwrap();
+    assert_eq!(reply.msg_type, dhcpv6_msg::REPLY);
+    let bindings = server.bindings();
+    assert_eq!(bindings.len(), 2);
+    assert_eq!(bindings[0].addr, ip("2001:db8:1::100"));
+    assert_eq!(bindings[1].prefix_len, Some(56));
+
+    // Second client gets next address and prefix, a third one none
+    let mut other = solicit();
+    other.options[0] = Dhcpv6Option::ClientId(duid_ll([2, 0, 0, 0, 0, 0x43]));
+    let advertise = server.handle(&other).unwrap();
+    assert_eq!(
+        ia_addr(advertise.option(dhcpv6_opt::IA_NA)),
+        Some(ip("2001:db8:1::101"))
+    );
+    assert_eq!(
+        ia_addr(advertise.option(dhcpv6_opt::IA_PD)),
+        Some(ip("2001:db8:ff00:100::"))
+    );
+    other.msg_type = dhcpv6_msg::REQUEST;
+    other
+        .options
+        .push(Dhcpv6Option::ServerId(duid_ll(ROUTER_MAC)));
+    server.handle(&other).unwrap();
+    let mut third = solicit();
+    third.options[0] = Dhcpv6Option::ClientId(duid_ll([2, 0, 0, 0, 0, 0x44]));
+    let advertise = server.handle(&third).unwrap();
+    assert_eq!(
+        advertise
+            .option(dhcpv6_opt::IA_NA)
+            .and_then(|ia| ia.status()),
+        Some(dhcpv6_status::NO_ADDRS_AVAIL)
+    );
+
+    // Release frees the address for the third client
+    let mut release = Dhcpv6::new(dhcpv6_msg::RELEASE, 7);
+    release.options.extend(vec![
+        Dhcpv6Option::ClientId(duid_ll(HOST_MAC)),
+        Dhcpv6Option::ServerId(duid_ll(ROUTER_MAC)),
+        reply.option(dhcpv6_opt::IA_NA).unwrap().clone(),
+    ]);
+    let released = server.handle(&release).unwrap();
+    assert!(released.options.contains(&Dhcpv6Option::StatusCode {
+        code: dhcpv6_status::SUCCESS,
+        message: String::new(),
+    }));
+    let advertise = server.handle(&third).unwrap();
+    assert_eq!(
+        ia_addr(advertise.option(dhcpv6_opt::IA_NA)),
+        Some(ip("2001:db8:1::100"))
+    );
+
+    // Decline keeps the address out of the pool
+    let mut decline = release.clone();
+    decline.msg_type = dhcpv6_msg::DECLINE;
+    decline.options[0] = Dhcpv6Option::ClientId(duid_ll([2, 0, 0, 0, 0, 0x43]));
+    decline.options[2] = server
+        .handle(&other)
+        .unwrap()
+        .option(dhcpv6_opt::IA_NA)
+        .unwrap()
+        .clone();
+    server.handle(&decline).unwrap();
+    assert!(server
+        .bindings()
+        .iter()
+        .any(|b| b.addr == ip("2001:db8:1::101") && b.state == BindingState::Declined));
+
+    // Information-Request gets configuration only
+    let mut info = Dhcpv6::new(dhcpv6_msg::INFORMATION_REQUEST, 9);
+    info.options
+        .push(Dhcpv6Option::Oro(vec![dhcpv6_opt::DNS_SERVERS]));
+    let reply = server.handle(&info).unwrap();
+    assert!(reply.option(dhcpv6_opt::IA_NA).is_none());
+    assert_eq!(
+        reply.option(dhcpv6_opt::DOMAIN_LIST),
+        Some(&Dhcpv6Option::DomainList(vec!["lab.example".to_string()]))
+    );
+
+    // Confirm checks addresses are on link
+    let mut confirm = Dhcpv6::new(dhcpv6_msg::CONFIRM, 10);
+    confirm.options.extend(vec![
+        Dhcpv6Option::ClientId(duid_ll(HOST_MAC)),
+        Dhcpv6Option::IaNa {
+            iaid: 1,
+            t1: 0,
+            t2: 0,
+            options: vec![Dhcpv6Option::IaAddr {
+                addr: ip("2001:db8:2::1"),
+                preferred: 0,
+                valid: 0,
+                options: Vec::new(),
+            }],
+        },
+    ]);
+    let reply = server.handle(&confirm).unwrap();
+    assert!(reply.options.contains(&Dhcpv6Option::StatusCode {
+        code: dhcpv6_status::NOT_ON_LINK,
+        message: "Not on link".to_string(),
+    }));
+}
+
+fn dhcpv6_frame(msg: Dhcpv6) -> Vec<u8> {
+    let mut ipv6 = IPv6Hdr::new();
+    ipv6.src_ip_addr = link_local(HOST_MAC);
+    ipv6.dst_ip_addr = ALL_DHCP_SERVERS;
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(
+            HOST_MAC,
+            multicast_mac(ALL_DHCP_SERVERS),
+            eth_type::IPv6 as u16,
+        ))
+        .header(ipv6)
+        .header(UdpHdr::from(dhcpv6_port::CLIENT, dhcpv6_port::SERVER))
+        .header(msg);
+    pdu.build().unwrap();
+    pdu.buffer
+}
+
+#[test]
+fn router_runs_on_loopback() {
+    let (mut host, mut peer) = LoopbackIo::pair();
+    let mut router = router();
+    router.opts.managed = true;
+    let mut opts = server().opts;
+    opts.rapid_commit = true;
+    router.dhcp = Some(Dhcpv6Server::new(opts));
+    let dhcp = router.dhcp.clone().unwrap();
+    let handle = thread::spawn(move || router.run(&mut peer, Some(2)).unwrap());
+
+    let parse = |frame: Vec<u8>| Pdu::parse(&frame);
+    let first = parse(host.recv().unwrap());
+    match first.headers.get(&7) {
+        Some(Proto::RouterAdvert(advert)) => {
+            assert!(advert.managed);
+            assert_eq!(advert.lifetime, 1800);
+        }
+        _ => panic!("No Router Advertisement"),
+    }
+
+    let mut rs = solicit_frame(link_local(HOST_MAC));
+    rs.build().unwrap();
+    host.send(&rs.buffer).unwrap();
+    let answer = parse(host.recv().unwrap());
+    match answer.headers.get(&2) {
+        Some(Proto::Eth(eth)) => assert_eq!(eth.dst_hw_addr, HOST_MAC),
+        _ => panic!("No Ethernet header"),
+    }
+
+    let mut msg = solicit();
+    msg.options.push(Dhcpv6Option::RapidCommit);
+    host.send(&dhcpv6_frame(msg)).unwrap();
+    let reply = host.recv().unwrap();
+    assert!(checksum_valid(&reply, ip_proto::UDP));
+    match parse(reply).headers.get(&7) {
+        Some(Proto::Dhcpv6(reply)) => {
+            assert_eq!(reply.msg_type, dhcpv6_msg::REPLY);
+            assert!(reply.option(dhcpv6_opt::RAPID_COMMIT).is_some());
+        }
+        _ => panic!("No DHCPv6 reply"),
+    }
+
+    let stats = handle.join().unwrap();
+    assert_eq!((stats.adverts, stats.solicited, stats.dhcp), (2, 1, 1));
+    assert_eq!(dhcp.bindings().len(), 2);
+
+    // Second periodic advertisement, then the withdrawal
+    let frames: Vec<Pdu> = (0..2).map(|_| parse(host.recv().unwrap())).collect();
+    match frames[1].headers.get(&7) {
+        Some(Proto::RouterAdvert(advert)) => assert_eq!(advert.lifetime, 0),
+        _ => panic!("No withdrawal"),
+    }
+}
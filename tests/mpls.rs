@@ -0,0 +1,184 @@
+use pakit::hdr::{
+    eth_type, mpls_label, Dns, DnsQuestion, EthHdr, IPv4Hdr, IPv6Hdr, Mpls, MplsLabel, MplsQuery,
+    Raw, UdpHdr,
+};
+use pakit::proto::Proto;
+use pakit::{Pdu, QueryHdr, Rules};
+
+const PE_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const P_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+const CE_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x42];
+
+fn dns_query() -> Dns {
+    let mut dns = Dns::new();
+    dns.id = 0x4242;
+    dns.questions.push(DnsQuestion::new("pe.lab", 1));
+    dns
+}
+
+fn labelled(mpls: Mpls, inner: Pdu) -> Pdu {
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(
+            PE_MAC,
+            P_MAC,
+            eth_type::MPLS_UNICAST as u16,
+        ))
+        .header(mpls);
+    pdu.inner = Some(Box::new(inner));
+    pdu.build().unwrap();
+    pdu
+}
+
+fn parsed_mpls(pdu: &Pdu) -> &Mpls {
+    match pdu.headers.get(&3) {
+        Some(Proto::Mpls(mpls)) => mpls,
+        _ => panic!("MPLS not parsed"),
+    }
+}
+
+#[test]
+fn label_stack_round_trip() {
+    let mut top = MplsLabel::new(16001);
+    top.tc = 5;
+    top.ttl = 255;
+    let mpls = Mpls::new(vec![top, MplsLabel::new(24), MplsLabel::new(0xf_ffff)]);
+    let bytes = mpls.to_bytes();
+    assert_eq!(bytes.len(), mpls.length());
+    assert_eq!(&bytes[..4], &[0x03, 0xe8, 0x1a, 0xff]);
+    // Only bottom label has S bit set
+    assert_eq!(bytes[6] & 0x01, 0);
+    assert_eq!(&bytes[8..], &[0xff, 0xff, 0xf1, 64]);
+    assert_eq!(Mpls::from_bytes(&bytes).unwrap(), mpls);
+
+    // Stack without bottom
+    assert!(Mpls::from_bytes(&bytes[..8]).is_err());
+
+    let pw = Mpls::pseudowire(100, 200);
+    let bytes = pw.to_bytes();
+    assert_eq!(pw.length(), 12);
+    assert_eq!(&bytes[8..], &[0, 0, 0, 0]);
+}
+
+#[test]
+fn guesses_ip_payload() {
+    let inner = Pdu::new()
+        .header(IPv4Hdr::from("10.1.0.1", "10.2.0.1", 0).unwrap())
+        .header(UdpHdr::from(40000, 53))
+        .header(dns_query());
+    let pdu = labelled(Mpls::new(vec![MplsLabel::new(300)]), inner);
+    assert_eq!(&pdu.buffer[12..14], &[0x88, 0x47]);
+    assert_eq!(pdu.buffer[18], 0x45);
+
+    let parsed = Pdu::parse(&pdu.buffer);
+    assert_eq!(parsed_mpls(&parsed).labels[0].label, 300);
+    assert_eq!(parsed_mpls(&parsed).control_word, None);
+    let inner = parsed.inner.as_ref().unwrap();
+    assert!(matches!(inner.headers.get(&3), Some(Proto::IPv4(_))));
+    match inner.headers.get(&7) {
+        Some(Proto::Dns(dns)) => assert_eq!(dns.questions[0].name, "pe.lab"),
+        _ => panic!("Inner DNS not parsed"),
+    }
+
+    // IPv6 under explicit null and a VPN label
+    let inner = Pdu::new()
+        .header(IPv6Hdr::from("2001:db8::1", "2001:db8::2", 0).unwrap())
+        .header(UdpHdr::from(40000, 53))
+        .header(dns_query());
+    let mpls = Mpls::new(vec![
+        MplsLabel::new(mpls_label::IPV6_EXPLICIT_NULL),
+        MplsLabel::new(17),
+    ]);
+    let parsed = Pdu::parse(&labelled(mpls, inner).buffer);
+    assert_eq!(parsed_mpls(&parsed).labels.len(), 2);
+    let inner = parsed.inner.unwrap();
+    assert!(matches!(inner.headers.get(&3), Some(Proto::IPv6(_))));
+    assert!(matches!(inner.headers.get(&7), Some(Proto::Dns(_))));
+}
+
+#[test]
+fn guesses_pseudowire_ethernet() {
+    let frame = Pdu::new()
+        .header(EthHdr::from_raw(CE_MAC, PE_MAC, eth_type::IPv4 as u16))
+        .header(IPv4Hdr::from("192.168.0.2", "192.168.0.1", 0).unwrap())
+        .header(UdpHdr::from(40000, 53))
+        .header(dns_query());
+    let pdu = labelled(Mpls::pseudowire(16001, 1000), frame);
+
+    let parsed = Pdu::parse(&pdu.buffer);
+    let mpls = parsed_mpls(&parsed);
+    assert_eq!(mpls.labels[1].label, 1000);
+    assert_eq!(mpls.control_word, Some(0));
+    let inner = parsed.inner.as_ref().unwrap();
+    match inner.headers.get(&2) {
+        Some(Proto::Eth(eth)) => assert_eq!(eth.src_hw_addr, CE_MAC),
+        _ => panic!("Pseudowire Ethernet not parsed"),
+    }
+    assert!(matches!(inner.headers.get(&7), Some(Proto::Dns(_))));
+
+    // Payload too short for any guess stays raw
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(
+            PE_MAC,
+            P_MAC,
+            eth_type::MPLS_MULTICAST as u16,
+        ))
+        .header(Mpls::new(vec![MplsLabel::new(5000)]))
+        .header(Raw::from(&[0x12, 0x34][..]));
+    pdu.build().unwrap();
+    let parsed = Pdu::parse(&pdu.buffer);
+    assert!(parsed.inner.is_none());
+    assert!(matches!(parsed.headers.get(&7), Some(Proto::Raw(_))));
+}
+
+#[test]
+fn query_matches_labels() {
+    let inner = Pdu::new()
+        .header(IPv4Hdr::from("10.1.0.1", "10.2.0.1", 0).unwrap())
+        .header(UdpHdr::from(40000, 53))
+        .header(dns_query());
+    let mpls = Mpls::new(vec![MplsLabel::new(16001), MplsLabel::new(24)]);
+    let parsed = Pdu::parse(&labelled(mpls, inner).buffer);
+
+    assert!(MplsQuery::new() == parsed);
+    assert!(MplsQuery::from(24) == parsed);
+    assert!(MplsQuery::from(25) != parsed);
+    let mut query = MplsQuery::from(24);
+    query.top = Some(16001);
+    query.depth = Some(2);
+    assert!(query == parsed);
+    query.bottom = Some(16001);
+    assert!(query != parsed);
+
+    let mut rules = Rules::new();
+    rules.add_rule(QueryHdr::Mpls(MplsQuery::from(24)), |pdu| Some(pdu));
+    assert!(rules.find(&parsed).is_some());
+    assert!(rules.find(&Pdu::new()).is_none());
+}
+
+#[test]
+fn nesting_is_capped() {
+    // Thousands of Ethernet pseudowires nested in one another
+    let mut frame = vec![0; 14];
+    for _ in 0..3000 {
+        let mut layer = vec![0; 12];
+        layer.extend_from_slice(&(eth_type::MPLS_UNICAST as u16).to_be_bytes());
+        layer.extend_from_slice(&((16001 << 12) | 64u32).to_be_bytes());
+        layer.extend_from_slice(&((1000 << 12) | 0x100 | 64u32).to_be_bytes());
+        layer.extend_from_slice(&[0; 4]);
+        layer.extend_from_slice(&frame);
+        frame = layer;
+    }
+
+    let mut pdu = Pdu::parse(&frame);
+    let mut depth = 0;
+    while let Some(inner) = pdu.inner {
+        pdu = *inner;
+        depth += 1;
+    }
+    assert_eq!(depth, 8);
+    assert_eq!(parsed_mpls(&pdu).control_word, Some(0));
+    match pdu.headers.get(&7) {
+        Some(Proto::Raw(raw)) => assert_eq!(raw.data.len(), 14 + 26 * (3000 - 9)),
+        _ => panic!("Deepest payload not kept raw"),
+    }
+}
@@ -0,0 +1,246 @@
+use pakit::flow::{
+    Flow, FlowRecord, FlowTable, NetFlowCollector, NetFlowExporter, NetFlowVersion, TEMPLATE_IPV4,
+    TEMPLATE_IPV6,
+};
+use pakit::hdr::{
+    ie, netflow_port, tcp_flags, EthHdr, FieldSpec, FlowSet, IPv4Hdr, IPv6Hdr, Ipfix, NetFlowV9,
+    Raw, TcpHdr, Template, UdpHdr, VARIABLE_LEN,
+};
+use pakit::io::{LoopbackIo, PacketIo};
+use pakit::proto::Proto;
+use pakit::Pdu;
+use std::net::IpAddr;
+use std::time::Duration;
+
+const EXPORTER: [u8; 4] = [10, 0, 0, 100];
+
+/// Flows of one TCP connection over IPv4 and one UDP packet over IPv6
+fn flows() -> Vec<Flow> {
+    let mut table = FlowTable::new();
+    let mut flows = Vec::new();
+    let frames: Vec<(Vec<u8>, u64)> = vec![
+        (
+            tcp_frame("10.0.0.1", "10.0.0.2", 4000, 80, tcp_flags::SYN),
+            1000,
+        ),
+        (
+            tcp_frame("10.0.0.2", "10.0.0.1", 80, 4000, tcp_flags::RST),
+            1002,
+        ),
+        (udp6_frame(), 1001),
+    ];
+    for (frame, secs) in frames {
+        table.process_frame(&frame, Duration::from_secs(secs), |f| flows.push(f));
+    }
+    table.finish(|f| flows.push(f));
+    flows
+}
+
+fn tcp_frame(src: &str, dst: &str, src_port: u16, dst_port: u16, flags: u16) -> Vec<u8> {
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw([0xaa; 6], [0xbb; 6], 0x0800))
+        .header(IPv4Hdr::from(src, dst, 0).unwrap())
+        .header(TcpHdr::from(src_port, dst_port, flags));
+    pdu.build().unwrap();
+    pdu.buffer
+}
+
+fn udp6_frame() -> Vec<u8> {
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw([0xaa; 6], [0xbb; 6], 0x86dd))
+        .header(IPv6Hdr::from("fe80::1", "fe80::2", 0).unwrap())
+        .header(UdpHdr::from(5353, 5353))
+        .header(Raw::from(b"mdns"));
+    pdu.build().unwrap();
+    pdu.buffer
+}
+
+/// Puts export held by `pdu` in IPv4 and UDP, builds and parses it
+fn carry(pdu: Pdu, port: u16) -> Pdu {
+    let mut ipv4 = IPv4Hdr::new();
+    ipv4.src_ip_addr = EXPORTER;
+    ipv4.dst_ip_addr = [10, 0, 0, 1];
+    let mut pdu = pdu.header(ipv4).header(UdpHdr::from(50000, port));
+    pdu.build().unwrap();
+    Pdu::parse_ip(&pdu.buffer)
+}
+
+fn exporter() -> IpAddr {
+    EXPORTER.into()
+}
+
+#[test]
+fn export_and_collect() {
+    let flows = flows();
+    assert_eq!(flows.len(), 2);
+    let mut collector = NetFlowCollector::new();
+
+    let mut v5 = NetFlowExporter::new(NetFlowVersion::V5);
+    v5.boot = Duration::from_secs(900);
+    let pdus = v5.export(&flows, Duration::from_secs(1010));
+    assert_eq!(pdus.len(), 1);
+    let pdu = carry(pdus.into_iter().next().unwrap(), netflow_port::NETFLOW);
+    let netflow = match pdu.headers.get(&7) {
+        Some(Proto::NetFlowV5(netflow)) => netflow,
+        _ => panic!("NetFlow v5 not parsed"),
+    };
+    // IPv6 flow doesn't fit, TCP flow makes a record per direction
+    assert_eq!(netflow.records.len(), 2);
+    assert_eq!(netflow.sys_uptime, 110_000);
+    assert_eq!(netflow.records[0].first, 100_000);
+    assert_eq!(netflow.records[1].src_port, 80);
+    let records = collector.process(&pdu);
+    assert_eq!(records[1].uint(ie::TCP_FLAGS), Some(tcp_flags::RST as u64));
+    assert_eq!(records[0].get(ie::IPV4_SRC_ADDR), Some(&[10, 0, 0, 1][..]));
+
+    for version in [NetFlowVersion::V9, NetFlowVersion::Ipfix].iter() {
+        let mut netflow = NetFlowExporter::new(*version);
+        netflow.max_records = 2;
+        netflow.template_refresh = 2;
+        netflow.domain = 7;
+        let pdus = netflow.export(&flows, Duration::from_secs(1010));
+        assert_eq!(pdus.len(), 2);
+        let mut records: Vec<FlowRecord> = Vec::new();
+        // Second message comes first and waits for templates
+        for pdu in pdus.into_iter().rev() {
+            records.extend(collector.process(&carry(pdu, netflow_port::IPFIX)));
+        }
+        assert_eq!(collector.missing, 1);
+        collector.missing = 0;
+        assert_eq!(records.len(), 2);
+        assert!(records
+            .iter()
+            .all(|record| record.template_id == TEMPLATE_IPV4 && record.domain == 7));
+        assert_eq!(records[0].uint(ie::L4_DST_PORT), Some(80));
+        assert_eq!(records[0].uint(ie::IN_BYTES), Some(40));
+        let version_id = match version {
+            NetFlowVersion::V9 => 9,
+            _ => 10,
+        };
+        let template = collector.template(exporter(), version_id, 7, TEMPLATE_IPV6);
+        assert_eq!(template, Some(&netflow.templates()[1]));
+        if *version == NetFlowVersion::Ipfix {
+            assert_eq!(records[1].uint(ie::FLOW_END_MILLISECONDS), Some(1_002_000));
+        }
+
+        // Third message carries templates again
+        let pdus = netflow.export(&[], Duration::from_secs(1020));
+        let sets = match carry(pdus.into_iter().next().unwrap(), 2055)
+            .headers
+            .get(&7)
+        {
+            Some(Proto::NetFlowV9(netflow)) => netflow.sets.clone(),
+            Some(Proto::Ipfix(ipfix)) => ipfix.sets.clone(),
+            _ => panic!("export not parsed"),
+        };
+        assert!(matches!(sets.as_slice(), [FlowSet::Template(_)]));
+    }
+}
+
+#[test]
+fn ipfix_edge_cases() {
+    let template = Template::new(
+        300,
+        vec![
+            FieldSpec::new(ie::IPV4_SRC_ADDR, 4),
+            // Unknown element and reverse octet count of RFC 5103
+            FieldSpec::new(32000, 3),
+            FieldSpec::enterprise(ie::IN_BYTES, 8, 29305),
+            FieldSpec::new(82, VARIABLE_LEN),
+        ],
+    );
+    let long = vec![b'x'; 300];
+    let mut data = template
+        .encode(&[&[192, 0, 2, 1], b"abc", &9u64.to_be_bytes(), b"eth0"])
+        .unwrap();
+    data.extend(
+        template
+            .encode(&[&[192, 0, 2, 2], b"def", &1u64.to_be_bytes(), &long])
+            .unwrap(),
+    );
+    // Padding
+    data.extend_from_slice(&[0, 0]);
+    assert!(template.encode(&[&[1, 2, 3], b"", b"", b""]).is_err());
+
+    let mut options = Template::new(400, vec![FieldSpec::new(149, 4), FieldSpec::new(41, 8)]);
+    options.scope_fields = 1;
+    let mut ipfix = Ipfix::new();
+    ipfix.domain_id = 3;
+    ipfix.sets = vec![
+        FlowSet::Template(vec![template.clone()]),
+        FlowSet::OptionsTemplate(vec![options.clone()]),
+        FlowSet::Data { id: 300, data },
+    ];
+    let bytes = ipfix.to_bytes();
+    assert_eq!(
+        u16::from_be_bytes([bytes[2], bytes[3]]) as usize,
+        bytes.len()
+    );
+    let parsed = Ipfix::from_bytes(&bytes).unwrap();
+    assert_eq!(parsed.sets, ipfix.sets);
+
+    // Sent to a port not known for IPFIX, so only the collector decodes it
+    let pdu = carry(Pdu::new().header(ipfix), 6000);
+    assert!(matches!(pdu.headers.get(&7), Some(Proto::Raw(_))));
+    let mut collector = NetFlowCollector::new();
+    let records = collector.process(&pdu);
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].get(32000), Some(&b"abc"[..]));
+    assert_eq!(records[0].get(ie::IN_BYTES), None);
+    assert_eq!(
+        records[0].get_enterprise(29305, ie::IN_BYTES),
+        Some(&9u64.to_be_bytes()[..])
+    );
+    assert_eq!(records[0].get(82), Some(&b"eth0"[..]));
+    assert_eq!(records[1].get(82), Some(&long[..]));
+    assert_eq!(collector.template(exporter(), 10, 3, 400), Some(&options));
+
+    // Withdrawal of one template, then of every options template
+    let mut withdraw = Ipfix::new();
+    withdraw.domain_id = 3;
+    withdraw.sets = vec![
+        FlowSet::Template(vec![Template::withdrawal(300)]),
+        FlowSet::OptionsTemplate(vec![Template::withdrawal(3)]),
+    ];
+    let bytes = withdraw.to_bytes();
+    assert_eq!(bytes.len(), 16 + 8 + 8);
+    assert_eq!(Ipfix::from_bytes(&bytes).unwrap().sets, withdraw.sets);
+    collector.collect(exporter(), &bytes).unwrap();
+    assert_eq!(collector.template(exporter(), 10, 3, 300), None);
+    assert_eq!(collector.template(exporter(), 10, 3, 400), None);
+
+    // NetFlow v9 options templates give scope and option lengths in bytes
+    let mut v9 = NetFlowV9::new();
+    v9.sets = vec![FlowSet::OptionsTemplate(vec![options])];
+    let bytes = v9.to_bytes();
+    assert_eq!(&bytes[24..30], &[1, 144, 0, 4, 0, 4]);
+    assert_eq!(NetFlowV9::from_bytes(&bytes).unwrap().sets, v9.sets);
+    assert!(Ipfix::from_bytes(&bytes).is_err());
+}
+
+#[test]
+fn send_to_collector() {
+    let (mut host, mut peer) = LoopbackIo::pair();
+    let mut netflow = NetFlowExporter::new(NetFlowVersion::Ipfix);
+    netflow.src_ip = EXPORTER;
+    let sent = netflow
+        .send(
+            &mut host,
+            [10, 0, 0, 1],
+            netflow_port::IPFIX,
+            &flows(),
+            Duration::from_secs(1010),
+        )
+        .unwrap();
+    assert_eq!(sent, 1);
+
+    let pdu = Pdu::parse_ip(&peer.recv().unwrap());
+    match pdu.headers.get(&4) {
+        Some(Proto::UDP(udp)) => assert_eq!(udp.dst_port.to_usize(), 4739),
+        _ => panic!("UDP header missing"),
+    }
+    let records = NetFlowCollector::new().process(&pdu);
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[2].template_id, TEMPLATE_IPV6);
+    assert_eq!(records[2].uint(ie::L4_SRC_PORT), Some(5353));
+}
@@ -17,3 +17,25 @@ fn create_packet() {
     //println!("{:?}", packet.buffer);
     assert_eq!(1, 1);
 }
+
+#[test]
+fn build_from_ip_layer() {
+    use pakit::hdr::{ip_proto, IPv4Hdr, Raw};
+    use pakit::proto::Proto;
+    use pakit::utility::checksum;
+    use pakit::Pdu;
+
+    let mut packet = Pdu::new()
+        .header(IPv4Hdr::from("10.0.0.1", "10.0.0.2", ip_proto::ICMP).unwrap())
+        .header(Raw::from(&[8, 0, 0xf7, 0xff]));
+    packet.build().unwrap();
+    assert_eq!(packet.buffer.len(), 24);
+    assert_eq!(packet.buffer[2..4], [0, 24]);
+    assert_eq!(checksum(&packet.buffer[0..20]), 0);
+
+    let parsed = Pdu::parse_ip(&packet.buffer);
+    match parsed.headers.get(&7) {
+        Some(Proto::Raw(raw)) => assert_eq!(raw.data, vec![8, 0, 0xf7, 0xff]),
+        _ => panic!("Expected payload"),
+    }
+}
@@ -1,10 +1,25 @@
 #[test]
 #[cfg(feature = "pcap")]
 fn save_to_pcap_test() {
-    use pakit::Channel;
-    Channel::from("lo")
-        .unwrap()
-        .capture_to_pcap("./test.pcap", Some(5))
-        .unwrap();
-    assert!(std::path::Path::new("./test.pcap").exists());
+    use pakit::io::{LoopbackIo, PacketIo, PcapIo};
+
+    let path = std::env::temp_dir().join("pakit_save_test.pcap");
+    let path = path.to_str().unwrap();
+    let frames: Vec<Vec<u8>> = (0..5u8).map(|i| vec![i; 60]).collect();
+    let (mut host, mut capture) = LoopbackIo::pair();
+    for frame in &frames {
+        host.send(frame).unwrap();
+    }
+    {
+        let mut sink = PcapIo::create(path).unwrap();
+        for _ in 0..frames.len() {
+            sink.send(&capture.recv().unwrap()).unwrap();
+        }
+    }
+
+    let mut source = PcapIo::open(path).unwrap();
+    for frame in &frames {
+        assert_eq!(&source.recv().unwrap(), frame);
+    }
+    assert!(source.recv().is_err());
 }
@@ -0,0 +1,266 @@
+use pakit::arp::ArpResolver;
+use pakit::hdr::{icmp_type, ArpHdr, EthHdr, IPv4Hdr, IcmpHdr};
+use pakit::io::{LoopbackIo, PacketIo};
+use pakit::ping::{ping, ping_eth, ping_sweep, ping_sweep_eth, PingOpts, RttStats};
+use pakit::proto::Proto;
+use pakit::Pdu;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PEER_MAC: [u8; 6] = [0xbb; 6];
+
+/// Builds echo reply to `request`, `None` if it is not an echo request
+fn echo_reply(request: &Pdu) -> Option<Pdu> {
+    let (ipv4, icmp) = match (request.headers.get(&3), request.headers.get(&4)) {
+        (Some(Proto::IPv4(ipv4)), Some(Proto::ICMP(icmp))) => (ipv4, icmp),
+        _ => return None,
+    };
+    if icmp.icmp_type.to_usize() != icmp_type::ECHO_REQUEST as usize {
+        return None;
+    }
+    let mut reply_ip = IPv4Hdr::new();
+    reply_ip.src_ip_addr = ipv4.dst_ip_addr;
+    reply_ip.dst_ip_addr = ipv4.src_ip_addr;
+    let mut reply_icmp =
+        IcmpHdr::echo_request(icmp.id.to_usize() as u16, icmp.seq.to_usize() as u16);
+    reply_icmp.icmp_type = pakit::dstructs::Bits::from(icmp_type::ECHO_REPLY.into(), 8);
+    let mut reply = Pdu::new().header(reply_ip).header(reply_icmp);
+    if let Some(Proto::Raw(raw)) = request.headers.get(&7) {
+        reply.set_header(raw.clone());
+    }
+    Some(reply)
+}
+
+/// Answers echo requests at IP layer for hosts `.1` and `.3`, dropping seq 1
+fn l3_responder(mut io: LoopbackIo) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while let Ok(packet) = io.recv() {
+            let request = Pdu::parse_ip(&packet);
+            let dst = match request.headers.get(&3) {
+                Some(Proto::IPv4(ipv4)) => ipv4.dst_ip_addr,
+                _ => continue,
+            };
+            let seq = match request.headers.get(&4) {
+                Some(Proto::ICMP(icmp)) => icmp.seq.to_usize(),
+                _ => continue,
+            };
+            if (dst[3] != 1 && dst[3] != 3) || (dst[3] == 1 && seq == 1) {
+                continue;
+            }
+            if let Some(mut reply) = echo_reply(&request) {
+                reply.build().unwrap();
+                reply.send_on(&mut io).unwrap();
+            }
+        }
+    })
+}
+
+fn opts() -> PingOpts {
+    let mut opts = PingOpts::new();
+    opts.count = 3;
+    opts.interval = Duration::from_millis(5);
+    opts.timeout = Duration::from_millis(50);
+    opts
+}
+
+#[test]
+fn echo_build_parse() {
+    let mut pdu = Pdu::new()
+        .header(IPv4Hdr::from("10.0.0.1", "10.0.0.2", 0).unwrap())
+        .header(IcmpHdr::echo_request(0x1234, 7));
+    pdu.build().unwrap();
+    assert_eq!(pdu.buffer.len(), 28);
+    // Protocol is filled in from ICMP layer and both checksums are valid
+    assert_eq!(pdu.buffer[9], 1);
+    assert_eq!(pakit::utility::checksum(&pdu.buffer[0..20]), 0);
+    assert_eq!(pakit::utility::checksum(&pdu.buffer[20..]), 0);
+
+    let parsed = Pdu::parse_ip(&pdu.buffer);
+    match parsed.headers.get(&4) {
+        Some(Proto::ICMP(icmp)) => {
+            assert_eq!(icmp.id.to_usize(), 0x1234);
+            assert_eq!(icmp.seq.to_usize(), 7);
+        }
+        _ => panic!("ICMP header missing"),
+    }
+    let reply = echo_reply(&parsed).unwrap();
+    let mut other = echo_reply(&parsed).unwrap();
+    other.set_header(IcmpHdr::echo_request(0x1234, 8));
+    assert!(reply.answers(&parsed));
+    assert!(!other.answers(&parsed));
+}
+
+#[test]
+fn ping_with_loss() {
+    let (mut host, peer) = LoopbackIo::pair();
+    let handle = l3_responder(peer);
+
+    let stats = ping(&mut host, "10.0.0.1", &opts()).unwrap();
+    assert_eq!(stats.transmitted, 3);
+    assert_eq!(stats.received, 2);
+    assert!(stats.probes[1].rtt.is_none());
+    assert_eq!(stats.probes[2].bytes, 64);
+    assert_eq!(stats.probes[0].ttl, Some(64));
+    assert!((stats.loss() - 100.0 / 3.0).abs() < 0.01);
+    assert!(stats
+        .to_string()
+        .starts_with("3 packets transmitted, 2 received, 33% packet loss\nrtt"));
+
+    let stats = ping(&mut host, "10.0.0.2", &opts()).unwrap();
+    assert_eq!(stats.received, 0);
+    assert!(stats.rtt.is_none());
+
+    let alive = ping_sweep(&mut host, "10.0.0.0/29", &opts()).unwrap();
+    let alive: Vec<[u8; 4]> = alive.iter().map(|host| host.ip).collect();
+    assert_eq!(alive, vec![[10, 0, 0, 1], [10, 0, 0, 3]]);
+
+    drop(host);
+    handle.join().unwrap();
+}
+
+#[test]
+fn ping_past_seq_wrap() {
+    let (mut host, mut peer) = LoopbackIo::pair();
+    // Only answers probes sent after sequence numbers wrapped
+    let handle = thread::spawn(move || {
+        let mut received = 0;
+        while let Ok(packet) = peer.recv() {
+            received += 1;
+            if received <= 1 << 16 {
+                continue;
+            }
+            if let Some(mut reply) = echo_reply(&Pdu::parse_ip(&packet)) {
+                reply.build().unwrap();
+                reply.send_on(&mut peer).unwrap();
+            }
+        }
+    });
+
+    let mut opts = opts();
+    opts.count = (1 << 16) + 2;
+    opts.interval = Duration::from_secs(0);
+    let stats = ping(&mut host, "10.0.0.1", &opts).unwrap();
+    assert_eq!(stats.received, 2);
+    assert!(stats.probes[1 << 16].rtt.is_some());
+    assert!(stats.probes[(1 << 16) + 1].rtt.is_some());
+
+    drop(host);
+    handle.join().unwrap();
+}
+
+#[test]
+fn ping_over_ethernet() {
+    let (mut host, mut peer) = LoopbackIo::pair();
+    let handle = thread::spawn(move || {
+        while let Ok(frame) = peer.recv() {
+            let request = Pdu::parse(&frame);
+            let src_mac = match request.headers.get(&2) {
+                Some(Proto::Eth(eth)) => eth.src_hw_addr,
+                _ => continue,
+            };
+            let mut reply = match request.headers.get(&3) {
+                Some(Proto::Arp(arp)) => {
+                    let mut reply = ArpHdr::new();
+                    reply.set_arp_reply();
+                    reply.src_hw_addr = PEER_MAC;
+                    reply.src_proto_addr = arp.dst_proto_addr;
+                    reply.dst_hw_addr = arp.src_hw_addr;
+                    reply.dst_proto_addr = arp.src_proto_addr;
+                    Pdu::new()
+                        .header(EthHdr::from_raw(PEER_MAC, src_mac, 0x0806))
+                        .header(reply)
+                }
+                _ => match echo_reply(&request) {
+                    Some(reply) => reply.header(EthHdr::from_raw(PEER_MAC, src_mac, 0x0800)),
+                    None => continue,
+                },
+            };
+            reply.build().unwrap();
+            reply.send_on(&mut peer).unwrap();
+        }
+    });
+
+    let mut resolver = ArpResolver::new([0xaa; 6], [10, 0, 0, 100]);
+    let stats = ping_eth(&mut host, &mut resolver, "10.0.0.1", &opts()).unwrap();
+    assert_eq!(stats.received, 3);
+    assert_eq!(resolver.cached([10, 0, 0, 1]), Some(PEER_MAC));
+
+    drop(host);
+    handle.join().unwrap();
+}
+
+#[test]
+fn sweep_not_paced_by_interval() {
+    let (mut host, peer) = LoopbackIo::pair();
+    let handle = l3_responder(peer);
+
+    // Interval only spaces requests to the same host
+    let mut opts = opts();
+    opts.interval = Duration::from_secs(2);
+    opts.rate = None;
+    let time_start = Instant::now();
+    let alive = ping_sweep(&mut host, "10.0.0.0/29", &opts).unwrap();
+    assert!(time_start.elapsed() < opts.interval);
+    assert_eq!(alive.len(), 2);
+
+    drop(host);
+    handle.join().unwrap();
+}
+
+#[test]
+fn sweep_eth_resolves_at_once() {
+    let (mut host, mut peer) = LoopbackIo::pair();
+    // Only `.1` is on the link
+    let handle = thread::spawn(move || {
+        while let Ok(frame) = peer.recv() {
+            let request = Pdu::parse(&frame);
+            let mut reply = match request.headers.get(&3) {
+                Some(Proto::Arp(arp)) if arp.dst_proto_addr == [10, 0, 0, 1] => {
+                    let mut reply = ArpHdr::new();
+                    reply.set_arp_reply();
+                    reply.src_hw_addr = PEER_MAC;
+                    reply.src_proto_addr = arp.dst_proto_addr;
+                    reply.dst_hw_addr = arp.src_hw_addr;
+                    reply.dst_proto_addr = arp.src_proto_addr;
+                    Pdu::new()
+                        .header(EthHdr::from_raw(PEER_MAC, arp.src_hw_addr, 0x0806))
+                        .header(reply)
+                }
+                Some(Proto::Arp(_)) => continue,
+                _ => match echo_reply(&request) {
+                    Some(reply) => reply.header(EthHdr::from_raw(PEER_MAC, [0xaa; 6], 0x0800)),
+                    None => continue,
+                },
+            };
+            reply.build().unwrap();
+            reply.send_on(&mut peer).unwrap();
+        }
+    });
+
+    let mut resolver = ArpResolver::new([0xaa; 6], [10, 0, 0, 100]);
+    resolver.timeout = Duration::from_millis(200);
+    resolver.retries = 1;
+    let mut opts = opts();
+    opts.count = 1;
+    let time_start = Instant::now();
+    let alive = ping_sweep_eth(&mut host, &mut resolver, "10.0.0.0/29", &opts).unwrap();
+    // Five silent hosts resolved one after another would take a second
+    assert!(time_start.elapsed() < Duration::from_millis(800));
+    let alive: Vec<[u8; 4]> = alive.iter().map(|host| host.ip).collect();
+    assert_eq!(alive, vec![[10, 0, 0, 1]]);
+    assert_eq!(resolver.cached([10, 0, 0, 1]), Some(PEER_MAC));
+
+    drop(host);
+    handle.join().unwrap();
+}
+
+#[test]
+fn rtt_summary() {
+    let ms = Duration::from_millis;
+    let rtt = RttStats::from(&[ms(1), ms(2), ms(3)]).unwrap();
+    assert_eq!(rtt.min, ms(1));
+    assert_eq!(rtt.max, ms(3));
+    assert!((rtt.avg.as_secs_f64() - 0.002).abs() < 1e-9);
+    assert!((rtt.mdev.as_secs_f64() - 0.000_816_5).abs() < 1e-6);
+    assert!(RttStats::from(&[]).is_none());
+}
@@ -0,0 +1,51 @@
+#[cfg(feature = "pcap")]
+fn udp_frame() -> Vec<u8> {
+    let mut frame = vec![
+        0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0x08, 0x00,
+    ];
+    let mut ip = vec![
+        0x45, 0x00, 0x00, 0x20, 0x12, 0x34, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 10, 0, 0, 1, 10, 0,
+        0, 2,
+    ];
+    let sum = pakit::utility::checksum(&ip);
+    ip[10..12].copy_from_slice(&sum.to_be_bytes());
+    frame.append(&mut ip);
+    frame.extend_from_slice(&[0x30, 0x39, 0x00, 0x35, 0x00, 0x0c, 0x12, 0x34, 1, 2, 3, 4]);
+    frame
+}
+
+#[test]
+#[cfg(feature = "pcap")]
+fn rewrite_udp_frame() {
+    use pakit::replay::RewriteMap;
+    use pakit::utility::checksum;
+
+    let mut map = RewriteMap::new();
+    map.add_mac("aa:aa:aa:aa:aa:aa", "cc:cc:cc:cc:cc:cc")
+        .unwrap();
+    map.add_ip("10.0.0.1", "192.168.1.1").unwrap();
+
+    let mut frame = udp_frame();
+    assert!(map.rewrite(&mut frame).unwrap());
+
+    assert_eq!(&frame[6..12], &[0xcc; 6]);
+    assert_eq!(&frame[26..30], &[192, 168, 1, 1]);
+    assert_eq!(checksum(&frame[14..34]), 0);
+
+    let mut pseudo = vec![192, 168, 1, 1, 10, 0, 0, 2, 0, 0x11, 0, 12];
+    pseudo.extend_from_slice(&frame[34..]);
+    assert_eq!(checksum(&pseudo), 0);
+}
+
+#[test]
+#[cfg(feature = "pcap")]
+fn rewrite_untouched_frame() {
+    use pakit::replay::RewriteMap;
+
+    let mut map = RewriteMap::new();
+    map.add_ip("10.9.9.9", "10.8.8.8").unwrap();
+
+    let mut frame = udp_frame();
+    assert!(!map.rewrite(&mut frame).unwrap());
+    assert_eq!(frame, udp_frame());
+}
@@ -0,0 +1,487 @@
+use pakit::dstructs::Bits;
+use pakit::hdr::{dns_type, icmp_type, tcp_flags, Dns, EthHdr, IPv4Hdr, IcmpHdr, Raw, TcpHdr};
+use pakit::io::{LoopbackIo, PacketIo};
+use pakit::proto::Proto;
+use pakit::tcp::{
+    parse_ports, syn_scan, PortState, Side, StreamHandler, StreamKey, StreamReassembler,
+    SynScanOpts, TcpConn, TcpOpts, TcpState,
+};
+use pakit::{PaError, Pdu};
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+fn reply_ip(probe: &IPv4Hdr) -> IPv4Hdr {
+    let mut ipv4 = IPv4Hdr::new();
+    ipv4.src_ip_addr = probe.dst_ip_addr;
+    ipv4.dst_ip_addr = probe.src_ip_addr;
+    ipv4
+}
+
+/// Port 22 is open, 80 closed, 443 answers ICMP unreachable and 25 opens
+/// only for second SYN. Returns RST segments received, checking each carries
+/// the sequence number acknowledged.
+fn port_responder(mut io: LoopbackIo) -> thread::JoinHandle<Vec<TcpHdr>> {
+    thread::spawn(move || {
+        let mut resets = Vec::new();
+        let mut syns_25 = 0;
+        let mut seqs = HashMap::new();
+        while let Ok(packet) = io.recv() {
+            let probe = Pdu::parse_ip(&packet);
+            let (ipv4, syn) = match (probe.headers.get(&3), probe.headers.get(&4)) {
+                (Some(Proto::IPv4(ipv4)), Some(Proto::TCP(tcp))) => (ipv4, tcp),
+                _ => continue,
+            };
+            if syn.has_flags(tcp_flags::RST) {
+                let seq = seqs[&syn.src_port.to_usize()];
+                assert_eq!(syn.seq_num.to_usize(), (seq + 1) & 0xffff_ffff);
+                resets.push(syn.clone());
+                continue;
+            }
+            seqs.insert(syn.src_port.to_usize(), syn.seq_num.to_usize());
+            let port = syn.dst_port.to_usize() as u16;
+            let flags = match port {
+                22 => tcp_flags::SYN | tcp_flags::ACK,
+                80 => tcp_flags::RST | tcp_flags::ACK,
+                25 => {
+                    syns_25 += 1;
+                    match syns_25 {
+                        1 => continue,
+                        _ => tcp_flags::SYN | tcp_flags::ACK,
+                    }
+                }
+                443 => {
+                    let mut icmp = IcmpHdr::new();
+                    icmp.icmp_type = Bits::from(icmp_type::DEST_UNREACHABLE.into(), 8);
+                    icmp.code = Bits::from(13, 8);
+                    let mut ipv4 = reply_ip(ipv4);
+                    ipv4.src_ip_addr = [10, 0, 0, 1];
+                    let mut reply = Pdu::new()
+                        .header(ipv4)
+                        .header(icmp)
+                        .header(Raw::from(&packet[..28]));
+                    reply.build().unwrap();
+                    reply.send_on(&mut io).unwrap();
+                    continue;
+                }
+                _ => continue,
+            };
+            let mut answer = TcpHdr::from(port, syn.src_port.to_usize() as u16, flags);
+            answer.ack_num = Bits::from((syn.seq_num.to_usize() + 1) & 0xffff_ffff, 32);
+            answer.seq_num = Bits::from(1000, 32);
+            let mut reply = Pdu::new().header(reply_ip(ipv4)).header(answer);
+            reply.build().unwrap();
+            reply.send_on(&mut io).unwrap();
+        }
+        resets
+    })
+}
+
+#[test]
+fn port_lists() {
+    assert_eq!(
+        parse_ports("80, 20-23,22").unwrap(),
+        vec![20, 21, 22, 23, 80]
+    );
+    assert!(parse_ports("10-5").is_err());
+    assert!(parse_ports("http").is_err());
+    assert!(parse_ports("70000").is_err());
+}
+
+#[test]
+fn syn_scan_states() {
+    let (mut host, peer) = LoopbackIo::pair();
+    let handle = port_responder(peer);
+
+    let mut opts = SynScanOpts::new();
+    opts.rate = None;
+    opts.timeout = Duration::from_millis(50);
+    opts.src_ip = [10, 0, 0, 100];
+    let results = syn_scan(&mut host, "10.0.0.9", &[443, 22, 80, 25, 8080], &opts).unwrap();
+    let states: Vec<(u16, PortState)> = results.iter().map(|r| (r.port, r.state)).collect();
+    assert_eq!(
+        states,
+        vec![
+            (22, PortState::Open),
+            (25, PortState::Open),
+            (80, PortState::Closed),
+            (443, PortState::Filtered),
+            (8080, PortState::Filtered),
+        ]
+    );
+    assert!(results[3].rtt.is_some());
+    assert!(results[4].rtt.is_none());
+    assert_eq!(PortState::Filtered.to_string(), "filtered");
+
+    drop(host);
+    let resets = handle.join().unwrap();
+    // Both open ports are torn down
+    let mut reset_ports: Vec<usize> = resets.iter().map(|rst| rst.dst_port.to_usize()).collect();
+    reset_ports.sort_unstable();
+    assert_eq!(reset_ports, vec![22, 25]);
+}
+
+#[test]
+fn syn_scan_resets_late_answers() {
+    let (mut host, mut peer) = LoopbackIo::pair();
+    // Answers first SYN only together with the second, after its round ended
+    let handle = thread::spawn(move || {
+        let mut syns = Vec::new();
+        let mut resets = 0;
+        while let Ok(packet) = peer.recv() {
+            let probe = Pdu::parse_ip(&packet);
+            let (ipv4, tcp) = match (probe.headers.get(&3), probe.headers.get(&4)) {
+                (Some(Proto::IPv4(ipv4)), Some(Proto::TCP(tcp))) => (ipv4.clone(), tcp.clone()),
+                _ => continue,
+            };
+            if tcp.has_flags(tcp_flags::RST) {
+                resets += 1;
+                continue;
+            }
+            syns.push((ipv4, tcp));
+            if syns.len() < 2 {
+                continue;
+            }
+            for (ipv4, syn) in syns.drain(..) {
+                let flags = tcp_flags::SYN | tcp_flags::ACK;
+                let mut answer = TcpHdr::from(7, syn.src_port.to_usize() as u16, flags);
+                answer.ack_num = Bits::from((syn.seq_num.to_usize() + 1) & 0xffff_ffff, 32);
+                let mut reply = Pdu::new().header(reply_ip(&ipv4)).header(answer);
+                reply.build().unwrap();
+                reply.send_on(&mut peer).unwrap();
+            }
+        }
+        resets
+    });
+
+    let mut opts = SynScanOpts::new();
+    opts.rate = None;
+    opts.timeout = Duration::from_millis(50);
+    opts.src_ip = [10, 0, 0, 100];
+    let results = syn_scan(&mut host, "10.0.0.9", &[7], &opts).unwrap();
+    assert_eq!(results[0].state, PortState::Open);
+    thread::sleep(Duration::from_millis(50));
+
+    drop(host);
+    // Half-open connections of both rounds are torn down
+    assert_eq!(handle.join().unwrap(), 2);
+}
+
+#[test]
+fn syn_scan_more_ports_than_sources() {
+    let (mut host, mut peer) = LoopbackIo::pair();
+    // Filtering host drops everything
+    let handle = thread::spawn(move || while peer.recv().is_ok() {});
+
+    let mut opts = SynScanOpts::new();
+    opts.rate = None;
+    opts.retries = 0;
+    opts.timeout = Duration::from_millis(10);
+    opts.src_ip = [10, 0, 0, 100];
+    let ports: Vec<u16> = (1..=30000).collect();
+    let results = syn_scan(&mut host, "10.0.0.9", &ports, &opts).unwrap();
+    assert_eq!(results.len(), ports.len());
+    assert!(results.iter().all(|r| r.state == PortState::Filtered));
+
+    drop(host);
+    handle.join().unwrap();
+}
+
+fn conn_opts(ip: [u8; 4]) -> TcpOpts {
+    let mut opts = TcpOpts::new();
+    opts.src_ip = ip;
+    opts.mss = 1000;
+    opts.rto = Duration::from_millis(50);
+    opts.timeout = Duration::from_secs(5);
+    opts
+}
+
+/// Accepts one connection on port 8080 and reads until peer closes or
+/// resets, echoing data back once `echo` bytes were read
+fn server(
+    mut io: LoopbackIo,
+    window: u16,
+    echo: usize,
+) -> thread::JoinHandle<(Vec<u8>, Option<PaError>)> {
+    thread::spawn(move || {
+        let mut opts = conn_opts([10, 0, 0, 2]);
+        opts.window = window;
+        let mut conn = TcpConn::listen(8080, opts).unwrap();
+        conn.accept(&mut io).unwrap();
+        // Let window fill up before reading
+        thread::sleep(Duration::from_millis(100));
+        let mut received = Vec::new();
+        loop {
+            match conn.recv(&mut io, Duration::from_secs(5)) {
+                Ok(data) if data.is_empty() => break,
+                Ok(data) => {
+                    received.extend_from_slice(&data);
+                    if received.len() == echo {
+                        conn.send(&mut io, &received).unwrap();
+                    }
+                }
+                Err(err) => return (received, Some(err)),
+            }
+        }
+        conn.close(&mut io).unwrap();
+        assert_eq!(conn.state(), TcpState::Closed);
+        (received, None)
+    })
+}
+
+#[test]
+fn conn_exchange_and_teardown() {
+    let (mut host, peer) = LoopbackIo::pair();
+    let handle = server(peer, 2000, 5000);
+
+    let data: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+    let mut client = TcpConn::client("10.0.0.2", 8080, conn_opts([10, 0, 0, 1])).unwrap();
+    let mut dropped = false;
+    // Lose first data segment once, it has to be retransmitted
+    client.set_hook(move |pdu: &mut Pdu| {
+        if dropped || !pdu.headers.contains_key(&7) {
+            return true;
+        }
+        dropped = true;
+        false
+    });
+    client.connect(&mut host).unwrap();
+    assert_eq!(client.state(), TcpState::Established);
+    assert_eq!(client.peer_window(), 2000);
+
+    client.send(&mut host, &data).unwrap();
+    assert_eq!(client.in_flight(), 2000);
+    client.poll(&mut host, Duration::from_millis(20)).unwrap();
+    assert!(client.in_flight() <= 2000);
+    client.flush(&mut host).unwrap();
+
+    let mut echoed = Vec::new();
+    while echoed.len() < data.len() {
+        echoed.extend(client.recv(&mut host, Duration::from_secs(5)).unwrap());
+    }
+    assert_eq!(echoed, data);
+    client.close(&mut host).unwrap();
+    assert_eq!(client.state(), TcpState::TimeWait);
+
+    let (received, err) = handle.join().unwrap();
+    assert_eq!(received, data);
+    assert!(err.is_none());
+}
+
+#[test]
+fn conn_misbehaving_segments() {
+    let (mut host, peer) = LoopbackIo::pair();
+    let handle = server(peer, 65535, 0);
+
+    let mut client = TcpConn::client("10.0.0.2", 8080, conn_opts([10, 0, 0, 1])).unwrap();
+    client.connect(&mut host).unwrap();
+
+    // Bad checksum is dropped by peer, retransmission gets through
+    let mut corrupted = false;
+    client.set_hook(move |pdu: &mut Pdu| {
+        if let (false, Some(Proto::TCP(tcp))) = (corrupted, pdu.headers.get_mut(&4)) {
+            if tcp.has_flags(tcp_flags::PSH) {
+                tcp.checksum = Bits::from(0xdead, 16);
+                corrupted = true;
+            }
+        }
+        true
+    });
+    client.send(&mut host, b"hello").unwrap();
+    client.flush(&mut host).unwrap();
+    client.clear_hook();
+
+    // Overlapping bytes keep data received first
+    let seq = client.snd_nxt();
+    let overlap = client.segment(
+        tcp_flags::PSH | tcp_flags::ACK,
+        seq.wrapping_sub(3),
+        b"XXXworld",
+    );
+    client.inject(&mut host, overlap).unwrap();
+    // RST outside of window is ignored, one at next sequence resets peer
+    let stray = client.segment(tcp_flags::RST, seq.wrapping_add(1 << 20), &[]);
+    client.inject(&mut host, stray).unwrap();
+    let rst = client.segment(tcp_flags::RST, seq.wrapping_add(5), &[]);
+    client.inject(&mut host, rst).unwrap();
+
+    drop(host);
+    let (received, err) = handle.join().unwrap();
+    assert_eq!(received, b"helloworld");
+    assert_eq!(err.unwrap().msg, "Connection reset by peer");
+}
+
+/// Records stream events as text
+#[derive(Default)]
+struct Recorder {
+    events: Vec<String>,
+}
+
+impl StreamHandler for Recorder {
+    fn on_open(&mut self, key: &StreamKey, _ts: Duration) {
+        self.events
+            .push(format!("open {} > {}", key.client, key.server));
+    }
+
+    fn on_data(&mut self, _key: &StreamKey, side: Side, data: &[u8], _ts: Duration) {
+        let text = String::from_utf8_lossy(data);
+        self.events.push(format!("{:?}: {}", side, text));
+    }
+
+    fn on_gap(&mut self, _key: &StreamKey, side: Side, len: u32, _ts: Duration) {
+        self.events.push(format!("{:?} gap {}", side, len));
+    }
+
+    fn on_close(&mut self, _key: &StreamKey, _ts: Duration) {
+        self.events.push("close".to_string());
+    }
+
+    fn on_reset(&mut self, _key: &StreamKey, side: Side, _ts: Duration) {
+        self.events.push(format!("{:?} reset", side));
+    }
+
+    fn on_timeout(&mut self, _key: &StreamKey, ts: Duration) {
+        self.events.push(format!("timeout {}", ts.as_secs()));
+    }
+}
+
+/// Segment from 10.0.0.1:40000 to 10.0.0.2:80 or back if `!from_client`
+fn segment(from_client: bool, flags: u16, seq: u32, data: &[u8]) -> Pdu {
+    let (src, dst, sport, dport) = if from_client {
+        ("10.0.0.1", "10.0.0.2", 40000, 80)
+    } else {
+        ("10.0.0.2", "10.0.0.1", 80, 40000)
+    };
+    let mut tcp = TcpHdr::from(sport, dport, flags);
+    tcp.seq_num = Bits::from(seq as usize, 32);
+    let pdu = Pdu::new()
+        .header(IPv4Hdr::from(src, dst, 6).unwrap())
+        .header(tcp);
+    if data.is_empty() {
+        pdu
+    } else {
+        pdu.header(Raw::from(data))
+    }
+}
+
+#[test]
+fn stream_reassembly() {
+    use tcp_flags::{ACK, FIN, PSH, RST, SYN};
+    let mut reassembler = StreamReassembler::new();
+    let mut recorder = Recorder::default();
+    let ts = Duration::from_secs;
+
+    // Client sequence numbers wrap around during the exchange
+    let isn: u32 = 0xffff_fff8;
+    let client =
+        |flags, offset: u32, data: &[u8]| segment(true, flags, isn.wrapping_add(offset), data);
+    let server = |flags, seq, data: &[u8]| segment(false, flags, seq, data);
+    let packets = vec![
+        client(SYN, 0, b""),
+        server(SYN | ACK, 5000, b""),
+        client(ACK, 1, b""),
+        client(PSH | ACK, 7, b"world"),
+        client(PSH | ACK, 1, b"hello "),
+        // Retransmission and overlap keep data seen first
+        client(PSH | ACK, 1, b"hello "),
+        client(PSH | ACK, 10, b"XXthere"),
+        server(PSH | ACK, 5001, b"hi"),
+        client(FIN | ACK, 17, b""),
+        server(FIN | ACK, 5003, b""),
+        client(ACK, 18, b""),
+    ];
+    for pdu in packets.iter() {
+        reassembler.process(pdu, ts(1), &mut recorder);
+    }
+    assert_eq!(
+        recorder.events,
+        vec![
+            "open 10.0.0.1:40000 > 10.0.0.2:80",
+            "Client: hello ",
+            "Client: world",
+            "Client: there",
+            "Server: hi",
+            "close",
+        ]
+    );
+    assert_eq!(reassembler.streams(), 0);
+
+    // Capture starting mid-stream, with a segment missing before RST
+    recorder.events.clear();
+    reassembler.process(&server(PSH | ACK, 1000, b"abc"), ts(2), &mut recorder);
+    reassembler.process(&server(PSH | ACK, 1010, b"xyz"), ts(2), &mut recorder);
+    reassembler.process(&client(RST, 50, b""), ts(3), &mut recorder);
+    assert_eq!(
+        recorder.events,
+        vec![
+            "open 10.0.0.2:80 > 10.0.0.1:40000",
+            "Client: abc",
+            "Client gap 7",
+            "Client: xyz",
+            "Server reset",
+        ]
+    );
+
+    // Gap skipped once too much waits behind it, then idle connection times out
+    recorder.events.clear();
+    reassembler.max_buffer = 4;
+    reassembler.process(&client(PSH | ACK, 1, b"ab"), ts(10), &mut recorder);
+    reassembler.process(&client(PSH | ACK, 5, b"efghij"), ts(10), &mut recorder);
+    reassembler.process(&server(PSH | ACK, 7, b"late"), ts(200), &mut recorder);
+    assert_eq!(
+        recorder.events,
+        vec![
+            "open 10.0.0.1:40000 > 10.0.0.2:80",
+            "Client: ab",
+            "Client gap 2",
+            "Client: efghij",
+            "timeout 200",
+            "open 10.0.0.2:80 > 10.0.0.1:40000",
+            "Client: late",
+        ]
+    );
+    reassembler.finish(&mut recorder);
+    assert_eq!(recorder.events.last().unwrap(), "timeout 320");
+}
+
+#[test]
+fn dns_port_payload() {
+    // Segment holding one whole message is parsed as DNS, data still flows
+    let dns = Dns::query(7, "www.example.com", dns_type::A);
+    let mut tcp = TcpHdr::from(40000, 53, tcp_flags::PSH | tcp_flags::ACK);
+    tcp.seq_num = Bits::from(1000, 32);
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw([0xaa; 6], [0xbb; 6], 0x0800))
+        .header(IPv4Hdr::from("10.0.0.1", "10.0.0.2", 6).unwrap())
+        .header(tcp)
+        .header(dns.clone());
+    pdu.build().unwrap();
+    let parsed = Pdu::parse(&pdu.buffer);
+    assert!(matches!(parsed.headers.get(&7), Some(Proto::Dns(_))));
+    let mut msg = dns.to_bytes().unwrap();
+    msg.splice(0..0, (msg.len() as u16).to_be_bytes());
+    assert_eq!(parsed.payload(), &msg[..]);
+
+    let mut reassembler = StreamReassembler::new();
+    let mut recorder = Recorder::default();
+    reassembler.process(&parsed, Duration::from_secs(1), &mut recorder);
+    assert_eq!(
+        recorder.events,
+        vec![
+            "open 10.0.0.1:40000 > 10.0.0.2:53".to_string(),
+            format!("Client: {}", String::from_utf8_lossy(&msg)),
+        ]
+    );
+
+    let (mut host, mut peer) = LoopbackIo::pair();
+    let handle = thread::spawn(move || {
+        let mut conn = TcpConn::listen(53, conn_opts([10, 0, 0, 2])).unwrap();
+        conn.accept(&mut peer).unwrap();
+        conn.recv(&mut peer, Duration::from_secs(5)).unwrap()
+    });
+    let mut client = TcpConn::client("10.0.0.2", 53, conn_opts([10, 0, 0, 1])).unwrap();
+    client.connect(&mut host).unwrap();
+    client.send(&mut host, &msg).unwrap();
+    client.flush(&mut host).unwrap();
+    assert_eq!(handle.join().unwrap(), msg);
+}
@@ -0,0 +1,190 @@
+use pakit::dstructs::Bits;
+use pakit::hdr::{icmp_type, tcp_flags, IPv4Hdr, IcmpHdr, Raw, TcpHdr, UdpHdr};
+use pakit::io::{LoopbackIo, PacketIo};
+use pakit::proto::Proto;
+use pakit::traceroute::{traceroute, TraceOpts, TraceProto};
+use pakit::utility::{checksum, pseudo_checksum};
+use pakit::Pdu;
+use std::thread;
+use std::time::Duration;
+
+const TARGET: [u8; 4] = [10, 0, 9, 9];
+
+fn icmp_reply(src: [u8; 4], dst: [u8; 4], icmp: IcmpHdr, quote: &[u8]) -> Pdu {
+    let mut ipv4 = IPv4Hdr::new();
+    ipv4.src_ip_addr = src;
+    ipv4.dst_ip_addr = dst;
+    Pdu::new()
+        .header(ipv4)
+        .header(icmp)
+        .header(Raw::from(quote))
+}
+
+/// Simulates path of two routers before `TARGET`, second router drops its
+/// second probe. Returns every probe received.
+fn path_responder(mut io: LoopbackIo) -> thread::JoinHandle<Vec<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut probes = Vec::new();
+        let mut hop2_probes = 0;
+        while let Ok(packet) = io.recv() {
+            probes.push(packet.clone());
+            let probe = Pdu::parse_ip(&packet);
+            let ipv4 = match probe.headers.get(&3) {
+                Some(Proto::IPv4(ipv4)) => ipv4.clone(),
+                _ => continue,
+            };
+            let ttl = ipv4.ttl.to_usize() as u8;
+            let quote = &packet[..28];
+            let mut reply = if ttl < 3 {
+                if ttl == 2 {
+                    hop2_probes += 1;
+                    if hop2_probes == 2 {
+                        continue;
+                    }
+                }
+                let mut icmp = IcmpHdr::new();
+                icmp.icmp_type = Bits::from(icmp_type::TIME_EXCEEDED.into(), 8);
+                icmp_reply([10, 0, ttl, 1], ipv4.src_ip_addr, icmp, quote)
+            } else {
+                match probe.headers.get(&4) {
+                    Some(Proto::UDP(_)) => {
+                        let mut icmp = IcmpHdr::new();
+                        icmp.icmp_type = Bits::from(icmp_type::DEST_UNREACHABLE.into(), 8);
+                        icmp.code = Bits::from(3, 8);
+                        icmp_reply(TARGET, ipv4.src_ip_addr, icmp, quote)
+                    }
+                    Some(Proto::ICMP(req)) => {
+                        let mut icmp = IcmpHdr::echo_request(
+                            req.id.to_usize() as u16,
+                            req.seq.to_usize() as u16,
+                        );
+                        icmp.icmp_type = Bits::from(icmp_type::ECHO_REPLY.into(), 8);
+                        icmp_reply(TARGET, ipv4.src_ip_addr, icmp, &packet[28..])
+                    }
+                    Some(Proto::TCP(syn)) => {
+                        let mut ack = TcpHdr::from(
+                            syn.dst_port.to_usize() as u16,
+                            syn.src_port.to_usize() as u16,
+                            tcp_flags::SYN | tcp_flags::ACK,
+                        );
+                        ack.ack_num = Bits::from(syn.seq_num.to_usize() + 1, 32);
+                        let mut reply_ip = IPv4Hdr::new();
+                        reply_ip.src_ip_addr = TARGET;
+                        reply_ip.dst_ip_addr = ipv4.src_ip_addr;
+                        Pdu::new().header(reply_ip).header(ack)
+                    }
+                    _ => continue,
+                }
+            };
+            reply.build().unwrap();
+            reply.send_on(&mut io).unwrap();
+        }
+        probes
+    })
+}
+
+fn opts(proto: TraceProto) -> TraceOpts {
+    let mut opts = TraceOpts::new(proto);
+    opts.max_ttl = 5;
+    opts.timeout = Duration::from_millis(50);
+    opts.src_ip = [10, 0, 0, 100];
+    opts
+}
+
+#[test]
+fn udp_tcp_build_parse() {
+    let mut pdu = Pdu::new()
+        .header(IPv4Hdr::from("10.0.0.1", "10.0.0.2", 0).unwrap())
+        .header(UdpHdr::from(5000, 53))
+        .header(Raw::from(&[1, 2, 3]));
+    pdu.build().unwrap();
+    assert_eq!(pdu.buffer[9], 17);
+    assert_eq!(&pdu.buffer[24..26], &[0, 11]);
+    assert_eq!(
+        pseudo_checksum(&[10, 0, 0, 1], &[10, 0, 0, 2], 17, &pdu.buffer[20..]),
+        0
+    );
+    match Pdu::parse_ip(&pdu.buffer).headers.get(&4) {
+        Some(Proto::UDP(udp)) => assert_eq!(udp.dst_port.to_usize(), 53),
+        _ => panic!("UDP header missing"),
+    }
+
+    let mut syn = TcpHdr::from(40000, 443, tcp_flags::SYN);
+    syn.set_options(&[2, 4, 0x05, 0xb4, 1]).unwrap();
+    assert_eq!(syn.length(), 28);
+    let mut pdu = Pdu::new()
+        .header(IPv4Hdr::from("10.0.0.1", "10.0.0.2", 0).unwrap())
+        .header(syn.clone());
+    pdu.build().unwrap();
+    assert_eq!(pdu.buffer.len(), 48);
+    assert_eq!(pdu.buffer[9], 6);
+    assert_eq!(checksum(&pdu.buffer[..20]), 0);
+    assert_eq!(
+        pseudo_checksum(&[10, 0, 0, 1], &[10, 0, 0, 2], 6, &pdu.buffer[20..]),
+        0
+    );
+    match Pdu::parse_ip(&pdu.buffer).headers.get(&4) {
+        Some(Proto::TCP(tcp)) => {
+            assert!(tcp.has_flags(tcp_flags::SYN));
+            assert!(!tcp.has_flags(tcp_flags::ACK));
+            assert_eq!(tcp.options, syn.options);
+        }
+        _ => panic!("TCP header missing"),
+    }
+}
+
+#[test]
+fn trace_udp() {
+    let (mut host, peer) = LoopbackIo::pair();
+    let handle = path_responder(peer);
+
+    let result = traceroute(&mut host, "10.0.9.9", &opts(TraceProto::Udp)).unwrap();
+    assert!(result.reached);
+    assert_eq!(result.hops.len(), 3);
+    assert_eq!(result.hops[0].probes[0].addr, Some([10, 0, 1, 1]));
+    assert!(result.hops[1].probes[1].addr.is_none());
+    assert_eq!(result.hops[1].probes[2].addr, Some([10, 0, 2, 1]));
+    assert!(result.hops[2].reached);
+    assert!(result.to_string().contains("\n 2  10.0.2.1  "));
+    assert!(result.to_string().contains("  *  "));
+
+    drop(host);
+    let probes = handle.join().unwrap();
+    assert_eq!(probes.len(), 9);
+    for (i, probe) in probes.iter().enumerate() {
+        // Flow stays the same, probe number is carried in a valid checksum
+        assert_eq!(&probe[20..24], &probes[0][20..24]);
+        assert_eq!(&probe[26..28], &((i + 1) as u16).to_be_bytes());
+        assert_eq!(
+            pseudo_checksum(&probe[12..16], &TARGET, 17, &probe[20..]),
+            0
+        );
+    }
+}
+
+#[test]
+fn trace_icmp_and_tcp() {
+    let (mut host, peer) = LoopbackIo::pair();
+    let handle = path_responder(peer);
+
+    let result = traceroute(&mut host, "10.0.9.9", &opts(TraceProto::Icmp)).unwrap();
+    assert!(result.reached);
+    assert_eq!(result.hops.len(), 3);
+    assert!(result.hops[2]
+        .probes
+        .iter()
+        .all(|probe| probe.addr == Some(TARGET)));
+
+    let result = traceroute(&mut host, "10.0.9.9", &opts(TraceProto::Tcp)).unwrap();
+    assert!(result.reached);
+    assert_eq!(result.hops.len(), 3);
+    assert_eq!(result.hops[0].probes[2].addr, Some([10, 0, 1, 1]));
+
+    drop(host);
+    let probes = handle.join().unwrap();
+    // ICMP checksum stays constant across probes
+    for probe in probes[..9].iter() {
+        assert_eq!(&probe[22..24], &probes[0][22..24]);
+        assert_eq!(checksum(&probe[20..]), 0);
+    }
+}
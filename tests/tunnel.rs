@@ -0,0 +1,262 @@
+use pakit::hdr::{
+    eth_type, geneve_port, gre_proto, ip_proto, vxlan_port, Dns, DnsQuestion, Erspan, EthHdr,
+    Geneve, GeneveOption, Gre, IPv4Hdr, IPv6Hdr, Raw, UdpHdr, Vxlan, VXLAN_VNI_VALID,
+};
+use pakit::proto::Proto;
+use pakit::utility::checksum;
+use pakit::Pdu;
+
+const OUTER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+const VTEP_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 2];
+const VM_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x42];
+
+/// Ethernet frame of a DNS query between two VMs
+fn inner_frame() -> Pdu {
+    let mut dns = Dns::new();
+    dns.id = 0x1234;
+    dns.questions.push(DnsQuestion::new("vm.lab", 1));
+    Pdu::new()
+        .header(EthHdr::from_raw(VM_MAC, OUTER_MAC, eth_type::IPv4 as u16))
+        .header(IPv4Hdr::from("192.168.0.2", "192.168.0.1", 0).unwrap())
+        .header(UdpHdr::from(40000, 53))
+        .header(dns)
+}
+
+fn outer_udp(port: u16) -> Pdu {
+    Pdu::new()
+        .header(EthHdr::from_raw(OUTER_MAC, VTEP_MAC, eth_type::IPv4 as u16))
+        .header(IPv4Hdr::from("10.0.0.1", "10.0.0.2", 0).unwrap())
+        .header(UdpHdr::from(51000, port))
+}
+
+#[test]
+fn headers_round_trip() {
+    let mut gre = Gre::new(gre_proto::TEB);
+    gre.checksum = Some(0xbeef);
+    gre.key = Some(100);
+    gre.seq = Some(7);
+    let bytes = gre.to_bytes();
+    assert_eq!(gre.length(), 16);
+    assert_eq!(&bytes[..6], &[0xb0, 0, 0x65, 0x58, 0xbe, 0xef]);
+    assert_eq!(Gre::from_bytes(&bytes).unwrap(), gre);
+    assert!(Gre::from_bytes(&bytes[..15]).is_err());
+    // Enhanced GRE of PPTP isn't supported
+    assert!(Gre::from_bytes(&[0x30, 1, 0x88, 0x0b, 0, 0, 0, 0]).is_err());
+
+    let mut erspan = Erspan::type2(0x155);
+    erspan.vlan = 100;
+    erspan.cos = 5;
+    erspan.truncated = true;
+    erspan.index = 0xabcde;
+    let bytes = erspan.to_bytes();
+    assert_eq!(bytes, vec![0x10, 100, 0xa5, 0x55, 0, 0x0a, 0xbc, 0xde]);
+    assert_eq!(Erspan::from_bytes(&bytes).unwrap(), erspan);
+
+    let mut erspan = Erspan::type3(0x3ff);
+    erspan.timestamp = 123_456;
+    erspan.sgt = 42;
+    erspan.hw_id = 0x2a;
+    erspan.direction = true;
+    erspan.granularity = 3;
+    erspan.platform = Some([1, 2, 3, 4, 5, 6, 7, 8]);
+    let bytes = erspan.to_bytes();
+    assert_eq!(bytes.len(), erspan.length());
+    assert_eq!(erspan.length(), 20);
+    assert_eq!(Erspan::from_bytes(&bytes).unwrap(), erspan);
+    assert!(Erspan::from_bytes(&bytes[..19]).is_err());
+    assert!(Erspan::from_bytes(&[0x30, 0, 0, 0, 0, 0, 0, 0]).is_err());
+
+    let vxlan = Vxlan::new(0xabcdef);
+    let bytes = vxlan.to_bytes();
+    assert_eq!(bytes, vec![VXLAN_VNI_VALID, 0, 0, 0, 0xab, 0xcd, 0xef, 0]);
+    assert_eq!(Vxlan::from_bytes(&bytes).unwrap(), vxlan);
+
+    let mut geneve = Geneve::new(5000);
+    geneve.critical = true;
+    geneve.protocol = gre_proto::TEB;
+    geneve
+        .options
+        .push(GeneveOption::new(0x0103, 0x80, vec![1, 2, 3]));
+    geneve
+        .options
+        .push(GeneveOption::new(0xffff, 1, Vec::new()));
+    let bytes = geneve.to_bytes().unwrap();
+    assert_eq!(bytes.len(), geneve.length());
+    assert_eq!(&bytes[..4], &[3, 0x40, 0x65, 0x58]);
+    assert_eq!(&bytes[8..16], &[1, 3, 0x80, 1, 1, 2, 3, 0]);
+    let parsed = Geneve::from_bytes(&bytes).unwrap();
+    assert!(parsed.options[0].critical());
+    assert_eq!(parsed.options[0].data, vec![1, 2, 3, 0]);
+    assert_eq!(parsed.options[1], geneve.options[1]);
+    assert!(Geneve::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+    geneve.options[0].data = vec![0; 128];
+    assert!(geneve.to_bytes().is_err());
+}
+
+fn check_inner(inner: &Pdu) {
+    match inner.headers.get(&2) {
+        Some(Proto::Eth(eth)) => assert_eq!(eth.src_hw_addr, VM_MAC),
+        _ => panic!("Inner Ethernet not parsed"),
+    }
+    match inner.headers.get(&7) {
+        Some(Proto::Dns(dns)) => assert_eq!(dns.questions[0].name, "vm.lab"),
+        _ => panic!("Inner DNS not parsed"),
+    }
+}
+
+#[test]
+fn vxlan_nests_inner_frame() {
+    let mut pdu = outer_udp(vxlan_port::VXLAN).header(Vxlan::new(100));
+    pdu.inner = Some(Box::new(inner_frame()));
+    pdu.build().unwrap();
+    let mut inner = inner_frame();
+    inner.build().unwrap();
+    assert_eq!(&pdu.buffer[50..], &inner.buffer[..]);
+
+    let parsed = Pdu::parse(&pdu.buffer);
+    match parsed.headers.get(&7) {
+        Some(Proto::Vxlan(vxlan)) => assert_eq!(vxlan.vni, 100),
+        _ => panic!("VXLAN not parsed"),
+    }
+    check_inner(parsed.inner.as_ref().unwrap());
+
+    // Cut VXLAN header stays raw
+    let mut cut = outer_udp(vxlan_port::VXLAN).header(Raw::from(&[8, 0, 0][..]));
+    cut.build().unwrap();
+    let parsed = Pdu::parse(&cut.buffer);
+    assert!(parsed.inner.is_none());
+    assert!(matches!(parsed.headers.get(&7), Some(Proto::Raw(_))));
+}
+
+#[test]
+fn geneve_fills_protocol() {
+    let mut geneve = Geneve::new(7);
+    geneve
+        .options
+        .push(GeneveOption::new(0x0102, 3, vec![0xaa; 4]));
+    let mut pdu = outer_udp(geneve_port::GENEVE).header(geneve);
+    pdu.inner = Some(Box::new(inner_frame()));
+    pdu.build().unwrap();
+
+    let parsed = Pdu::parse(&pdu.buffer);
+    match parsed.headers.get(&7) {
+        Some(Proto::Geneve(geneve)) => {
+            assert_eq!(geneve.protocol, gre_proto::TEB);
+            assert_eq!(geneve.options[0].data, vec![0xaa; 4]);
+        }
+        _ => panic!("Geneve not parsed"),
+    }
+    check_inner(parsed.inner.as_ref().unwrap());
+
+    // Inner IP packet without Ethernet
+    let mut inner = inner_frame();
+    inner.headers.remove(&2);
+    let mut pdu = outer_udp(geneve_port::GENEVE).header(Geneve::new(7));
+    pdu.inner = Some(Box::new(inner));
+    pdu.build().unwrap();
+    let parsed = Pdu::parse(&pdu.buffer);
+    match parsed.headers.get(&7) {
+        Some(Proto::Geneve(geneve)) => assert_eq!(geneve.protocol, eth_type::IPv4 as u16),
+        _ => panic!("Geneve not parsed"),
+    }
+    let inner = parsed.inner.unwrap();
+    assert!(inner.headers.get(&2).is_none());
+    assert!(matches!(inner.headers.get(&7), Some(Proto::Dns(_))));
+}
+
+#[test]
+fn gre_carries_ip_and_erspan() {
+    // IPv4 in GRE over IPv6 with checksum and key
+    let mut gre = Gre::new(0);
+    gre.checksum = Some(0);
+    gre.key = Some(42);
+    let mut inner = inner_frame();
+    inner.headers.remove(&2);
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(OUTER_MAC, VTEP_MAC, eth_type::IPv6 as u16))
+        .header(IPv6Hdr::from("2001:db8::1", "2001:db8::2", 0).unwrap())
+        .header(gre);
+    pdu.inner = Some(Box::new(inner));
+    pdu.build().unwrap();
+    assert_eq!(pdu.buffer[20], ip_proto::GRE);
+    assert_eq!(checksum(&pdu.buffer[54..]), 0);
+
+    let parsed = Pdu::parse(&pdu.buffer);
+    match parsed.headers.get(&4) {
+        Some(Proto::Gre(gre)) => {
+            assert_eq!(gre.protocol, eth_type::IPv4 as u16);
+            assert_eq!(gre.key, Some(42));
+        }
+        _ => panic!("GRE not parsed"),
+    }
+    assert!(parsed.headers.get(&7).is_none());
+    let inner = parsed.inner.unwrap();
+    assert!(matches!(inner.headers.get(&3), Some(Proto::IPv4(_))));
+    assert!(matches!(inner.headers.get(&7), Some(Proto::Dns(_))));
+
+    // Mirrored frame in ERSPAN type III
+    let mut gre = Gre::new(0);
+    gre.seq = Some(1);
+    let mut pdu = Pdu::new()
+        .header(EthHdr::from_raw(OUTER_MAC, VTEP_MAC, eth_type::IPv4 as u16))
+        .header(IPv4Hdr::from("10.0.0.1", "10.0.0.2", 0).unwrap())
+        .header(gre)
+        .header(Erspan::type3(12));
+    pdu.inner = Some(Box::new(inner_frame()));
+    pdu.build().unwrap();
+
+    let parsed = Pdu::parse(&pdu.buffer);
+    match parsed.headers.get(&4) {
+        Some(Proto::Gre(gre)) => assert_eq!(gre.protocol, gre_proto::ERSPAN_III),
+        _ => panic!("GRE not parsed"),
+    }
+    match parsed.headers.get(&7) {
+        Some(Proto::Erspan(erspan)) => assert_eq!(erspan.session_id, 12),
+        _ => panic!("ERSPAN not parsed"),
+    }
+    check_inner(parsed.inner.as_ref().unwrap());
+
+    // Unknown protocol type stays raw
+    let mut pdu = Pdu::new()
+        .header(IPv4Hdr::from("10.0.0.1", "10.0.0.2", 0).unwrap())
+        .header(Gre::new(0x880b))
+        .header(Raw::from(&[1, 2, 3][..]));
+    pdu.build().unwrap();
+    let parsed = Pdu::parse_ip(&pdu.buffer);
+    assert!(parsed.inner.is_none());
+    assert!(matches!(parsed.headers.get(&7), Some(Proto::Raw(_))));
+}
+
+#[test]
+fn nesting_is_capped() {
+    // Thousands of IPv4 in GRE layers, as a crafted frame could hold
+    let mut packet = vec![0x45, 0, 0, 20, 0, 0, 0, 0, 64, ip_proto::UDP, 0, 0];
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    for _ in 0..2500 {
+        let len = (packet.len() + 24) as u16;
+        let mut layer = vec![0x45, 0];
+        layer.extend_from_slice(&len.to_be_bytes());
+        layer.extend_from_slice(&[0, 0, 0, 0, 64, ip_proto::GRE, 0, 0]);
+        layer.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        layer.extend_from_slice(&[0, 0, 0x08, 0x00]);
+        layer.extend_from_slice(&packet);
+        packet = layer;
+    }
+    let mut frame = vec![0; 12];
+    frame.extend_from_slice(&[0x08, 0x00]);
+    frame.extend_from_slice(&packet);
+
+    let mut pdu = Pdu::parse(&frame);
+    let mut depth = 0;
+    while let Some(inner) = pdu.inner {
+        pdu = *inner;
+        depth += 1;
+    }
+    assert_eq!(depth, 8);
+    assert!(matches!(pdu.headers.get(&4), Some(Proto::Gre(_))));
+    match pdu.headers.get(&7) {
+        Some(Proto::Raw(raw)) => assert_eq!(raw.data.len(), 20 + 24 * (2500 - 9)),
+        _ => panic!("Deepest payload not kept raw"),
+    }
+}